@@ -0,0 +1,36 @@
+#![no_main]
+
+// Bytes arbitrarios como imagen entera (bloque 0, tabla de inodos, bitmap y
+// datos, en bloques de QRFS_BLOCK_SIZE): montarla y pasarle el fsck nunca puede
+// entrar en pánico. Un montaje que falla tiene que hacerlo con un error.
+
+use std::fs;
+use std::path::PathBuf;
+
+use libfuzzer_sys::fuzz_target;
+use qrfs::{block_file_name, QrfsImage, QRFS_BLOCK_SIZE};
+
+/// Más bloques no agregan caminos nuevos y hacen cada corrida más lenta.
+const MAX_BLOCKS: usize = 64;
+
+fuzz_target!(|data: &[u8]| {
+    let dir = image_dir();
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    for (i, chunk) in data.chunks(QRFS_BLOCK_SIZE as usize).take(MAX_BLOCKS).enumerate() {
+        let mut block = chunk.to_vec();
+        block.resize(QRFS_BLOCK_SIZE as usize, 0);
+        fs::write(dir.join(block_file_name(i as u32)), block).unwrap();
+    }
+
+    if let Ok(image) = QrfsImage::open(&dir) {
+        drop(image);
+    }
+    let _ = qrfs::check(&dir);
+});
+
+/// Carpeta de trabajo propia de este proceso (libFuzzer puede correr varios).
+fn image_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("qrfs-fuzz-{}", std::process::id()))
+}
@@ -0,0 +1,20 @@
+#![no_main]
+
+// Bytes arbitrarios como bloque 0: interpretarlos y validarlos nunca puede
+// entrar en pánico, sólo devolver un error.
+
+use libfuzzer_sys::fuzz_target;
+use qrfs::SuperblockDisk;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(sb) = SuperblockDisk::from_block_bytes(data) else {
+        return;
+    };
+
+    let _ = sb.check_magic();
+    let _ = sb.verify_checksum();
+    let _ = sb.check_features();
+    if sb.check_layout().is_ok() {
+        assert!(sb.max_inodes as u64 <= sb.inode_table_capacity());
+    }
+});
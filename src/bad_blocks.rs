@@ -0,0 +1,51 @@
+// Bloques dañados de una imagen.
+//
+// Con las hojas impresas puede pasar que una página quede ilegible (manchada,
+// rota, mal escaneada). `mark_bad_block` anota su bloque en la lista del
+// superblock (`SuperblockDisk::bad_blocks`): desde ahí `alloc_block` no lo
+// vuelve a entregar y leerlo como datos de un archivo falla con EIO en vez de
+// devolver basura. Si el bloque estaba libre se marca usado en el bitmap, así
+// los contadores de bloques libres siguen cerrando; si era de un archivo, ese
+// archivo conserva el puntero y el resto de su contenido se puede copiar.
+
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::fs::{bitmap_set, bitmap_test, load_bitmap, load_superblock, write_bitmap, write_superblock};
+
+/// Resultado de `mark_bad_block`.
+#[derive(Debug, Clone, Copy)]
+pub struct BadBlockMark {
+    /// `false` si el bloque ya estaba en la lista.
+    pub added: bool,
+    /// El bloque ya estaba asignado (a un archivo, a un directorio o a un
+    /// bloque de punteros): su contenido se da por perdido.
+    pub in_use: bool,
+}
+
+/// Bloques marcados como dañados en la imagen de `qr_folder`.
+pub fn bad_blocks(qr_folder: &Path) -> Result<Vec<u32>> {
+    Ok(load_superblock(qr_folder)?.bad_block_list())
+}
+
+/// Agrega `block` a la lista de bloques dañados de la imagen. No debe correrse
+/// con el FS montado.
+pub fn mark_bad_block(qr_folder: &Path, block: u32) -> Result<BadBlockMark> {
+    let mut superblock = load_superblock(qr_folder)?;
+    let mut bitmap = load_bitmap(qr_folder, &superblock)?;
+    let in_use = bitmap_test(&bitmap, block);
+
+    if !superblock.add_bad_block(block)? {
+        return Ok(BadBlockMark { added: false, in_use });
+    }
+
+    if !in_use {
+        bitmap_set(&mut bitmap, block, true);
+        superblock.free_blocks = superblock.free_blocks.saturating_sub(1);
+        write_bitmap(qr_folder, &superblock, &bitmap)?;
+    }
+    write_superblock(qr_folder, &superblock)?;
+
+    Ok(BadBlockMark { added: true, in_use })
+}
@@ -0,0 +1,200 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use qrfs::{QrfsImage, QRFS_BLOCK_SIZE};
+
+/// Bloques que se anticipan en la medición con lectura anticipada (por defecto
+/// está desactivada).
+const BENCH_READ_AHEAD: usize = 8;
+
+/// Mide el costo de IO de QRFS (un archivo por bloque + relectura de la tabla de
+/// inodos en cada operación) usando la API sin FUSE.
+///
+/// Uso: bench_qrfs qrfolder/ [iteraciones]
+///
+/// La imagen original no se modifica: se copia a una carpeta temporal y las
+/// pruebas corren sobre la copia, que se borra al terminar.
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let qr_folder = args
+        .next()
+        .map(PathBuf::from)
+        .context("Uso: bench_qrfs qrfolder/ [iteraciones]")?;
+
+    let iterations: usize = match args.next() {
+        Some(n) => n.parse().context("iteraciones debe ser un número entero positivo")?,
+        None => 20,
+    };
+    if iterations == 0 {
+        return Err(anyhow!("iteraciones debe ser mayor que 0"));
+    }
+
+    let work_dir = env::temp_dir().join(format!("qrfs-bench-{}", std::process::id()));
+    copy_image(&qr_folder, &work_dir)?;
+
+    let result = run_benchmarks(&work_dir, iterations);
+    if let Err(e) = fs::remove_dir_all(&work_dir) {
+        eprintln!("Advertencia: no se pudo borrar {:?}: {e}", work_dir);
+    }
+    result
+}
+
+fn run_benchmarks(work_dir: &Path, iterations: usize) -> Result<()> {
+    let image = QrfsImage::open(work_dir).context("No se pudo abrir la copia de la imagen")?;
+    let block_size = QRFS_BLOCK_SIZE as usize;
+
+    // Archivo de 12 bloques: lo máximo que direccionan los punteros directos
+    let file_blocks = 12;
+    let data: Vec<u8> = (0..file_blocks * block_size).map(|i| (i % 251) as u8).collect();
+    let seq_path = Path::new("/bench.seq");
+
+    println!("bench_qrfs: {} iteraciones, archivo de {} bytes\n", iterations, data.len());
+
+    image.create_file(seq_path)?;
+
+    // 1. Escritura secuencial (reemplazo completo del archivo)
+    let seq_write = measure(iterations, |_| image.write_file(seq_path, &data))?;
+    report("escritura secuencial", &seq_write, Some(data.len()));
+
+    // 2. Lectura secuencial del archivo completo
+    let seq_read = measure(iterations, |_| {
+        let read = image.read_file(seq_path)?;
+        if read != data {
+            return Err(anyhow!("La lectura secuencial no coincide con lo escrito"));
+        }
+        Ok(())
+    })?;
+    report("lectura secuencial", &seq_read, Some(data.len()));
+
+    // 2-bis. Lectura en orden de a un bloque (como un proceso que hace read() en
+    //        un bucle), sin y con lectura anticipada
+    let image = image.read_ahead(0);
+    let blocks_plain = measure(iterations, |_| read_by_blocks(&image, seq_path, &data))?;
+    report("lectura por bloques", &blocks_plain, Some(data.len()));
+
+    let image = image.read_ahead(BENCH_READ_AHEAD);
+    let blocks_ahead = measure(iterations, |_| read_by_blocks(&image, seq_path, &data))?;
+    report(
+        &format!("lectura por bloques (+{})", BENCH_READ_AHEAD),
+        &blocks_ahead,
+        Some(data.len()),
+    );
+
+    // 3. Lectura aleatoria de un bloque
+    let mut rng = XorShift(0x9E37_79B9_7F4A_7C15);
+    let rand_read = measure(iterations, |_| {
+        let block = rng.next() as usize % file_blocks;
+        image.read_at(seq_path, (block * block_size) as u64, block_size)?;
+        Ok(())
+    })?;
+    report("lectura aleatoria (1 bloque)", &rand_read, Some(block_size));
+
+    image.remove(seq_path)?;
+
+    // 4. Metadatos: cada operación consume un inodo nuevo, así que se limita a
+    //    los que queden libres en la imagen
+    let mut created = Vec::new();
+    let create = measure(iterations, |i| {
+        let path = PathBuf::from(format!("/bench.meta.{}", i));
+        image.create_file(&path)?;
+        created.push(path);
+        Ok(())
+    });
+    match create {
+        Ok(times) => report("create", &times, None),
+        Err(e) => println!("{:<30} interrumpido tras {} archivos: {e}", "create", created.len()),
+    }
+
+    let stat = measure(created.len(), |i| image.stat(&created[i]).map(|_| ()))?;
+    report("stat", &stat, None);
+
+    let unlink = measure(created.len(), |i| image.remove(&created[i]))?;
+    report("unlink", &unlink, None);
+
+    Ok(())
+}
+
+/// Lee `path` de a un bloque, en orden, y lo compara con `expected`.
+fn read_by_blocks(image: &QrfsImage, path: &Path, expected: &[u8]) -> Result<()> {
+    let block_size = QRFS_BLOCK_SIZE as usize;
+    for (i, chunk) in expected.chunks(block_size).enumerate() {
+        let read = image.read_at(path, (i * block_size) as u64, block_size)?;
+        if read != chunk {
+            return Err(anyhow!("La lectura del bloque {} no coincide con lo escrito", i));
+        }
+    }
+    Ok(())
+}
+
+/// Corre `op` `n` veces y devuelve la duración de cada corrida.
+fn measure<F>(n: usize, mut op: F) -> Result<Vec<Duration>>
+where
+    F: FnMut(usize) -> Result<()>,
+{
+    let mut times = Vec::with_capacity(n);
+    for i in 0..n {
+        let start = Instant::now();
+        op(i)?;
+        times.push(start.elapsed());
+    }
+    Ok(times)
+}
+
+fn report(name: &str, times: &[Duration], bytes_per_op: Option<usize>) {
+    if times.is_empty() {
+        println!("{:<30} sin operaciones", name);
+        return;
+    }
+
+    let total: Duration = times.iter().sum();
+    let mut sorted = times.to_vec();
+    sorted.sort();
+    let avg = total / times.len() as u32;
+    let p50 = sorted[sorted.len() / 2];
+    let max = sorted[sorted.len() - 1];
+    let ops_per_sec = times.len() as f64 / total.as_secs_f64();
+
+    print!(
+        "{:<30} {:>6} ops  {:>10.1} ops/s  prom {:>9.3?}  p50 {:>9.3?}  máx {:>9.3?}",
+        name,
+        times.len(),
+        ops_per_sec,
+        avg,
+        p50,
+        max
+    );
+    if let Some(bytes) = bytes_per_op {
+        let mib = (bytes * times.len()) as f64 / (1024.0 * 1024.0);
+        print!("  {:>8.2} MiB/s", mib / total.as_secs_f64());
+    }
+    println!();
+}
+
+/// Copia los archivos de bloque (y el marcador de overlay, si existe) a `dest`.
+fn copy_image(src: &Path, dest: &Path) -> Result<()> {
+    fs::create_dir_all(dest).with_context(|| format!("No se pudo crear {:?}", dest))?;
+
+    for entry in fs::read_dir(src).with_context(|| format!("No se pudo leer el directorio {:?}", src))? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            fs::copy(entry.path(), dest.join(entry.file_name()))
+                .with_context(|| format!("No se pudo copiar {:?}", entry.path()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Generador pseudoaleatorio mínimo (xorshift64) para elegir bloques.
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
@@ -0,0 +1,326 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Result};
+use qrfs::{
+    build_layout,
+    create_block_files,
+    init_fresh_fs,
+    list_block_files,
+    make_dir_block,
+    write_fresh_fs,
+    write_manifest,
+    BlockStore,
+    DirEntryDisk,
+    InodeDisk,
+    QRFS_BLOCK_SIZE,
+    QRFS_DEFAULT_BLOCK_EXT,
+};
+
+const USAGE: &str = "Uso: from_dir.qrfs [--blocks N] [--exclude PATRÓN]... origen/ qrfolder/";
+
+/// Arma una imagen QRFS nueva en `qrfolder/` con el contenido de un directorio
+/// del host: estructura de directorios, contenido de los archivos, permisos,
+/// dueño y tiempos.
+///
+/// Sin `--blocks` la imagen se dimensiona sola con un 25% de margen sobre lo
+/// que ocupa el árbol (bloques de datos e inodos). Los enlaces simbólicos y los
+/// archivos especiales se omiten con un aviso.
+///
+/// Cada `--exclude PATRÓN` (se puede repetir) deja afuera lo que coincida,
+/// con todo su contenido si es un directorio. Ver `is_excluded`.
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1).peekable();
+
+    let mut explicit_blocks: Option<u32> = None;
+    let mut excludes: Vec<String> = Vec::new();
+    while let Some(opt) = args.next_if(|a| a.starts_with("--")) {
+        match opt.as_str() {
+            "--blocks" => {
+                let n = args.next().context(USAGE)?;
+                explicit_blocks = Some(n.parse().context("--blocks debe ser un número entero positivo")?);
+            }
+            "--exclude" => excludes.push(args.next().context(USAGE)?),
+            other => return Err(anyhow!("Opción desconocida {:?}\n{}", other, USAGE)),
+        }
+    }
+
+    let source = args.next().map(PathBuf::from).context(USAGE)?;
+    let qr_folder = args.next().map(PathBuf::from).context(USAGE)?;
+    if args.next().is_some() {
+        return Err(anyhow!(USAGE));
+    }
+
+    // 1. Recorrer el árbol de origen y numerar los inodos (raíz = 1)
+    let mut nodes = Vec::new();
+    scan_tree(&source, Path::new(""), 1, &excludes, &mut nodes)?;
+
+    let block_size = QRFS_BLOCK_SIZE as u64;
+    let data_blocks: u32 = nodes.iter().map(|n| n.blocks_needed(block_size)).sum();
+    let inode_count = nodes.len() as u32;
+
+    // 2. Elegir el tamaño de la imagen
+    let total_blocks = match explicit_blocks {
+        Some(n) => n,
+        None => auto_size(data_blocks + data_blocks / 4, inode_count + inode_count / 4)?,
+    };
+
+    let layout = build_layout(total_blocks)?;
+    let available_data = layout.total_blocks - layout.data_blocks_start;
+    if data_blocks > available_data || inode_count > layout.max_inodes {
+        return Err(anyhow!(
+            "El árbol necesita {} bloques de datos y {} inodos; una imagen de {} bloques tiene {} y {}",
+            data_blocks,
+            inode_count,
+            total_blocks,
+            available_data,
+            layout.max_inodes
+        ));
+    }
+
+    // 3. Formatear en memoria y completar inodos, bitmap y bloques del árbol.
+    //    El bloque de la raíz (data_blocks_start) ya viene reservado.
+    let (mut superblock, mut inodes, mut bitmap) = init_fresh_fs(&layout)?;
+    let mut blocks: Vec<(u32, Vec<u8>)> = Vec::new();
+    let mut next_block = layout.data_blocks_start + 1;
+
+    for node in &nodes {
+        let mut direct_blocks = [0u32; 12];
+
+        let (file_type, size, nlink) = match &node.kind {
+            NodeKind::Dir { children } => {
+                let block = if node.ino == 1 {
+                    layout.data_blocks_start
+                } else {
+                    take_block(&mut next_block, &mut bitmap)
+                };
+                direct_blocks[0] = block;
+
+                let subdirs = children
+                    .iter()
+                    .filter(|(_, ino)| nodes[*ino as usize - 1].is_dir())
+                    .count() as u32;
+                let slots = children.len() + 2;
+                blocks.push((block, make_dir_block(node.ino, node.parent, children)?));
+                (2, (slots * std::mem::size_of::<DirEntryDisk>()) as u64, 2 + subdirs)
+            }
+            NodeKind::File { path, size } => {
+                let data = fs::read(path).with_context(|| format!("No se pudo leer {:?}", path))?;
+                if data.len() as u64 != *size {
+                    return Err(anyhow!("{:?} cambió de tamaño mientras se importaba", path));
+                }
+                for (i, chunk) in data.chunks(QRFS_BLOCK_SIZE as usize).enumerate() {
+                    let block = take_block(&mut next_block, &mut bitmap);
+                    direct_blocks[i] = block;
+                    blocks.push((block, chunk.to_vec()));
+                }
+                (1, *size, 1)
+            }
+        };
+
+        inodes[node.ino as usize - 1] = InodeDisk {
+            id: node.ino as u32,
+            file_type,
+            perm: (node.meta.mode() & 0o7777) as u16,
+            uid: node.meta.uid(),
+            gid: node.meta.gid(),
+            size,
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+            nlink,
+            direct_blocks,
+            indirect_block: 0,
+            double_indirect_block: 0,
+            generation: 0,
+        };
+        // Con la feature nsec-time se conservan también los nanosegundos
+        let inode = &mut inodes[node.ino as usize - 1];
+        inode.set_atime(unix_time(node.meta.atime(), node.meta.atime_nsec()));
+        inode.set_mtime(unix_time(node.meta.mtime(), node.meta.mtime_nsec()));
+        inode.set_ctime(unix_time(node.meta.ctime(), node.meta.ctime_nsec()));
+    }
+
+    // La raíz y su bloque ya están descontados por init_fresh_fs
+    superblock.free_blocks -= data_blocks - 1;
+    superblock.free_inodes -= inode_count - 1;
+
+    // 4. Crear los archivos de bloque recién ahora, cuando ya se sabe que el árbol
+    //    entra, y escribir metadatos y después los bloques de datos y de directorio
+    create_block_files(&qr_folder, total_blocks, QRFS_DEFAULT_BLOCK_EXT)?;
+    let entries = list_block_files(&qr_folder)?;
+
+    write_fresh_fs(&entries, &layout, &superblock, &inodes, &bitmap)?;
+
+    let store = BlockStore::open(&qr_folder)?;
+    for (block, data) in &blocks {
+        store.write_block(*block, data)?;
+    }
+    write_manifest(&qr_folder, &entries)?;
+
+    println!(
+        "from_dir.qrfs: {} archivos y {} directorios importados en {:?} ({} bloques, {} de datos usados, {} inodos máximos).",
+        nodes.iter().filter(|n| !n.is_dir()).count(),
+        nodes.iter().filter(|n| n.is_dir()).count(),
+        qr_folder,
+        total_blocks,
+        data_blocks,
+        layout.max_inodes
+    );
+
+    Ok(())
+}
+
+enum NodeKind {
+    Dir { children: Vec<(OsString, u64)> },
+    File { path: PathBuf, size: u64 },
+}
+
+/// Archivo o directorio del árbol de origen; `ino` es su posición + 1 en `nodes`.
+struct Node {
+    ino: u64,
+    parent: u64,
+    meta: fs::Metadata,
+    kind: NodeKind,
+}
+
+impl Node {
+    fn is_dir(&self) -> bool {
+        matches!(self.kind, NodeKind::Dir { .. })
+    }
+
+    /// Bloques de datos que ocupa en la imagen (un directorio siempre usa uno).
+    fn blocks_needed(&self, block_size: u64) -> u32 {
+        match &self.kind {
+            NodeKind::Dir { .. } => 1,
+            NodeKind::File { size, .. } => size.div_ceil(block_size) as u32,
+        }
+    }
+}
+
+/// Agrega `path` (un directorio, `rel` dentro del origen) y todo su contenido
+/// que no esté excluido a `nodes`. Los hijos se ordenan por nombre para que dos
+/// corridas sobre el mismo árbol den la misma imagen.
+fn scan_tree(path: &Path, rel: &Path, parent: u64, excludes: &[String], nodes: &mut Vec<Node>) -> Result<()> {
+    let meta = fs::metadata(path).with_context(|| format!("No se pudo leer {:?}", path))?;
+    let ino = nodes.len() as u64 + 1;
+    let parent = if ino == 1 { ino } else { parent };
+
+    nodes.push(Node {
+        ino,
+        parent,
+        meta,
+        kind: NodeKind::Dir { children: Vec::new() },
+    });
+
+    let mut dir_entries: Vec<_> = fs::read_dir(path)
+        .with_context(|| format!("No se pudo leer el directorio {:?}", path))?
+        .collect::<std::io::Result<_>>()?;
+    dir_entries.sort_by_key(|e| e.file_name());
+
+    let mut children = Vec::new();
+    for entry in dir_entries {
+        let name = entry.file_name();
+        let child_path = entry.path();
+        let child_rel = rel.join(&name);
+        if is_excluded(&child_rel, excludes) {
+            continue;
+        }
+        let file_type = entry.file_type()?;
+        let child_ino = nodes.len() as u64 + 1;
+
+        if file_type.is_dir() {
+            scan_tree(&child_path, &child_rel, ino, excludes, nodes)?;
+        } else if file_type.is_file() {
+            let meta = entry.metadata()?;
+            let size = meta.len();
+            let max_size = 12 * QRFS_BLOCK_SIZE as u64;
+            if size > max_size {
+                return Err(anyhow!(
+                    "{:?} mide {} bytes; QRFS sólo direcciona {} bytes por archivo (12 bloques directos)",
+                    child_path,
+                    size,
+                    max_size
+                ));
+            }
+            nodes.push(Node {
+                ino: child_ino,
+                parent: ino,
+                meta,
+                kind: NodeKind::File { path: child_path, size },
+            });
+        } else {
+            eprintln!("Advertencia: se omite {:?} (no es archivo ni directorio)", child_path);
+            continue;
+        }
+
+        children.push((name, child_ino));
+    }
+
+    nodes[ino as usize - 1].kind = NodeKind::Dir { children };
+    Ok(())
+}
+
+/// `true` si `rel` (ruta relativa al origen) coincide con algún patrón de
+/// `--exclude`. Un patrón sin `/` se compara con el nombre en cualquier nivel
+/// (`.git`, `*.tmp`); uno con `/` con la ruta entera desde el origen
+/// (`build/cache`, `docs/*.pdf`). `*` es cualquier tramo sin `/`, `**`
+/// cualquier tramo y `?` un carácter.
+fn is_excluded(rel: &Path, excludes: &[String]) -> bool {
+    let rel = rel.as_os_str().as_bytes();
+    let name = rel.rsplit(|&c| c == b'/').next().unwrap_or(rel);
+    excludes.iter().any(|pattern| {
+        let pattern = pattern.trim_start_matches('/').as_bytes();
+        let text = if pattern.contains(&b'/') { rel } else { name };
+        glob_match(pattern, text)
+    })
+}
+
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', rest @ ..] => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+        [b'*', rest @ ..] => {
+            let segment = text.iter().position(|&c| c == b'/').unwrap_or(text.len());
+            (0..=segment).any(|i| glob_match(rest, &text[i..]))
+        }
+        [b'?', rest @ ..] => matches!(text, [c, tail @ ..] if *c != b'/' && glob_match(rest, tail)),
+        [p, rest @ ..] => matches!(text, [c, tail @ ..] if c == p && glob_match(rest, tail)),
+    }
+}
+
+/// Menor cantidad de bloques cuyo layout tiene al menos `data_blocks` bloques de
+/// datos y `inodes` inodos.
+fn auto_size(data_blocks: u32, inodes: u32) -> Result<u32> {
+    let mut total = data_blocks.max(1) + 3;
+    loop {
+        let layout = build_layout(total)?;
+        if total - layout.data_blocks_start >= data_blocks && layout.max_inodes >= inodes {
+            return Ok(total);
+        }
+        total = total
+            .checked_add(1)
+            .ok_or_else(|| anyhow!("El árbol no entra en ninguna imagen QRFS"))?;
+    }
+}
+
+/// Reserva el siguiente bloque de datos libre (la imagen es nueva: se asignan en orden).
+fn take_block(next_block: &mut u32, bitmap: &mut [u8]) -> u32 {
+    let block = *next_block;
+    bitmap[block as usize / 8] |= 1 << (block % 8);
+    *next_block += 1;
+    block
+}
+
+/// Tiempo de `stat` (segundos y nanosegundos); los anteriores a 1970 quedan en 0.
+fn unix_time(secs: i64, nsec: i64) -> SystemTime {
+    if secs < 0 {
+        return UNIX_EPOCH;
+    }
+    UNIX_EPOCH + Duration::new(secs as u64, nsec.clamp(0, 999_999_999) as u32)
+}
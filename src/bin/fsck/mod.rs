@@ -1,9 +0,0 @@
-pub mod fsck_types;
-pub mod fsck_backend;
-pub mod fsck;
-pub mod mock;
-
-pub mod qrfs_backend;
-
-/*Es el archivo índice de módulo fsck.
-Aquí simplemente declara qué submódulos existen.*/
\ No newline at end of file
@@ -1,135 +1,253 @@
 use colored::*;
-use qrfs::fsck::{mock::MockBackend, fsck_types::*, fsck};
 
 use std::env;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
 
-use qrfs::fsck::{fsck_types::*, fsck, qrfs_backend::QrfsBackend};
+use qrfs::fsck::{fsck, fsck_types::FsckOptions, qrfs_backend::QrfsBackend};
+use qrfs::fsck::repair::{find_repairs, run_repairs, RepairMode};
 
 fn main() {
-    let mut args = env::args().skip(1);
-    let qrfolder = args.next().expect("Uso: fsck_qrfs qrfolder/");
+    let args: Vec<String> = env::args().skip(1).collect();
+    let compact_dirs = args.iter().any(|a| a == "--compact-dirs");
+    let list_deleted = args.iter().any(|a| a == "--list-deleted");
+    let rebuild_inodes = args.iter().any(|a| a == "--rebuild-inodes");
+    let repair_counters = args.iter().any(|a| a == "--repair-counters");
+    let check_dots = args.iter().any(|a| a == "--check-dots");
+    let repair_dots = args.iter().any(|a| a == "--repair-dots");
+    let block_checksums = args.iter().any(|a| a == "--block-checksum-scan");
+    // Las reparaciones corren pero no escriben: se lista lo que cambiarían
+    let dry_run = args.iter().any(|a| a == "--dry-run");
+    // Reparación de lo que encuentra el chequeo: en lote, mostrando cada una o preguntando
+    let repair_mode = if args.iter().any(|a| a == "--interactive") {
+        Some(RepairMode::Interactive)
+    } else if args.iter().any(|a| a == "--yes") {
+        Some(RepairMode::Yes)
+    } else if args.iter().any(|a| a == "--repair") {
+        Some(RepairMode::Batch)
+    } else {
+        None
+    };
+    const USAGE: &str = "Uso: fsck_qrfs [--compact-dirs | --list-deleted | --rebuild-inodes | --repair-counters \
+                         | --check-dots | --repair-dots] \
+                         [--repair | --yes | --interactive] [--dry-run] [--block-checksum-scan] [--limit N] [--path /ruta] qrfolder/";
 
-    let backend = QrfsBackend::new(PathBuf::from(qrfolder));
+    // --limit y --path llevan valor: se sacan antes de buscar la carpeta
+    let mut opts = FsckOptions {
+        block_checksums,
+        ..FsckOptions::default()
+    };
+    let mut positional = Vec::new();
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--limit" => {
+                let n = rest.next().and_then(|n| n.parse().ok()).expect(USAGE);
+                opts.limit = Some(n);
+            }
+            "--path" => opts.path = Some(rest.next().cloned().expect(USAGE)),
+            a if !a.starts_with("--") => positional.push(arg.clone()),
+            _ => {}
+        }
+    }
+    let qrfolder = positional.first().cloned().expect(USAGE);
 
-    let mut rep = FsckReport::new();
-    fsck(&backend, &mut rep);
+    // Los modos que escriben no corren sobre una imagen montada (ni junto a
+    // otro fsck que escribe): el lock se suelta al salir. Con --dry-run sólo se lee
+    let writes = repair_counters || repair_dots || compact_dirs || rebuild_inodes || repair_mode.is_some();
+    let _lock = if writes && !dry_run {
+        match qrfs::lock_folder(&PathBuf::from(&qrfolder)) {
+            Ok(lock) => lock,
+            Err(e) => {
+                eprintln!("{} {e:?}", "✗".red().bold());
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
 
-    println!("{}", "Resultado de fsck.qrfs".bold());
+    // Arreglo rápido: sólo los contadores libres del superblock, sin recorrer directorios
+    if repair_counters {
+        match planned(&qrfolder, dry_run, || qrfs::repair_counters(Path::new(&qrfolder))) {
+            Ok(r) if r.changed() => {
+                println!(
+                    "{} Contadores corregidos: bloques libres {} -> {}, inodos libres {} -> {}.",
+                    "✓".green().bold(),
+                    r.old_free_blocks,
+                    r.free_blocks,
+                    r.old_free_inodes,
+                    r.free_inodes
+                );
+            }
+            Ok(r) => println!(
+                "{} Contadores correctos ({} bloques libres, {} inodos libres).",
+                "✓".green().bold(),
+                r.free_blocks,
+                r.free_inodes
+            ),
+            Err(e) => {
+                eprintln!("{} {e:?}", "✗".red().bold());
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
 
-    for err in &rep.errors {
-        println!("{} {}", "✗".red().bold(), err.red());
+    // "." y ".." al principio de cada directorio (con --repair-dots se reescriben)
+    if check_dots || repair_dots {
+        match planned(&qrfolder, dry_run, || qrfs::check_dot_entries(Path::new(&qrfolder), repair_dots)) {
+            Ok(problems) => {
+                for p in &problems {
+                    let falta = match (p.bad_dot, p.bad_dotdot) {
+                        (true, true) => "\".\" y \"..\"",
+                        (true, false) => "\".\"",
+                        _ => "\"..\"",
+                    };
+                    if p.repaired {
+                        println!("  {} directorio {}: {} restaurado (padre {})", "✓".green(), p.ino, falta, p.parent);
+                    } else if repair_dots {
+                        println!("  {} directorio {}: {} sin reparar (no hay slots libres)", "✗".red(), p.ino, falta);
+                    } else {
+                        println!("  {} directorio {}: falta o está mal {}", "!".yellow(), p.ino, falta);
+                    }
+                }
+                println!("{} directorios con \".\" o \"..\" dañados.", problems.len());
+            }
+            Err(e) => {
+                eprintln!("{} {e:?}", "✗".red().bold());
+                std::process::exit(1);
+            }
+        }
+        return;
     }
 
-    println!("\n{}", "Resumen".bold().underline());
-    if rep.errors.is_empty() {
-        println!("{} Sistema de archivos limpio.\n", "✓ OK".green().bold());
-    } else {
-        println!(
-            "{} {} errores encontrados.\n",
-            "✗ FSCK completado con errores:".red().bold(),
-            rep.errors.len().to_string().yellow()
-        );
+    // Mantenimiento: compactar directorios (elimina lápidas de entradas borradas)
+    if compact_dirs {
+        match planned(&qrfolder, dry_run, || qrfs::compact_directories(Path::new(&qrfolder))) {
+            Ok(n) => println!("{} {} entradas borradas eliminadas.", "✓".green().bold(), n),
+            Err(e) => {
+                eprintln!("{} {e:?}", "✗".red().bold());
+                std::process::exit(1);
+            }
+        }
+        return;
     }
-}
 
+    // Inodos borrados con unlink que todavía se pueden recuperar
+    if list_deleted {
+        match qrfs::list_deleted(&PathBuf::from(&qrfolder)) {
+            Ok(deleted) => {
+                println!("{}", "Inodos borrados".bold());
+                for d in &deleted {
+                    let estado = if d.recoverable {
+                        "recuperable".green()
+                    } else {
+                        "bloques reutilizados".red()
+                    };
+                    println!("  ino {:>5}  {:>8} bytes  borrado en {}  {}", d.ino, d.size, d.deleted_at, estado);
+                }
+                println!("{} inodos borrados.", deleted.len());
+            }
+            Err(e) => {
+                eprintln!("{} {e:?}", "✗".red().bold());
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
 
-fn main2() {
-    // ——————————————————————————————————————————
-    // BACKEND SIMULADO (con errores para mostrar colores)
-    // ——————————————————————————————————————————
-    let backend = MockBackend {
-    superblock: Superblock {
-        magic: 0x1234,
-        num_inodes: 2,
-        num_blocks: 10,
-        root_inode: 0,
-    },
-
-    inodes: vec![
-        Inode {
-            is_dir: true,
-            size: 0,
-            direct: vec![],
-            indirect1: None,
-            indirect2: None,
-        },
-        Inode {
-            is_dir: false,
-            size: 5,
-            direct: vec![1],
-            indirect1: None,
-            indirect2: None,
+    // Recuperación profunda: reconstruir la tabla de inodos desde los directorios
+    if rebuild_inodes {
+        match planned(&qrfolder, dry_run, || qrfs::rebuild_inode_table(Path::new(&qrfolder))) {
+            Ok(rep) => {
+                println!("{}", "Reconstrucción de la tabla de inodos".bold());
+                for (ino, block) in &rep.directories {
+                    println!("  {} directorio ino {} (bloque {})", "✓".green(), ino, block);
+                }
+                for (ino, name) in &rep.files {
+                    println!(
+                        "  {} archivo ino {} {:?}: recreado vacío, contenido no ubicado",
+                        "?".yellow(),
+                        ino,
+                        name
+                    );
+                }
+                if !rep.unplaced_blocks.is_empty() {
+                    println!(
+                        "  {} {} bloques de datos sin dueño (siguen reservados): {:?}",
+                        "?".yellow(),
+                        rep.unplaced_blocks.len(),
+                        rep.unplaced_blocks
+                    );
+                }
+                for w in &rep.warnings {
+                    println!("  {} {}", "!".red(), w);
+                }
+                println!(
+                    "{} directorios con confianza alta, {} archivos con confianza baja.",
+                    rep.directories.len(),
+                    rep.files.len()
+                );
+            }
+            Err(e) => {
+                eprintln!("{} {e:?}", "✗".red().bold());
+                std::process::exit(1);
+            }
         }
-    ],
-
-    dirs: vec![
-        vec![
-            Dirent { name: ".".into(),  inode: 0, is_dir: true, valid: true },
-            Dirent { name: "..".into(), inode: 0, is_dir: true, valid: true },
-            Dirent { name: "file".into(), inode: 1, is_dir: false, valid: true },
-        ]
-    ],
-
-    blocks: vec![ vec![]; 10 ],
-
-    bitmap: vec![
-        false,  // 0 libre
-        true,   // 1 usado por inode 1
-        false, false, false,
-        false, false, false, false, false,
-    ],
-};
-
-
-    // ——————————————————————————————————————————
-    //       EJECUTAR FSCK
-    // ——————————————————————————————————————————
-    let rep = fsck::run_fsck(&backend);
-
-    println!("\n{}", " QRFS FILESYSTEM CHECK ".on_blue().bold());
-    println!("{}", "──────────────────────────────────────────".blue());
-
-    // ——————————————————————————————————————————
-    //       RESULTADOS DE BLOQUES
-    // ——————————————————————————————————————————
-    println!("\n{}", "Bloques".bold().underline());
-
-    if rep.blocks_ok {
-        println!("  {} Bloques OK", "✓".green());
-    } else {
-        println!("  {} Errores en bloques", "✗".red());
+        return;
     }
 
-    // ——————————————————————————————————————————
-    //       RESULTADOS DE INODOS
-    // ——————————————————————————————————————————
-    println!("\n{}", "Inodos".bold().underline());
+    let mut backend = QrfsBackend::new(PathBuf::from(&qrfolder));
 
-    if rep.inodes_ok {
-        println!("  {} Inodos OK", "✓".green());
-    } else {
-        println!("  {} Errores en inodos", "✗".red());
+    // Primero las reparaciones (si se pidieron) y después el chequeo sobre el resultado
+    if let Some(mode) = repair_mode {
+        let repairs = find_repairs(&backend);
+        let stdin = io::stdin();
+        let result = planned(&qrfolder, dry_run, || {
+            let s = run_repairs(&mut backend, &repairs, mode, stdin.lock(), &mut io::stdout())?;
+            // Un bloque liberado o marcado usado cambia el contador del superblock
+            if s.applied > 0 {
+                if let Err(e) = qrfs::repair_counters(Path::new(&qrfolder)) {
+                    eprintln!("{} No se pudieron recalcular los contadores: {e:?}", "✗".red().bold());
+                }
+            }
+            Ok(s)
+        });
+        match result {
+            Ok(s) => {
+                println!(
+                    "{} {} reparaciones {}, {} omitidas.\n",
+                    "✓".green().bold(),
+                    s.applied,
+                    if dry_run { "que se aplicarían" } else { "aplicadas" },
+                    s.skipped
+                );
+            }
+            Err(e) => {
+                eprintln!("{} {e:?}", "✗".red().bold());
+                std::process::exit(1);
+            }
+        }
     }
 
-    // ——————————————————————————————————————————
-    //       ERRORES DETALLADOS
-    // ——————————————————————————————————————————
-    println!("\n{}", "Errores detectados".bold().underline());
+    let rep = fsck::run_fsck_with(&backend, &opts);
 
-    if rep.errors.is_empty() {
-        println!("  {} No se encontraron errores", "✓".green());
-    } else {
-        for err in &rep.errors {
-            println!("  {} {}", "•".red(), err.red());
-        }
+    println!("{}", "Resultado de fsck.qrfs".bold());
+
+    for err in &rep.errors {
+        println!("{} {}", "✗".red().bold(), err.red());
     }
 
-    // ——————————————————————————————————————————
-    //       RESUMEN FINAL
-    // ——————————————————————————————————————————
-    println!("\n{}", "Resumen".bold().underline());
+    for note in &rep.notes {
+        println!("{} {}", "!".yellow(), note);
+    }
 
+    if rep.truncated {
+        println!("{} Se alcanzó el límite de {} errores; puede haber más.", "!".yellow(), rep.errors.len());
+    }
+
+    println!("\n{}", "Resumen".bold().underline());
     if rep.errors.is_empty() {
         println!("{} Sistema de archivos limpio.\n", "✓ OK".green().bold());
     } else {
@@ -140,3 +258,20 @@ fn main2() {
         );
     }
 }
+
+/// Corre una reparación. Con `--dry-run` no escribe nada: antes de su resultado
+/// lista cada cambio que habría hecho (campos del superblock, bits del bitmap,
+/// campos de inodos y otros bloques).
+fn planned<T>(qrfolder: &str, dry_run: bool, f: impl FnOnce() -> anyhow::Result<T>) -> anyhow::Result<T> {
+    if !dry_run {
+        return f();
+    }
+
+    let (value, changes) = qrfs::dry_run(Path::new(qrfolder), f)?;
+    println!("{}", "Cambios que se harían (--dry-run, no se escribió nada)".bold());
+    for change in &changes {
+        println!("  {} {}", "~".cyan(), change);
+    }
+    println!("{} cambios.\n", changes.len());
+    Ok(value)
+}
@@ -0,0 +1,315 @@
+use std::collections::{BTreeSet, HashSet};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use qrfs::{
+    bitmap_set, bitmap_test, block_file_name, list_block_files, lock_folder, refresh_manifest, write_manifest,
+    BlockStore, InodeDisk, InodeTable, SuperblockDisk, QRFS_BLOCK_SIZE, QRFS_MANIFEST_NAME,
+};
+
+/// Agranda un QRFS existente agregando archivos de bloque nuevos al final.
+///
+/// Uso: grow.qrfs qrfolder/ total_bloques
+///
+/// - Crea los archivos de bloque que faltan (llenos de ceros).
+/// - Si el bitmap ya no alcanza, toma bloques del inicio del área de datos
+///   (reubicando su contenido a bloques nuevos) y corre `data_blocks_start`.
+/// - Actualiza `total_blocks` y `free_blocks` en el superblock.
+/// - Si la carpeta tiene manifiesto, le agrega los bloques nuevos.
+/// - No corre sobre una imagen montada: toma el lock de la carpeta antes de
+///   escribir nada.
+///
+/// La tabla de inodos no se agranda: hacerlo obligaría a correr también el
+/// bitmap y toda el área de datos.
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let qr_folder = args
+        .next()
+        .map(PathBuf::from)
+        .context("Uso: grow.qrfs qrfolder/ total_bloques")?;
+
+    let new_total: u32 = args
+        .next()
+        .context("Uso: grow.qrfs qrfolder/ total_bloques")?
+        .parse()
+        .context("total_bloques debe ser un número entero positivo")?;
+
+    if args.next().is_some() {
+        return Err(anyhow!("Uso: grow.qrfs qrfolder/ total_bloques"));
+    }
+
+    // La imagen no puede estar montada (ni otro proceso escribiéndola) mientras
+    // se mueven bloques: el lock se suelta al salir
+    let _lock = lock_folder(&qr_folder)?;
+
+    // 1. Leer el superblock
+    let store = BlockStore::open(&qr_folder)?;
+    if store.is_empty() {
+        return Err(anyhow!("La carpeta {:?} no contiene bloques QRFS", qr_folder));
+    }
+
+    let mut sb = SuperblockDisk::from_block_bytes(&store.read_block(0)?)?;
+    sb.check_magic()?;
+    if !sb.verify_checksum() {
+        return Err(anyhow!("El checksum del superblock no coincide (superblock checksum mismatch)"));
+    }
+    sb.check_features()?;
+    sb.check_layout()?;
+
+    // Los bloques de paridad van justo después de los del FS: habría que
+    // moverlos y recalcular el último grupo
+    if sb.parity_group != 0 {
+        return Err(anyhow!(
+            "La imagen tiene paridad {}:{} y grow.qrfs todavía no sabe agrandarla",
+            sb.parity_group,
+            sb.parity_per_group
+        ));
+    }
+
+    if store.len() != sb.total_blocks as usize {
+        return Err(anyhow!(
+            "La carpeta tiene {} archivos pero el superblock indica {} bloques",
+            store.len(),
+            sb.total_blocks
+        ));
+    }
+
+    let old_total = sb.total_blocks;
+    if new_total <= old_total {
+        return Err(anyhow!(
+            "El nuevo tamaño ({}) debe ser mayor que el actual ({})",
+            new_total,
+            old_total
+        ));
+    }
+
+    // 2. Crear los archivos de bloque nuevos (numerados a continuación del último).
+    //    Con manifiesto los archivos pueden haberse renombrado: no se pisa ninguno,
+    //    y los nuevos se agregan al manifiesto para que el store los vea
+    let block_size = QRFS_BLOCK_SIZE as usize;
+    let mut entries = list_block_files(&qr_folder)?;
+    for b in old_total..new_total {
+        let path = qr_folder.join(block_file_name(b));
+        if path.exists() {
+            return Err(anyhow!("Ya existe {:?}: no se puede crear el bloque {}", path, b));
+        }
+        fs::write(&path, vec![0u8; block_size])
+            .with_context(|| format!("No se pudo crear el bloque {:?}", path))?;
+        entries.push(path);
+    }
+    if qr_folder.join(QRFS_MANIFEST_NAME).is_file() {
+        write_manifest(&qr_folder, &entries)?;
+    }
+
+    let store = BlockStore::open(&qr_folder)?;
+    if store.len() != new_total as usize {
+        return Err(anyhow!(
+            "Después de crear los bloques nuevos la carpeta tiene {} en vez de {}",
+            store.len(),
+            new_total
+        ));
+    }
+
+    // 3. Cargar el bitmap actual y extenderlo al nuevo tamaño
+    let mut bitmap = store.read_blocks(sb.free_bitmap_start, sb.free_bitmap_blocks)?;
+    bitmap.truncate((old_total as usize).div_ceil(8));
+    bitmap.resize((new_total as usize).div_ceil(8), 0);
+
+    let needed_bitmap_blocks = bitmap.len().div_ceil(block_size);
+    let extra = (needed_bitmap_blocks as u32).saturating_sub(sb.free_bitmap_blocks);
+
+    // 4. Si el bitmap necesita más bloques, se toman los primeros bloques de datos.
+    //    Los que estén en uso se reubican a un bloque nuevo y se corrige el puntero.
+    //    Los libres que todavía guardan datos de un archivo borrado también se
+    //    copian (el destino queda libre, pero no se reusa acá) para que undelete
+    //    los siga encontrando.
+    if extra > 0 {
+        // La tabla de inodos no se mueve: sirve el superblock de antes
+        let table = InodeTable::new(store.clone(), sb);
+        let mut inodes: Vec<(u64, InodeDisk)> = table.iter()?.collect();
+        let mut dirty = BTreeSet::new();
+        let mut next_free = sb.data_blocks_start + extra;
+        let mut kept_for_deleted = HashSet::new();
+
+        for k in 0..extra {
+            let target = sb.data_blocks_start + k;
+            let in_use = bitmap_test(&bitmap, target);
+            if !in_use && !deleted_inode_uses(&store, &inodes, target)? {
+                bitmap_set(&mut bitmap, target, true);
+                continue;
+            }
+
+            while next_free < new_total
+                && (bitmap_test(&bitmap, next_free) || kept_for_deleted.contains(&next_free))
+            {
+                next_free += 1;
+            }
+            if next_free >= new_total {
+                return Err(anyhow!(
+                    "No hay bloques libres para reubicar el bloque {} del área de datos",
+                    target
+                ));
+            }
+
+            let dest = next_free;
+            store.write_block(dest, &store.read_block(target)?)?;
+            if in_use {
+                bitmap_set(&mut bitmap, dest, true);
+            } else {
+                bitmap_set(&mut bitmap, target, true);
+                kept_for_deleted.insert(dest);
+            }
+
+            dirty.extend(relocate_pointer(&store, &mut inodes, target, dest)?);
+        }
+
+        for (ino, inode) in inodes.iter().filter(|(ino, _)| dirty.contains(ino)) {
+            table.put(*ino, inode)?;
+        }
+    }
+
+    // 5. Actualizar superblock
+    let added = new_total - old_total;
+    sb.total_blocks = new_total;
+    sb.free_bitmap_blocks += extra;
+    sb.data_blocks_start += extra;
+    sb.free_blocks = sb.free_blocks + added - extra;
+
+    // 6. Escribir bitmap y superblock
+    bitmap.resize(sb.free_bitmap_blocks as usize * block_size, 0);
+    store.write_blocks(sb.free_bitmap_start, &bitmap)?;
+
+    sb.seal();
+    store.write_block(0, &sb.to_block_bytes())?;
+
+    // 7. El manifiesto, si lo hay, ya lista los bloques nuevos: sólo cambian
+    //    los checksums de los que se escribieron
+    refresh_manifest(&qr_folder)?;
+
+    println!(
+        "grow.qrfs: {} -> {} bloques ({} bloques nuevos de bitmap, {} bloques libres).",
+        old_total, new_total, extra, sb.free_blocks
+    );
+
+    Ok(())
+}
+
+/// Inodos con punteros a bloques: los vivos y los borrados que todavía se
+/// pueden recuperar. Un inodo inline guarda datos, no punteros, en
+/// `direct_blocks`.
+fn has_pointers(inode: &InodeDisk) -> bool {
+    inode.id != 0 && (inode.nlink != 0 || inode.is_deleted()) && !inode.is_inline()
+}
+
+/// `true` si algún inodo borrado (recuperable) lista `block`, como dato o como
+/// bloque de punteros.
+fn deleted_inode_uses(store: &BlockStore, inodes: &[(u64, InodeDisk)], block: u32) -> Result<bool> {
+    for (_, inode) in inodes.iter().filter(|(_, i)| i.is_deleted() && has_pointers(i)) {
+        if inode.direct_blocks.contains(&block)
+            || inode.indirect_block == block
+            || inode.double_indirect_block == block
+        {
+            return Ok(true);
+        }
+        if inode.indirect_block != 0 && table_ptrs(store, inode.indirect_block)?.contains(&block) {
+            return Ok(true);
+        }
+        if inode.double_indirect_block != 0 {
+            for mid in table_ptrs(store, inode.double_indirect_block)? {
+                if mid == block || table_ptrs(store, mid)?.contains(&block) {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Punteros distintos de cero del bloque de punteros `table`.
+fn table_ptrs(store: &BlockStore, table: u32) -> Result<Vec<u32>> {
+    Ok(store
+        .read_block(table)
+        .with_context(|| format!("No se pudo leer el bloque de punteros {}", table))?
+        .chunks_exact(4)
+        .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+        .filter(|&b| b != 0)
+        .collect())
+}
+
+/// Cambia cualquier referencia a `from` por `to` dentro de los inodos (también
+/// los borrados, ver `has_pointers`) y de sus bloques indirectos (simple y
+/// doble). Devuelve los números de los inodos que se modificaron.
+fn relocate_pointer(
+    store: &BlockStore,
+    inodes: &mut [(u64, InodeDisk)],
+    from: u32,
+    to: u32,
+) -> Result<Vec<u64>> {
+    let mut dirty = Vec::new();
+
+    for (ino, inode) in inodes.iter_mut() {
+        if !has_pointers(inode) {
+            continue;
+        }
+
+        let mut changed = false;
+        for blk in inode.direct_blocks.iter_mut() {
+            if *blk == from {
+                *blk = to;
+                changed = true;
+            }
+        }
+        if inode.indirect_block == from {
+            inode.indirect_block = to;
+            changed = true;
+        }
+        if inode.double_indirect_block == from {
+            inode.double_indirect_block = to;
+            changed = true;
+        }
+        if changed {
+            dirty.push(*ino);
+        }
+
+        // Punteros guardados dentro del bloque indirecto simple
+        if inode.indirect_block != 0 {
+            relocate_in_table(store, inode.indirect_block, from, to)?;
+        }
+
+        // Y en el doble indirecto: sus punteros y los de cada bloque que lista
+        // (ya leídos de su lugar nuevo si eran ellos los reubicados)
+        if inode.double_indirect_block != 0 {
+            let outer = relocate_in_table(store, inode.double_indirect_block, from, to)?;
+            for chunk in outer.chunks_exact(4) {
+                let mid = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                if mid != 0 {
+                    relocate_in_table(store, mid, from, to)?;
+                }
+            }
+        }
+    }
+
+    Ok(dirty)
+}
+
+/// Cambia `from` por `to` en el bloque de punteros `table` y devuelve su
+/// contenido (ya corregido).
+fn relocate_in_table(store: &BlockStore, table: u32, from: u32, to: u32) -> Result<Vec<u8>> {
+    let mut data = store
+        .read_block(table)
+        .with_context(|| format!("No se pudo leer el bloque de punteros {}", table))?;
+    let mut changed = false;
+    for chunk in data.chunks_mut(4) {
+        let ptr = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        if ptr == from {
+            chunk.copy_from_slice(&to.to_le_bytes());
+            changed = true;
+        }
+    }
+    if changed {
+        store.write_block(table, &data)?;
+    }
+    Ok(data)
+}
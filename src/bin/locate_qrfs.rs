@@ -0,0 +1,28 @@
+use std::env;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use qrfs::files_backing_path;
+
+const USAGE: &str = "Uso: locate.qrfs qrfolder/ ruta/en/la/imagen";
+
+/// Lista los archivos de bloque que guardan un archivo de la imagen (ver
+/// `qrfs::files_backing_path`): los que hay que imprimir o escanear para
+/// tenerlo completo. La imagen no tiene que estar montada.
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let qr_folder = args.next().map(PathBuf::from).context(USAGE)?;
+    let path = args.next().map(PathBuf::from).context(USAGE)?;
+    if args.next().is_some() {
+        return Err(anyhow!(USAGE));
+    }
+
+    let files = files_backing_path(&qr_folder, &path)
+        .with_context(|| format!("No se pudo ubicar {:?} en {:?}", path, qr_folder))?;
+
+    for (block, file) in &files {
+        println!("{:>8}  {}", block, file.display());
+    }
+    println!("locate.qrfs: {:?} ocupa {} archivos de bloque.", path, files.len());
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use std::env;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use qrfs::{bad_blocks, mark_bad_block};
+
+const USAGE: &str = "Uso: mark_bad.qrfs qrfolder/ [BLOQUE...]";
+
+/// Marca bloques de la imagen como dañados (ver `qrfs::mark_bad_block`): no se
+/// vuelven a asignar y leerlos da EIO. Sin bloques, lista los que ya están
+/// marcados. La imagen no tiene que estar montada.
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let qr_folder = args.next().map(PathBuf::from).context(USAGE)?;
+    let blocks = args
+        .map(|a| a.parse::<u32>().map_err(|_| anyhow!("Bloque inválido: {:?}\n{}", a, USAGE)))
+        .collect::<Result<Vec<_>>>()?;
+
+    for &block in &blocks {
+        let mark = mark_bad_block(&qr_folder, block)
+            .with_context(|| format!("No se pudo marcar el bloque {} en {:?}", block, qr_folder))?;
+        if !mark.added {
+            println!("mark_bad: el bloque {} ya estaba marcado", block);
+        } else if mark.in_use {
+            eprintln!(
+                "mark_bad: el bloque {} estaba en uso: sin paridad, el archivo que lo tiene da EIO en esa parte",
+                block
+            );
+        }
+    }
+
+    let list = bad_blocks(&qr_folder)?;
+    if list.is_empty() {
+        println!("mark_bad.qrfs: {:?} no tiene bloques dañados.", qr_folder);
+    } else {
+        println!("mark_bad.qrfs: bloques dañados en {:?}: {:?}", qr_folder, list);
+    }
+    Ok(())
+}
@@ -1,48 +1,93 @@
 use std::env;
-use std::fs::{self, File};
-use std::io::Write;
-use std::mem;
 use std::path::PathBuf;
-use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::{anyhow, Context, Result};
 use qrfs::{
-    SuperblockDisk,
-    InodeDisk,
-    DirEntryDisk,
-    QRFS_BLOCK_SIZE,
-    QRFS_MAGIC,
-    QRFS_VERSION,
-    QRFS_NAME_LEN,
+    add_parity,
+    build_layout,
+    capacity_summary,
+    create_block_files,
+    init_fresh_fs,
+    list_block_files,
+    write_fresh_fs,
+    write_manifest,
+    FsLayout,
+    QRFS_BLOCK_PREFIX,
+    QRFS_DEFAULT_BLOCK_EXT,
 };
 
 
+const USAGE: &str = "Uso: mkfs.qrfs [--blocks N [--ext EXT] | --existing] [--parity K:M] [--summary] qrfolder/";
+
 fn main() -> Result<()> {
-    // 1. Leer qrfolder/ desde los argumentos
-    let mut args = env::args().skip(1);
-    let qr_folder = args
-        .next()
-        .map(PathBuf::from)
-        .context("Uso: mkfs.qrfs qrfolder/")?;
+    // 1. Leer opciones y qrfolder/ desde los argumentos
+    //    --blocks N   crea N archivos de bloque nuevos (block_XXXXXXXX.<ext>)
+    //    --ext EXT    extensión de los bloques nuevos (por defecto "qr")
+    //    --existing   formatea los archivos de bloque que ya tiene la carpeta
+    //    --parity K:M agrega M bloques de paridad por cada K bloques de la imagen
+    //    --summary    muestra la capacidad para tamaños de archivo típicos
+    let mut args = env::args().skip(1).peekable();
+    let mut new_blocks: Option<u32> = None;
+    let mut ext = QRFS_DEFAULT_BLOCK_EXT.to_string();
+    let mut existing = false;
+    let mut summary = false;
+    let mut parity: Option<(u16, u16)> = None;
+
+    while let Some(opt) = args.next_if(|a| a.starts_with("--")) {
+        match opt.as_str() {
+            "--blocks" => {
+                let n = args.next().context(USAGE)?;
+                new_blocks = Some(n.parse().context("--blocks debe ser un número entero positivo")?);
+            }
+            "--ext" => ext = args.next().context(USAGE)?,
+            "--existing" => existing = true,
+            "--parity" => parity = Some(parse_parity(&args.next().context(USAGE)?)?),
+            "--summary" => summary = true,
+            other => return Err(anyhow!("Opción desconocida {:?}\n{}", other, USAGE)),
+        }
+    }
+
+    let qr_folder = args.next().map(PathBuf::from).context(USAGE)?;
+
+    // Forma vieja: `mkfs.qrfs qrfolder/ total_bloques` equivale a --blocks
+    if let Some(n) = args.next() {
+        if new_blocks.is_some() {
+            return Err(anyhow!(USAGE));
+        }
+        new_blocks = Some(n.parse().context("total_bloques debe ser un número entero positivo")?);
+    }
 
     if args.next().is_some() {
-        return Err(anyhow!("Uso: mkfs.qrfs qrfolder/ (solo un argumento)"));
+        return Err(anyhow!(USAGE));
     }
 
-    // 2. Listar y ordenar los archivos QR -> total_blocks
-    let mut entries: Vec<PathBuf> = fs::read_dir(&qr_folder)
-        .with_context(|| format!("No se pudo leer el directorio {:?}", qr_folder))?
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().map(|t| t.is_file()).unwrap_or(false))
-        .map(|e| e.path())
-        .collect();
+    match (new_blocks, existing) {
+        (Some(_), true) => {
+            return Err(anyhow!("--blocks y --existing no se pueden combinar\n{}", USAGE));
+        }
+        (None, false) => {
+            return Err(anyhow!(
+                "Indique cuántos bloques crear (--blocks N) o --existing para usar los de la carpeta\n{}",
+                USAGE
+            ));
+        }
+        _ => {}
+    }
+
+    // 2. Crear los archivos de bloque pedidos, o listar los que ya existen
+    //    (en el orden de su manifest.json si tienen uno y si no, sólo cuentan
+    //    los que siguen el formato block_<N>[.ext])
+    if let Some(count) = new_blocks {
+        create_block_files(&qr_folder, count, &ext)?;
+    }
 
-    entries.sort();
+    let entries = list_block_files(&qr_folder)?;
 
     if entries.is_empty() {
         return Err(anyhow!(
-            "La carpeta {:?} no contiene archivos para usar como bloques QR",
-            qr_folder
+            "La carpeta {:?} no contiene archivos de bloque ({}<N>[.ext])",
+            qr_folder,
+            QRFS_BLOCK_PREFIX
         ));
     }
 
@@ -58,14 +103,12 @@ fn main() -> Result<()> {
     //    - superblock en el primer archivo (bloque 0)
     //    - tabla de inodos en los siguientes
     //    - bitmap en los que siguen
-    //    - rellenar bloques de datos con ceros
-    write_superblock(&entries, &superblock)?;
-    write_inode_table(&entries, &layout, &inodes)?;
-    write_bitmap(&entries, &layout, &bitmap)?;
-    // Primero cero todo el área de datos
-    zero_data_blocks(&entries, &layout)?;
-    // Luego escribo el contenido real del directorio raíz en su bloque
-    write_root_directory_block(&entries, &layout)?;
+    //    - rellenar bloques de datos con ceros y el bloque del directorio raíz
+    write_fresh_fs(&entries, &layout, &superblock, &inodes, &bitmap)?;
+
+    // 6. Escribir manifest.json con el orden de los bloques y sus checksums: de
+    //    acá en adelante el orden no depende de los nombres de los archivos
+    write_manifest(&qr_folder, &entries)?;
 
     println!(
         "mkfs.qrfs: sistema QRFS creado con {} bloques, {} inodos máximos, {} bloques de datos.",
@@ -74,375 +117,48 @@ fn main() -> Result<()> {
         superblock.total_blocks - superblock.data_blocks_start
     );
 
-    Ok(())
-}
-
-/// Estructura auxiliar para el layout calculado.
-struct FsLayout {
-    total_blocks: u32,
-    inode_table_start: u32,
-    inode_table_blocks: u32,
-    free_bitmap_start: u32,
-    free_bitmap_blocks: u32,
-    data_blocks_start: u32,
-    max_inodes: u32,
-}
-
-/// Cálculo del layout básico del filesystem dentro de los bloques QR.
-fn build_layout(total_blocks: u32) -> Result<FsLayout> {
-    if total_blocks < 3 {
-        return Err(anyhow!(
-            "Se requieren al menos 3 bloques para crear el filesystem (se tienen {}).",
-            total_blocks
-        ));
-    }
-
-    let block_size = QRFS_BLOCK_SIZE as usize;
-    let inode_size = mem::size_of::<InodeDisk>();
-
-    if inode_size == 0 || inode_size > block_size {
-        return Err(anyhow!(
-            "InodeDisk no cabe en un bloque: inode_size={}, block_size={}",
-            inode_size,
-            block_size
-        ));
-    }
-
-    let inodes_per_block = block_size / inode_size;
-
-    // Heurística simple:
-    // - Reservar ~10% de los bloques para la tabla de inodos (al menos 1).
-    // - El número de inodos es inodes_per_block * inode_table_blocks.
-    let mut inode_table_blocks = (total_blocks / 10).max(1);
-    if inode_table_blocks > total_blocks - 2 {
-        inode_table_blocks = 1;
-    }
-    let max_inodes = inodes_per_block as u32 * inode_table_blocks;
-
-    // Bitmap: 1 bit por bloque.
-    let bitmap_bits = total_blocks as usize;
-    let bitmap_bytes = (bitmap_bits + 7) / 8;
-    let free_bitmap_blocks =
-        ((bitmap_bytes as u32) + QRFS_BLOCK_SIZE - 1) / QRFS_BLOCK_SIZE;
-
-    let inode_table_start = 1;
-    let free_bitmap_start = inode_table_start + inode_table_blocks;
-    let data_blocks_start = free_bitmap_start + free_bitmap_blocks;
-
-    if data_blocks_start >= total_blocks {
-        return Err(anyhow!(
-            "No hay espacio para bloques de datos: total_blocks={}, data_blocks_start={}",
-            total_blocks,
-            data_blocks_start
-        ));
-    }
-
-    Ok(FsLayout {
-        total_blocks,
-        inode_table_start,
-        inode_table_blocks,
-        free_bitmap_start,
-        free_bitmap_blocks,
-        data_blocks_start,
-        max_inodes,
-    })
-}
-
-/// Inicializa un filesystem vacío: superblock, inodos (incluyendo root) y bitmap.
-fn init_fresh_fs(layout: &FsLayout) -> Result<(SuperblockDisk, Vec<InodeDisk>, Vec<u8>)> {
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs() as u64;
-
-    // Bloque de datos que vamos a usar para el directorio raíz
-    let root_data_block = layout.data_blocks_start;
-
-    // Total de bloques de datos, y dejamos 1 ocupado por el root
-    let total_data_blocks = layout.total_blocks - layout.data_blocks_start;
-    let data_blocks_after_root = total_data_blocks.saturating_sub(1);
-
-    let superblock = SuperblockDisk {
-        magic: QRFS_MAGIC,
-        version: QRFS_VERSION,
-        block_size: QRFS_BLOCK_SIZE,
-        total_blocks: layout.total_blocks,
-        inode_table_start: layout.inode_table_start,
-        inode_table_blocks: layout.inode_table_blocks,
-        free_bitmap_start: layout.free_bitmap_start,
-        free_bitmap_blocks: layout.free_bitmap_blocks,
-        data_blocks_start: layout.data_blocks_start,
-        max_inodes: layout.max_inodes,
-        root_inode: 1,
-        free_blocks: data_blocks_after_root, // << antes usabas todos como libres
-        free_inodes: layout.max_inodes.checked_sub(1).unwrap_or(0),
-        reserved: [0u8; 64],
-    };
-
-    // Crear vector de inodos vacíos.
-    let mut inodes = vec![
-        InodeDisk {
-            id: 0,
-            file_type: 0,
-            perm: 0,
-            uid: 0,
-            gid: 0,
-            size: 0,
-            atime: 0,
-            mtime: 0,
-            ctime: 0,
-            nlink: 0,
-            direct_blocks: [0u32; 12],
-            indirect_block: 0,
-            double_indirect_block: 0,
-            _padding: 0,
-        };
-        layout.max_inodes as usize
-    ];
-
-    // Inodo 1 = directorio raíz
-if !inodes.is_empty() {
-    // tamaño del directorio raíz con "." y ".."
-    let dir_entry_size = std::mem::size_of::<DirEntryDisk>();
-    let root_dir_size = (2 * dir_entry_size) as u64;
-
-    inodes[0] = InodeDisk {
-        id: 1,
-        file_type: 2, // 2 = directorio
-        perm: 0o755,
-        uid: 0,
-        gid: 0,
-        size: root_dir_size, // << antes 0
-        atime: now,
-        mtime: now,
-        ctime: now,
-        nlink: 2, // "." y ".."
-        direct_blocks: {
-            let mut blocks = [0u32; 12];
-            blocks[0] = root_data_block; // << bloque de datos usado por el root
-            blocks
-        },
-        indirect_block: 0,
-        double_indirect_block: 0,
-        _padding: 0,
-    };
-}
-
-    // Bitmap: 1 bit por bloque, 1 = usado, 0 = libre.
-    let bitmap_bits = layout.total_blocks as usize;
-    let bitmap_bytes = (bitmap_bits + 7) / 8;
-    let mut bitmap = vec![0u8; bitmap_bytes];
-
-    // Marcar como usados todos los bloques de metadata:
-    // [0 .. data_blocks_start)
-    for b in 0..layout.data_blocks_start {
-        let idx = b as usize;
-        let byte = idx / 8;
-        let bit = (idx % 8) as u8;
-        bitmap[byte] |= 1 << bit;
-    }
-    // Marcar como usado el bloque de datos del directorio raíz
-    {
-        let idx = root_data_block as usize;
-        let byte = idx / 8;
-        let bit = (idx % 8) as u8;
-        bitmap[byte] |= 1 << bit;
-    }
-
-    Ok((superblock, inodes, bitmap))
-}
-
-/// Escribe el superblock en el bloque 0.
-fn write_superblock(entries: &[PathBuf], sb: &SuperblockDisk) -> Result<()> {
-    let data = struct_to_bytes(sb);
-    write_block(&entries[0], &data)
-}
-
-/// Escribe la tabla de inodos a partir de inode_table_start.
-fn write_inode_table(
-    entries: &[PathBuf],
-    layout: &FsLayout,
-    inodes: &[InodeDisk],
-) -> Result<()> {
-    let block_size = QRFS_BLOCK_SIZE as usize;
-    let max_bytes = (layout.inode_table_blocks as usize) * block_size;
-
-    // Serializamos todos los inodos
-    let mut data = slice_of_structs_to_bytes(inodes);
-
-    if data.len() > max_bytes {
-        // Opción 1: truncar silenciosamente (no recomendado en prod)
-        // data.truncate(max_bytes);
-
-        // Opción 2: fallar explícitamente:
-        return Err(anyhow!(
-            "La tabla de inodos ({:?} bytes) no cabe en los bloques reservados ({:?} bytes)",
-            data.len(),
-            max_bytes
-        ));
-    }
-
-    // Rellenar con ceros hasta ocupar exactamente la región
-    if data.len() < max_bytes {
-        data.resize(max_bytes, 0);
-    }
-
-    write_blocks(entries, layout.inode_table_start, &data)
-}
-
-/// Escribe el bitmap de bloques libres.
-fn write_bitmap(
-    entries: &[PathBuf],
-    layout: &FsLayout,
-    bitmap: &[u8],
-) -> Result<()> {
-    let block_size = QRFS_BLOCK_SIZE as usize;
-    let max_bytes = (layout.free_bitmap_blocks as usize) * block_size;
-
-    if bitmap.len() > max_bytes {
-        return Err(anyhow!(
-            "El bitmap ({:?} bytes) no cabe en los bloques reservados ({:?} bytes)",
-            bitmap.len(),
-            max_bytes
-        ));
-    }
-
-    let mut data = bitmap.to_vec();
-    if data.len() < max_bytes {
-        data.resize(max_bytes, 0);
-    }
-
-    write_blocks(entries, layout.free_bitmap_start, &data)
-}
-
-
-/// Rellena los bloques de datos con ceros.
-fn zero_data_blocks(entries: &[PathBuf], layout: &FsLayout) -> Result<()> {
-    let block_size = QRFS_BLOCK_SIZE as usize;
-    let zero_block = vec![0u8; block_size];
-
-    let start = layout.data_blocks_start as usize;
-    let end = layout.total_blocks as usize;
-
-    for i in start..end {
-        write_block(&entries[i], &zero_block)?;
-    }
-
-    Ok(())
-}
-
-/// Serializa una estructura arbitraria (repr(C), Copy) a bytes.
-fn struct_to_bytes<T: Copy>(val: &T) -> Vec<u8> {
-    let size = mem::size_of::<T>();
-    unsafe {
-        let ptr = val as *const T as *const u8;
-        std::slice::from_raw_parts(ptr, size).to_vec()
+    // 7. Bloques de paridad después de los del FS (quedan en el manifiesto)
+    if let Some((group, per_group)) = parity {
+        let added = add_parity(&qr_folder, group, per_group)?;
+        println!(
+            "mkfs.qrfs: paridad {}:{}, {} bloques de paridad agregados.",
+            group, per_group, added
+        );
     }
-}
 
-/// Serializa un slice de estructuras (repr(C), Copy) a bytes contiguos.
-fn slice_of_structs_to_bytes<T: Copy>(slice: &[T]) -> Vec<u8> {
-    let size = mem::size_of::<T>() * slice.len();
-    unsafe {
-        let ptr = slice.as_ptr() as *const u8;
-        std::slice::from_raw_parts(ptr, size).to_vec()
+    if summary {
+        print_summary(&layout);
     }
-}
-
-/// Escribe un bloque lógico completo sobre el archivo correspondiente.
-fn write_block(path: &PathBuf, data: &[u8]) -> Result<()> {
-    let block_size = QRFS_BLOCK_SIZE as usize;
-    let mut buf = vec![0u8; block_size];
-
-    let to_copy = data.len().min(block_size);
-    buf[..to_copy].copy_from_slice(&data[..to_copy]);
 
-    let mut file = File::create(path)
-        .with_context(|| format!("No se pudo crear/escribir el archivo {:?}", path))?;
-    file.write_all(&buf)?;
     Ok(())
 }
 
-/// Escribe datos en varios bloques consecutivos, comenzando en `start_block`.
-fn write_blocks(
-    entries: &[PathBuf],
-    start_block: u32,
-    data: &[u8],
-) -> Result<()> {
-    let block_size = QRFS_BLOCK_SIZE as usize;
-    let mut offset = 0usize;
-    let mut block_index = start_block as usize;
-
-    while offset < data.len() {
-        if block_index >= entries.len() {
-            return Err(anyhow!(
-                "No hay suficientes bloques para escribir los metadatos (se quedó corto en el bloque {}).",
-                block_index
-            ));
-        }
-
-        let end = (offset + block_size).min(data.len());
-        write_block(&entries[block_index], &data[offset..end])?;
-        offset = end;
-        block_index += 1;
-    }
-
-    Ok(())
+/// `K:M` de `--parity`.
+fn parse_parity(spec: &str) -> Result<(u16, u16)> {
+    let invalid = || anyhow!("--parity espera K:M (por ejemplo 4:2), no {:?}", spec);
+    let (group, per_group) = spec.split_once(':').ok_or_else(invalid)?;
+    Ok((
+        group.parse().map_err(|_| invalid())?,
+        per_group.parse().map_err(|_| invalid())?,
+    ))
 }
 
-fn make_root_dir_block() -> Vec<u8> {
-    use std::mem;
-
-    let mut entries = Vec::new();
-
-    // "." → inode 1
-    let mut name_dot = [0u8; QRFS_NAME_LEN];
-    name_dot[0] = b'.';
+/// Cifras derivadas del layout para elegir la cantidad de bloques.
+fn print_summary(layout: &FsLayout) {
+    let summary = capacity_summary(layout);
 
-    let dot = DirEntryDisk {
-        inode: 1,
-        name: name_dot,
-    };
-    entries.push(dot);
-
-    // ".." → inode 1 (porque es raíz)
-    let mut name_dotdot = [0u8; QRFS_NAME_LEN];
-    name_dotdot[0] = b'.';
-    name_dotdot[1] = b'.';
-
-    let dotdot = DirEntryDisk {
-        inode: 1,
-        name: name_dotdot,
-    };
-    entries.push(dotdot);
-
-    let entry_size = mem::size_of::<DirEntryDisk>();
-    let mut buf = Vec::with_capacity(entries.len() * entry_size);
-
-    for e in &entries {
-        let ptr = e as *const DirEntryDisk as *const u8;
-        let slice = unsafe { std::slice::from_raw_parts(ptr, entry_size) };
-        buf.extend_from_slice(slice);
+    println!("Capacidad:");
+    println!("  datos utilizables: {} bytes", summary.data_bytes);
+    println!(
+        "  metadatos: {} de {} bloques ({:.1}%)",
+        summary.metadata_blocks, layout.total_blocks, summary.metadata_percent
+    );
+    for files in &summary.files {
+        println!(
+            "  archivos de {} KB: hasta {} (limitan los {})",
+            files.file_size / 1024,
+            files.max_files,
+            if files.limited_by_inodes { "inodos" } else { "bloques" }
+        );
     }
-
-    buf
 }
-
-fn write_root_directory_block(
-    entries: &[PathBuf],
-    layout: &FsLayout,
-) -> Result<()> {
-    let data = make_root_dir_block();
-
-    // El bloque de datos del root es layout.data_blocks_start
-    let root_block_index = layout.data_blocks_start as usize;
-
-    if root_block_index >= entries.len() {
-        return Err(anyhow!(
-            "Índice de bloque de datos raíz fuera de rango: {}",
-            root_block_index
-        ));
-    }
-
-    write_block(&entries[root_block_index], &data)
-}
\ No newline at end of file
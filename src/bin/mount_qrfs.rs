@@ -1,14 +1,110 @@
 // src/bin/mount_qrfs.rs
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use anyhow::{Context, Result};
-use qrfs::QrfsFilesystem; // struct que vive en la librería
+use anyhow::{anyhow, Context, Result};
+use qrfs::{is_zip_image, manifest_mismatches, BlockStore, MountConfig, QrfsFilesystem, QRFS_MANIFEST_NAME, QRFS_READ_AHEAD_DEFAULT}; // struct que vive en la librería
 
 fn main() -> Result<()> {
     // 1. Leer argumentos de la línea de comandos
-    //    Esperamos: mount_qrfs qrfolder/ mountpoint/
-    let mut args = env::args().skip(1); // saltamos el nombre del binario
+    //    Esperamos: mount_qrfs [opciones] qrfolder/ mountpoint/ [start_qr]
+    //    (qrfolder/ también puede ser un .zip con la imagen: se monta de sólo lectura)
+    //    --config archivo     opciones por defecto (si no, qrfolder/qrfs.toml si existe)
+    //    --lower base/        qrfolder/ es la capa superior de un overlay sobre base/
+    //    --case-insensitive   nombres sin distinguir mayúsculas (se conserva el original)
+    //    --read-only          montaje de sólo lectura
+    //    --allow-other        otros usuarios pueden acceder al montaje
+    //    --allow-nonempty     montar aunque el punto de montaje tenga contenido
+    //    --scan               leer todos los bloques antes de montar y reportar los ilegibles
+    //    --scan-strict        igual, pero no monta si hay alguno
+    //    --start-qr-strict    no montar si start_qr no está en la carpeta
+    //    --types-strict       no montar si el tipo de un inodo contradice el árbol
+    //    --flush-interval S   sincronizar la imagen cada S segundos (además de al desmontar)
+    //    --resident-limit B   archivos de más de B bytes se escriben directo a disco
+    //    --compress           los archivos nuevos guardan sus datos comprimidos
+    //    --no-page-cache-buffer ningún archivo queda en memoria: todo read/write va a disco
+    //    --meta-dir           directorio virtual .qrfs-meta con superblock, bitmap e inodos
+    //    --zero-on-free       llenar de ceros los bloques que se liberan
+    //    --read-ahead N       bloques que se cargan por adelantado al leer en orden (0 = no)
+    let mut args = env::args().skip(1).peekable(); // saltamos el nombre del binario
+
+    let mut config_path = None;
+    let mut lower = None;
+    let mut case_insensitive = false;
+    let mut read_only = false;
+    let mut allow_other = false;
+    let mut allow_nonempty = false;
+    let mut scan = false;
+    let mut scan_strict = false;
+    let mut start_qr_strict = false;
+    let mut types_strict = false;
+    let mut flush_interval = None;
+    let mut resident_limit = None;
+    let mut compress = false;
+    let mut no_page_cache_buffer = false;
+    let mut meta_dir = false;
+    let mut zero_on_free = false;
+    let mut read_ahead = None;
+    while let Some(opt) = args.next_if(|a| a.starts_with("--")) {
+        match opt.as_str() {
+            "--config" => {
+                config_path = Some(
+                    args.next()
+                        .map(PathBuf::from)
+                        .context("Uso: mount_qrfs --config qrfs.toml qrfolder/ mountpoint/")?,
+                );
+            }
+            "--lower" => {
+                lower = Some(
+                    args.next()
+                        .map(PathBuf::from)
+                        .context("Uso: mount_qrfs --lower base/ qrfolder/ mountpoint/")?,
+                );
+            }
+            "--case-insensitive" => case_insensitive = true,
+            "--read-only" => read_only = true,
+            "--allow-other" => allow_other = true,
+            "--allow-nonempty" => allow_nonempty = true,
+            "--scan" => scan = true,
+            "--scan-strict" => scan_strict = true,
+            "--start-qr-strict" => start_qr_strict = true,
+            "--types-strict" => types_strict = true,
+            "--compress" => compress = true,
+            "--no-page-cache-buffer" => no_page_cache_buffer = true,
+            "--meta-dir" => meta_dir = true,
+            "--zero-on-free" => zero_on_free = true,
+            "--flush-interval" => {
+                let secs = args
+                    .next()
+                    .context("Uso: mount_qrfs --flush-interval SEGUNDOS qrfolder/ mountpoint/")?;
+                let secs: u64 = secs
+                    .parse()
+                    .ok()
+                    .filter(|&s| s > 0)
+                    .with_context(|| format!("--flush-interval inválido: {:?}", secs))?;
+                flush_interval = Some(secs);
+            }
+            "--resident-limit" => {
+                let bytes = args
+                    .next()
+                    .context("Uso: mount_qrfs --resident-limit BYTES qrfolder/ mountpoint/")?;
+                let bytes: u64 = bytes
+                    .parse()
+                    .with_context(|| format!("--resident-limit inválido: {:?}", bytes))?;
+                resident_limit = Some(bytes);
+            }
+            "--read-ahead" => {
+                let blocks = args
+                    .next()
+                    .context("Uso: mount_qrfs --read-ahead BLOQUES qrfolder/ mountpoint/")?;
+                let blocks: usize = blocks
+                    .parse()
+                    .with_context(|| format!("--read-ahead inválido: {:?}", blocks))?;
+                read_ahead = Some(blocks);
+            }
+            _ => return Err(anyhow!("Opción desconocida: {}", opt)),
+        }
+    }
 
     let qr_folder = args
         .next()
@@ -23,14 +119,114 @@ fn main() -> Result<()> {
     // (Opcional) 3er argumento: archivo de inicio específico del FS
     let start_qr = args.next().map(PathBuf::from);
 
-    // 2. Passphrase (opcional). Por ahora la dejamos en None.
+    // 2. Opciones: el archivo de configuración da los valores por defecto y la
+    //    línea de comandos los pisa
+    let mut config = MountConfig::find(&qr_folder, config_path.as_deref())?;
+    if lower.is_some() {
+        config.lower = lower;
+    }
+    if start_qr.is_some() {
+        config.start_qr = start_qr;
+    }
+    config.case_insensitive |= case_insensitive;
+    config.read_only |= read_only;
+    if is_zip_image(&qr_folder) && !config.read_only {
+        println!("{:?} es un zip: se monta de sólo lectura (para escribir hay que extraerlo)", qr_folder);
+        config.read_only = true;
+    }
+    config.allow_other |= allow_other;
+    config.allow_nonempty |= allow_nonempty;
+    config.scan |= scan;
+    config.scan_strict |= scan_strict;
+    config.start_qr_strict |= start_qr_strict;
+    config.types_strict |= types_strict;
+    config.compress |= compress;
+    config.no_page_cache_buffer |= no_page_cache_buffer;
+    config.meta_dir |= meta_dir;
+    config.zero_on_free |= zero_on_free;
+    if read_ahead.is_some() {
+        config.read_ahead = read_ahead;
+    }
+    if flush_interval.is_some() {
+        config.flush_interval = flush_interval;
+    }
+    if resident_limit.is_some() {
+        config.resident_limit = resident_limit;
+    }
+
+    // 3. Passphrase (opcional). Por ahora la dejamos en None.
     let passphrase = None::<String>;
 
-    // 3. Construir la estructura del FS desde la carpeta de QRs.
+    // 4. Construir la estructura del FS desde la carpeta de QRs.
     //    Este método está implementado en la librería (fs.rs)
-    let fs = QrfsFilesystem::mount_from_folder(&qr_folder, passphrase, start_qr)
-        .context("Error al inicializar QRFS")?;
+    let fs = match &config.lower {
+        Some(lower) => QrfsFilesystem::overlay(lower, &qr_folder),
+        None if config.start_qr_strict => {
+            QrfsFilesystem::mount_from_folder_strict(&qr_folder, passphrase, config.start_qr.clone())
+        }
+        None => QrfsFilesystem::mount_from_folder(&qr_folder, passphrase, config.start_qr.clone()),
+    }
+    .context("Error al inicializar QRFS")?
+    .case_insensitive(config.case_insensitive)
+    .resident_limit(config.resident_limit)
+    .compress(config.compress)
+    .no_page_cache_buffer(config.no_page_cache_buffer)
+    .meta_dir(config.meta_dir)
+    .zero_on_free(config.zero_on_free)
+    .read_ahead(config.read_ahead.unwrap_or(QRFS_READ_AHEAD_DEFAULT));
+
+    // 4-bis. Validar todos los bloques (ya con el overlay armado, si lo hay)
+    //        antes de que el FS quede visible
+    if config.scan || config.scan_strict {
+        scan_blocks(&qr_folder, config.scan_strict)?;
+    }
+
+    // 4-ter. El montaje ya avisó de los inodos con un tipo que contradice el
+    //        árbol; en modo estricto no se sigue
+    if config.types_strict {
+        let conflicts = fs.type_conflicts()?;
+        if !conflicts.is_empty() {
+            return Err(anyhow!(
+                "Hay {} inodos con un tipo que contradice el árbol: no se monta (--types-strict)",
+                conflicts.len()
+            ));
+        }
+    }
+
+    // 5. Montar el filesystem con FUSE en mountpoint
+    fs.run_with_options(mountpoint, &config)
+}
+
+/// Lee cada bloque una vez e imprime un resumen de los que fallan. En modo
+/// estricto un bloque malo es un error y el montaje no sigue.
+fn scan_blocks(qr_folder: &Path, strict: bool) -> Result<()> {
+    let store = BlockStore::open(qr_folder)?;
+    let bad = store.scan();
+
+    // Un checksum distinto no impide montar: puede ser sólo que el manifiesto
+    // quedó desactualizado (un montaje que no llegó a sincronizar)
+    let stale = manifest_mismatches(qr_folder)?;
+    if !stale.is_empty() {
+        eprintln!(
+            "scan: {} bloques no coinciden con el checksum de {}: {:?}",
+            stale.len(),
+            QRFS_MANIFEST_NAME,
+            stale
+        );
+    }
+
+    if bad.is_empty() {
+        println!("scan: {} bloques leídos sin errores", store.len());
+        return Ok(());
+    }
+
+    eprintln!("scan: {} de {} bloques no se pudieron leer:", bad.len(), store.len());
+    for (block, err) in &bad {
+        eprintln!("  bloque {}: {:#}", block, err);
+    }
 
-    // 4. Montar el filesystem con FUSE en mountpoint
-    fs.run(mountpoint)
+    if strict {
+        return Err(anyhow!("Hay bloques ilegibles: no se monta (--scan-strict)"));
+    }
+    Ok(())
 }
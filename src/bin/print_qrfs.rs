@@ -0,0 +1,43 @@
+use std::env;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use qrfs::{print_pdf, QRFS_PRINT_PER_PAGE_DEFAULT};
+
+const USAGE: &str = "Uso: print.qrfs [--per-page N] qrfolder/ salida.pdf";
+
+/// Genera un PDF para imprimir la imagen en papel: un QR por bloque, `N` por
+/// hoja (6 por defecto) y en orden, cada uno con su número de bloque debajo
+/// (ver `qrfs::print_pdf`).
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1).peekable();
+
+    let mut per_page = QRFS_PRINT_PER_PAGE_DEFAULT;
+    while let Some(opt) = args.next_if(|a| a.starts_with("--")) {
+        match opt.as_str() {
+            "--per-page" => {
+                per_page = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .filter(|&n| n > 0)
+                    .context(USAGE)?;
+            }
+            other => return Err(anyhow!("Opción desconocida {:?}\n{}", other, USAGE)),
+        }
+    }
+
+    let qr_folder = args.next().map(PathBuf::from).context(USAGE)?;
+    let out = args.next().map(PathBuf::from).context(USAGE)?;
+    if args.next().is_some() {
+        return Err(anyhow!(USAGE));
+    }
+
+    let report = print_pdf(&qr_folder, &out, per_page)
+        .with_context(|| format!("No se pudo generar el PDF de {:?}", qr_folder))?;
+
+    println!(
+        "print.qrfs: {} bloques en {} hojas ({} por hoja) en {:?}.",
+        report.blocks, report.pages, per_page, out
+    );
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use std::env;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use qrfs::import_scans;
+
+const USAGE: &str = "Uso: scan.qrfs escaneos/ destino/";
+
+/// Reconstruye una carpeta de bloques a partir de los QRs escaneados de las
+/// hojas de `print.qrfs` (ver `qrfs::import_scans`). Cada archivo de
+/// `escaneos/` es el contenido de un QR ya decodificado; el orden y los nombres
+/// no importan.
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let scans = args.next().map(PathBuf::from).context(USAGE)?;
+    let dest = args.next().map(PathBuf::from).context(USAGE)?;
+    if args.next().is_some() {
+        return Err(anyhow!(USAGE));
+    }
+
+    let report = import_scans(&scans, &dest)
+        .with_context(|| format!("No se pudo reconstruir la imagen desde {:?}", scans))?;
+
+    for file in &report.rejected {
+        eprintln!("scan: {} no es un bloque de la imagen, se ignora", file.display());
+    }
+
+    if report.total_blocks == 0 {
+        return Err(anyhow!("No hay ningún bloque QRFS en {:?}", scans));
+    }
+    if !report.missing.is_empty() {
+        return Err(anyhow!(
+            "Faltan {} de {} bloques: {:?}. No se escribió {:?}",
+            report.missing.len(),
+            report.total_blocks,
+            report.missing,
+            dest
+        ));
+    }
+
+    println!("scan.qrfs: {} bloques reconstruidos en {:?}.", report.total_blocks, dest);
+    Ok(())
+}
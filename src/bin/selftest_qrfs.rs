@@ -0,0 +1,162 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
+
+use anyhow::{anyhow, Context, Result};
+use colored::*;
+use qrfs::{
+    build_layout, check, create_block_files, init_fresh_fs, list_block_files, write_fresh_fs, write_manifest,
+    QrfsImage, QRFS_BLOCK_SIZE, QRFS_DEFAULT_BLOCK_EXT,
+};
+
+const USAGE: &str = "Uso: selftest.qrfs";
+
+/// Bloques de la imagen de prueba.
+const SELFTEST_BLOCKS: u32 = 64;
+
+/// Bloques del archivo que se escribe y se vuelve a leer.
+const SELFTEST_FILE_BLOCKS: usize = 5;
+
+/// Verifica una instalación de QRFS sin FUSE ni root: formatea una imagen en
+/// una carpeta temporal (con la misma librería que `mkfs.qrfs`) y recorre con
+/// `QrfsImage` el ciclo completo de un archivo y un directorio, informando cada
+/// paso. Al terminar la carpeta se borra; el código de salida es 1 si algún
+/// paso falló.
+///
+/// La lista de pasos es también la de las operaciones soportadas.
+fn main() -> Result<ExitCode> {
+    if env::args().nth(1).is_some() {
+        return Err(anyhow!(USAGE));
+    }
+
+    let work_dir = env::temp_dir().join(format!("qrfs-selftest-{}", std::process::id()));
+    let result = run_selftest(&work_dir);
+    if let Err(e) = fs::remove_dir_all(&work_dir) {
+        eprintln!("Advertencia: no se pudo borrar {:?}: {e}", work_dir);
+    }
+
+    let failed = result?;
+    if failed == 0 {
+        println!("\n{} selftest.qrfs: todos los pasos pasaron.", "✓".green().bold());
+        Ok(ExitCode::SUCCESS)
+    } else {
+        println!("\n{} selftest.qrfs: {} pasos fallaron.", "✗".red().bold(), failed);
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+/// Corre los pasos en orden y devuelve cuántos fallaron. Después del primer
+/// fallo el resto se omite: cada paso parte de lo que dejó el anterior.
+fn run_selftest(work_dir: &Path) -> Result<usize> {
+    let data: Vec<u8> = (0..SELFTEST_FILE_BLOCKS * QRFS_BLOCK_SIZE as usize + 100)
+        .map(|i| (i % 251) as u8)
+        .collect();
+    let dir = Path::new("/selftest");
+    let file = Path::new("/selftest/datos.bin");
+    let moved = Path::new("/selftest/movido.bin");
+
+    let mut steps = Steps::default();
+    steps.run("mkfs", || format_image(work_dir));
+
+    let mut opened = None;
+    steps.run("abrir", || {
+        opened = Some(QrfsImage::open(work_dir)?);
+        Ok(())
+    });
+
+    if let Some(image) = &opened {
+        steps.run("mkdir", || image.mkdir(dir).map(|_| ()));
+        steps.run("create", || image.create_file(file).map(|_| ()));
+        steps.run("write de varios bloques", || image.write_file(file, &data));
+        steps.run("read y comparar", || expect_content(image, file, &data));
+        steps.run("rename", || {
+            image.rename(file, moved)?;
+            expect_missing(image, file)?;
+            expect_content(image, moved, &data)
+        });
+        steps.run("truncate", || {
+            let size = QRFS_BLOCK_SIZE as usize + 10;
+            image.truncate(moved, size as u64)?;
+            expect_content(image, moved, &data[..size])
+        });
+        steps.run("unlink", || {
+            image.remove(moved)?;
+            expect_missing(image, moved)
+        });
+        steps.run("rmdir", || {
+            image.rmdir(dir)?;
+            expect_missing(image, dir)
+        });
+    }
+
+    // La imagen se cierra antes de revisarla
+    drop(opened);
+    steps.run("fsck", || {
+        let report = check(work_dir);
+        if report.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("{}", report.errors.join("; ")))
+        }
+    });
+
+    Ok(steps.failed)
+}
+
+/// Pasos ya corridos: cuántos fallaron.
+#[derive(Default)]
+struct Steps {
+    failed: usize,
+}
+
+impl Steps {
+    /// Corre un paso e informa el resultado, o lo omite si ya falló uno.
+    fn run(&mut self, name: &str, step: impl FnOnce() -> Result<()>) {
+        if self.failed > 0 {
+            println!("  - {} (omitido)", name);
+            return;
+        }
+        match step() {
+            Ok(()) => println!("{} {}", "✓".green().bold(), name),
+            Err(e) => {
+                println!("{} {}: {e:#}", "✗".red().bold(), name);
+                self.failed += 1;
+            }
+        }
+    }
+}
+
+/// Formatea `work_dir` como `mkfs.qrfs --blocks SELFTEST_BLOCKS`.
+fn format_image(work_dir: &Path) -> Result<()> {
+    fs::create_dir_all(work_dir).with_context(|| format!("No se pudo crear {:?}", work_dir))?;
+    create_block_files(work_dir, SELFTEST_BLOCKS, QRFS_DEFAULT_BLOCK_EXT)?;
+
+    let entries = list_block_files(work_dir)?;
+    let layout = build_layout(entries.len() as u32)?;
+    let (superblock, inodes, bitmap) = init_fresh_fs(&layout)?;
+    write_fresh_fs(&entries, &layout, &superblock, &inodes, &bitmap)?;
+    write_manifest(work_dir, &entries)
+}
+
+/// Falla si `path` todavía existe.
+fn expect_missing(image: &QrfsImage, path: &Path) -> Result<()> {
+    match image.stat(path) {
+        Ok(_) => Err(anyhow!("{:?} sigue existiendo", path)),
+        Err(_) => Ok(()),
+    }
+}
+
+/// Lee `path` entero y lo compara con `expected`.
+fn expect_content(image: &QrfsImage, path: &Path, expected: &[u8]) -> Result<()> {
+    let read = image.read_file(path)?;
+    if read != expected {
+        return Err(anyhow!(
+            "{:?}: se leyeron {} bytes distintos de los {} esperados",
+            path,
+            read.len(),
+            expected.len()
+        ));
+    }
+    Ok(())
+}
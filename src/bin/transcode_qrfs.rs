@@ -0,0 +1,77 @@
+use std::env;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use qrfs::{create_block_files, list_block_files, write_manifest, BlockStore, SuperblockDisk, QRFS_DEFAULT_BLOCK_EXT};
+
+const USAGE: &str = "Uso: transcode.qrfs [--ext EXT] origen/ destino/";
+
+/// Copia una imagen QRFS a una carpeta nueva bloque por bloque, con los
+/// archivos de bloque en otra extensión (`--ext png`, por defecto "qr"). El
+/// orden de los bloques y el superblock se conservan tal cual; si el origen es
+/// la capa superior de un overlay se copia la vista combinada.
+///
+/// Hoy todos los formatos guardan los bytes del bloque sin codificar: la
+/// conversión es sólo de nombres. Cuando exista la codificación QR, este es el
+/// lugar donde cada bloque se decodifica del formato de origen y se codifica en
+/// el de destino.
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1).peekable();
+
+    let mut ext = QRFS_DEFAULT_BLOCK_EXT.to_string();
+    while let Some(opt) = args.next_if(|a| a.starts_with("--")) {
+        match opt.as_str() {
+            "--ext" => ext = args.next().context(USAGE)?,
+            other => return Err(anyhow!("Opción desconocida {:?}\n{}", other, USAGE)),
+        }
+    }
+
+    let source = args.next().map(PathBuf::from).context(USAGE)?;
+    let dest = args.next().map(PathBuf::from).context(USAGE)?;
+    if args.next().is_some() {
+        return Err(anyhow!(USAGE));
+    }
+
+    // 1. El origen tiene que ser una imagen QRFS válida
+    let src = BlockStore::open(&source)?;
+    if src.is_empty() {
+        return Err(anyhow!("La carpeta {:?} no contiene bloques QRFS", source));
+    }
+    let superblock = src.read_block(0)?;
+    let sb = SuperblockDisk::from_block_bytes(&superblock)?;
+    sb.check_magic()?;
+    if !sb.verify_checksum() {
+        return Err(anyhow!("El checksum del superblock de {:?} no coincide", source));
+    }
+
+    // 2. Crear los bloques del destino (falla si la carpeta ya tiene bloques)
+    let count = src.len() as u32;
+    create_block_files(&dest, count, &ext)?;
+    let dst = BlockStore::open(&dest)?;
+
+    for b in 0..count {
+        let data = src
+            .read_block(b)
+            .with_context(|| format!("No se pudo leer el bloque {} de {:?}", b, source))?;
+        dst.write_block(b, &data)
+            .with_context(|| format!("No se pudo escribir el bloque {} en {:?}", b, dest))?;
+    }
+
+    // 3. Validación: el superblock vuelve igual y se sigue reconociendo
+    let copied = dst.read_block(0)?;
+    if copied != superblock {
+        return Err(anyhow!("El superblock copiado en {:?} no coincide con el original", dest));
+    }
+    SuperblockDisk::from_block_bytes(&copied)
+        .and_then(|sb| sb.check_magic())
+        .with_context(|| format!("El superblock copiado en {:?} no es válido", dest))?;
+
+    // 4. Manifiesto del destino con sus propios nombres de archivo
+    write_manifest(&dest, &list_block_files(&dest)?)?;
+
+    println!(
+        "transcode.qrfs: {} bloques copiados de {:?} a {:?} (extensión .{}).",
+        count, source, dest, ext
+    );
+    Ok(())
+}
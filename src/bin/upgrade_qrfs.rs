@@ -0,0 +1,42 @@
+use std::env;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use qrfs::{upgrade_image, QRFS_SUPERBLOCK_BACKUP};
+
+const USAGE: &str = "Uso: upgrade.qrfs qrfolder/";
+
+/// Actualiza una imagen QRFS al formato actual (ver `qrfs::upgrade_image`).
+/// La imagen no tiene que estar montada. Si hay algo que cambiar, el bloque 0
+/// original queda en `qrfolder/superblock.bak`.
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let qr_folder = args.next().map(PathBuf::from).context(USAGE)?;
+    if args.next().is_some() {
+        return Err(anyhow!(USAGE));
+    }
+
+    let report = upgrade_image(&qr_folder)
+        .with_context(|| format!("No se pudo actualizar la imagen {:?}", qr_folder))?;
+
+    if report.changes.is_empty() {
+        println!("upgrade.qrfs: {:?} ya está en la versión {}.", qr_folder, report.version);
+        return Ok(());
+    }
+
+    for change in &report.changes {
+        println!("  {}", change);
+    }
+    let versions = if report.old_version == report.version {
+        format!("versión {}", report.version)
+    } else {
+        format!("versión {} -> {}", report.old_version, report.version)
+    };
+    println!(
+        "upgrade.qrfs: {:?} actualizada ({}); copia del superblock en {:?}.",
+        qr_folder,
+        versions,
+        qr_folder.join(QRFS_SUPERBLOCK_BACKUP)
+    );
+    Ok(())
+}
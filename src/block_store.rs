@@ -0,0 +1,767 @@
+// Resolución de bloques lógicos a archivos físicos.
+//
+// Todo acceso a bloques de fs.rs pasa por aquí: el bloque N de una imagen es el
+// N-ésimo archivo de la carpeta en orden. El orden lo da el manifiesto
+// (`manifest.json`) si la carpeta tiene uno y, si no, el número en el nombre de
+// cada archivo. Una carpeta también puede ser la capa superior de un overlay
+// (ver `QrfsFilesystem::overlay`): en ese caso sólo contiene los bloques
+// modificados y un marcador con la ruta de la capa base. En vez de una carpeta
+// se puede abrir un `.zip` con la imagen, de sólo lectura (ver `zip_store`).
+// Durante un lote (`QrfsImage::batch`) las escrituras de una imagen quedan en
+// memoria y se guardan todas juntas al final.
+
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::QRFS_CONFIG_NAME;
+use crate::fs::{crc32, SuperblockDisk, QRFS_BLOCK_SIZE, QRFS_FEATURE_PARITY};
+use crate::parity;
+use crate::zip_store::{self, is_zip_image, ZipImage};
+
+/// Archivo que convierte una carpeta en capa superior de un overlay; contiene
+/// la ruta de la carpeta base (capa inferior, nunca se escribe).
+pub const QRFS_OVERLAY_MARKER: &str = ".qrfs-lower";
+
+/// Manifiesto con el orden de los archivos de bloque. Con él los archivos pueden
+/// llamarse de cualquier forma: el bloque N es el que el manifiesto dice.
+pub const QRFS_MANIFEST_NAME: &str = "manifest.json";
+
+/// Versión del formato del manifiesto.
+const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Manifest {
+    version: u32,
+    blocks: Vec<ManifestEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ManifestEntry {
+    index: u32,
+    /// Nombre del archivo dentro de la carpeta (sin directorios).
+    file: String,
+    /// CRC32 del contenido al escribir el manifiesto.
+    crc32: u32,
+}
+
+/// Prefijo de los archivos de bloque. El nombre completo es `block_<N>[.ext]`:
+/// `<N>` es el número de bloque en decimal (con o sin ceros a la izquierda) y
+/// `.ext` una única extensión opcional (`.png`, `.qr`). Cualquier otro archivo de
+/// la carpeta (`.DS_Store`, `.swp` de un editor, un `fsck.log`, ...) se ignora.
+/// Una carpeta sin ningún `block_<N>` pero con un superblock (imágenes
+/// anteriores a este formato) se lee como antes: sus archivos en orden alfabético.
+pub const QRFS_BLOCK_PREFIX: &str = "block_";
+
+/// Escrituras de bloques hechas por este proceso (en cualquier imagen).
+static BLOCK_WRITES: AtomicU64 = AtomicU64::new(0);
+
+/// Contador que avanza con cada escritura de un bloque. Un caché que guarda el
+/// valor de antes de leer un bloque sabe que su copia sigue valiendo mientras
+/// el contador no cambie.
+pub(crate) fn write_generation() -> u64 {
+    BLOCK_WRITES.load(Ordering::Acquire)
+}
+
+/// Archivos de bloque escritos por este proceso, por carpeta.
+static FILE_WRITES: OnceLock<Mutex<HashMap<PathBuf, u64>>> = OnceLock::new();
+
+/// Cuántas veces escribió este proceso un archivo de bloque de `qr_folder` (la
+/// carpeta tal como se abrió). A diferencia de `write_generation`, sólo cuenta
+/// lo que llegó a los archivos: los bloques de un lote cuentan una vez, al
+/// guardarse. Sirve para medir cuánto escribe una operación.
+pub fn block_file_writes(qr_folder: &Path) -> u64 {
+    FILE_WRITES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap()
+        .get(qr_folder)
+        .copied()
+        .unwrap_or(0)
+}
+
+/// Bloques escritos y todavía no guardados, por imagen.
+type Deferred = Arc<Mutex<BTreeMap<u32, Vec<u8>>>>;
+
+/// Imágenes con las escrituras diferidas (ver `QrfsImage::batch`), por carpeta.
+/// Mientras dura el lote la carpeta no se vuelve a listar en cada acceso: se
+/// reusa el `BlockStore` abierto al empezar.
+static DEFERRED: OnceLock<Mutex<HashMap<PathBuf, BlockStore>>> = OnceLock::new();
+
+fn deferred_images() -> MutexGuard<'static, HashMap<PathBuf, BlockStore>> {
+    DEFERRED.get_or_init(Default::default).lock().unwrap()
+}
+
+/// Desde ahora, hasta `commit_deferred` o `discard_deferred`, los bloques que
+/// se escriben en `qr_folder` quedan en memoria (y las lecturas los ven).
+pub(crate) fn begin_deferred(qr_folder: &Path) -> Result<()> {
+    // `open` también toma el registro: se abre antes de tomarlo
+    let mut store = BlockStore::open(qr_folder)?;
+    if store.deferred.is_some() {
+        return Err(anyhow::anyhow!(
+            "{:?} ya tiene un lote de escrituras en curso",
+            qr_folder
+        ));
+    }
+
+    store.deferred = Some(Default::default());
+    deferred_images().insert(qr_folder.to_path_buf(), store);
+    Ok(())
+}
+
+/// Escribe los bloques diferidos de `qr_folder`, cada uno una vez y en orden, y
+/// vuelve a las escrituras directas. Devuelve cuántos bloques escribió.
+pub(crate) fn commit_deferred(qr_folder: &Path) -> Result<usize> {
+    let Some(mut store) = deferred_images().remove(qr_folder) else {
+        return Ok(0);
+    };
+
+    let blocks = store
+        .deferred
+        .take()
+        .map(|d| std::mem::take(&mut *d.lock().unwrap()))
+        .unwrap_or_default();
+    let count = blocks.len();
+    store.store_blocks(blocks)?;
+    Ok(count)
+}
+
+/// Olvida los bloques diferidos de `qr_folder`: el disco queda como estaba.
+/// Devuelve los bloques que no se escribieron.
+pub(crate) fn discard_deferred(qr_folder: &Path) -> BTreeMap<u32, Vec<u8>> {
+    let Some(mut store) = deferred_images().remove(qr_folder) else {
+        return BTreeMap::new();
+    };
+
+    // Lo que se leyó de los bloques diferidos ya no vale
+    BLOCK_WRITES.fetch_add(1, Ordering::Release);
+    store
+        .deferred
+        .take()
+        .map(|d| std::mem::take(&mut *d.lock().unwrap()))
+        .unwrap_or_default()
+}
+
+/// Extensiones de archivos temporales que nunca se toman como bloque.
+const TEMP_EXTENSIONS: [&str; 4] = ["swp", "tmp", "bak", "log"];
+
+#[derive(Clone)]
+pub struct BlockStore {
+    /// Archivos de bloque de la imagen base, en orden lógico (en un zip, los
+    /// nombres de sus entradas). Compartidos entre copias: clonar un store no
+    /// copia el listado.
+    entries: Arc<Vec<PathBuf>>,
+    /// Carpeta superior de un overlay: guarda `NNNNNNNN.blk` por cada bloque copiado.
+    upper: Option<PathBuf>,
+    /// Zip del que salen los bloques si la imagen está empaquetada.
+    zip: Option<Arc<ZipImage>>,
+    /// Escrituras diferidas de la imagen, si hay un lote en curso.
+    deferred: Option<Deferred>,
+}
+
+impl BlockStore {
+    pub fn open(qr_folder: &Path) -> Result<Self> {
+        if let Some(store) = deferred_images().get(qr_folder) {
+            return Ok(store.clone());
+        }
+
+        if is_zip_image(qr_folder) {
+            let zip = zip_store::open(qr_folder)?;
+            return Ok(Self {
+                entries: Arc::new(zip.entries().iter().map(PathBuf::from).collect()),
+                upper: None,
+                zip: Some(zip),
+                deferred: None,
+            });
+        }
+
+        let marker = qr_folder.join(QRFS_OVERLAY_MARKER);
+        if marker.is_file() {
+            let lower = fs::read_to_string(&marker)
+                .with_context(|| format!("No se pudo leer el marcador de overlay {:?}", marker))?;
+            let lower = PathBuf::from(lower.trim());
+
+            return Ok(Self {
+                entries: Arc::new(list_block_files(&lower)?),
+                upper: Some(qr_folder.to_path_buf()),
+                zip: None,
+                deferred: None,
+            });
+        }
+
+        Ok(Self {
+            entries: Arc::new(list_block_files(qr_folder)?),
+            upper: None,
+            zip: None,
+            deferred: None,
+        })
+    }
+
+    /// Este store sin volver a listar `qr_folder`, o el del lote en curso si la
+    /// carpeta tiene uno (sus escrituras tienen que quedar diferidas).
+    pub(crate) fn with_deferred(&self, qr_folder: &Path) -> BlockStore {
+        deferred_images().get(qr_folder).unwrap_or(self).clone()
+    }
+
+    /// Cantidad de bloques de la imagen.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Archivo del que se lee el bloque (la copia de la capa superior si existe).
+    pub fn block_path(&self, block_index: u32) -> Result<PathBuf> {
+        let idx = block_index as usize;
+        if idx >= self.entries.len() {
+            return Err(anyhow::anyhow!(
+                "Índice de bloque fuera de rango: {} (hay {} archivos QR)",
+                idx,
+                self.entries.len()
+            ));
+        }
+
+        if let Some(upper) = self.upper_path(block_index) {
+            if upper.is_file() {
+                return Ok(upper);
+            }
+        }
+
+        Ok(self.entries[idx].clone())
+    }
+
+    /// Archivo en el que se escribe el bloque: en un overlay siempre la capa superior.
+    fn writable_path(&self, block_index: u32) -> Result<PathBuf> {
+        let path = self.block_path(block_index)?;
+        Ok(self.upper_path(block_index).unwrap_or(path))
+    }
+
+    fn upper_path(&self, block_index: u32) -> Option<PathBuf> {
+        self.upper
+            .as_ref()
+            .map(|u| u.join(format!("{:08}.blk", block_index)))
+    }
+
+    /// Lee un bloque. Si su archivo falta o no se puede leer y la imagen tiene
+    /// paridad, lo reconstruye con el resto de su grupo.
+    pub fn read_block(&self, block_index: u32) -> Result<Vec<u8>> {
+        let err = match self.read_block_file(block_index) {
+            Ok(data) => return Ok(data),
+            Err(e) => e,
+        };
+
+        match parity::reconstruct(self, block_index) {
+            Ok(Some(data)) => {
+                eprintln!(
+                    "Bloque {} reconstruido con la paridad de su grupo ({:#})",
+                    block_index, err
+                );
+                Ok(data)
+            }
+            Ok(None) => Err(err),
+            Err(e) => Err(err.context(format!("Tampoco se pudo reconstruir con la paridad: {:#}", e))),
+        }
+    }
+
+    /// Lee el archivo del bloque tal cual, sin recurrir a la paridad.
+    pub(crate) fn read_block_file(&self, block_index: u32) -> Result<Vec<u8>> {
+        let path = self.block_path(block_index)?;
+        if let Some(data) = self.deferred.as_ref().and_then(|d| d.lock().unwrap().get(&block_index).cloned()) {
+            return Ok(data);
+        }
+        if let Some(zip) = &self.zip {
+            return zip.read_block(&path.to_string_lossy());
+        }
+
+        let mut file = File::open(&path)
+            .with_context(|| format!("No se pudo abrir el bloque {:?}", path))?;
+
+        let mut buf = vec![0u8; QRFS_BLOCK_SIZE as usize];
+        file.read_exact(&mut buf)
+            .with_context(|| format!("No se pudo leer el bloque completo de {:?}", path))?;
+
+        Ok(buf)
+    }
+
+    /// Escribe un bloque completo (se rellena con ceros si `data` es más corto).
+    /// Como siempre se escribe el bloque entero, el copy-up de un overlay es implícito.
+    pub fn write_block(&self, block_index: u32, data: &[u8]) -> Result<()> {
+        if let Some(zip) = &self.zip {
+            return Err(anyhow::anyhow!(
+                "La imagen {:?} está en un zip y es de sólo lectura: hay que extraerla para modificarla",
+                zip.path()
+            ));
+        }
+        self.block_path(block_index)?;
+
+        let block_size = QRFS_BLOCK_SIZE as usize;
+        let mut buf = vec![0u8; block_size];
+        let len = std::cmp::min(block_size, data.len());
+        buf[..len].copy_from_slice(&data[..len]);
+
+        if let Some(deferred) = &self.deferred {
+            deferred.lock().unwrap().insert(block_index, buf);
+            BLOCK_WRITES.fetch_add(1, Ordering::Release);
+            return Ok(());
+        }
+
+        self.store_blocks(BTreeMap::from([(block_index, buf)]))
+    }
+
+    /// Guarda bloques completos en sus archivos junto con la paridad de sus
+    /// grupos, si la imagen tiene (calculada antes de escribir nada).
+    fn store_blocks(&self, blocks: BTreeMap<u32, Vec<u8>>) -> Result<()> {
+        for (block, data) in parity::with_parity(self, blocks)? {
+            self.write_block_file(block, &data)?;
+        }
+        Ok(())
+    }
+
+    fn write_block_file(&self, block_index: u32, buf: &[u8]) -> Result<()> {
+        let path = self.writable_path(block_index)?;
+        let mut file = File::create(&path)
+            .with_context(|| format!("No se pudo abrir el bloque {:?} para escritura", path))?;
+        let written = file.write_all(buf);
+        // Aunque falle, el archivo ya cambió (quedó truncado o a medias)
+        BLOCK_WRITES.fetch_add(1, Ordering::Release);
+        if let Some(folder) = path.parent() {
+            *FILE_WRITES.get_or_init(Default::default).lock().unwrap().entry(folder.to_path_buf()).or_default() += 1;
+        }
+        written.with_context(|| format!("No se pudo escribir completamente el bloque {:?}", path))?;
+
+        Ok(())
+    }
+
+    /// Lee `count` bloques consecutivos desde `start` (tabla de inodos, bitmap).
+    pub fn read_blocks(&self, start: u32, count: u32) -> Result<Vec<u8>> {
+        self.check_range(start, count)?;
+
+        let mut buf = Vec::with_capacity(count as usize * QRFS_BLOCK_SIZE as usize);
+        for b in start..start + count {
+            buf.extend_from_slice(&self.read_block(b)?);
+        }
+        Ok(buf)
+    }
+
+    /// Escribe `data` en bloques consecutivos desde `start`.
+    pub fn write_blocks(&self, start: u32, data: &[u8]) -> Result<()> {
+        let block_size = QRFS_BLOCK_SIZE as usize;
+        let count = data.len().div_ceil(block_size) as u32;
+        self.check_range(start, count)?;
+
+        for (i, chunk) in data.chunks(block_size).enumerate() {
+            self.write_block(start + i as u32, chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Intenta leer cada bloque de la imagen una vez y devuelve los que fallan
+    /// (archivo ilegible o truncado) con su error. Sirve para detectar al montar
+    /// lo que de otro modo aparecería recién en la primera lectura.
+    pub fn scan(&self) -> Vec<(u32, anyhow::Error)> {
+        (0..self.entries.len() as u32)
+            .filter_map(|b| self.read_block(b).err().map(|e| (b, e)))
+            .collect()
+    }
+
+    /// Fuerza a disco (fsync) todos los archivos de bloque. Cubre sólo lo ya
+    /// escrito en ellos: el contenido de los archivos residentes lo vuelca antes
+    /// `QrfsFilesystem::sync` (o el fsync del archivo).
+    pub fn sync_all(&self) -> Result<()> {
+        if self.zip.is_some() {
+            return Ok(()); // nunca se escribe
+        }
+        for b in 0..self.entries.len() as u32 {
+            let path = self.block_path(b)?;
+            // Un archivo perdido de una imagen con paridad no tiene nada que sincronizar
+            if !path.exists() {
+                continue;
+            }
+            File::open(&path)
+                .and_then(|f| f.sync_all())
+                .with_context(|| format!("No se pudo sincronizar el bloque {:?}", path))?;
+        }
+        Ok(())
+    }
+
+    fn check_range(&self, start: u32, count: u32) -> Result<()> {
+        let end = start as usize + count as usize;
+        if end > self.entries.len() {
+            return Err(anyhow::anyhow!(
+                "Rango de bloques fuera de la imagen ({}..{} en {} archivos)",
+                start,
+                end,
+                self.entries.len()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Número de bloque de un nombre de archivo, o `None` si no sigue el formato
+/// `block_<N>[.ext]`.
+pub fn block_file_index(file_name: &str) -> Option<u32> {
+    let rest = file_name.strip_prefix(QRFS_BLOCK_PREFIX)?;
+    let (digits, ext) = match rest.split_once('.') {
+        Some((digits, ext)) => (digits, Some(ext)),
+        None => (rest, None),
+    };
+
+    if digits.is_empty() || !digits.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    if let Some(ext) = ext {
+        let valid = !ext.is_empty()
+            && ext.bytes().all(|c| c.is_ascii_alphanumeric())
+            && !TEMP_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str());
+        if !valid {
+            return None;
+        }
+    }
+
+    digits.parse().ok()
+}
+
+/// Extensión de los archivos de bloque que crean las herramientas por defecto.
+pub const QRFS_DEFAULT_BLOCK_EXT: &str = "qr";
+
+/// Nombre con el que las herramientas (mkfs, grow) crean el archivo del bloque.
+pub fn block_file_name(block_index: u32) -> String {
+    block_file_name_with_ext(block_index, QRFS_DEFAULT_BLOCK_EXT)
+}
+
+/// Como `block_file_name` pero con otra extensión (`png`, `bin`, ...). No valida
+/// `ext`: si el nombre resultante no sigue el formato, `block_file_index` lo ignora.
+pub fn block_file_name_with_ext(block_index: u32, ext: &str) -> String {
+    format!("{}{:08}.{}", QRFS_BLOCK_PREFIX, block_index, ext)
+}
+
+/// Archivos de bloque de la carpeta en orden lógico: el del manifiesto si hay
+/// uno y si no el de los números de los nombres.
+pub fn list_block_files(qr_folder: &Path) -> Result<Vec<PathBuf>> {
+    match read_manifest(qr_folder)? {
+        Some(manifest) => manifest_block_files(qr_folder, &manifest),
+        None => sorted_block_files(qr_folder),
+    }
+}
+
+/// Archivos de bloque de la carpeta ordenados por número de bloque.
+fn sorted_block_files(qr_folder: &Path) -> Result<Vec<PathBuf>> {
+    let entries: Vec<(u32, PathBuf)> = fs::read_dir(qr_folder)
+        .with_context(|| format!("No se pudo leer el directorio {:?}", qr_folder))?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().map(|t| t.is_file()).unwrap_or(false))
+        .filter_map(|e| {
+            let index = block_file_index(&e.file_name().to_string_lossy())?;
+            Some((index, e.path()))
+        })
+        .collect();
+
+    // Imágenes creadas antes del formato `block_<N>`: el orden es el de los nombres
+    if entries.is_empty() {
+        return legacy_block_files(qr_folder);
+    }
+
+    // En una imagen con paridad un archivo perdido no corre los índices: su
+    // lugar queda en la lista y leerlo lo reconstruye
+    let block0 = entries.iter().find(|(index, _)| *index == 0).map(|(_, path)| path.clone());
+    if let Some(total) = block0.as_deref().and_then(parity_image_blocks) {
+        if let Some(files) = with_lost_blocks(qr_folder, &entries, total) {
+            return Ok(files);
+        }
+    }
+
+    Ok(starting_at_superblock(qr_folder, in_block_order(qr_folder, entries)?))
+}
+
+/// Archivos de bloque de una carpeta sin ningún `block_<N>`: como antes del
+/// formato, todos los archivos regulares ordenados por nombre. Se saltan los
+/// ocultos (`.DS_Store`, `.qrfs.lock`, ...), los temporales y los que QRFS guarda
+/// junto a los bloques (`manifest.json`, `qrfs.toml`). Sólo si alguno empieza
+/// con un superblock: una carpeta con otros archivos (un `LEAME.txt` antes de
+/// `mkfs.qrfs --blocks`) no tiene bloques.
+fn legacy_block_files(qr_folder: &Path) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(qr_folder)
+        .with_context(|| format!("No se pudo leer el directorio {:?}", qr_folder))?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().map(|t| t.is_file()).unwrap_or(false))
+        .filter(|e| {
+            let name = e.file_name().to_string_lossy().into_owned();
+            let temp = name
+                .rsplit_once('.')
+                .is_some_and(|(_, ext)| TEMP_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()));
+            !name.starts_with('.') && !temp && name != QRFS_MANIFEST_NAME && name != QRFS_CONFIG_NAME
+        })
+        .map(|e| e.path())
+        .collect();
+    files.sort();
+
+    if superblock_files(&files).is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(starting_at_superblock(qr_folder, files))
+}
+
+/// Superblock del archivo `path` si empieza con uno válido (magic y checksum).
+/// Sólo lee el comienzo del archivo.
+fn superblock_in_file(path: &Path) -> Option<SuperblockDisk> {
+    let mut buf = vec![0u8; std::mem::size_of::<SuperblockDisk>()];
+    File::open(path).ok()?.read_exact(&mut buf).ok()?;
+    let sb = SuperblockDisk::from_block_bytes(&buf).ok()?;
+    (sb.check_magic().is_ok() && sb.verify_checksum()).then_some(sb)
+}
+
+/// Archivos de `files` que empiezan con un superblock válido: los candidatos a
+/// bloque 0.
+pub(crate) fn superblock_files(files: &[PathBuf]) -> Vec<PathBuf> {
+    files.iter().filter(|f| superblock_in_file(f).is_some()).cloned().collect()
+}
+
+/// Bloque 0 detectado en cada carpeta sin manifiesto, para no volver a
+/// recorrerla en cada `BlockStore::open`.
+static DETECTED_BLOCK0: OnceLock<Mutex<HashMap<PathBuf, PathBuf>>> = OnceLock::new();
+
+/// Pone primero al bloque 0: el único archivo con un superblock válido, con el
+/// resto en orden desde él y dando la vuelta al final (p. ej. los nombres
+/// quedaron numerados desde otra hoja al escanear). Se revisan todos los
+/// archivos aunque el primero ya tenga un superblock: puede ser una copia. Con
+/// cero o con varios candidatos el orden queda como está (el montaje explica
+/// por qué no sirve; ver `superblock_files`).
+fn starting_at_superblock(qr_folder: &Path, mut files: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut detected = DETECTED_BLOCK0.get_or_init(Default::default).lock().unwrap();
+    let cached = detected
+        .get(qr_folder)
+        .and_then(|block0| files.iter().position(|f| f == block0))
+        .filter(|&pos| superblock_in_file(&files[pos]).is_some());
+
+    let pos = match cached {
+        Some(pos) => pos,
+        None => {
+            let candidates: Vec<usize> = (0..files.len())
+                .filter(|&i| superblock_in_file(&files[i]).is_some())
+                .collect();
+            let [pos] = candidates[..] else {
+                return files;
+            };
+            detected.insert(qr_folder.to_path_buf(), files[pos].clone());
+            pos
+        }
+    };
+
+    files.rotate_left(pos);
+    files
+}
+
+/// Si `block0` es el superblock de una imagen con paridad, cuántos archivos de
+/// bloque tiene la imagen (los del FS y los de paridad).
+fn parity_image_blocks(block0: &Path) -> Option<u32> {
+    let sb = superblock_in_file(block0)?;
+    (sb.features & QRFS_FEATURE_PARITY != 0).then(|| sb.image_blocks())
+}
+
+/// Los `total` archivos de la imagen en orden, con el nombre que tendría cada
+/// uno que falta (misma extensión que el bloque 0). `None` si sobra o se repite
+/// algún número: eso no lo explica un archivo perdido.
+fn with_lost_blocks(qr_folder: &Path, entries: &[(u32, PathBuf)], total: u32) -> Option<Vec<PathBuf>> {
+    let mut slots: Vec<Option<PathBuf>> = vec![None; total as usize];
+    for (index, path) in entries {
+        if slots.get_mut(*index as usize)?.replace(path.clone()).is_some() {
+            return None;
+        }
+    }
+
+    let ext = slots[0]
+        .as_ref()
+        .and_then(|p| p.extension())
+        .and_then(|e| e.to_str())
+        .unwrap_or(QRFS_DEFAULT_BLOCK_EXT)
+        .to_string();
+    let files = slots
+        .into_iter()
+        .enumerate()
+        .map(|(i, path)| path.unwrap_or_else(|| qr_folder.join(block_file_name_with_ext(i as u32, &ext))))
+        .collect();
+    Some(files)
+}
+
+/// Ordena `(número de bloque, archivo)` por número. Los números deben ser
+/// exactamente 0..N: un hueco o un duplicado correría los índices de todos los
+/// bloques siguientes, así que se rechaza.
+pub(crate) fn in_block_order<T: std::fmt::Debug + Ord>(source: &Path, mut entries: Vec<(u32, T)>) -> Result<Vec<T>> {
+    entries.sort();
+
+    for (expected, (index, file)) in entries.iter().enumerate() {
+        if *index as usize != expected {
+            return Err(anyhow::anyhow!(
+                "Los archivos de bloque de {:?} no son consecutivos: se esperaba el bloque {} y se encontró {:?}",
+                source,
+                expected,
+                file
+            ));
+        }
+    }
+
+    Ok(entries.into_iter().map(|(_, file)| file).collect())
+}
+
+fn read_manifest(qr_folder: &Path) -> Result<Option<Manifest>> {
+    let path = qr_folder.join(QRFS_MANIFEST_NAME);
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let text = fs::read_to_string(&path)
+        .with_context(|| format!("No se pudo leer el manifiesto {:?}", path))?;
+    parse_manifest(&path, &text).map(Some)
+}
+
+/// Interpreta el texto de un manifiesto (`path` sólo se usa en los mensajes).
+pub(crate) fn parse_manifest(path: &Path, text: &str) -> Result<Manifest> {
+    let manifest: Manifest = serde_json::from_str(text)
+        .with_context(|| format!("El manifiesto {:?} no es válido", path))?;
+
+    if manifest.version != MANIFEST_VERSION {
+        return Err(anyhow::anyhow!(
+            "Versión de manifiesto no soportada en {:?}: {} (se esperaba {})",
+            path,
+            manifest.version,
+            MANIFEST_VERSION
+        ));
+    }
+    Ok(manifest)
+}
+
+/// Archivos del manifiesto en orden; cada uno tiene que existir en la carpeta,
+/// salvo en una imagen con paridad (ver `sorted_block_files`).
+fn manifest_block_files(qr_folder: &Path, manifest: &Manifest) -> Result<Vec<PathBuf>> {
+    let entries: Vec<PathBuf> = manifest_order(qr_folder, manifest)?
+        .into_iter()
+        .map(|file| qr_folder.join(file))
+        .collect();
+
+    if let Some(index) = entries.iter().position(|path| !path.is_file()) {
+        let tolerated = entries
+            .first()
+            .and_then(|block0| parity_image_blocks(block0))
+            .is_some_and(|total| total as usize == entries.len());
+        if !tolerated {
+            return Err(anyhow::anyhow!(
+                "El bloque {} del manifiesto de {:?} no existe: {:?}",
+                index,
+                qr_folder,
+                entries[index]
+            ));
+        }
+    }
+    Ok(entries)
+}
+
+/// Nombres de archivo del manifiesto en orden. Igual que con los nombres, los
+/// índices deben ser exactamente 0..N.
+pub(crate) fn manifest_order<'a>(source: &Path, manifest: &'a Manifest) -> Result<Vec<&'a str>> {
+    let mut blocks: Vec<&ManifestEntry> = manifest.blocks.iter().collect();
+    blocks.sort_by_key(|e| e.index);
+
+    let mut files = Vec::with_capacity(blocks.len());
+    for (expected, entry) in blocks.into_iter().enumerate() {
+        if entry.index as usize != expected {
+            return Err(anyhow::anyhow!(
+                "El manifiesto de {:?} no es consecutivo: se esperaba el bloque {} y se encontró el {}",
+                source,
+                expected,
+                entry.index
+            ));
+        }
+        // Sólo nombres sueltos: el manifiesto no puede apuntar fuera de la carpeta
+        if Path::new(&entry.file).file_name() != Some(std::ffi::OsStr::new(&entry.file)) {
+            return Err(anyhow::anyhow!(
+                "El manifiesto de {:?} nombra un archivo inválido para el bloque {}: {:?}",
+                source,
+                entry.index,
+                entry.file
+            ));
+        }
+        files.push(entry.file.as_str());
+    }
+    Ok(files)
+}
+
+/// Escribe el manifiesto de la carpeta con `entries` (archivos de bloque en
+/// orden lógico, dentro de `qr_folder`) y el checksum actual de cada uno.
+pub fn write_manifest(qr_folder: &Path, entries: &[PathBuf]) -> Result<()> {
+    let mut blocks = Vec::with_capacity(entries.len());
+    for (index, path) in entries.iter().enumerate() {
+        let file = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .with_context(|| format!("Nombre de archivo de bloque inválido: {:?}", path))?
+            .to_string();
+        // Un archivo perdido (sólo puede faltar en una imagen con paridad) queda
+        // en el manifiesto con checksum 0 y `manifest_mismatches` lo reporta
+        let crc = if path.exists() {
+            crc32(&fs::read(path).with_context(|| format!("No se pudo leer el bloque {:?}", path))?)
+        } else {
+            0
+        };
+        blocks.push(ManifestEntry {
+            index: index as u32,
+            file,
+            crc32: crc,
+        });
+    }
+
+    let manifest = Manifest {
+        version: MANIFEST_VERSION,
+        blocks,
+    };
+    let text = serde_json::to_string_pretty(&manifest)?;
+
+    // Se escribe aparte y se renombra: un manifiesto a medias dejaría la
+    // imagen sin orden
+    let path = qr_folder.join(QRFS_MANIFEST_NAME);
+    let tmp = qr_folder.join(format!("{}.tmp", QRFS_MANIFEST_NAME));
+    fs::write(&tmp, text).with_context(|| format!("No se pudo escribir el manifiesto {:?}", tmp))?;
+    fs::rename(&tmp, &path).with_context(|| format!("No se pudo reemplazar el manifiesto {:?}", path))?;
+    Ok(())
+}
+
+/// Actualiza los checksums del manifiesto si la carpeta tiene uno (si no, no
+/// hace nada). El orden no cambia.
+pub fn refresh_manifest(qr_folder: &Path) -> Result<()> {
+    if read_manifest(qr_folder)?.is_none() {
+        return Ok(());
+    }
+    write_manifest(qr_folder, &list_block_files(qr_folder)?)
+}
+
+/// CRC32 de cada bloque según el manifiesto, por número de bloque (`None` si
+/// la carpeta no tiene manifiesto).
+pub(crate) fn manifest_checksums(qr_folder: &Path) -> Result<Option<HashMap<u32, u32>>> {
+    Ok(read_manifest(qr_folder)?.map(|m| m.blocks.iter().map(|e| (e.index, e.crc32)).collect()))
+}
+
+/// Bloques cuyo contenido no coincide con el checksum del manifiesto (vacío si
+/// la carpeta no tiene manifiesto). Un bloque escrito después de la última
+/// sincronización también aparece: el manifiesto quedó desactualizado.
+pub fn manifest_mismatches(qr_folder: &Path) -> Result<Vec<u32>> {
+    let Some(manifest) = read_manifest(qr_folder)? else {
+        return Ok(Vec::new());
+    };
+
+    let mut mismatches: Vec<u32> = manifest
+        .blocks
+        .iter()
+        .filter(|e| fs::read(qr_folder.join(&e.file)).map_or(true, |data| crc32(&data) != e.crc32))
+        .map(|e| e.index)
+        .collect();
+    mismatches.sort_unstable();
+    Ok(mismatches)
+}
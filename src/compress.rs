@@ -0,0 +1,122 @@
+// Compresión de los datos de un archivo (inodos con `QRFS_INODE_COMPRESSED`).
+//
+// El contenido se guarda como una secuencia de bloques empaquetados: cada bloque
+// empieza con un encabezado de 4 bytes (largo guardado y largo original, u16
+// little-endian) y sigue con el tramo del archivo comprimido con deflate. Si un
+// tramo no se achica se guarda tal cual y los dos largos coinciden. Como un
+// bloque puede guardar más bytes de los que mide, un archivo comprimible ocupa
+// menos bloques (menos QRs); a cambio el bloque lógico N ya no corresponde a un
+// offset fijo y el archivo se lee y se reescribe entero.
+
+use anyhow::{anyhow, Result};
+use miniz_oxide::deflate::compress_to_vec;
+use miniz_oxide::inflate::decompress_to_vec_with_limit;
+
+/// Bytes del encabezado al comienzo de cada bloque de un archivo comprimido.
+pub const QRFS_COMPRESSED_HEADER: usize = 4;
+
+/// Nivel de deflate: en QRFS el espacio (cantidad de QRs) vale más que la CPU.
+const LEVEL: u8 = 9;
+
+/// Máximo de bytes originales por bloque (el largo se guarda en un u16).
+const MAX_RAW: usize = u16::MAX as usize;
+
+/// Parte `data` en bloques de `block_size` bytes, cada uno con su encabezado.
+/// Cada bloque se llena con el tramo más largo cuya versión comprimida entra;
+/// un tramo que no se achica se guarda sin comprimir.
+pub(crate) fn pack(data: &[u8], block_size: usize) -> Vec<Vec<u8>> {
+    let room = block_size - QRFS_COMPRESSED_HEADER;
+    let mut blocks = Vec::new();
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let (raw_len, payload) = fit(&data[pos..], room);
+        let stored = payload.as_deref().unwrap_or(&data[pos..pos + raw_len]);
+
+        let mut block = Vec::with_capacity(block_size);
+        block.extend_from_slice(&(stored.len() as u16).to_le_bytes());
+        block.extend_from_slice(&(raw_len as u16).to_le_bytes());
+        block.extend_from_slice(stored);
+        block.resize(block_size, 0);
+        blocks.push(block);
+
+        pos += raw_len;
+    }
+    blocks
+}
+
+/// Tramo del comienzo de `rest` que va en un bloque con `room` bytes libres:
+/// su largo y su versión comprimida (`None` = se guarda tal cual).
+fn fit(rest: &[u8], room: usize) -> (usize, Option<Vec<u8>>) {
+    let first = rest.len().min(room);
+    let compressed = compress_to_vec(&rest[..first], LEVEL);
+    if compressed.len() >= first {
+        return (first, None);
+    }
+
+    // Se duplica el tramo mientras siga entrando y, al pasarse, se busca el
+    // límite exacto entre el último que entró y el primero que no
+    let mut best = (first, compressed);
+    let mut too_long = None;
+    while too_long.is_none() {
+        let next = (best.0 * 2).min(rest.len()).min(MAX_RAW);
+        if next == best.0 {
+            return (best.0, Some(best.1));
+        }
+        let compressed = compress_to_vec(&rest[..next], LEVEL);
+        if compressed.len() <= room {
+            best = (next, compressed);
+        } else {
+            too_long = Some(next);
+        }
+    }
+
+    let (mut lo, mut hi) = (best.0, too_long.unwrap_or(best.0));
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        let compressed = compress_to_vec(&rest[..mid], LEVEL);
+        if compressed.len() <= room {
+            best = (mid, compressed);
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    (best.0, Some(best.1))
+}
+
+/// Agrega a `out` el tramo original guardado en un bloque de `pack`.
+pub(crate) fn unpack_block(block: &[u8], out: &mut Vec<u8>) -> Result<()> {
+    if block.len() < QRFS_COMPRESSED_HEADER {
+        return Err(anyhow!("Bloque comprimido demasiado corto ({} bytes)", block.len()));
+    }
+    let stored = u16::from_le_bytes([block[0], block[1]]) as usize;
+    let raw = u16::from_le_bytes([block[2], block[3]]) as usize;
+    let payload = block
+        .get(QRFS_COMPRESSED_HEADER..QRFS_COMPRESSED_HEADER + stored)
+        .ok_or_else(|| anyhow!("Encabezado de bloque comprimido inválido (guardado = {} bytes)", stored))?;
+
+    if stored == raw {
+        out.extend_from_slice(payload);
+        return Ok(());
+    }
+    if stored > raw || raw == 0 {
+        return Err(anyhow!(
+            "Encabezado de bloque comprimido inválido (guardado = {}, original = {})",
+            stored,
+            raw
+        ));
+    }
+
+    let data = decompress_to_vec_with_limit(payload, raw)
+        .map_err(|e| anyhow!("No se pudo descomprimir el bloque: {:?}", e.status))?;
+    if data.len() != raw {
+        return Err(anyhow!(
+            "El bloque comprimido tiene {} bytes y el encabezado dice {}",
+            data.len(),
+            raw
+        ));
+    }
+    out.extend_from_slice(&data);
+    Ok(())
+}
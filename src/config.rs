@@ -0,0 +1,130 @@
+// Opciones de montaje leídas de un archivo `qrfs.toml`.
+//
+// El archivo se busca en la carpeta de QRs (o donde indique `--config`) y sólo
+// da valores por defecto: lo que se pase por línea de comandos lo pisa.
+//
+//     # qrfs.toml
+//     case_insensitive = true
+//     read_only = false
+//     lower = "/datos/base"
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// Nombre del archivo de configuración dentro de la carpeta de QRs.
+pub const QRFS_CONFIG_NAME: &str = "qrfs.toml";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MountConfig {
+    /// Carpeta base de un overlay (la carpeta de QRs pasa a ser la capa superior).
+    pub lower: Option<PathBuf>,
+    /// Bloque a usar como bloque 0 en vez del primero de la carpeta.
+    pub start_qr: Option<PathBuf>,
+    /// Un `start_qr` que no está en la carpeta impide el montaje (si no, se
+    /// avisa y se usa el primer bloque).
+    pub start_qr_strict: bool,
+    /// Un inodo cuyo tipo contradice el árbol (un directorio sin bloque, un
+    /// archivo usado como directorio) impide el montaje (si no, se avisa).
+    pub types_strict: bool,
+    /// Nombres comparados sin distinguir mayúsculas.
+    pub case_insensitive: bool,
+    /// Montaje de sólo lectura (el kernel responde EROFS a toda escritura).
+    pub read_only: bool,
+    /// Permite que otros usuarios accedan al montaje (requiere user_allow_other).
+    pub allow_other: bool,
+    /// Permite montar sobre un directorio que no está vacío (su contenido queda
+    /// tapado mientras dure el montaje).
+    pub allow_nonempty: bool,
+    /// Leer todos los bloques antes de montar y reportar los que fallan.
+    pub scan: bool,
+    /// Como `scan`, pero un solo bloque ilegible impide el montaje.
+    pub scan_strict: bool,
+    /// Segundos entre sincronizaciones periódicas de la imagen (sin valor: sólo
+    /// al desmontar).
+    pub flush_interval: Option<u64>,
+    /// Tamaño en bytes a partir del cual un archivo se escribe directo a sus
+    /// bloques en vez de quedar entero en memoria (sin valor: sin límite).
+    pub resident_limit: Option<u64>,
+    /// Los archivos nuevos guardan sus datos comprimidos.
+    pub compress: bool,
+    /// Ningún archivo se guarda en memoria: cada write va a sus bloques y cada
+    /// read sale de disco.
+    pub no_page_cache_buffer: bool,
+    /// Mostrar en la raíz el directorio virtual `.qrfs-meta` con los metadatos
+    /// como texto.
+    pub meta_dir: bool,
+    /// Llenar de ceros los bloques que se liberan.
+    pub zero_on_free: bool,
+    /// Bloques que se cargan por adelantado en una lectura secuencial (sin
+    /// valor: `QRFS_READ_AHEAD_DEFAULT`, desactivada; 0 la desactiva).
+    pub read_ahead: Option<usize>,
+}
+
+impl MountConfig {
+    /// Lee y valida un archivo de configuración. Una clave desconocida es un
+    /// error, así un typo no se ignora en silencio.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("No se pudo leer la configuración {:?}", path))?;
+        toml::from_str(&text).with_context(|| format!("Configuración inválida en {:?}", path))
+    }
+
+    /// Configuración para montar `qr_folder`: la de `explicit` si se indicó (y
+    /// debe existir), si no `qr_folder/qrfs.toml` si existe, si no la por defecto.
+    pub fn find(qr_folder: &Path, explicit: Option<&Path>) -> Result<Self> {
+        if let Some(path) = explicit {
+            return Self::load(path);
+        }
+
+        let default_path = qr_folder.join(QRFS_CONFIG_NAME);
+        if default_path.is_file() {
+            Self::load(&default_path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_reads_the_folder_config_unless_one_is_given() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(!MountConfig::find(dir.path(), None).unwrap().read_only);
+
+        fs::write(
+            dir.path().join(QRFS_CONFIG_NAME),
+            "read_only = true\nflush_interval = 30\nlower = \"/datos/base\"\n",
+        )
+        .unwrap();
+        let config = MountConfig::find(dir.path(), None).unwrap();
+        assert!(config.read_only);
+        assert!(!config.case_insensitive);
+        assert_eq!(config.flush_interval, Some(30));
+        assert_eq!(config.lower, Some(PathBuf::from("/datos/base")));
+
+        // --config reemplaza al de la carpeta, y tiene que existir
+        let explicit = dir.path().join("otro.toml");
+        fs::write(&explicit, "case_insensitive = true\n").unwrap();
+        let config = MountConfig::find(dir.path(), Some(&explicit)).unwrap();
+        assert!(config.case_insensitive);
+        assert!(!config.read_only);
+        assert!(MountConfig::find(dir.path(), Some(&dir.path().join("no-existe.toml"))).is_err());
+    }
+
+    #[test]
+    fn unknown_key_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(QRFS_CONFIG_NAME);
+        fs::write(&path, "read_onyl = true\n").unwrap();
+
+        let err = MountConfig::load(&path).unwrap_err();
+        assert!(format!("{err:#}").contains("read_onyl"), "{err:#}");
+    }
+}
@@ -0,0 +1,101 @@
+// Archivo virtual de control del montaje.
+//
+// `.qrfs-control` aparece en la raíz como `.qrfs-stats`, pero se escribe: cada
+// escritura es un comando y una lectura devuelve el resultado del último. Hoy
+// el único comando es `fsck`, que revisa la imagen montada sin desmontarla y
+// deja el `FsckReport` en JSON:
+//
+//     echo fsck > /mnt/qrfs/.qrfs-control
+//     cat /mnt/qrfs/.qrfs-control
+
+use std::time::SystemTime;
+
+use fuser::{FileAttr, FileType};
+
+use crate::fs::{write_counters, QrfsInner};
+use crate::fsck;
+
+/// Nombre del archivo virtual de control en la raíz.
+pub const QRFS_CONTROL_NAME: &str = ".qrfs-control";
+
+/// Inodo reservado para el archivo de control (ver `QRFS_STATS_INO`).
+pub const QRFS_CONTROL_INO: u64 = u64::MAX - 2;
+
+/// Ejecuta un comando escrito en el archivo de control y guarda su resultado
+/// para la próxima lectura. Devuelve el errno si el comando no existe o falla.
+///
+/// El que llama tiene el lock de escritura: ninguna otra operación cambia la
+/// imagen mientras corre, así el fsck ve un estado consistente.
+pub(crate) fn run_command(inner: &mut QrfsInner, command: &[u8]) -> Result<(), i32> {
+    let command = std::str::from_utf8(command).map_err(|_| libc::EINVAL)?;
+
+    match command.trim() {
+        "fsck" => {
+            // El fsck lee el disco: primero van los contadores que sólo están en memoria
+            if let Err(e) = write_counters(inner) {
+                eprintln!("Error al sincronizar el superblock antes del fsck: {e:?}");
+                return Err(libc::EIO);
+            }
+            let report = fsck::check(&inner.qr_folder);
+            inner.control_output = report.to_json().into_bytes();
+            Ok(())
+        }
+        _ => Err(libc::EINVAL),
+    }
+}
+
+/// Atributos del archivo de control, con el tamaño del último resultado.
+pub fn control_attr(size: u64) -> FileAttr {
+    let now = SystemTime::now();
+    FileAttr {
+        ino: QRFS_CONTROL_INO,
+        size,
+        blocks: 0,
+        atime: now,
+        mtime: now,
+        ctime: now,
+        crtime: now,
+        kind: FileType::RegularFile,
+        perm: 0o644,
+        nlink: 1,
+        uid: 0,
+        gid: 0,
+        rdev: 0,
+        blksize: 512,
+        flags: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::{write_file_data, QrfsFilesystem};
+    use crate::image::QrfsImage;
+    use crate::test_util::{fresh_image, pattern};
+    use std::path::Path;
+
+    #[test]
+    fn fsck_command_reports_the_mounted_image_as_json() {
+        let dir = fresh_image(64);
+        let ino = {
+            let image = QrfsImage::open(dir.path()).unwrap();
+            image.mkdir(Path::new("/sub")).unwrap();
+            image.create_file(Path::new("/datos.bin")).unwrap()
+        };
+
+        let fs = QrfsFilesystem::mount_from_folder(dir.path(), None, None).unwrap();
+        let mut inner = fs.inner.write().unwrap();
+        let data = pattern(5000);
+        assert_eq!(write_file_data(&mut inner, ino, 0, &data), Ok(5000));
+
+        run_command(&mut inner, b"fsck\n").unwrap();
+        let report: serde_json::Value = serde_json::from_slice(&inner.control_output).unwrap();
+        assert_eq!(report["errors"], serde_json::json!([]), "{}", report);
+        assert_eq!(report["blocks_ok"], true);
+
+        // Un comando desconocido no pisa el resultado anterior
+        let previous = inner.control_output.clone();
+        assert_eq!(run_command(&mut inner, b"format"), Err(libc::EINVAL));
+        assert_eq!(inner.control_output, previous);
+    }
+}
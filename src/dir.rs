@@ -1,10 +1,13 @@
-use std::ffi::OsStr;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::mem;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 
 use fuser::{FileAttr, FileType};
 use libc::{ENOTDIR, ENOENT, ENOTEMPTY};
 use thiserror::Error;
 
-use crate::fs::{inode_to_attr, QrfsInner};
+use crate::fs::{inode_to_attr, QrfsInner, DirEntryDisk, QRFS_MAX_NAME, QRFS_NAME_LEN};
 
 #[derive(Debug, Error)]
 pub enum DirError {
@@ -18,6 +21,16 @@ pub enum DirError {
     NoSpace,
     #[error("operación no soportada")]
     NotSupported,
+    #[error("es un directorio")]
+    IsDirectory,
+    #[error("la entrada ya existe")]
+    AlreadyExists,
+    #[error("nombre demasiado largo")]
+    NameTooLong,
+    #[error("argumento inválido")]
+    InvalidArgument,
+    #[error("error de entrada/salida")]
+    Io,
 }
 
 
@@ -29,16 +42,80 @@ impl DirError {
             DirError::NotEmpty => ENOTEMPTY,
             DirError::NoSpace => libc::ENOSPC,
             DirError::NotSupported => libc::ENOSYS,
+            DirError::IsDirectory => libc::EISDIR,
+            DirError::AlreadyExists => libc::EEXIST,
+            DirError::NameTooLong => libc::ENAMETOOLONG,
+            DirError::InvalidArgument => libc::EINVAL,
+            DirError::Io => libc::EIO,
         }
     }
 }
 
 pub struct DirEntry {
     pub ino: u64,
-    pub name: String,
+    pub name: OsString,
     pub file_type: FileType,
 }
 
+// --------- Formato en disco de las entradas ---------
+
+/// Desempaqueta un bloque de directorio en sus entradas.
+/// Los slots con `inode == 0` son lápidas (entradas borradas) y se saltan,
+/// así las entradas vivas conservan su posición en el bloque.
+pub fn unpack_dir_entries(buf: &[u8]) -> Vec<DirEntry> {
+    let entry_size = mem::size_of::<DirEntryDisk>();
+    let mut entries = Vec::new();
+
+    for chunk in buf.chunks_exact(entry_size) {
+        let disk_entry: DirEntryDisk = unsafe {
+            let ptr = chunk.as_ptr() as *const DirEntryDisk;
+            ptr.read_unaligned()
+        };
+
+        if disk_entry.inode == 0 {
+            continue;
+        }
+
+        let name = entry_name(&disk_entry);
+        if name.is_empty() {
+            continue;
+        }
+
+        entries.push(DirEntry {
+            ino: disk_entry.inode as u64,
+            name,
+            // El tipo real se resuelve con la tabla de inodos
+            file_type: FileType::RegularFile,
+        });
+    }
+
+    entries
+}
+
+/// Construye una entrada en disco para (nombre, inodo). El nombre se guarda con
+/// sus bytes tal cual: Linux admite nombres que no son UTF-8.
+pub fn pack_dir_entry(ino: u64, name: &OsStr) -> DirEntryDisk {
+    let mut raw = [0u8; QRFS_NAME_LEN];
+    let bytes = name.as_bytes();
+    let len = bytes.len().min(QRFS_NAME_LEN);
+    raw[..len].copy_from_slice(&bytes[..len]);
+
+    DirEntryDisk {
+        inode: ino as u32,
+        name: raw,
+    }
+}
+
+/// Nombre de una entrada en disco (hasta el primer byte nulo), sin convertir.
+pub fn entry_name(entry: &DirEntryDisk) -> OsString {
+    let len = entry
+        .name
+        .iter()
+        .position(|&b| b == 0)
+        .unwrap_or(QRFS_NAME_LEN);
+    OsString::from_vec(entry.name[..len].to_vec())
+}
+
 // --------- Funciones usadas por Filesystem ---------
 
 pub fn is_directory(inner: &QrfsInner, ino: u64) -> bool {
@@ -49,6 +126,24 @@ pub fn is_directory(inner: &QrfsInner, ino: u64) -> bool {
     }
 }
 
+/// `true` si el directorio `dir` es `ancestor` o está debajo de él, siguiendo
+/// la cadena de padres hasta la raíz (la raíz es su propio padre). La cadena
+/// nunca es más larga que la cantidad de directorios: si lo fuera ya hay un
+/// ciclo, y se corta ahí.
+fn is_ancestor_or_self(inner: &QrfsInner, ancestor: u64, dir: u64) -> bool {
+    let mut current = dir;
+    for _ in 0..=inner.directories.len() {
+        if current == ancestor {
+            return true;
+        }
+        match inner.directories.get(&current) {
+            Some(d) if d.parent != current => current = d.parent,
+            _ => return false,
+        }
+    }
+    false
+}
+
 
 pub fn list_directory(inner: &QrfsInner, ino: u64) -> Result<Vec<DirEntry>, DirError> {
     let dir = inner
@@ -59,17 +154,121 @@ pub fn list_directory(inner: &QrfsInner, ino: u64) -> Result<Vec<DirEntry>, DirE
     let mut entries = Vec::new();
 
     for (name, child_ino) in &dir.entries {
-        let inode = inner.inodes.get(child_ino).ok_or(DirError::NotFound)?;
+        // El montaje carga todos los inodos (con el tipo de la tabla, ver
+        // `InodeDisk::kind`); uno que salió con un forget se vuelve a leer de la
+        // tabla en disco en vez de suponer archivo regular
+        let file_type = match inner.inodes.get(child_ino) {
+            Some(inode) => inode.kind,
+            None => file_type_from_disk(inner, *child_ino)?,
+        };
         entries.push(DirEntry {
             ino: *child_ino,
             name: name.clone(),
-            file_type: inode.kind,
+            file_type,
         });
     }
 
+    // readdir lista siempre por nombre (bytes): el orden del HashMap cambia de un
+    // montaje a otro, y los offsets de un readdir en varias llamadas tienen que
+    // seguir apuntando a las mismas entradas
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
     Ok(entries)
 }
 
+/// Listado completo de un directorio como lo ve FUSE: ".", ".." y las
+/// entradas en el orden de `list_directory`, con los números de inodo ya
+/// traducidos con `fuse_ino`. La entrada en la posición `i` tiene el offset
+/// `i + 1` en readdir.
+pub fn snapshot_directory(inner: &QrfsInner, ino: u64) -> Result<Vec<DirEntry>, DirError> {
+    let entries = list_directory(inner, ino)?;
+    let parent = parent_inode(inner, ino).unwrap_or(ino);
+
+    let mut snapshot = Vec::with_capacity(entries.len() + 2);
+    snapshot.push(DirEntry { ino: inner.fuse_ino(ino), name: ".".into(), file_type: FileType::Directory });
+    snapshot.push(DirEntry { ino: inner.fuse_ino(parent), name: "..".into(), file_type: FileType::Directory });
+    snapshot.extend(entries.into_iter().map(|e| DirEntry { ino: inner.fuse_ino(e.ino), ..e }));
+    Ok(snapshot)
+}
+
+/// Listados abiertos con opendir (handle -> `snapshot_directory` de ese
+/// momento). readdir pagina sobre la copia del handle, así un directorio que
+/// cambia mientras se lista no repite ni saltea entradas; releasedir la suelta.
+#[derive(Default)]
+pub struct DirStreams {
+    next_fh: u64,
+    open: HashMap<u64, Vec<DirEntry>>,
+}
+
+impl DirStreams {
+    /// Guarda un listado y devuelve su handle.
+    pub fn insert(&mut self, entries: Vec<DirEntry>) -> u64 {
+        self.next_fh += 1;
+        self.open.insert(self.next_fh, entries);
+        self.next_fh
+    }
+
+    pub fn get(&self, fh: u64) -> Option<&[DirEntry]> {
+        self.open.get(&fh).map(Vec::as_slice)
+    }
+
+    pub fn remove(&mut self, fh: u64) {
+        self.open.remove(&fh);
+    }
+}
+
+/// Tipo de un inodo leído directamente de la tabla de inodos en disco.
+fn file_type_from_disk(inner: &QrfsInner, ino: u64) -> Result<FileType, DirError> {
+    let inode_disk = inner.load_inode(ino)
+        .map_err(|_| DirError::NotFound)?;
+    if inode_disk.id == 0 || inode_disk.nlink == 0 || inode_disk.is_deleted() {
+        return Err(DirError::NotFound);
+    }
+
+    Ok(inode_disk.kind())
+}
+
+/// Compara dos nombres de entrada. Con `case_insensitive` (opción de montaje
+/// `--case-insensitive`) "Foo" y "foo" son el mismo nombre; en disco se guarda
+/// siempre el nombre tal como se creó. Un nombre que no es UTF-8 sólo es igual
+/// a otro con exactamente los mismos bytes.
+pub fn names_equal(a: &OsStr, b: &OsStr, case_insensitive: bool) -> bool {
+    if a == b {
+        return true;
+    }
+    match (case_insensitive, a.to_str(), b.to_str()) {
+        (true, Some(a), Some(b)) => a.to_lowercase() == b.to_lowercase(),
+        _ => false,
+    }
+}
+
+/// Rechaza los nombres que no entran en una entrada de directorio (más de
+/// `QRFS_MAX_NAME` bytes), en vez de guardarlos truncados.
+pub fn check_name(name: &OsStr) -> Result<(), DirError> {
+    if name.len() > QRFS_MAX_NAME {
+        return Err(DirError::NameTooLong);
+    }
+    Ok(())
+}
+
+/// Busca `name` en el directorio `dir_ino` según el modo del montaje y devuelve el
+/// nombre guardado junto con su inodo.
+pub fn find_entry(inner: &QrfsInner, dir_ino: u64, name: &OsStr) -> Option<(OsString, u64)> {
+    let dir = inner.directories.get(&dir_ino)?;
+
+    if let Some((stored, ino)) = dir.entries.get_key_value(name) {
+        return Some((stored.clone(), *ino));
+    }
+    if !inner.case_insensitive {
+        return None;
+    }
+
+    dir.entries
+        .iter()
+        .find(|(stored, _)| names_equal(stored, name, true))
+        .map(|(stored, ino)| (stored.clone(), *ino))
+}
+
 pub fn parent_inode(inner: &QrfsInner, ino: u64) -> Option<u64> {
     if let Some(dir) = inner.directories.get(&ino) {
         Some(dir.parent)
@@ -78,53 +277,59 @@ pub fn parent_inode(inner: &QrfsInner, ino: u64) -> Option<u64> {
     }
 }
 
+/// Crea el directorio `name` en `parent` con permisos `perm` (ya sin los bits
+/// del umask).
 pub fn create_directory(
     inner: &mut QrfsInner,
     parent: u64,
     name: &OsStr,
-    _mode: u32,
+    perm: u16,
 ) -> Result<FileAttr, DirError> {
     if !is_directory(inner, parent) {
         return Err(DirError::NotDirectory);
     }
 
-    let name_str = name.to_string_lossy().to_string();
-
-    // 1) Revisar existencia SIN mantener un &mut vivo
-    {
-        let parent_dir = inner
-            .directories
-            .get(&parent)
-            .ok_or(DirError::NotDirectory)?;
+    check_name(name)?;
 
-        if parent_dir.entries.contains_key(&name_str) {
-            return Err(DirError::NotSupported);
-        }
+    // 1) Revisar existencia (sin distinguir mayúsculas si el montaje lo pide)
+    if !inner.directories.contains_key(&parent) {
+        return Err(DirError::NotDirectory);
+    }
+    if find_entry(inner, parent, name).is_some() {
+        return Err(DirError::AlreadyExists);
     }
 
-    // 2) Reservar nuevo inodo
-    let new_ino = inner.next_ino;
-    inner.next_ino += 1;
-
-    // Crear inodo directorio
-    let inode = crate::fs::Inode::dir(new_ino);
+    // 2) Reservar nuevo inodo (sin tocar nada si la tabla está llena) y el
+    //    bloque de datos donde van "." y ".."
+    let (new_ino, generation) = crate::fs::alloc_inode(inner).ok_or(DirError::NoSpace)?;
+    let block = crate::fs::alloc_block(inner).map_err(|e| {
+        eprintln!("No se pudo reservar el bloque del directorio {:?}: {e:?}", name);
+        DirError::NoSpace
+    })?;
+
+    // Crear inodo directorio, en disco y en memoria
+    let mut inode = crate::fs::Inode::dir(new_ino);
+    inode.perm = perm;
+    inode.generation = generation;
+    if let Err(e) = crate::fs::create_dir_disk(inner, new_ino, parent, name, &inode, block) {
+        eprintln!("Error al crear el directorio {:?} (inodo {}) en disco: {e:?}", name, new_ino);
+    }
     inner.inodes.insert(new_ino, inode);
 
     // Crear nodo de directorio vacío
-    let new_dir = crate::fs::DirNode {
+    let new_dir = crate::fs::Directory {
         entries: Default::default(),
         parent,
     };
     inner.directories.insert(new_ino, new_dir);
 
-
     // 3) Agregar entrada al padre
     {
         let parent_dir = inner
             .directories
             .get_mut(&parent)
             .ok_or(DirError::NotDirectory)?;
-        parent_dir.entries.insert(name_str, new_ino);
+        parent_dir.entries.insert(name.to_os_string(), new_ino);
     }
 
     // 4) Devolver FileAttr
@@ -142,20 +347,13 @@ pub fn remove_directory(
     parent: u64,
     name: &OsStr,
 ) -> Result<(), DirError> {
-    let name_str = name.to_string_lossy().to_string();
-
-    // 1) Obtener el ino del hijo SIN dejar vivo un &mut
-    let child_ino = {
-        let parent_dir = inner
-            .directories
-            .get(&parent)
-            .ok_or(DirError::NotDirectory)?;
+    if !inner.directories.contains_key(&parent) {
+        return Err(DirError::NotDirectory);
+    }
 
-        match parent_dir.entries.get(&name_str) {
-            Some(ino) => *ino,
-            None => return Err(DirError::NotFound),
-        }
-    };
+    // 1) Obtener el ino del hijo (y el nombre tal como está guardado)
+    let (stored_name, child_ino) =
+        find_entry(inner, parent, name).ok_or(DirError::NotFound)?;
 
     // 2) Verificar que sea directorio
     if !is_directory(inner, child_ino) {
@@ -178,10 +376,18 @@ pub fn remove_directory(
             .directories
             .get_mut(&parent)
             .ok_or(DirError::NotDirectory)?;
-        parent_dir.entries.remove(&name_str);
+        parent_dir.entries.remove(&stored_name);
+    }
+
+    // 4-bis) Marcar la entrada como lápida en el bloque del padre
+    if let Err(e) = crate::fs::remove_dir_entry_disk(&inner.qr_folder, &inner.superblock, parent, &stored_name) {
+        eprintln!("Error al borrar la entrada {:?} del directorio {} en disco: {e:?}", stored_name, parent);
     }
 
-    // 5) Eliminar estructuras del hijo
+    // 5) Liberar el inodo y el bloque del hijo y eliminar sus estructuras
+    if let Err(e) = crate::fs::free_dir_disk(inner, child_ino) {
+        eprintln!("Error al liberar el directorio {} en disco: {e:?}", child_ino);
+    }
     inner.directories.remove(&child_ino);
     inner.inodes.remove(&child_ino);
 
@@ -189,6 +395,87 @@ pub fn remove_directory(
 }
 
 
+/// Quita un archivo de su directorio. El inodo no se pone en cero: queda como
+/// borrado (ver `fs::soft_delete_inode`) y se puede recuperar con `undelete`
+/// hasta que sus bloques se reutilicen.
+pub fn unlink_entry(
+    inner: &mut QrfsInner,
+    parent: u64,
+    name: &OsStr,
+) -> Result<(), DirError> {
+    if !inner.directories.contains_key(&parent) {
+        return Err(DirError::NotDirectory);
+    }
+
+    // 1) Obtener el ino del hijo (y el nombre tal como está guardado)
+    let (stored_name, child_ino) =
+        find_entry(inner, parent, name).ok_or(DirError::NotFound)?;
+
+    // 2) Los directorios se borran con rmdir
+    if is_directory(inner, child_ino) {
+        return Err(DirError::IsDirectory);
+    }
+
+    // 3) Eliminar del padre (memoria y disco)
+    if let Some(parent_dir) = inner.directories.get_mut(&parent) {
+        parent_dir.entries.remove(&stored_name);
+    }
+
+    if let Err(e) = crate::fs::remove_dir_entry_disk(&inner.qr_folder, &inner.superblock, parent, &stored_name) {
+        eprintln!("Error al borrar la entrada {:?} del directorio {} en disco: {e:?}", stored_name, parent);
+    }
+
+    // 4) Un enlace menos; con el último, borrado suave del inodo
+    release_file(inner, child_ino);
+
+    Ok(())
+}
+
+/// Suelta el enlace de un archivo que perdió una entrada (unlink o el destino
+/// de un rename). Si falla en disco, igual deja de estar en memoria.
+fn release_file(inner: &mut QrfsInner, ino: u64) {
+    if let Err(e) = crate::fs::drop_link_disk(inner, ino) {
+        eprintln!("Error al quitar un enlace del inodo {}: {e:?}", ino);
+        inner.inodes.remove(&ino);
+        inner.files.remove(&ino);
+    }
+}
+
+/// Reglas de POSIX para que `child_ino` reemplace a `target_ino` en un rename:
+/// un directorio sólo reemplaza a un directorio vacío y un archivo sólo a otro
+/// archivo.
+fn check_replace(inner: &QrfsInner, child_ino: u64, target_ino: u64) -> Result<(), DirError> {
+    match (is_directory(inner, child_ino), is_directory(inner, target_ino)) {
+        (true, true) => {
+            let empty = inner.directories.get(&target_ino).is_none_or(|d| d.entries.is_empty());
+            if empty {
+                Ok(())
+            } else {
+                Err(DirError::NotEmpty)
+            }
+        }
+        (true, false) => Err(DirError::NotDirectory),
+        (false, true) => Err(DirError::IsDirectory),
+        (false, false) => Ok(()),
+    }
+}
+
+/// Libera lo que un rename reemplazó, con la misma contabilidad que `unlink`
+/// (o `rmdir`, si era un directorio vacío).
+fn release_replaced(inner: &mut QrfsInner, target_ino: u64) {
+    if !is_directory(inner, target_ino) {
+        release_file(inner, target_ino);
+        return;
+    }
+
+    if let Err(e) = crate::fs::free_dir_disk(inner, target_ino) {
+        eprintln!("Error al liberar el directorio {} en disco: {e:?}", target_ino);
+    }
+    inner.directories.remove(&target_ino);
+    inner.inodes.remove(&target_ino);
+}
+
+
 pub fn rename_entry(
     inner: &mut QrfsInner,
     parent: u64,
@@ -199,45 +486,361 @@ pub fn rename_entry(
     if !is_directory(inner, parent) || !is_directory(inner, newparent) {
         return Err(DirError::NotDirectory);
     }
+    check_name(newname)?;
+
+    // 1) Buscar el inodo del hijo (y el nombre tal como está guardado) y la
+    //    entrada que ya ocupe el nombre destino, si hay una
+    let (stored_name, child_ino) =
+        find_entry(inner, parent, name).ok_or(DirError::NotFound)?;
+    let existing = find_entry(inner, newparent, newname);
+
+    // Un directorio no puede ir a parar dentro de sí mismo (`mv a a/b`): el
+    // árbol quedaría con un ciclo desconectado de la raíz
+    if is_directory(inner, child_ino) && is_ancestor_or_self(inner, child_ino, newparent) {
+        return Err(DirError::InvalidArgument);
+    }
 
-    let name_str = name.to_string_lossy().to_string();
-    let newname_str = newname.to_string_lossy().to_string();
+    // Destino que ya es el mismo inodo (otro enlace): no hay nada que hacer.
+    // Con --case-insensitive, "foo" -> "FOO" se resuelve en rename_in_place.
+    if parent != newparent && matches!(existing, Some((_, ino)) if ino == child_ino) {
+        return Ok(());
+    }
+    if let Some((_, target_ino)) = &existing {
+        if *target_ino != child_ino {
+            check_replace(inner, child_ino, *target_ino)?;
+        }
+    }
 
-    // 1) Buscar el inodo del hijo sin mantener vivos dos &mut
-    let child_ino = {
-        let parent_dir = inner
-            .directories
-            .get(&parent)
-            .ok_or(DirError::NotDirectory)?;
+    if parent == newparent {
+        return rename_in_place(inner, parent, &stored_name, newname, child_ino, existing);
+    }
 
-        match parent_dir.entries.get(&name_str) {
-            Some(ino) => *ino,
-            None => return Err(DirError::NotFound),
+    // 2) Primero la entrada nueva en el destino (en disco), después se saca del
+    //    origen: si algo falla a mitad de camino queda, a lo sumo, con dos
+    //    nombres, pero nunca sin ninguno. Si el destino existía se reemplaza
+    //    (se borra y su slot queda libre para la entrada nueva).
+    if let Some((old_name, _)) = &existing {
+        if let Err(e) = crate::fs::remove_dir_entry_disk(&inner.qr_folder, &inner.superblock, newparent, old_name) {
+            eprintln!("Error al borrar la entrada {:?} del directorio {} en disco: {e:?}", old_name, newparent);
         }
-    };
-
-    // 2) Sacar del padre original
-    {
-        let parent_dir = inner
-            .directories
-            .get_mut(&parent)
-            .ok_or(DirError::NotDirectory)?;
-        parent_dir.entries.remove(&name_str);
+    }
+    let added = crate::fs::ensure_dir_block(inner, newparent).and_then(|()| {
+        crate::fs::add_dir_entry_disk(&inner.qr_folder, &inner.superblock, newparent, newname, child_ino)
+    });
+    if let Err(e) = added {
+        eprintln!("Error al agregar la entrada {:?} al directorio {} en disco: {e:?}", newname, newparent);
+        return Err(DirError::NoSpace);
     }
 
-    // 3) Insertar en el nuevo padre
+    // 3) Memoria: insertar en el nuevo padre y recién entonces sacar del original
     {
         let newparent_dir = inner
             .directories
             .get_mut(&newparent)
             .ok_or(DirError::NotDirectory)?;
-        newparent_dir.entries.insert(newname_str, child_ino);
+        if let Some((old_name, _)) = &existing {
+            newparent_dir.entries.remove(old_name);
+        }
+        newparent_dir.entries.insert(newname.to_os_string(), child_ino);
+    }
+    if let Some(parent_dir) = inner.directories.get_mut(&parent) {
+        parent_dir.entries.remove(&stored_name);
     }
 
-    // 4) Si es directorio, actualizar su campo parent
+    // 3-bis) Lápida en el origen
+    if let Err(e) = crate::fs::remove_dir_entry_disk(&inner.qr_folder, &inner.superblock, parent, &stored_name) {
+        eprintln!("Error al borrar la entrada {:?} del directorio {} en disco: {e:?}", stored_name, parent);
+    }
+
+    // 4) Si es directorio, actualizar su ".." en disco y su campo parent
+    if inner.directories.contains_key(&child_ino) {
+        if let Err(e) = crate::fs::set_dotdot_disk(inner, child_ino, newparent) {
+            eprintln!("Error al actualizar \"..\" del directorio {} en disco: {e:?}", child_ino);
+        }
+    }
     if let Some(child_dir) = inner.directories.get_mut(&child_ino) {
         child_dir.parent = newparent;
     }
 
+    // 5) Lo que ocupaba el nombre destino pierde ese enlace
+    if let Some((_, target_ino)) = existing {
+        release_replaced(inner, target_ino);
+    }
+
     Ok(())
 }
+
+/// Rename dentro del mismo directorio: sólo cambia la clave de la entrada, en
+/// memoria y en su mismo slot en disco. Renombrar al mismo nombre no hace nada.
+fn rename_in_place(
+    inner: &mut QrfsInner,
+    dir_ino: u64,
+    name: &OsStr,
+    newname: &OsStr,
+    child_ino: u64,
+    existing: Option<(OsString, u64)>,
+) -> Result<(), DirError> {
+    if name == newname {
+        return Ok(());
+    }
+
+    // Sin distinguir mayúsculas, "foo" -> "FOO" encuentra la propia entrada
+    // como destino: es un cambio de mayúsculas, no hay nada que reemplazar
+    let replaced = existing.filter(|(n, _)| n != name);
+
+    // Otro enlace al mismo inodo en el mismo directorio: POSIX no hace nada
+    if matches!(replaced, Some((_, ino)) if ino == child_ino) {
+        return Ok(());
+    }
+
+    if let Err(e) = crate::fs::rename_dir_entry_disk(
+        &inner.qr_folder,
+        &inner.superblock,
+        dir_ino,
+        name,
+        newname,
+        replaced.as_ref().map(|(n, _)| n.as_os_str()),
+    ) {
+        eprintln!(
+            "Error al renombrar {:?} a {:?} en el directorio {} en disco: {e:?}",
+            name, newname, dir_ino
+        );
+        return Err(DirError::Io);
+    }
+
+    let dir = inner
+        .directories
+        .get_mut(&dir_ino)
+        .ok_or(DirError::NotDirectory)?;
+    if let Some((old, _)) = &replaced {
+        dir.entries.remove(old);
+    }
+    dir.entries.remove(name);
+    dir.entries.insert(newname.to_os_string(), child_ino);
+
+    // Lo que ocupaba el nombre destino pierde ese enlace
+    if let Some((_, target_ino)) = replaced {
+        release_replaced(inner, target_ino);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::QrfsFilesystem;
+    use crate::image::QrfsImage;
+    use crate::inode_table::InodeTable;
+    use crate::test_util::{fresh_image, pattern};
+    use std::path::Path;
+
+    #[test]
+    fn case_insensitive_lookup_and_create() {
+        let dir = fresh_image(64);
+        let fs = QrfsFilesystem::mount_from_folder(dir.path(), None, None).unwrap().case_insensitive(true);
+        {
+            let mut inner = fs.inner.write().unwrap();
+            let root = inner.root_ino;
+            let ino = create_directory(&mut inner, root, OsStr::new("Foo"), 0o755).unwrap().ino;
+
+            assert_eq!(find_entry(&inner, root, OsStr::new("foo")), Some(("Foo".into(), ino)));
+            assert_eq!(find_entry(&inner, root, OsStr::new("FOO")), Some(("Foo".into(), ino)));
+            assert!(matches!(
+                create_directory(&mut inner, root, OsStr::new("foo"), 0o755),
+                Err(DirError::AlreadyExists)
+            ));
+        }
+        drop(fs);
+
+        // En disco queda el nombre como se creó, y sin la opción "foo" es otro nombre
+        let fs = QrfsFilesystem::mount_from_folder(dir.path(), None, None).unwrap();
+        let mut inner = fs.inner.write().unwrap();
+        let root = inner.root_ino;
+        assert!(find_entry(&inner, root, OsStr::new("Foo")).is_some());
+        assert!(find_entry(&inner, root, OsStr::new("foo")).is_none());
+        create_directory(&mut inner, root, OsStr::new("foo"), 0o755).unwrap();
+    }
+
+    #[test]
+    fn list_directory_types_right_after_mount() {
+        let dir = fresh_image(64);
+        let (sub, file) = {
+            let image = QrfsImage::open(dir.path()).unwrap();
+            let sub = image.mkdir(Path::new("/sub")).unwrap();
+            (sub, image.create_file(Path::new("/f")).unwrap())
+        };
+
+        let fs = QrfsFilesystem::mount_from_folder(dir.path(), None, None).unwrap();
+        let mut inner = fs.inner.write().unwrap();
+        let root = inner.root_ino;
+        let kind_of = |inner: &QrfsInner, ino: u64| {
+            list_directory(inner, root).unwrap().into_iter().find(|e| e.ino == ino).unwrap().file_type
+        };
+        assert_eq!(kind_of(&inner, sub), FileType::Directory);
+        assert_eq!(kind_of(&inner, file), FileType::RegularFile);
+
+        // Sin el inodo en memoria (lo soltó un forget) el tipo sale de disco
+        inner.inodes.remove(&file);
+        assert_eq!(kind_of(&inner, file), FileType::RegularFile);
+    }
+
+    #[test]
+    fn list_directory_is_sorted_and_stable_across_mounts() {
+        let dir = fresh_image(64);
+        {
+            let image = QrfsImage::open(dir.path()).unwrap();
+            for name in ["zeta", "alfa", "Beta", "medio", "b", "a1", "a10", "a2"] {
+                image.create_file(&Path::new("/").join(name)).unwrap();
+            }
+        }
+
+        let listing = || {
+            let fs = QrfsFilesystem::mount_from_folder(dir.path(), None, None).unwrap();
+            let inner = fs.inner.read().unwrap();
+            list_directory(&inner, inner.root_ino)
+                .unwrap()
+                .into_iter()
+                .map(|e| e.name.into_string().unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        let first = listing();
+        let mut sorted = first.clone();
+        sorted.sort();
+        assert_eq!(first, sorted);
+        assert_eq!(first.len(), 8);
+        assert_eq!(listing(), first);
+    }
+
+    #[test]
+    fn rename_onto_one_of_two_hard_links() {
+        let dir = fresh_image(64);
+        let data = pattern(3000);
+        let (sub, linked) = {
+            let image = QrfsImage::open(dir.path()).unwrap();
+            let sub = image.mkdir(Path::new("/d")).unwrap();
+            let linked = image.create_file(Path::new("/a")).unwrap();
+            image.write_file(Path::new("/a"), &data).unwrap();
+            for name in ["nuevo", "ultimo"] {
+                let path = Path::new("/").join(name);
+                image.create_file(&path).unwrap();
+                image.write_file(&path, name.as_bytes()).unwrap();
+            }
+            (sub, linked)
+        };
+
+        // No hay operación link: el segundo enlace se arma a mano en disco
+        let sb = crate::fs::load_superblock(dir.path()).unwrap();
+        crate::fs::add_dir_entry_disk(dir.path(), &sb, sub, OsStr::new("otro"), linked).unwrap();
+        let table = InodeTable::open(dir.path(), &sb).unwrap();
+        let mut inode = table.get(linked).unwrap();
+        inode.nlink = 2;
+        table.put(linked, &inode).unwrap();
+
+        // Reemplazar uno de los enlaces deja los datos en el otro
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.rename(Path::new("/nuevo"), Path::new("/a")).unwrap();
+        assert_eq!(image.read_file(Path::new("/a")).unwrap(), b"nuevo");
+        assert_eq!(image.read_file(Path::new("/d/otro")).unwrap(), data);
+        assert_eq!(image.stat(Path::new("/d/otro")).unwrap().nlink, 1);
+
+        // Con el último enlace el inodo y sus bloques se liberan
+        let free_before = crate::fs::load_superblock(dir.path()).unwrap().free_blocks;
+        image.rename(Path::new("/ultimo"), Path::new("/d/otro")).unwrap();
+        assert_eq!(image.read_file(Path::new("/d/otro")).unwrap(), b"ultimo");
+        drop(image);
+        let free_after = crate::fs::load_superblock(dir.path()).unwrap().free_blocks;
+        assert_eq!(free_after, free_before + 3);
+        assert!(crate::check(dir.path()).errors.is_empty());
+    }
+
+    #[test]
+    fn moving_a_directory_into_its_own_subtree_is_einval() {
+        let dir = fresh_image(64);
+        let (a, b) = {
+            let image = QrfsImage::open(dir.path()).unwrap();
+            let a = image.mkdir(Path::new("/a")).unwrap();
+            let b = image.mkdir(Path::new("/a/b")).unwrap();
+            image.mkdir(Path::new("/otro")).unwrap();
+            (a, b)
+        };
+
+        let fs = QrfsFilesystem::mount_from_folder(dir.path(), None, None).unwrap();
+        let mut inner = fs.inner.write().unwrap();
+        let root = inner.root_ino;
+        for newparent in [a, b] {
+            let err = rename_entry(&mut inner, root, OsStr::new("a"), newparent, OsStr::new("a")).unwrap_err();
+            assert!(matches!(err, DirError::InvalidArgument));
+            assert_eq!(err.as_errno(), libc::EINVAL);
+        }
+        assert_eq!(find_entry(&inner, root, OsStr::new("a")), Some(("a".into(), a)));
+
+        // A un directorio que no cuelga de él sí se puede mover
+        let (_, other) = find_entry(&inner, root, OsStr::new("otro")).unwrap();
+        rename_entry(&mut inner, root, OsStr::new("a"), other, OsStr::new("a")).unwrap();
+        drop(inner);
+        drop(fs);
+        assert!(crate::check(dir.path()).errors.is_empty());
+    }
+
+    #[test]
+    fn open_listing_is_not_affected_by_later_changes() {
+        let dir = fresh_image(64);
+        let fs = QrfsFilesystem::mount_from_folder(dir.path(), None, None).unwrap();
+        let mut inner = fs.inner.write().unwrap();
+        let root = inner.root_ino;
+        for name in ["a", "b", "c"] {
+            create_directory(&mut inner, root, OsStr::new(name), 0o755).unwrap();
+        }
+        let names = |entries: &[DirEntry]| {
+            entries.iter().map(|e| e.name.to_string_lossy().into_owned()).collect::<Vec<_>>()
+        };
+
+        let fh = inner.dir_streams.lock().unwrap().insert(snapshot_directory(&inner, root).unwrap());
+        let listed = names(inner.dir_streams.lock().unwrap().get(fh).unwrap());
+        assert_eq!(listed, [".", "..", "a", "b", "c"]);
+
+        // A mitad del listado se borra una entrada y se agrega otra
+        remove_directory(&mut inner, root, OsStr::new("a")).unwrap();
+        create_directory(&mut inner, root, OsStr::new("d"), 0o755).unwrap();
+        assert_eq!(names(inner.dir_streams.lock().unwrap().get(fh).unwrap()), listed);
+        assert_eq!(names(&snapshot_directory(&inner, root).unwrap()), [".", "..", "b", "c", "d"]);
+
+        inner.dir_streams.lock().unwrap().remove(fh);
+        assert!(inner.dir_streams.lock().unwrap().get(fh).is_none());
+    }
+
+    #[test]
+    fn listing_types_come_from_the_inode_table() {
+        let dir = fresh_image(64);
+        {
+            let image = QrfsImage::open(dir.path()).unwrap();
+            image.mkdir(Path::new("/dir")).unwrap();
+            image.create_file(Path::new("/archivo")).unwrap();
+            image.mkdir(Path::new("/otro")).unwrap();
+        }
+
+        let fs = QrfsFilesystem::mount_from_folder(dir.path(), None, None).unwrap();
+        let mut inner = fs.inner.write().unwrap();
+        let root = inner.root_ino;
+        let kinds = |inner: &QrfsInner| {
+            list_directory(inner, root)
+                .unwrap()
+                .into_iter()
+                .map(|e| (e.name.to_string_lossy().into_owned(), e.file_type))
+                .collect::<Vec<_>>()
+        };
+        let expected = [
+            ("archivo".to_string(), FileType::RegularFile),
+            ("dir".to_string(), FileType::Directory),
+            ("otro".to_string(), FileType::Directory),
+        ];
+        assert_eq!(kinds(&inner), expected);
+
+        // Sin el inodo en memoria (después de un forget) el tipo sale de la tabla
+        let (_, ino) = find_entry(&inner, root, OsStr::new("dir")).unwrap();
+        inner.inodes.remove(&ino);
+        assert_eq!(kinds(&inner), expected);
+    }
+}
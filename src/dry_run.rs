@@ -0,0 +1,214 @@
+// Modo de prueba de las reparaciones (`fsck.qrfs --dry-run`).
+//
+// En vez de un camino aparte para cada reparación, `dry_run` corre la de
+// verdad con las escrituras de bloques diferidas (igual que un lote de
+// `QrfsImage::batch`), y al final las descarta: nada llega a los archivos de
+// bloque. Lo que quedó diferido es el diff de la reparación, y se traduce a lo
+// que significa en la imagen: campos del superblock, bits del bitmap, campos de
+// cada inodo de la tabla y, para el resto, qué bloques cambian.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::block_store::{self, BlockStore};
+use crate::fs::{bitmap_test, InodeDisk, SuperblockDisk, QRFS_BLOCK_SIZE};
+
+/// Un cambio que la reparación habría escrito.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlannedChange {
+    /// Un campo del superblock (el checksum no se lista: cambia con cualquiera).
+    Superblock { field: &'static str, old: String, new: String },
+    /// El bit de `block` en el bitmap pasa a usado (`true`) o libre (`false`).
+    Bitmap { block: u32, used: bool },
+    /// Un campo del inodo `ino` en la tabla.
+    Inode { ino: u64, field: &'static str, old: String, new: String },
+    /// Otro bloque (datos, directorio, punteros): cuántos bytes cambian.
+    Block { block: u32, bytes: usize },
+}
+
+impl fmt::Display for PlannedChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlannedChange::Superblock { field, old, new } => write!(f, "superblock.{}: {} -> {}", field, old, new),
+            PlannedChange::Bitmap { block, used: true } => write!(f, "bitmap: bloque {} libre -> usado", block),
+            PlannedChange::Bitmap { block, used: false } => write!(f, "bitmap: bloque {} usado -> libre", block),
+            PlannedChange::Inode { ino, field, old, new } => write!(f, "inodo {}.{}: {} -> {}", ino, field, old, new),
+            PlannedChange::Block { block, bytes } => write!(f, "bloque {}: cambian {} bytes", block, bytes),
+        }
+    }
+}
+
+/// Corre `f` (una reparación sobre `qr_folder`) sin escribir nada y devuelve
+/// su resultado junto con los cambios que habría hecho, en orden de bloque.
+///
+/// Sólo se retienen las escrituras de bloques que hace este proceso a través
+/// de la misma ruta `qr_folder`; `f` no debe crear ni borrar archivos por su
+/// cuenta. Si `f` falla o entra en pánico tampoco se escribe nada.
+pub fn dry_run<T>(qr_folder: &Path, f: impl FnOnce() -> Result<T>) -> Result<(T, Vec<PlannedChange>)> {
+    block_store::begin_deferred(qr_folder)?;
+
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    let written = block_store::discard_deferred(qr_folder);
+    let value = match result {
+        Ok(value) => value?,
+        Err(payload) => panic::resume_unwind(payload),
+    };
+
+    Ok((value, planned_changes(qr_folder, &written)?))
+}
+
+/// Traduce los bloques que se habrían escrito a cambios de la imagen,
+/// comparándolos con lo que hay en disco.
+fn planned_changes(qr_folder: &Path, written: &BTreeMap<u32, Vec<u8>>) -> Result<Vec<PlannedChange>> {
+    let store = BlockStore::open(qr_folder)?;
+    let mut changes = Vec::new();
+    if written.is_empty() {
+        return Ok(changes);
+    }
+
+    // Sin un superblock legible no se sabe qué es cada bloque: sólo bytes
+    let sb = store
+        .read_block(0)
+        .ok()
+        .and_then(|buf| SuperblockDisk::from_block_bytes(&buf).ok())
+        .filter(|sb| sb.check_magic().is_ok() && sb.check_layout().is_ok());
+
+    let Some(sb) = sb else {
+        for (&block, new) in written {
+            changes.extend(block_change(&store, block, new));
+        }
+        return Ok(changes);
+    };
+
+    let bitmap = sb.free_bitmap_start..sb.free_bitmap_start + sb.free_bitmap_blocks;
+    let table = sb.inode_table_start..sb.inode_table_start + sb.inode_table_blocks;
+
+    if let Some(new) = written.get(&0) {
+        let new_sb = SuperblockDisk::from_block_bytes(new)?;
+        changes.extend(superblock_changes(&sb, &new_sb));
+    }
+
+    if written.keys().any(|b| bitmap.contains(b)) {
+        let (old, new) = region(&store, written, bitmap.start, bitmap.len() as u32)?;
+        for block in 0..sb.total_blocks {
+            let used = bitmap_test(&new, block);
+            if bitmap_test(&old, block) != used {
+                changes.push(PlannedChange::Bitmap { block, used });
+            }
+        }
+    }
+
+    if written.keys().any(|b| table.contains(b)) {
+        let (old, new) = region(&store, written, table.start, table.len() as u32)?;
+        let inode_size = mem::size_of::<InodeDisk>();
+        let inodes = (sb.max_inodes as u64).min(sb.inode_table_capacity());
+        for ino in 1..=inodes {
+            let offset = (ino as usize - 1) * inode_size;
+            let (old, new) = (&old[offset..offset + inode_size], &new[offset..offset + inode_size]);
+            if old != new {
+                changes.extend(inode_changes(ino, &InodeDisk::from_bytes(old)?, &InodeDisk::from_bytes(new)?));
+            }
+        }
+    }
+
+    for (&block, new) in written {
+        if block != 0 && !bitmap.contains(&block) && !table.contains(&block) {
+            changes.extend(block_change(&store, block, new));
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Bytes de `count` bloques desde `start`, como están en disco y como
+/// quedarían con los bloques de `written`.
+fn region(store: &BlockStore, written: &BTreeMap<u32, Vec<u8>>, start: u32, count: u32) -> Result<(Vec<u8>, Vec<u8>)> {
+    let old = store.read_blocks(start, count)?;
+    let mut new = old.clone();
+    let block_size = QRFS_BLOCK_SIZE as usize;
+    for (&block, data) in written.range(start..start + count) {
+        let offset = (block - start) as usize * block_size;
+        new[offset..offset + block_size].copy_from_slice(&data[..block_size]);
+    }
+    Ok((old, new))
+}
+
+fn block_change(store: &BlockStore, block: u32, new: &[u8]) -> Option<PlannedChange> {
+    // Un bloque que no se puede leer cambia entero
+    let bytes = match store.read_block(block) {
+        Ok(old) => old.iter().zip(new).filter(|(a, b)| a != b).count(),
+        Err(_) => new.len(),
+    };
+    (bytes > 0).then_some(PlannedChange::Block { block, bytes })
+}
+
+/// Campos que difieren entre dos valores del mismo struct, como
+/// `(nombre, viejo, nuevo)`.
+macro_rules! field_diff {
+    ($old:expr, $new:expr, $($field:ident),+ $(,)?) => {{
+        let mut diff = Vec::new();
+        $(
+            let (old, new) = (format!("{:?}", $old.$field), format!("{:?}", $new.$field));
+            if old != new {
+                diff.push((stringify!($field), old, new));
+            }
+        )+
+        diff
+    }};
+}
+
+fn superblock_changes(old: &SuperblockDisk, new: &SuperblockDisk) -> Vec<PlannedChange> {
+    let diff = field_diff!(
+        old,
+        new,
+        magic,
+        version,
+        block_size,
+        total_blocks,
+        inode_table_start,
+        inode_table_blocks,
+        free_bitmap_start,
+        free_bitmap_blocks,
+        data_blocks_start,
+        max_inodes,
+        root_inode,
+        free_blocks,
+        free_inodes,
+        features,
+        bad_blocks,
+        parity_group,
+        parity_per_group,
+    );
+    diff.into_iter()
+        .map(|(field, old, new)| PlannedChange::Superblock { field, old, new })
+        .collect()
+}
+
+fn inode_changes(ino: u64, old: &InodeDisk, new: &InodeDisk) -> Vec<PlannedChange> {
+    let diff = field_diff!(
+        old,
+        new,
+        id,
+        file_type,
+        perm,
+        uid,
+        gid,
+        size,
+        atime,
+        mtime,
+        ctime,
+        nlink,
+        direct_blocks,
+        indirect_block,
+        double_indirect_block,
+        generation,
+    );
+    diff.into_iter()
+        .map(|(field, old, new)| PlannedChange::Inode { ino, field, old, new })
+        .collect()
+}
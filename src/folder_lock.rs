@@ -0,0 +1,88 @@
+// Lock de la carpeta de QRs contra dos escritores a la vez.
+//
+// Dos montajes de la misma carpeta, o un `fsck.qrfs --repair` sobre una imagen
+// montada, reescriben el bitmap, la tabla de inodos y el superblock cada uno
+// con lo que tiene en memoria: la imagen termina mezclada. Por eso el montaje
+// (`QrfsFilesystem::mount_from_folder`, también a través de `QrfsImage::open`)
+// y los modos de fsck que escriben toman un `flock` exclusivo sobre el archivo
+// `.qrfs.lock` de la carpeta, y no siguen si otro proceso ya lo tiene.
+//
+// El lock es del proceso: dentro de uno mismo se comparte (dos `QrfsImage` de
+// la misma carpeta, o la imagen que recarga un lote que falló) y se suelta al
+// caer el último `FolderLock`, o cuando el proceso termina aunque sea por un
+// pánico o una señal. El archivo queda en la carpeta; no es un bloque y el
+// listado de bloques lo ignora.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::ErrorKind;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, Weak};
+
+use anyhow::{Context, Result};
+
+use crate::zip_store::is_zip_image;
+
+/// Nombre del archivo de lock dentro de la carpeta de QRs.
+pub const QRFS_LOCK_NAME: &str = ".qrfs.lock";
+
+/// Lock tomado sobre una carpeta; se suelta cuando cae la última copia del
+/// proceso.
+#[derive(Clone)]
+pub struct FolderLock {
+    _file: Arc<File>,
+}
+
+/// Locks que tiene este proceso, por carpeta (canonicalizada).
+static HELD: OnceLock<Mutex<HashMap<PathBuf, Weak<File>>>> = OnceLock::new();
+
+fn held_locks() -> MutexGuard<'static, HashMap<PathBuf, Weak<File>>> {
+    HELD.get_or_init(Default::default).lock().unwrap()
+}
+
+/// Toma el lock de `qr_folder`, o falla si lo tiene otro proceso. Si este
+/// proceso ya lo tiene, devuelve otra referencia al mismo.
+///
+/// Una imagen en un zip (que nunca se escribe) no tiene lock: `Ok(None)`. Lo
+/// mismo una carpeta donde no se puede crear el archivo (un medio de sólo
+/// lectura), con un aviso.
+pub fn lock_folder(qr_folder: &Path) -> Result<Option<FolderLock>> {
+    if is_zip_image(qr_folder) {
+        return Ok(None);
+    }
+
+    let folder = fs::canonicalize(qr_folder)
+        .with_context(|| format!("No se pudo resolver la carpeta {:?}", qr_folder))?;
+
+    let mut held = held_locks();
+    if let Some(file) = held.get(&folder).and_then(Weak::upgrade) {
+        return Ok(Some(FolderLock { _file: file }));
+    }
+
+    let path = folder.join(QRFS_LOCK_NAME);
+    let file = match OpenOptions::new().create(true).truncate(false).write(true).open(&path) {
+        Ok(file) => file,
+        Err(e) if matches!(e.kind(), ErrorKind::PermissionDenied | ErrorKind::ReadOnlyFilesystem) => {
+            eprintln!("Advertencia: no se pudo crear {:?} ({}); se sigue sin lock", path, e);
+            return Ok(None);
+        }
+        Err(e) => return Err(e).with_context(|| format!("No se pudo abrir el lock {:?}", path)),
+    };
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        let err = std::io::Error::last_os_error();
+        if err.kind() == ErrorKind::WouldBlock {
+            return Err(anyhow::anyhow!(
+                "La carpeta {:?} está en uso por otro proceso (montada o con un fsck que escribe): {:?} está tomado",
+                qr_folder,
+                path
+            ));
+        }
+        return Err(err).with_context(|| format!("No se pudo tomar el lock {:?}", path));
+    }
+
+    let file = Arc::new(file);
+    held.insert(folder, Arc::downgrade(&file));
+    Ok(Some(FolderLock { _file: file }))
+}
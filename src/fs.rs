@@ -1,14 +1,27 @@
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, RwLock};
-use std::ffi::OsStr;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::sync::{Arc, Mutex, RwLock};
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::OsStrExt;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::fs;
-use std::fs::File;
-use std::io::{Read, Write};
 use std::mem;
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use crate::dir; // para usar dir::unpack_dir_entries y dir::DirEntry
+use crate::block_store::{
+    refresh_manifest, superblock_files, BlockStore, QRFS_BLOCK_PREFIX, QRFS_MANIFEST_NAME, QRFS_OVERLAY_MARKER,
+};
+use crate::stats::{self, MountStats, QrfsStats, QRFS_STATS_INO, QRFS_STATS_NAME};
+use crate::control::{self, QRFS_CONTROL_INO, QRFS_CONTROL_NAME};
+use crate::meta::{self, MetaFile, QRFS_META_INO, QRFS_META_NAME};
+use crate::config::MountConfig;
+use crate::inode_table::InodeTable;
+use crate::compress;
+use crate::readahead::{ReadAhead, QRFS_READ_AHEAD_DEFAULT};
+use crate::zip_store::is_zip_image;
+use crate::folder_lock::{lock_folder, FolderLock};
+use crate::parity::{self, QRFS_PARITY_MAX_SHARDS};
 
 
 use anyhow::{Result, Context};
@@ -26,14 +39,18 @@ use fuser::{
     ReplyWrite,
     ReplyOpen,
     ReplyStatfs,
+    ReplyLock,
+    ReplyLseek,
+    ReplyPoll,
     Request,
+    TimeOrNow,
 };
 
 use libc::ENOENT;
 
-use crate::dir;
-
-pub const ROOT_INO: u64 = 1;
+/// Número con el que FUSE identifica siempre a la raíz, sea cual sea
+/// `superblock.root_inode` (ver `QrfsInner::fuse_ino`).
+pub const ROOT_INO: u64 = fuser::FUSE_ROOT_ID;
 
 // -----------------------------------------------------------------------------
 // Constantes y estructuras de disco de QRFS
@@ -43,6 +60,70 @@ pub const QRFS_BLOCK_SIZE: u32 = 1024;
 pub const QRFS_MAGIC: u32   = 0x5152_4653; 
 pub const QRFS_VERSION: u32 = 1;
 pub const QRFS_NAME_LEN: usize = 56;
+/// Nombre más largo que se acepta (lo que statfs informa como `namelen`): se deja
+/// lugar para el nulo final del campo `name` de `DirEntryDisk`.
+pub const QRFS_MAX_NAME: usize = QRFS_NAME_LEN - 1;
+
+/// Punteros directos de cada inodo (`InodeDisk::direct_blocks`).
+const DIRECT_POINTERS: u64 = 12;
+
+/// Tamaño máximo (bytes) de un archivo con bloques de `block_size` bytes: lo
+/// que direccionan los 12 punteros directos, los `block_size / 4` del bloque
+/// indirecto y los `(block_size / 4)²` del doble indirecto.
+pub fn max_file_size(block_size: u32) -> u64 {
+    let block_size = block_size as u64;
+    let ptrs = block_size / 4;
+    (DIRECT_POINTERS + ptrs)
+        .saturating_add(ptrs.saturating_mul(ptrs))
+        .saturating_mul(block_size)
+}
+
+/// Bloques que ocupa un archivo de `size` bytes sin huecos: los de datos más
+/// los de punteros (el indirecto y, si hace falta, el doble indirecto con sus
+/// bloques de segundo nivel).
+pub fn file_blocks_for_size(size: u64, block_size: u32) -> u64 {
+    let ptrs = block_size as u64 / 4;
+    let data = size.div_ceil(block_size as u64);
+
+    let mut pointer_blocks = 0;
+    if data > DIRECT_POINTERS {
+        pointer_blocks += 1;
+    }
+    if data > DIRECT_POINTERS + ptrs {
+        pointer_blocks += 1 + (data - DIRECT_POINTERS - ptrs).div_ceil(ptrs);
+    }
+    data + pointer_blocks
+}
+
+/// Hay archivos con los datos comprimidos (`QRFS_INODE_COMPRESSED`). Se marca
+/// al crear el primero, así una versión que no sabe descomprimirlos no monta la
+/// imagen.
+pub const QRFS_FEATURE_COMPRESSION: u32 = 0x0000_0002;
+
+/// El superblock tiene una lista de bloques dañados (`bad_blocks`). Una versión
+/// que no la conoce podría volver a asignarlos, así que no monta la imagen.
+pub const QRFS_FEATURE_BAD_BLOCKS: u32 = 0x0000_0004;
+
+/// La imagen tiene bloques de paridad Reed-Solomon después de `total_blocks`
+/// (ver `parity`). Una versión que no los actualiza al escribir los dejaría
+/// desfasados y una reconstrucción devolvería datos viejos.
+pub const QRFS_FEATURE_PARITY: u32 = 0x0000_0008;
+
+/// El superblock lleva checksum. Lo marca `seal`: sin la marca la imagen es
+/// anterior al checksum y el campo en 0 se acepta; con ella, un checksum en 0
+/// es un superblock dañado.
+pub const QRFS_FEATURE_CHECKSUM: u32 = 0x0000_0010;
+
+/// Extensiones que esta versión sabe leer y escribir.
+pub const QRFS_SUPPORTED_FEATURES: u32 =
+    QRFS_FEATURE_COMPRESSION | QRFS_FEATURE_BAD_BLOCKS | QRFS_FEATURE_PARITY | QRFS_FEATURE_CHECKSUM;
+
+/// Lugares en la lista de bloques dañados del superblock.
+pub const QRFS_MAX_BAD_BLOCKS: usize = 13;
+
+/// Copia del bloque 0 que deja `upgrade_image` en la carpeta de QRs antes de
+/// modificar la imagen.
+pub const QRFS_SUPERBLOCK_BACKUP: &str = "superblock.bak";
 
 // -------------------- Estructuras en disco --------------------
 
@@ -67,7 +148,20 @@ pub struct SuperblockDisk {
     pub free_blocks: u32,
     pub free_inodes: u32,
 
-    pub reserved: [u8; 64],
+    /// CRC32 del superblock (calculado con este campo en 0). 0 = imagen sin
+    /// checksum, sólo si no tiene `QRFS_FEATURE_CHECKSUM`.
+    pub checksum: u32,
+    /// Extensiones de formato que usa la imagen (`QRFS_FEATURE_*`). Ocupa lo que
+    /// antes era reservado, así que las imágenes viejas quedan con 0.
+    pub features: u32,
+    /// Bloques de datos marcados como dañados (p. ej. una hoja que ya no se
+    /// puede escanear): no se asignan y leerlos da EIO. 0 = lugar libre, porque
+    /// el bloque 0 nunca es de datos. Ocupa lo que antes era reservado.
+    pub bad_blocks: [u32; QRFS_MAX_BAD_BLOCKS],
+    /// Paridad `k:m` (`mkfs --parity`): cada grupo de `parity_group` bloques
+    /// consecutivos tiene `parity_per_group` bloques de paridad. 0 = sin paridad.
+    pub parity_group: u16,
+    pub parity_per_group: u16,
 }
 
 #[repr(C)]
@@ -86,7 +180,433 @@ pub struct InodeDisk {
     pub direct_blocks: [u32; 12],
     pub indirect_block: u32,
     pub double_indirect_block: u32,
-    pub _padding: u32,
+    /// Cuántas veces se entregó este slot de la tabla (`alloc_inode`): un
+    /// inodo nuevo en un número reusado tiene otra generación, así un handle
+    /// viejo (p. ej. de NFS) no lo confunde con el archivo anterior. Ocupa lo
+    /// que antes era relleno, así que las imágenes viejas quedan con 0.
+    pub generation: u32,
+}
+
+impl SuperblockDisk {
+    /// Interpreta el comienzo de un bloque como superblock. Sólo falla si el
+    /// buffer es demasiado corto: la validación de los campos es aparte
+    /// (`check_magic`, `verify_checksum`, `check_features`, `check_layout`).
+    pub fn from_block_bytes(buf: &[u8]) -> Result<Self> {
+        if buf.len() < mem::size_of::<SuperblockDisk>() {
+            return Err(anyhow::anyhow!(
+                "Bloque demasiado corto para un superblock ({} bytes, hacen falta {})",
+                buf.len(),
+                mem::size_of::<SuperblockDisk>()
+            ));
+        }
+        Ok(unsafe { (buf.as_ptr() as *const SuperblockDisk).read_unaligned() })
+    }
+
+    /// El superblock tal cual, al comienzo de un bloque completo relleno con
+    /// ceros (lo inverso de `from_block_bytes`). No lo sella: eso lo hace quien
+    /// escribe el bloque 0.
+    pub fn to_block_bytes(&self) -> Vec<u8> {
+        let sb_size = mem::size_of::<SuperblockDisk>();
+        let mut buf = vec![0u8; (QRFS_BLOCK_SIZE as usize).max(sb_size)];
+        unsafe {
+            let src = (self as *const SuperblockDisk) as *const u8;
+            buf[..sb_size].copy_from_slice(std::slice::from_raw_parts(src, sb_size));
+        }
+        buf
+    }
+
+    /// Falla si las regiones del layout no tienen sentido: tamaño de bloque
+    /// distinto del de esta versión, tabla de inodos, bitmap y área de datos
+    /// fuera de orden o más allá de `total_blocks`, un bitmap que no cubre todos
+    /// los bloques, o `max_inodes`/`root_inode` fuera de la tabla. Con eso, el
+    /// resto del código puede usar los campos como índices sin desbordar.
+    pub fn check_layout(&self) -> Result<()> {
+        let problem = if self.block_size != QRFS_BLOCK_SIZE {
+            format!("block_size = {} (se esperaba {})", self.block_size, QRFS_BLOCK_SIZE)
+        } else if self.inode_table_start == 0
+            || self.inode_table_blocks == 0
+            || self.inode_table_start as u64 + self.inode_table_blocks as u64 > self.free_bitmap_start as u64
+        {
+            format!(
+                "tabla de inodos en {}..+{} (el bitmap empieza en {})",
+                self.inode_table_start, self.inode_table_blocks, self.free_bitmap_start
+            )
+        } else if self.free_bitmap_start as u64 + self.free_bitmap_blocks as u64 > self.data_blocks_start as u64 {
+            format!(
+                "bitmap en {}..+{} (los datos empiezan en {})",
+                self.free_bitmap_start, self.free_bitmap_blocks, self.data_blocks_start
+            )
+        } else if self.data_blocks_start > self.total_blocks {
+            format!(
+                "los datos empiezan en el bloque {} de {}",
+                self.data_blocks_start, self.total_blocks
+            )
+        } else if (self.free_bitmap_blocks as u64) * (QRFS_BLOCK_SIZE as u64) * 8 < self.total_blocks as u64 {
+            format!(
+                "el bitmap ({} bloques) no cubre los {} bloques",
+                self.free_bitmap_blocks, self.total_blocks
+            )
+        } else if self.max_inodes == 0 || self.max_inodes as u64 > self.inode_table_capacity() {
+            format!(
+                "max_inodes = {} (la tabla admite como máximo {})",
+                self.max_inodes,
+                self.inode_table_capacity()
+            )
+        } else if self.root_inode == 0 || self.root_inode > self.max_inodes {
+            format!("root_inode = {} (max_inodes = {})", self.root_inode, self.max_inodes)
+        } else if (self.parity_group == 0) != (self.parity_per_group == 0)
+            || self.parity_group as u32 + self.parity_per_group as u32 > QRFS_PARITY_MAX_SHARDS
+        {
+            format!("paridad {}:{}", self.parity_group, self.parity_per_group)
+        } else {
+            return Ok(());
+        };
+
+        Err(anyhow::anyhow!("Layout inválido en el superblock: {}", problem))
+    }
+
+    /// Cantidad máxima de inodos que caben en la tabla de inodos según el layout
+    /// (van contiguos, sin relleno al final de cada bloque).
+    pub fn inode_table_capacity(&self) -> u64 {
+        let table_bytes = self.inode_table_blocks as u64 * QRFS_BLOCK_SIZE as u64;
+        table_bytes / mem::size_of::<InodeDisk>() as u64
+    }
+
+    /// Bloques de paridad que siguen a los `total_blocks` del FS (0 sin paridad).
+    pub fn parity_blocks(&self) -> u32 {
+        if self.parity_group == 0 {
+            return 0;
+        }
+        self.total_blocks.div_ceil(self.parity_group as u32) * self.parity_per_group as u32
+    }
+
+    /// Archivos de bloque de la imagen: los del FS más los de paridad.
+    pub fn image_blocks(&self) -> u32 {
+        self.total_blocks + self.parity_blocks()
+    }
+
+    /// CRC32 de los bytes del superblock, tomando `checksum` como 0.
+    pub fn compute_checksum(&self) -> u32 {
+        let mut copy = *self;
+        copy.checksum = 0;
+
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                (&copy as *const SuperblockDisk) as *const u8,
+                mem::size_of::<SuperblockDisk>(),
+            )
+        };
+        crc32(bytes)
+    }
+
+    /// Marca la imagen con `QRFS_FEATURE_CHECKSUM` y recalcula y guarda el
+    /// checksum; se llama justo antes de escribir el bloque 0.
+    pub fn seal(&mut self) {
+        self.features |= QRFS_FEATURE_CHECKSUM;
+        self.checksum = self.compute_checksum();
+    }
+
+    /// `true` si el checksum coincide. Sólo una imagen anterior al checksum (sin
+    /// `QRFS_FEATURE_CHECKSUM`) puede tenerlo en 0.
+    pub fn verify_checksum(&self) -> bool {
+        if self.checksum == 0 && self.features & QRFS_FEATURE_CHECKSUM == 0 {
+            return true;
+        }
+        self.checksum == self.compute_checksum()
+    }
+
+    /// Falla si el bloque 0 no es un superblock QRFS. Las imágenes son volcados
+    /// crudos en el orden de bytes de la máquina que las escribió: si el magic
+    /// aparece invertido, la imagen viene de una máquina big-endian y leerla tal
+    /// cual daría punteros y tamaños sin sentido, así que se rechaza con un
+    /// mensaje específico en vez del genérico.
+    pub fn check_magic(&self) -> Result<()> {
+        if self.magic == QRFS_MAGIC {
+            return Ok(());
+        }
+
+        if self.magic == QRFS_MAGIC.swap_bytes() {
+            return Err(anyhow::anyhow!(
+                "La imagen parece tener los bytes invertidos (image appears byte-swapped): \
+                 fue escrita en una máquina con otro orden de bytes (magic leído = {:#X}) \
+                 y QRFS todavía no convierte entre formatos",
+                self.magic
+            ));
+        }
+
+        Err(anyhow::anyhow!(
+            "El magic del superblock no coincide (esperado = {:#X}, leído = {:#X})",
+            QRFS_MAGIC,
+            self.magic
+        ))
+    }
+
+    /// Falla si la imagen usa extensiones de formato que esta versión no conoce:
+    /// interpretarla sin ellas corrompería datos.
+    pub fn check_features(&self) -> Result<()> {
+        let unsupported = self.features & !QRFS_SUPPORTED_FEATURES;
+        if unsupported == 0 {
+            return Ok(());
+        }
+
+        Err(anyhow::anyhow!(
+            "La imagen usa extensiones desconocidas (features = {:#010X}) que esta versión de QRFS no soporta",
+            self.features
+        ))
+    }
+
+    /// `true` si `block` está en la lista de bloques dañados.
+    pub fn is_bad_block(&self, block: u32) -> bool {
+        block != 0 && self.bad_blocks.contains(&block)
+    }
+
+    /// Los bloques dañados de la lista, sin los lugares libres.
+    pub fn bad_block_list(&self) -> Vec<u32> {
+        self.bad_blocks.iter().copied().filter(|&b| b != 0).collect()
+    }
+
+    /// Agrega `block` a la lista de bloques dañados y marca la extensión.
+    /// Devuelve `false` si ya estaba. Falla si el bloque no es de datos o si la
+    /// lista está llena.
+    pub fn add_bad_block(&mut self, block: u32) -> Result<bool> {
+        if block < self.data_blocks_start || block >= self.total_blocks {
+            return Err(anyhow::anyhow!(
+                "El bloque {} está fuera del área de datos ({}..{})",
+                block,
+                self.data_blocks_start,
+                self.total_blocks
+            ));
+        }
+        if self.is_bad_block(block) {
+            return Ok(false);
+        }
+
+        let slot = self.bad_blocks.iter_mut().find(|b| **b == 0).ok_or_else(|| {
+            anyhow::anyhow!(
+                "La lista de bloques dañados está llena ({} bloques)",
+                QRFS_MAX_BAD_BLOCKS
+            )
+        })?;
+        *slot = block;
+        self.features |= QRFS_FEATURE_BAD_BLOCKS;
+        Ok(true)
+    }
+}
+
+/// CRC-32 (IEEE 802.3, polinomio reflejado 0xEDB88320).
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Bit de `InodeDisk.file_type` que indica que los datos del archivo están
+/// guardados inline en `direct_blocks` en vez de en un bloque de datos.
+pub const QRFS_INODE_INLINE: u16 = 0x8000;
+
+/// Máximo de bytes que caben inline en un inodo (el espacio de `direct_blocks`).
+pub const QRFS_INLINE_MAX: usize = 12 * 4;
+
+/// Bits bajos de `InodeDisk.file_type` con el tipo (1 = archivo, 2 = directorio);
+/// los bits altos son marcas (inline, borrado, directorio ordenado, comprimido,
+/// tiempos con nanosegundos).
+pub const QRFS_TYPE_MASK: u16 = 0x07FF;
+
+/// Bit de `InodeDisk.file_type` de un directorio cuyas entradas están guardadas
+/// ordenadas por nombre y sin lápidas (feature `sorted-dirs`), así se pueden
+/// buscar con búsqueda binaria y `readdir` sale siempre en el mismo orden.
+pub const QRFS_DIR_SORTED: u16 = 0x2000;
+
+/// Cantidad de entradas a partir de la cual un directorio pasa al formato ordenado
+/// (un bloque de 1024 bytes tiene lugar para 17 entradas).
+pub const QRFS_SORTED_DIR_THRESHOLD: usize = 8;
+
+/// Bit de `InodeDisk.file_type` de un inodo borrado "suavemente" por `unlink`:
+/// queda con `nlink = 0` y sus bloques libres en el bitmap, pero conserva los
+/// punteros para poder recuperarlo mientras nadie reutilice esos bloques.
+pub const QRFS_INODE_DELETED: u16 = 0x4000;
+
+/// Bit de `InodeDisk.file_type` de un archivo cuyos bloques guardan el contenido
+/// comprimido (ver `compress`): cada bloque lleva su encabezado y el archivo se
+/// lee y se reescribe entero.
+pub const QRFS_INODE_COMPRESSED: u16 = 0x1000;
+
+/// Bit de `InodeDisk.file_type` de un inodo cuyos `atime`/`mtime`/`ctime`
+/// guardan también los nanosegundos (feature `nsec-time`): cada campo es
+/// `segundos << 30 | nanos`, lo que alcanza hasta el año 2514. Sin la marca
+/// los campos son segundos, como en las imágenes viejas.
+pub const QRFS_INODE_NSEC_TIME: u16 = 0x0800;
+
+/// Bits de un tiempo con nanosegundos que ocupan los nanos.
+const NSEC_BITS: u32 = 30;
+
+impl InodeDisk {
+    /// Registro de un inodo libre: todos los campos en cero.
+    pub fn empty() -> Self {
+        Self {
+            id: 0,
+            file_type: 0,
+            perm: 0,
+            uid: 0,
+            gid: 0,
+            size: 0,
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+            nlink: 0,
+            direct_blocks: [0u32; 12],
+            indirect_block: 0,
+            double_indirect_block: 0,
+            generation: 0,
+        }
+    }
+
+    /// Interpreta el comienzo de `buf` como un inodo (falla si no alcanza).
+    pub fn from_bytes(buf: &[u8]) -> Result<Self> {
+        if buf.len() < mem::size_of::<InodeDisk>() {
+            return Err(anyhow::anyhow!(
+                "Buffer demasiado corto para un inodo ({} bytes, hacen falta {})",
+                buf.len(),
+                mem::size_of::<InodeDisk>()
+            ));
+        }
+        Ok(unsafe { (buf.as_ptr() as *const InodeDisk).read_unaligned() })
+    }
+
+    pub fn is_inline(&self) -> bool {
+        self.file_type & QRFS_INODE_INLINE != 0
+    }
+
+    pub fn has_nsec_time(&self) -> bool {
+        self.file_type & QRFS_INODE_NSEC_TIME != 0
+    }
+
+    pub fn atime(&self) -> SystemTime {
+        self.decode_time(self.atime)
+    }
+
+    pub fn mtime(&self) -> SystemTime {
+        self.decode_time(self.mtime)
+    }
+
+    pub fn ctime(&self) -> SystemTime {
+        self.decode_time(self.ctime)
+    }
+
+    pub fn set_atime(&mut self, t: SystemTime) {
+        self.atime = self.encode_time(t);
+    }
+
+    pub fn set_mtime(&mut self, t: SystemTime) {
+        self.mtime = self.encode_time(t);
+    }
+
+    pub fn set_ctime(&mut self, t: SystemTime) {
+        self.ctime = self.encode_time(t);
+    }
+
+    /// Los tres tiempos en `t` (un inodo recién creado).
+    pub fn set_times(&mut self, t: SystemTime) {
+        self.set_atime(t);
+        self.set_mtime(t);
+        self.set_ctime(t);
+    }
+
+    fn decode_time(&self, raw: u64) -> SystemTime {
+        if self.has_nsec_time() {
+            UNIX_EPOCH + Duration::new(raw >> NSEC_BITS, (raw & ((1 << NSEC_BITS) - 1)) as u32)
+        } else {
+            UNIX_EPOCH + Duration::from_secs(raw)
+        }
+    }
+
+    /// Valor en disco de `t` según el formato del inodo. Con la feature
+    /// `nsec-time`, un inodo con tiempos en segundos pasa primero al formato con
+    /// nanosegundos (los tres campos juntos, para no mezclar formatos).
+    fn encode_time(&mut self, t: SystemTime) -> u64 {
+        if cfg!(feature = "nsec-time") && !self.has_nsec_time() {
+            for field in [&mut self.atime, &mut self.mtime, &mut self.ctime] {
+                *field = (*field).min(u64::MAX >> NSEC_BITS) << NSEC_BITS;
+            }
+            self.file_type |= QRFS_INODE_NSEC_TIME;
+        }
+
+        let since = t.duration_since(UNIX_EPOCH).unwrap_or_default();
+        if self.has_nsec_time() {
+            since.as_secs().min(u64::MAX >> NSEC_BITS) << NSEC_BITS | since.subsec_nanos() as u64
+        } else {
+            since.as_secs()
+        }
+    }
+
+    /// Bytes inline del archivo (hasta `size`).
+    pub fn inline_data(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(QRFS_INLINE_MAX);
+        for ptr in self.direct_blocks.iter() {
+            bytes.extend_from_slice(&ptr.to_le_bytes());
+        }
+        bytes.truncate((self.size as usize).min(QRFS_INLINE_MAX));
+        bytes
+    }
+
+    /// Guarda `data` inline y marca el inodo. `data` debe caber en `QRFS_INLINE_MAX`.
+    pub fn set_inline_data(&mut self, data: &[u8]) {
+        let mut bytes = [0u8; QRFS_INLINE_MAX];
+        let len = data.len().min(QRFS_INLINE_MAX);
+        bytes[..len].copy_from_slice(&data[..len]);
+
+        for (i, chunk) in bytes.chunks(4).enumerate() {
+            self.direct_blocks[i] = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        self.file_type |= QRFS_INODE_INLINE;
+        self.size = len as u64;
+    }
+
+    /// Quita la marca inline y deja los punteros directos en cero.
+    pub fn clear_inline_data(&mut self) {
+        self.file_type &= !QRFS_INODE_INLINE;
+        self.direct_blocks = [0u32; 12];
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type & QRFS_TYPE_MASK == 2
+    }
+
+    /// Tipo que ve FUSE (el `d_type` de readdir). Es el único lugar que lo
+    /// deriva de la tabla: el inodo en memoria y readdir lo sacan de acá.
+    pub fn kind(&self) -> FileType {
+        if self.is_dir() {
+            FileType::Directory
+        } else {
+            FileType::RegularFile
+        }
+    }
+
+    pub fn is_sorted_dir(&self) -> bool {
+        self.is_dir() && self.file_type & QRFS_DIR_SORTED != 0
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.file_type & QRFS_INODE_COMPRESSED != 0
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.id != 0 && self.nlink == 0 && self.file_type & QRFS_INODE_DELETED != 0
+    }
+
+    /// Bloques de datos a los que apunta el inodo (ninguno si es inline).
+    pub fn data_blocks(&self) -> Vec<u32> {
+        if self.is_inline() {
+            return Vec::new();
+        }
+        self.direct_blocks.iter().copied().filter(|&b| b != 0).collect()
+    }
 }
 
 #[repr(C)]
@@ -100,6 +620,12 @@ pub struct DirEntryDisk {
 // Estructuras en memoria
 // -----------------------------------------------------------------------------
 
+/// Permisos de un inodo nuevo: el `mode` pedido sin los bits del `umask` (sin
+/// `default_permissions` el kernel le deja aplicarlo al FS).
+pub(crate) fn masked_perm(mode: u32, umask: u32) -> u16 {
+    (mode & !umask & 0o777) as u16
+}
+
 #[derive(Debug, Clone)]
 pub struct Inode {
     pub ino: u64,
@@ -112,6 +638,8 @@ pub struct Inode {
     pub mtime: SystemTime,
     pub ctime: SystemTime,
     pub nlink: u32,
+    /// Generación del slot (ver `InodeDisk::generation`).
+    pub generation: u32,
 }
 
 impl Inode {
@@ -129,6 +657,7 @@ impl Inode {
             mtime: now,
             ctime: now,
             nlink: 2, // "." y ".."
+            generation: 0,
         }
     }
 
@@ -146,14 +675,109 @@ impl Inode {
             mtime: now,
             ctime: now,
             nlink: 1,
+            generation: 0,
+        }
+    }
+
+    /// Construye el inodo en memoria a partir de su versión en disco.
+    pub fn from_disk(ino: u64, disk_inode: &InodeDisk) -> Self {
+        Self {
+            ino,
+            kind: disk_inode.kind(),
+            perm: disk_inode.perm,
+            uid: disk_inode.uid,
+            gid: disk_inode.gid,
+            size: disk_inode.size,
+            atime: disk_inode.atime(),
+            mtime: disk_inode.mtime(),
+            ctime: disk_inode.ctime(),
+            nlink: disk_inode.nlink,
+            generation: disk_inode.generation,
+        }
+    }
+
+    /// `true` si el usuario `uid`/`gid` tiene los permisos de `mask` (`R_OK`,
+    /// `W_OK`, `X_OK`) según los bits de dueño, grupo u otros, como el kernel.
+    /// root puede leer y escribir siempre, pero ejecutar sólo si algún bit x está puesto.
+    pub fn permits(&self, uid: u32, gid: u32, mask: i32) -> bool {
+        let mask = (mask & (libc::R_OK | libc::W_OK | libc::X_OK)) as u16;
+
+        if uid == 0 {
+            let needs_exec = mask & libc::X_OK as u16 != 0;
+            return !needs_exec || self.kind == FileType::Directory || self.perm & 0o111 != 0;
         }
+
+        let bits = if uid == self.uid {
+            self.perm >> 6
+        } else if gid == self.gid {
+            self.perm >> 3
+        } else {
+            self.perm
+        };
+        bits & mask == mask
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Directory {
     pub parent: u64,
-    pub entries: HashMap<String, u64>, // nombre -> ino
+    pub entries: HashMap<OsString, u64>, // nombre (bytes tal cual) -> ino
+}
+
+/// Lock advisorio (fcntl/flock) registrado en memoria.
+/// No se persiste: desaparece al desmontar.
+#[derive(Debug, Clone)]
+pub struct FileLock {
+    pub owner: u64,
+    pub start: u64,
+    pub end: u64,
+    pub typ: i32,
+    pub pid: u32,
+}
+
+impl FileLock {
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start <= end && start <= self.end
+    }
+
+    /// Dos locks de dueños distintos chocan si se solapan y alguno es de escritura.
+    fn conflicts_with(&self, owner: u64, start: u64, end: u64, typ: i32) -> bool {
+        self.owner != owner
+            && self.overlaps(start, end)
+            && (self.typ == libc::F_WRLCK || typ == libc::F_WRLCK)
+    }
+}
+
+/// Aplica un `setlk` de `owner` sobre `start..=end` a los locks de un archivo,
+/// como fcntl: los locks del mismo dueño que se solapan se recortan (o se
+/// parten en dos si el rango cae en el medio) y, salvo con F_UNLCK, se agrega
+/// el nuevo. Los de otros dueños no se tocan: el que llama ya revisó que no
+/// choquen.
+pub(crate) fn set_lock_range(locks: &mut Vec<FileLock>, owner: u64, start: u64, end: u64, typ: i32, pid: u32) {
+    let mut kept = Vec::with_capacity(locks.len() + 1);
+    for lock in locks.drain(..) {
+        if lock.owner != owner || !lock.overlaps(start, end) {
+            kept.push(lock);
+            continue;
+        }
+        if lock.start < start {
+            kept.push(FileLock { end: start - 1, ..lock.clone() });
+        }
+        if lock.end > end {
+            kept.push(FileLock { start: end + 1, ..lock });
+        }
+    }
+
+    if typ != libc::F_UNLCK {
+        kept.push(FileLock {
+            owner,
+            start,
+            end,
+            typ,
+            pid,
+        });
+    }
+    *locks = kept;
 }
 
 // -------------------- Estado en memoria del FS --------------------
@@ -166,15 +790,185 @@ pub struct QrfsInner {
 
     pub inodes: HashMap<u64, Inode>,
     pub directories: HashMap<u64, Directory>,
+
+    // Referencias del kernel a cada inodo: suben con lookup/create/mkdir y bajan
+    // con forget. Un archivo sin referencias sale de `inodes` (ver `forget_inode`).
+    // Tiene su propio Mutex para que lookup las cuente con el lock de lectura
+    pub lookups: Mutex<HashMap<u64, u64>>,
     pub next_ino: u64,
 
-    // Contenido de archivos regulares en memoria (ino -> bytes)
+    // Contenido de archivos regulares en memoria (ino -> bytes). Un archivo está
+    // entero en RAM (su ino está aquí) o entero en disco: `read` elige una sola
+    // fuente por llamada y nunca arma un rango con partes de las dos
     pub files: HashMap<u64, Vec<u8>>,
+
+    // Archivos de `files` con escrituras que todavía no están en sus bloques.
+    // Los vuelca `flush_resident` (sync, fsync, forget)
+    pub dirty_files: HashSet<u64>,
+
+    // Locks advisorios activos (ino -> locks de cada dueño)
+    pub locks: HashMap<u64, Vec<FileLock>>,
+
+    // Opción de montaje --case-insensitive: nombres comparados sin distinguir mayúsculas
+    pub case_insensitive: bool,
+
+    // Opción de montaje --resident-limit: tamaño a partir del cual un archivo deja
+    // `files` y se escribe directo a sus bloques (None = siempre en memoria)
+    pub resident_limit: Option<u64>,
+
+    // Opción de montaje --compress: los archivos nuevos guardan sus datos comprimidos
+    pub compress: bool,
+
+    // Opción de montaje --no-page-cache-buffer: ningún archivo entra en `files`
+    // (cada write va a sus bloques y cada read sale de disco) y el kernel tampoco
+    // guarda el contenido en su caché de páginas
+    pub no_page_cache_buffer: bool,
+
+    // Opción de montaje --meta-dir: la raíz muestra el directorio virtual
+    // .qrfs-meta con el superblock, el bitmap y los inodos como texto
+    pub meta_dir: bool,
+
+    // Opción de montaje --zero-on-free: free_block llena de ceros cada bloque que
+    // libera, así lo que tenía no queda en el QR hasta que se reuse
+    pub zero_on_free: bool,
+
+    // Bloque -> inodo borrado que lo lista, para que `alloc_block` deje de
+    // ofrecer ese inodo a undelete en cuanto reusa uno de sus bloques. Se arma
+    // la primera vez que hace falta (ver `deleted_blocks`)
+    pub deleted_blocks: Option<HashMap<u32, u64>>,
+
+    // Opción de montaje --read-ahead: bloques que se cargan por adelantado cuando
+    // un archivo se lee en orden
+    pub read_ahead: Arc<ReadAhead>,
+
+    // Contadores expuestos en el archivo virtual .qrfs-stats
+    pub stats: MountStats,
+
+    // Handles de poll del kernel que esperan un cambio en .qrfs-stats (ver
+    // `notify_stats`)
+    pub stats_pollers: HashSet<u64>,
+
+    // Resultado del último comando escrito en .qrfs-control
+    pub control_output: Vec<u8>,
+
+    // Bloque desde el que alloc_block empieza a buscar (cerca de la última asignación)
+    pub next_free_hint: u32,
+
+    // Archivos de bloque listados al montar (ver `QrfsInner::store`): asignar o
+    // liberar un bloque no vuelve a recorrer la carpeta
+    pub block_store: BlockStore,
+
+    // Copia en memoria del bitmap de bloques libres (hasta total_blocks). La
+    // mantienen alloc_block, free_block y undelete, que escriben en disco sólo
+    // el bloque del bitmap que cambió
+    pub bitmap: Vec<u8>,
+
+    // Listados de directorio abiertos con opendir (ver `dir::DirStreams`). Tiene
+    // su propio Mutex, como `lookups`, para que opendir y readdir sigan con el
+    // lock de lectura
+    pub dir_streams: Mutex<dir::DirStreams>,
+
+    // Lock de la carpeta (`.qrfs.lock`): mientras la imagen esté abierta ningún
+    // otro proceso la monta ni la repara. None en un zip
+    pub _folder_lock: Option<FolderLock>,
+
+    // Inodo raíz real (superblock.root_inode), el que FUSE ve como ROOT_INO
+    pub root_ino: u64,
+}
+
+impl QrfsInner {
+    /// Bloques de la imagen sin volver a listar la carpeta: el store del
+    /// montaje, o el del lote en curso (ver `QrfsImage::batch`).
+    pub(crate) fn store(&self) -> BlockStore {
+        self.block_store.with_deferred(&self.qr_folder)
+    }
+
+    /// Traduce un número de inodo entre FUSE y QRFS (en cualquiera de los dos
+    /// sentidos). El kernel llama ROOT_INO a la raíz; si la imagen tiene otra
+    /// raíz, ese número y `root_ino` se intercambian y el resto queda igual.
+    pub fn fuse_ino(&self, ino: u64) -> u64 {
+        if ino == ROOT_INO {
+            self.root_ino
+        } else if ino == self.root_ino {
+            ROOT_INO
+        } else {
+            ino
+        }
+    }
+
+    /// `inode_to_attr` con el número de inodo que ve FUSE.
+    pub fn fuse_attr(&self, inode: &Inode) -> FileAttr {
+        let mut attr = inode_to_attr(inode);
+        attr.ino = self.fuse_ino(attr.ino);
+        attr
+    }
+
+    /// El kernel guardó una referencia más a `ino` (respuesta de lookup, create o mkdir).
+    pub(crate) fn remember(&self, ino: u64) {
+        *self.lookups.lock().unwrap().entry(ino).or_insert(0) += 1;
+    }
+
+    /// El inodo `ino` en memoria; si salió de `inodes` con un forget, se vuelve
+    /// a cargar de la tabla en disco.
+    pub(crate) fn inode_or_load(&mut self, ino: u64) -> Result<&Inode> {
+        if !self.inodes.contains_key(&ino) {
+            let disk_inode = self.load_inode(ino)?;
+            if disk_inode.id == 0 || disk_inode.nlink == 0 {
+                return Err(anyhow::anyhow!("El inodo {} no está en uso", ino));
+            }
+            self.inodes.insert(ino, Inode::from_disk(ino, &disk_inode));
+        }
+        Ok(&self.inodes[&ino])
+    }
+
+    /// Lee el inodo `ino` de la tabla en disco y lo cuenta en `stats`.
+    pub(crate) fn load_inode(&self, ino: u64) -> Result<InodeDisk> {
+        self.stats.record_inode_read();
+        load_inode_disk(&self.qr_folder, &self.superblock, ino)
+    }
+
+    /// Bloque de datos de un archivo: del caché de lectura anticipada si está
+    /// ahí, si no de disco (ver `read_file_block`).
+    pub(crate) fn read_data_block(&self, block: u32) -> Result<Vec<u8>> {
+        if !self.superblock.is_bad_block(block) {
+            if let Some(data) = self.read_ahead.get(block) {
+                self.stats.record_read_ahead_hit();
+                return Ok(data);
+            }
+        }
+        read_file_block(&self.qr_folder, &self.superblock, block)
+    }
+
+    /// Anota la lectura `offset..end` del handle `fh` y, si sigue a la anterior,
+    /// empieza a cargar en segundo plano los bloques que vienen después.
+    pub(crate) fn read_ahead_after(&self, ino: u64, fh: u64, inode_disk: &InodeDisk, offset: u64, end: u64) {
+        if self.read_ahead.window() == 0 || !self.read_ahead.record(ino, fh, offset, end) {
+            return;
+        }
+        let next = end.div_ceil(self.superblock.block_size as u64);
+        self.read_ahead.prefetch(&self.qr_folder, self.superblock, *inode_disk, next);
+    }
 }
 
+/// Estado compartido del FS. Lo usan el hilo de la sesión FUSE, el de
+/// `run_with_options` (sincronización periódica y avisos de `.qrfs-stats`) y
+/// cualquier `QrfsImage` o clon que tenga el programa.
+///
+/// Locks, siempre en este orden y nunca al revés:
+/// 1. `inner`: de lectura para lo que sólo consulta (getattr, lookup de un
+///    inodo en memoria, readdir, read, stat) y de escritura para todo lo que
+///    cambia el estado o el disco. Se toma una sola vez por operación: los
+///    helpers reciben `&QrfsInner` o `&mut QrfsInner` y nunca lo vuelven a
+///    pedir. Pasar de lectura a escritura es soltar uno y tomar el otro (y
+///    volver a mirar lo que haya cambiado en el medio).
+/// 2. `QrfsInner::lookups`, sólo mientras se actualiza el contador, o
+///    `QrfsInner::dir_streams`, mientras se guarda, pagina o suelta un listado
+///    (nunca los dos a la vez).
+/// 3. Los Mutex internos de `ReadAhead`, `BlockStore` (escrituras diferidas)
+///    y los zips abiertos, que no llaman a nada que tome los anteriores.
 #[derive(Clone)]
 pub struct QrfsFilesystem {
-    inner: Arc<RwLock<QrfsInner>>,
+    pub(crate) inner: Arc<RwLock<QrfsInner>>,
 }
 
 // -----------------------------------------------------------------------------
@@ -206,34 +1000,65 @@ pub fn inode_to_attr(inode: &Inode) -> FileAttr {
 // -----------------------------------------------------------------------------
 
 impl QrfsFilesystem {
-    /// Construye el estado interno del FS a partir de una carpeta con QRs.
+    /// Como `mount_from_folder`, pero un `start_qr` que no está entre los
+    /// archivos de bloque es un error en vez de una advertencia: montar con otro
+    /// bloque 0 terminaría en un error de magic que no dice qué pasó.
+    pub fn mount_from_folder_strict(
+        qr_folder: &Path,
+        passphrase: Option<String>,
+        start_qr: Option<PathBuf>,
+    ) -> Result<Self> {
+        if let Some(start) = &start_qr {
+            let store = BlockStore::open(qr_folder)?;
+            let found = (0..store.len() as u32)
+                .filter_map(|b| store.block_path(b).ok())
+                .any(|p| start.file_name().is_some() && p.file_name() == start.file_name());
+            if !found {
+                return Err(anyhow::anyhow!(
+                    "El archivo de inicio {:?} (start_qr) no está entre los archivos de bloque de {:?}",
+                    start,
+                    qr_folder
+                ));
+            }
+        }
+
+        Self::mount_from_folder(qr_folder, passphrase, start_qr)
+    }
+
+    /// Construye el estado interno del FS a partir de una carpeta con QRs (o de
+    /// un `.zip` con la imagen, que queda de sólo lectura).
     /// - Lee el primer archivo como bloque 0 (superblock)
     /// - Valida magic y versión
     /// - Deja en memoria el superblock y contadores de bloques/inodos libres
     /// - Inicializa un root lógico (ino = 1) vacío
+    ///
+    /// Toma el lock de la carpeta (ver `lock_folder`) antes de leer nada: falla
+    /// si otro proceso la tiene montada o la está reparando.
     pub fn mount_from_folder(
         qr_folder: &Path,
         _passphrase: Option<String>,
         start_qr: Option<PathBuf>,
     ) -> Result<Self> {
-        // 1. Listar archivos de la carpeta de QRs
-                let mut entries: Vec<PathBuf> = fs::read_dir(qr_folder)
-            .with_context(|| format!("No se pudo leer el directorio {:?}", qr_folder))?
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().map(|t| t.is_file()).unwrap_or(false))
-            .map(|e| e.path())
-            .collect();
+        let folder_lock = lock_folder(qr_folder)?;
 
-        entries.sort();
+        // 1. Listar los bloques de la imagen (carpeta de QRs o capa superior de un overlay)
+        let store = BlockStore::open(qr_folder)?;
+        let mut entries: Vec<PathBuf> = (0..store.len() as u32)
+            .map(|b| store.block_path(b))
+            .collect::<Result<_>>()?;
 
         if entries.is_empty() {
             return Err(anyhow::anyhow!(
-                "No se encontraron archivos de bloques en {:?}",
+                "No se encontraron archivos de bloques ({}<N>) en {:?}",
+                QRFS_BLOCK_PREFIX,
                 qr_folder
             ));
         }
 
         // Si el usuario especificó un archivo de inicio, lo usamos como bloque 0
+        // (si no, el listado ya puso primero al único archivo con superblock)
+        let mut first_block = 0;
+        let start_given = start_qr.is_some();
         if let Some(start) = start_qr {
             // Comparamos por nombre de archivo (no por ruta absoluta)
             if let Some(pos) = entries
@@ -242,48 +1067,43 @@ impl QrfsFilesystem {
             {
                 // Ponemos ese archivo en la posición 0 (bloque lógico 0)
                 entries.swap(0, pos);
+                first_block = pos as u32;
             } else {
                 eprintln!(
-                    "Advertencia: start_qr {:?} no se encontró en {:?}, se usa el primer archivo",
+                    "Advertencia: start_qr {:?} no se encontró en {:?}, se usa el primer archivo \
+                     (con --start-qr-strict es un error)",
                     start.file_name(),
                     qr_folder
                 );
             }
         }
 
-
-        // 2. Leer el primer archivo como bloque 0 (superblock)
-        let first_block = &entries[0];
-        let mut file = File::open(first_block).with_context(|| {
-            format!("No se pudo abrir el primer bloque {:?}", first_block)
-        })?;
-
-        let mut buf = vec![0u8; QRFS_BLOCK_SIZE as usize];
-        file.read_exact(&mut buf)
-            .with_context(|| "No se pudo leer el superblock completo")?;
-
-        if mem::size_of::<SuperblockDisk>() > buf.len() {
-            return Err(anyhow::anyhow!(
-                "SuperblockDisk ({}) es más grande que el bloque ({})",
-                mem::size_of::<SuperblockDisk>(),
-                buf.len()
-            ));
+        // Sin manifiesto ni start_qr el bloque 0 sale del contenido de los
+        // archivos: si más de uno tiene un superblock válido (aunque el primero
+        // en orden sea uno, puede ser una copia) no hay cómo elegirlo
+        if !start_given && !is_zip_image(qr_folder) && !qr_folder.join(QRFS_MANIFEST_NAME).is_file() {
+            let candidates = superblock_files(&entries);
+            if candidates.len() > 1 {
+                return Err(anyhow::anyhow!(
+                    "Varios archivos de {:?} tienen un superblock válido y no se sabe cuál es el bloque 0: {:?}. \
+                     Indique cuál con start_qr",
+                    qr_folder,
+                    candidates
+                ));
+            }
         }
 
+        // 2. Leer el primer archivo como bloque 0 (superblock), a través del
+        //    store para que también funcione con una imagen en un zip
+        let buf = store
+            .read_block(first_block)
+            .with_context(|| format!("No se pudo leer el superblock de {:?}", entries[0]))?;
+
         // Interpretar los bytes como un SuperblockDisk
-        let superblock: SuperblockDisk = unsafe {
-            let ptr = buf.as_ptr() as *const SuperblockDisk;
-            ptr.read_unaligned()
-        };
+        let superblock = SuperblockDisk::from_block_bytes(&buf)?;
 
         // 3. Validar que esto parece un QRFS
-        if superblock.magic != QRFS_MAGIC {
-            return Err(anyhow::anyhow!(
-                "El magic del superblock no coincide (esperado = {:#X}, leído = {:#X})",
-                QRFS_MAGIC,
-                superblock.magic
-            ));
-        }
+        superblock.check_magic()?;
 
         if superblock.version != QRFS_VERSION {
             return Err(anyhow::anyhow!(
@@ -293,6 +1113,31 @@ impl QrfsFilesystem {
             ));
         }
 
+        // Un byte corrupto fuera del magic dejaría el resto de los campos mal sin aviso
+        if !superblock.verify_checksum() {
+            return Err(superblock_checksum_error(&superblock));
+        }
+
+        superblock.check_features()?;
+
+        // Archivos de bloque sobrantes o faltantes correrían los índices de los
+        // bloques (los de paridad van después de los del FS)
+        if entries.len() != superblock.image_blocks() as usize {
+            return Err(anyhow::anyhow!(
+                "La carpeta {:?} tiene {} archivos de bloque ({}<N>) pero el superblock indica {} bloques",
+                qr_folder,
+                entries.len(),
+                QRFS_BLOCK_PREFIX,
+                superblock.image_blocks()
+            ));
+        }
+
+        // 4. Validar las regiones del layout y max_inodes contra lo que realmente
+        //    cabe en la tabla de inodos (un superblock corrupto podría pedir miles
+        //    de millones de inodos o apuntar fuera de la imagen)
+        superblock.check_layout()?;
+        let bitmap = read_bitmap(&store, &superblock)?;
+
         // 5. Construir el estado interno leyendo inodos y directorio raíz desde disco
         let mut inodes: HashMap<u64, Inode> = HashMap::new();
         let mut directories: HashMap<u64, Directory> = HashMap::new();
@@ -300,42 +1145,22 @@ impl QrfsFilesystem {
 
         let root_ino = superblock.root_inode as u64;
 
-        // 5.1. Cargar todos los inodos válidos desde la tabla de inodos
-        for ino in 1..=superblock.max_inodes as u64 {
-            let disk_inode = match load_inode_disk(&qr_folder, &superblock, ino) {
-                Ok(inode) => inode,
-                Err(e) => {
-                    eprintln!("Advertencia: no se pudo cargar inodo {} desde disco: {e:?}", ino);
-                    continue;
-                }
-            };
+        // 5.1. Cargar todos los inodos válidos desde la tabla de inodos (una sola lectura)
+        for (ino, disk_inode) in InodeTable::open(qr_folder, &superblock)?.iter()? {
+
+            // Inodo borrado pero recuperable: no se carga, pero tampoco se reutiliza
+            // su número enseguida (así `undelete` sigue teniendo una ventana)
+            if disk_inode.is_deleted() {
+                max_ino_used = max_ino_used.max(ino);
+                continue;
+            }
 
             // Inodo no usado: id = 0 o nlink = 0
             if disk_inode.id == 0 || disk_inode.nlink == 0 {
                 continue;
             }
 
-            let kind = match disk_inode.file_type {
-                2 => FileType::Directory,
-                _ => FileType::RegularFile,
-            };
-
-            let atime = UNIX_EPOCH + Duration::from_secs(disk_inode.atime);
-            let mtime = UNIX_EPOCH + Duration::from_secs(disk_inode.mtime);
-            let ctime = UNIX_EPOCH + Duration::from_secs(disk_inode.ctime);
-
-            let inode = Inode {
-                ino,
-                kind,
-                perm: disk_inode.perm,
-                uid: disk_inode.uid,
-                gid: disk_inode.gid,
-                size: disk_inode.size,
-                atime,
-                mtime,
-                ctime,
-                nlink: disk_inode.nlink,
-            };
+            let inode = Inode::from_disk(ino, &disk_inode);
 
             if ino > max_ino_used {
                 max_ino_used = ino;
@@ -344,39 +1169,65 @@ impl QrfsFilesystem {
             inodes.insert(ino, inode);
         }
 
-        // 5.2. Cargar el directorio raíz desde disco
-        let mut root_parent = root_ino;
-        let mut root_entries_map: HashMap<String, u64> = HashMap::new();
-
-        match read_directory_from_disk(&qr_folder, &superblock, root_ino) {
-            Ok(entries) => {
-                for e in entries {
-                    if e.name == "." {
-                        continue;
-                    }
-                    if e.name == ".." {
-                        // Guardamos el parent real del root
-                        root_parent = e.ino;
-                        continue;
+        // 5.2. Cargar los directorios desde disco: la raíz siempre, y cualquier
+        //      otro inodo de directorio que tenga su bloque (p. ej. los que arma
+        //      from_dir.qrfs)
+        let mut dir_inos: Vec<u64> = inodes
+            .values()
+            .filter(|i| i.kind == FileType::Directory && i.ino != root_ino)
+            .map(|i| i.ino)
+            .collect();
+        dir_inos.sort();
+        dir_inos.insert(0, root_ino);
+
+        for dir_ino in dir_inos {
+            let mut parent = root_ino;
+            let mut entries_map: HashMap<OsString, u64> = HashMap::new();
+
+            match read_directory_from_disk(&qr_folder, &superblock, dir_ino) {
+                Ok(entries) => {
+                    for e in entries {
+                        if e.name == "." {
+                            continue;
+                        }
+                        if e.name == ".." {
+                            // Guardamos el parent real del directorio
+                            parent = e.ino;
+                            continue;
+                        }
+                        entries_map.insert(e.name.clone(), e.ino);
                     }
-                    root_entries_map.insert(e.name.clone(), e.ino);
+                }
+                Err(e) if dir_ino == root_ino => {
+                    eprintln!(
+                        "Advertencia: no se pudo leer el directorio raíz desde disco: {e:?}. Se inicializa vacío."
+                    );
+                }
+                Err(e) => {
+                    eprintln!("Advertencia: no se pudo leer el directorio {} desde disco: {e:?}", dir_ino);
                 }
             }
-            Err(e) => {
-                eprintln!(
-                    "Advertencia: no se pudo leer el directorio raíz desde disco: {e:?}. Se inicializa vacío."
-                );
-            }
+
+            // 5.3. Registrar el directorio en la tabla de directorios
+            directories.insert(
+                dir_ino,
+                Directory {
+                    parent,
+                    entries: entries_map,
+                },
+            );
         }
 
-        // 5.3. Registrar el directorio raíz en la tabla de directorios
-        directories.insert(
-            root_ino,
-            Directory {
-                parent: root_parent,
-                entries: root_entries_map,
-            },
-        );
+        // 5.4. Inodos cuyo tipo contradice el árbol: acá sólo se avisan (ver
+        //      `type_conflicts`)
+        match inode_type_conflicts(qr_folder, &superblock) {
+            Ok(conflicts) => {
+                for conflict in conflicts {
+                    eprintln!("Advertencia: {}", conflict);
+                }
+            }
+            Err(e) => eprintln!("Advertencia: no se pudieron revisar los tipos de los inodos: {e:?}"),
+        }
 
         // Si por alguna razón no hay ningún inodo usado, garantizamos al menos el root
         if max_ino_used == 0 {
@@ -396,6 +1247,26 @@ impl QrfsFilesystem {
             directories,
             next_ino: max_ino_used + 1,
             files: HashMap::new(),
+            dirty_files: HashSet::new(),
+            locks: HashMap::new(),
+            case_insensitive: false,
+            resident_limit: None,
+            compress: false,
+            no_page_cache_buffer: false,
+            meta_dir: false,
+            zero_on_free: false,
+            deleted_blocks: None,
+            read_ahead: Arc::new(ReadAhead::new(QRFS_READ_AHEAD_DEFAULT)),
+            stats: MountStats::default(),
+            stats_pollers: HashSet::new(),
+            control_output: Vec::new(),
+            lookups: Mutex::new(HashMap::new()),
+            next_free_hint: superblock.data_blocks_start,
+            block_store: store,
+            bitmap,
+            dir_streams: Mutex::new(dir::DirStreams::default()),
+            _folder_lock: folder_lock,
+            root_ino,
         };
 
 
@@ -405,174 +1276,371 @@ impl QrfsFilesystem {
 
     }
 
+    /// Inodos de la imagen montada cuyo `file_type` contradice su papel en el
+    /// árbol. El montaje sólo los avisa; `mount_qrfs --types-strict` no sigue si
+    /// hay alguno.
+    pub fn type_conflicts(&self) -> Result<Vec<TypeConflict>> {
+        let inner = self.inner.read().unwrap();
+        inode_type_conflicts(&inner.qr_folder, &inner.superblock)
+    }
+
+    /// Activa la comparación de nombres sin distinguir mayúsculas (conservando
+    /// el nombre original en disco).
+    pub fn case_insensitive(self, enabled: bool) -> Self {
+        self.inner.write().unwrap().case_insensitive = enabled;
+        self
+    }
+
+    /// Tamaño máximo (bytes) de un archivo guardado entero en memoria. Una
+    /// escritura que lo pasaría vuelca el archivo a sus bloques y lo saca de
+    /// memoria; desde ahí se lee y escribe directo en disco.
+    pub fn resident_limit(self, limit: Option<u64>) -> Self {
+        self.inner.write().unwrap().resident_limit = limit;
+        self
+    }
+
+    /// Cantidad de bloques que se cargan por adelantado cuando un archivo se lee
+    /// en orden (0 = sin lectura anticipada).
+    pub fn read_ahead(self, blocks: usize) -> Self {
+        self.inner.write().unwrap().read_ahead = Arc::new(ReadAhead::new(blocks));
+        self
+    }
+
+    /// Sin buffer en memoria: los archivos nuevos no quedan en RAM, cada write
+    /// va directo a sus bloques y cada read sale de disco (también para el
+    /// kernel, que los abre con DIRECT_IO). La memoria usada no depende del
+    /// tamaño de los archivos y sólo se ejercitan los caminos de disco.
+    pub fn no_page_cache_buffer(self, enabled: bool) -> Self {
+        self.inner.write().unwrap().no_page_cache_buffer = enabled;
+        self
+    }
+
+    /// Agrega a la raíz el directorio virtual `.qrfs-meta` (superblock, bitmap e
+    /// inodos como texto, de sólo lectura). No ocupa inodos ni bloques.
+    pub fn meta_dir(self, enabled: bool) -> Self {
+        self.inner.write().unwrap().meta_dir = enabled;
+        self
+    }
+
+    /// Cada bloque que se libera (truncate, unlink, reescrituras) se llena de
+    /// ceros en disco. Cuesta una escritura más por bloque.
+    pub fn zero_on_free(self, enabled: bool) -> Self {
+        self.inner.write().unwrap().zero_on_free = enabled;
+        self
+    }
+
+    /// Los archivos creados desde ahora guardan sus datos comprimidos. Nunca se
+    /// quedan enteros en memoria: cada escritura reescribe el archivo en disco.
+    pub fn compress(self, enabled: bool) -> Self {
+        self.inner.write().unwrap().compress = enabled;
+        self
+    }
+
+    /// Monta `lower` como imagen base de sólo lectura y `upper` como capa de cambios.
+    /// Las lecturas caen a `lower` cuando un bloque no fue copiado a `upper`; toda
+    /// escritura (datos, inodos, bitmap, superblock) queda en `upper`, así la imagen
+    /// base nunca se modifica. `upper` se crea si no existe y recuerda su base.
+    pub fn overlay(lower: &Path, upper: &Path) -> Result<Self> {
+        let lower = fs::canonicalize(lower)
+            .with_context(|| format!("No se pudo resolver la capa inferior {:?}", lower))?;
+
+        fs::create_dir_all(upper)
+            .with_context(|| format!("No se pudo crear la capa superior {:?}", upper))?;
+
+        let marker = upper.join(QRFS_OVERLAY_MARKER);
+        if marker.exists() {
+            let current = fs::read_to_string(&marker)
+                .with_context(|| format!("No se pudo leer {:?}", marker))?;
+            if Path::new(current.trim()) != lower {
+                return Err(anyhow::anyhow!(
+                    "La capa superior {:?} pertenece a otra imagen base ({:?})",
+                    upper,
+                    current.trim()
+                ));
+            }
+        } else {
+            fs::write(&marker, lower.to_string_lossy().as_bytes())
+                .with_context(|| format!("No se pudo escribir {:?}", marker))?;
+        }
+
+        Self::mount_from_folder(upper, None, None)
+    }
+
     /// Monta el FS con FUSE en el punto de montaje indicado.
     pub fn run(self, mountpoint: PathBuf) -> Result<()> {
-        let options = vec![
+        self.run_with_options(mountpoint, &MountConfig::default())
+    }
+
+    /// Contadores del montaje (los mismos de `.qrfs-stats`).
+    pub fn stats(&self) -> QrfsStats {
+        self.inner.read().unwrap().stats.counters()
+    }
+
+    /// Sincroniza la imagen: los archivos residentes con escrituras pendientes
+    /// a sus bloques, el superblock con los contadores en memoria, un fsync de
+    /// todos los archivos de bloque y los checksums del manifiesto (si la
+    /// carpeta tiene uno). Se llama al desmontar y cada `flush_interval`
+    /// segundos si el montaje lo pide.
+    pub fn sync(&self) -> Result<()> {
+        let mut inner = self.inner.write().unwrap();
+
+        let dirty: Vec<u64> = inner.dirty_files.iter().copied().collect();
+        for ino in dirty {
+            flush_resident(&mut inner, ino)?;
+        }
+        write_counters(&inner)?;
+        BlockStore::open(&inner.qr_folder)?.sync_all()?;
+        refresh_manifest(&inner.qr_folder)
+    }
+
+    /// Como `run`, con las opciones de montaje de `config` (sólo lectura,
+    /// allow_other, flush_interval).
+    ///
+    /// La sesión FUSE corre en otro hilo; este espera SIGINT/SIGTERM (o que el
+    /// FS se desmonte desde afuera) y entonces desmonta limpiamente, así un
+    /// Ctrl-C no deja el punto de montaje colgado ni datos sin sincronizar.
+    pub fn run_with_options(self, mountpoint: PathBuf, config: &MountConfig) -> Result<()> {
+        check_mountpoint(&mountpoint, config.allow_nonempty)?;
+
+        let mut options = vec![
             MountOption::FSName("qrfs".to_string()),
             MountOption::AutoUnmount,
-            MountOption::RW, // read-write
         ];
+        // Una imagen en un zip no se puede escribir: el kernel rechaza las
+        // escrituras con EROFS antes de que lleguen acá
+        let packed = is_zip_image(&self.inner.read().unwrap().qr_folder);
+        options.push(if config.read_only || packed {
+            MountOption::RO
+        } else {
+            MountOption::RW // read-write
+        });
+        if config.allow_other {
+            options.push(MountOption::AllowOther);
+        }
 
-        fuser::mount2(self, &mountpoint, &options)?;
-        Ok(())
-    }
-}
-
-fn get_qr_entries(qr_folder: &Path) -> Result<Vec<PathBuf>> {
-    let mut entries: Vec<PathBuf> = fs::read_dir(qr_folder)
-        .with_context(|| format!("No se pudo leer el directorio {:?}", qr_folder))?
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().map(|t| t.is_file()).unwrap_or(false))
-        .map(|e| e.path())
-        .collect();
+        // Las señales se bloquean antes de lanzar la sesión para que su hilo
+        // herede la máscara y sólo este hilo las reciba (con sigtimedwait)
+        let signals = block_exit_signals()?;
+        let session = fuser::spawn_mount2(self.clone(), &mountpoint, &options)?;
+        let notifier = session.notifier();
 
-    entries.sort();
-    Ok(entries)
-}
+        let interval = config.flush_interval.map(Duration::from_secs);
+        let mut last_flush = Instant::now();
+        let mut last_stats = Vec::new();
 
-fn read_fs_block(qr_folder: &Path, block_index: u32) -> Result<Vec<u8>> {
-    let entries = get_qr_entries(qr_folder)?;
-    let idx = block_index as usize;
+        while !session.guard.is_finished() {
+            if let Some(sig) = wait_signal(&signals, Duration::from_secs(1)) {
+                println!("Señal {} recibida: desmontando {:?}", sig, mountpoint);
+                break;
+            }
+            if interval.is_some_and(|i| last_flush.elapsed() >= i) {
+                if let Err(e) = self.sync() {
+                    eprintln!("Error en la sincronización periódica: {e:?}");
+                }
+                last_flush = Instant::now();
+            }
+            self.notify_stats(&notifier, &mut last_stats);
+        }
 
-    if idx >= entries.len() {
-        return Err(anyhow::anyhow!(
-            "Índice de bloque fuera de rango: {} (hay {} archivos QR)",
-            idx,
-            entries.len()
-        ));
+        // Soltar el montaje desmonta; la sesión termina y llama a destroy, que
+        // hace la última sincronización
+        let guard = {
+            let fuser::BackgroundSession { guard, .. } = session;
+            guard
+        };
+        guard
+            .join()
+            .map_err(|_| anyhow::anyhow!("El hilo de la sesión FUSE terminó con un pánico"))??;
+        Ok(())
     }
+}
 
-    let mut file = File::open(&entries[idx])
-        .with_context(|| format!("No se pudo abrir el bloque {:?}", entries[idx]))?;
+impl QrfsFilesystem {
+    /// Si los contadores cambiaron desde `last`, invalida lo que el kernel tenga
+    /// de `.qrfs-stats` y despierta a los que hacen poll sobre él. Corre desde
+    /// el hilo de `run_with_options` (a lo sumo una vez por segundo): avisar
+    /// desde una operación FUSE, con el kernel esperando la respuesta, puede
+    /// trabarlo.
+    fn notify_stats(&self, notifier: &fuser::Notifier, last: &mut Vec<u8>) {
+        let Some(pollers) = self.stats_changed(last) else {
+            return;
+        };
 
-    let mut buf = vec![0u8; QRFS_BLOCK_SIZE as usize];
-    file.read_exact(&mut buf)
-        .with_context(|| format!("No se pudo leer el bloque completo de {:?}", entries[idx]))?;
+        if let Err(e) = notifier.inval_inode(QRFS_STATS_INO, 0, 0) {
+            eprintln!("No se pudo invalidar {} en el kernel: {e}", QRFS_STATS_NAME);
+        }
+        // Cada handle sirve para un solo aviso: el kernel vuelve a llamar a poll
+        for kh in pollers {
+            if let Err(e) = notifier.poll(kh) {
+                eprintln!("No se pudo avisar el cambio de {}: {e}", QRFS_STATS_NAME);
+            }
+        }
+    }
 
-    Ok(buf)
+    /// Si los contadores cambiaron desde `last` (que pasa a ser la foto
+    /// actual), los handles de poll a despertar; quedan olvidados.
+    pub(crate) fn stats_changed(&self, last: &mut Vec<u8>) -> Option<HashSet<u64>> {
+        let mut inner = self.inner.write().unwrap();
+        let snapshot = inner.stats.snapshot(inner.free_blocks, inner.free_inodes);
+        if snapshot == *last {
+            return None;
+        }
+        *last = snapshot;
+        Some(mem::take(&mut inner.stats_pollers))
+    }
 }
 
-fn load_inode_disk(qr_folder: &Path, superblock: &SuperblockDisk, ino: u64) -> Result<InodeDisk> {
-    if ino == 0 || ino > superblock.max_inodes as u64 {
-        return Err(anyhow::anyhow!(
-            "Inodo fuera de rango: {} (max_inodes = {})",
-            ino,
-            superblock.max_inodes
-        ));
+/// Eventos de poll listos para `ino`: todo archivo se puede leer y, salvo los
+/// virtuales de sólo lectura, escribir.
+pub(crate) fn poll_ready(ino: u64) -> u32 {
+    let mut ready = (libc::POLLIN | libc::POLLRDNORM) as u32;
+    if ino != QRFS_STATS_INO && !meta::is_meta_ino(ino) {
+        ready |= (libc::POLLOUT | libc::POLLWRNORM) as u32;
     }
+    ready
+}
 
-    let inode_size = mem::size_of::<InodeDisk>();
-    let block_size = QRFS_BLOCK_SIZE as usize;
-    let total_bytes = (superblock.inode_table_blocks as usize) * block_size;
-
-    let entries = get_qr_entries(qr_folder)?;
-    let first_block = superblock.inode_table_start as usize;
-    let last_block_excl = first_block + superblock.inode_table_blocks as usize;
+/// Verifica el punto de montaje antes de llamar a FUSE, que ante un camino
+/// inexistente o que no es un directorio falla con errores poco claros. Un
+/// directorio con contenido sólo se acepta con `allow_nonempty`.
+pub fn check_mountpoint(mountpoint: &Path, allow_nonempty: bool) -> Result<()> {
+    let meta = match fs::metadata(mountpoint) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(anyhow::anyhow!(
+                "El punto de montaje {:?} no existe (se puede crear con `mkdir -p {}`)",
+                mountpoint,
+                mountpoint.display()
+            ));
+        }
+        Err(e) => {
+            return Err(e)
+                .with_context(|| format!("No se pudo acceder al punto de montaje {:?}", mountpoint));
+        }
+    };
+    if !meta.is_dir() {
+        return Err(anyhow::anyhow!("El punto de montaje {:?} no es un directorio", mountpoint));
+    }
 
-    if last_block_excl > entries.len() {
+    let mut entries = fs::read_dir(mountpoint)
+        .with_context(|| format!("No se pudo leer el punto de montaje {:?}", mountpoint))?;
+    if !allow_nonempty && entries.next().is_some() {
         return Err(anyhow::anyhow!(
-            "La tabla de inodos referencia bloques fuera de rango ({}..{} en {} archivos)",
-            first_block,
-            last_block_excl,
-            entries.len()
+            "El punto de montaje {:?} no está vacío (--allow-nonempty monta igual)",
+            mountpoint
         ));
     }
+    Ok(())
+}
 
-    let mut buf = Vec::with_capacity(total_bytes);
-    for block_idx in first_block..last_block_excl {
-        let mut file = File::open(&entries[block_idx])
-            .with_context(|| format!("No se pudo abrir el bloque de inodos {:?}", entries[block_idx]))?;
-        let mut block_buf = vec![0u8; block_size];
-        file.read_exact(&mut block_buf)
-            .with_context(|| format!("No se pudo leer completamente el bloque {:?}", entries[block_idx]))?;
-        buf.extend_from_slice(&block_buf);
+/// Bloquea SIGINT y SIGTERM en el hilo actual (y en los que cree después) y
+/// devuelve el conjunto para esperarlas con `wait_signal`.
+fn block_exit_signals() -> Result<libc::sigset_t> {
+    unsafe {
+        let mut set: libc::sigset_t = mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGINT);
+        libc::sigaddset(&mut set, libc::SIGTERM);
+
+        let err = libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+        if err != 0 {
+            return Err(std::io::Error::from_raw_os_error(err))
+                .context("No se pudieron bloquear SIGINT/SIGTERM");
+        }
+        Ok(set)
     }
+}
 
-    let idx_bytes = (ino as usize - 1) * inode_size;
-    if idx_bytes + inode_size > buf.len() {
-        return Err(anyhow::anyhow!(
-            "Inodo {} fuera del rango de la tabla (idx_bytes = {}, len = {})",
-            ino,
-            idx_bytes,
-            buf.len()
-        ));
+/// Espera hasta `timeout` una de las señales de `set`; devuelve su número.
+fn wait_signal(set: &libc::sigset_t, timeout: Duration) -> Option<i32> {
+    let ts = libc::timespec {
+        tv_sec: timeout.as_secs() as libc::time_t,
+        tv_nsec: timeout.subsec_nanos() as libc::c_long,
+    };
+    let sig = unsafe { libc::sigtimedwait(set, std::ptr::null_mut(), &ts) };
+    (sig > 0).then_some(sig)
+}
+
+/// Escribe en el superblock los contadores de bloques e inodos libres que
+/// tiene la memoria, si difieren de los de disco.
+pub(crate) fn write_counters(inner: &QrfsInner) -> Result<()> {
+    if inner.superblock.free_blocks == inner.free_blocks && inner.superblock.free_inodes == inner.free_inodes {
+        return Ok(());
     }
 
-    let inode: InodeDisk = unsafe {
-        let ptr = buf[idx_bytes..].as_ptr() as *const InodeDisk;
-        ptr.read_unaligned()
+    let sb = SuperblockDisk {
+        free_blocks: inner.free_blocks,
+        free_inodes: inner.free_inodes,
+        ..inner.superblock
     };
+    write_superblock(&inner.qr_folder, &sb)
+}
 
-    Ok(inode)
+pub(crate) fn read_fs_block(qr_folder: &Path, block_index: u32) -> Result<Vec<u8>> {
+    BlockStore::open(qr_folder)?.read_block(block_index)
 }
 
-fn load_bitmap(qr_folder: &Path, superblock: &SuperblockDisk) -> Result<Vec<u8>> {
-    let block_size = QRFS_BLOCK_SIZE as usize;
-    let total_bytes = (superblock.free_bitmap_blocks as usize) * block_size;
+/// Bloque de datos de un archivo. Uno marcado como dañado no se lee: se
+/// reconstruye con la paridad de su grupo si la imagen tiene y si no es un
+/// error (EIO en el montaje).
+pub(crate) fn read_file_block(qr_folder: &Path, superblock: &SuperblockDisk, block: u32) -> Result<Vec<u8>> {
+    if !superblock.is_bad_block(block) {
+        return read_fs_block(qr_folder, block);
+    }
 
-    let entries = get_qr_entries(qr_folder)?;
-    let first_block = superblock.free_bitmap_start as usize;
-    let last_block_excl = first_block + superblock.free_bitmap_blocks as usize;
+    let rebuilt = parity::reconstruct(&BlockStore::open(qr_folder)?, block)
+        .with_context(|| format!("No se pudo reconstruir el bloque dañado {}", block))?;
+    rebuilt.ok_or_else(|| anyhow::anyhow!("El bloque {} está marcado como dañado", block))
+}
 
-    if last_block_excl > entries.len() {
-        return Err(anyhow::anyhow!(
-            "El bitmap referencia bloques fuera de rango ({}..{} en {} archivos)",
-            first_block,
-            last_block_excl,
-            entries.len()
-        ));
-    }
+pub(crate) fn load_inode_disk(qr_folder: &Path, superblock: &SuperblockDisk, ino: u64) -> Result<InodeDisk> {
+    InodeTable::open(qr_folder, superblock)?.get(ino)
+}
 
-    let mut buf = Vec::with_capacity(total_bytes);
-    for block_idx in first_block..last_block_excl {
-        let mut file = File::open(&entries[block_idx])
-            .with_context(|| format!("No se pudo abrir el bloque de bitmap {:?}", entries[block_idx]))?;
-        let mut block_buf = vec![0u8; block_size];
-        file.read_exact(&mut block_buf)
-            .with_context(|| format!("No se pudo leer completamente el bloque {:?}", entries[block_idx]))?;
-        buf.extend_from_slice(&block_buf);
-    }
+pub(crate) fn load_bitmap(qr_folder: &Path, superblock: &SuperblockDisk) -> Result<Vec<u8>> {
+    read_bitmap(&BlockStore::open(qr_folder)?, superblock)
+}
+
+fn read_bitmap(store: &BlockStore, superblock: &SuperblockDisk) -> Result<Vec<u8>> {
+    let mut buf = store
+        .read_blocks(superblock.free_bitmap_start, superblock.free_bitmap_blocks)
+        .with_context(|| "No se pudo leer el bitmap")?;
 
     // Solo nos interesan los bits hasta total_blocks
-    let needed_bytes = ((superblock.total_blocks as usize) + 7) / 8;
+    let needed_bytes = (superblock.total_blocks as usize).div_ceil(8);
     buf.truncate(needed_bytes);
     Ok(buf)
 }
 
-fn write_bitmap(qr_folder: &Path, superblock: &SuperblockDisk, bitmap: &[u8]) -> Result<()> {
+pub(crate) fn write_bitmap(qr_folder: &Path, superblock: &SuperblockDisk, bitmap: &[u8]) -> Result<()> {
     let block_size = QRFS_BLOCK_SIZE as usize;
     let total_bytes = (superblock.free_bitmap_blocks as usize) * block_size;
 
-    let entries = get_qr_entries(qr_folder)?;
-    let first_block = superblock.free_bitmap_start as usize;
-    let last_block_excl = first_block + superblock.free_bitmap_blocks as usize;
-
-    if last_block_excl > entries.len() {
-        return Err(anyhow::anyhow!(
-            "El bitmap referencia bloques fuera de rango ({}..{} en {} archivos)",
-            first_block,
-            last_block_excl,
-            entries.len()
-        ));
-    }
-
     // Buffer completo de bloques para escribir
     let mut buf = vec![0u8; total_bytes];
     let copy_len = std::cmp::min(bitmap.len(), total_bytes);
     buf[..copy_len].copy_from_slice(&bitmap[..copy_len]);
 
-    for (i, chunk) in buf.chunks(block_size).enumerate() {
-        let block_idx = first_block + i;
-        let mut file = File::create(&entries[block_idx])
-            .with_context(|| format!("No se pudo abrir el bloque de bitmap {:?} para escritura", entries[block_idx]))?;
-        file.write_all(chunk)
-            .with_context(|| format!("No se pudo escribir completamente el bloque {:?}", entries[block_idx]))?;
-    }
+    BlockStore::open(qr_folder)?
+        .write_blocks(superblock.free_bitmap_start, &buf)
+        .with_context(|| "No se pudo escribir el bitmap")
+}
 
-    Ok(())
+/// Escribe el bloque del bitmap que guarda el bit de `block`, tomado de la copia
+/// en memoria (`QrfsInner::bitmap`). El resto del bitmap no se toca.
+fn write_bitmap_block(inner: &QrfsInner, block: u32) -> Result<()> {
+    let block_size = QRFS_BLOCK_SIZE as usize;
+    let index = block as usize / 8 / block_size;
+    let start = index * block_size;
+    let end = (start + block_size).min(inner.bitmap.len());
+
+    inner
+        .store()
+        .write_block(inner.superblock.free_bitmap_start + index as u32, &inner.bitmap[start..end])
+        .with_context(|| format!("No se pudo escribir el bloque {} del bitmap", index))
 }
 
-fn bitmap_test(bitmap: &[u8], block_index: u32) -> bool {
+pub fn bitmap_test(bitmap: &[u8], block_index: u32) -> bool {
     let idx = block_index as usize;
     let byte = idx / 8;
     let bit = (idx % 8) as u8;
@@ -584,7 +1652,7 @@ fn bitmap_test(bitmap: &[u8], block_index: u32) -> bool {
     (bitmap[byte] & (1 << bit)) != 0
 }
 
-fn bitmap_set(bitmap: &mut [u8], block_index: u32, used: bool) {
+pub fn bitmap_set(bitmap: &mut [u8], block_index: u32, used: bool) {
     let idx = block_index as usize;
     let byte = idx / 8;
     let bit = (idx % 8) as u8;
@@ -600,146 +1668,150 @@ fn bitmap_set(bitmap: &mut [u8], block_index: u32, used: bool) {
     }
 }
 
-fn write_superblock(qr_folder: &Path, sb: &SuperblockDisk) -> Result<()> {
-    let entries = get_qr_entries(qr_folder)?;
-    if entries.is_empty() {
+pub(crate) fn write_superblock(qr_folder: &Path, sb: &SuperblockDisk) -> Result<()> {
+    store_superblock(&BlockStore::open(qr_folder)?, sb)
+}
+
+fn store_superblock(store: &BlockStore, sb: &SuperblockDisk) -> Result<()> {
+    if store.is_empty() {
         return Err(anyhow::anyhow!(
             "No hay archivos de bloque para escribir el superblock"
         ));
     }
 
-    let block_size = QRFS_BLOCK_SIZE as usize;
-    let mut buf = vec![0u8; block_size];
     let sb_size = mem::size_of::<SuperblockDisk>();
-
-    if sb_size > buf.len() {
+    if sb_size > QRFS_BLOCK_SIZE as usize {
         return Err(anyhow::anyhow!(
             "SuperblockDisk ({}) es más grande que el bloque ({})",
             sb_size,
-            buf.len()
+            QRFS_BLOCK_SIZE
         ));
     }
 
-    unsafe {
-        let src = (sb as *const SuperblockDisk) as *const u8;
-        let slice = std::slice::from_raw_parts(src, sb_size);
-        buf[..sb_size].copy_from_slice(slice);
-    }
-
-    let mut file = File::create(&entries[0])
-        .with_context(|| format!("No se pudo abrir el bloque 0 ({:?}) para escritura", entries[0]))?;
-    file.write_all(&buf)
-        .with_context(|| "No se pudo escribir el superblock completo")?;
+    let mut sealed = *sb;
+    sealed.seal();
 
-    Ok(())
+    store
+        .write_block(0, &sealed.to_block_bytes())
+        .with_context(|| "No se pudo escribir el superblock completo")
 }
 
-fn write_inode_disk(
+pub(crate) fn write_inode_disk(
     qr_folder: &Path,
     superblock: &SuperblockDisk,
     ino: u64,
     inode: &InodeDisk,
 ) -> Result<()> {
-    if ino == 0 || ino > superblock.max_inodes as u64 {
-        return Err(anyhow::anyhow!(
-            "Inodo fuera de rango en write_inode_disk: {} (max_inodes = {})",
-            ino,
-            superblock.max_inodes
-        ));
-    }
+    InodeTable::open(qr_folder, superblock)?.put(ino, inode)
+}
 
-    let inode_size = mem::size_of::<InodeDisk>();
-    let block_size = QRFS_BLOCK_SIZE as usize;
-    let total_bytes = (superblock.inode_table_blocks as usize) * block_size;
+pub(crate) fn write_fs_block(qr_folder: &Path, block_index: u32, data: &[u8]) -> Result<()> {
+    BlockStore::open(qr_folder)?.write_block(block_index, data)
+}
 
-    let entries = get_qr_entries(qr_folder)?;
-    let first_block = superblock.inode_table_start as usize;
-    let last_block_excl = first_block + superblock.inode_table_blocks as usize;
+/// Puntero `index` de un bloque de punteros (indirecto).
+pub(crate) fn block_ptr(table: &[u8], index: usize) -> u32 {
+    let off = index * 4;
+    u32::from_le_bytes([table[off], table[off + 1], table[off + 2], table[off + 3]])
+}
 
-    if last_block_excl > entries.len() {
-        return Err(anyhow::anyhow!(
-            "La tabla de inodos referencia bloques fuera de rango ({}..{} en {} archivos)",
-            first_block,
-            last_block_excl,
-            entries.len()
-        ));
-    }
+fn set_block_ptr(table: &mut [u8], index: usize, block: u32) {
+    table[index * 4..index * 4 + 4].copy_from_slice(&block.to_le_bytes());
+}
 
-    // Leer tabla de inodos completa
-    let mut buf = Vec::with_capacity(total_bytes);
-    for block_idx in first_block..last_block_excl {
-        let mut file = File::open(&entries[block_idx])
-            .with_context(|| format!("No se pudo abrir el bloque de inodos {:?}", entries[block_idx]))?;
-        let mut block_buf = vec![0u8; block_size];
-        file.read_exact(&mut block_buf)
-            .with_context(|| format!("No se pudo leer completamente el bloque {:?}", entries[block_idx]))?;
-        buf.extend_from_slice(&block_buf);
-    }
+/// Punteros distintos de cero de un bloque de punteros.
+fn table_ptrs(table: &[u8], block_size: u32) -> impl Iterator<Item = u32> + '_ {
+    let ptrs = (block_size as usize / 4).min(table.len() / 4);
+    (0..ptrs).map(|i| block_ptr(table, i)).filter(|&b| b != 0)
+}
 
-    let idx_bytes = (ino as usize - 1) * inode_size;
-    if idx_bytes + inode_size > buf.len() {
-        return Err(anyhow::anyhow!(
-            "Inodo {} fuera del rango de la tabla al escribir (idx_bytes = {}, len = {})",
-            ino,
-            idx_bytes,
-            buf.len()
-        ));
-    }
+/// Dónde guarda un inodo el puntero de un bloque lógico.
+#[derive(Clone, Copy)]
+enum PtrSlot {
+    /// `direct_blocks[i]`.
+    Direct(usize),
+    /// Entrada `i` del bloque indirecto simple.
+    Indirect(usize),
+    /// Entrada `outer` del doble indirecto, que apunta a un bloque de punteros
+    /// cuya entrada `entry` apunta al bloque de datos.
+    Double { outer: usize, entry: usize },
+}
 
-    unsafe {
-        let ptr = inode as *const InodeDisk as *const u8;
-        let slice = std::slice::from_raw_parts(ptr, inode_size);
-        buf[idx_bytes..idx_bytes + inode_size].copy_from_slice(slice);
+/// Ubicación del puntero del bloque lógico `logical`, o `None` si queda más
+/// allá de lo que un inodo puede direccionar.
+fn ptr_slot(block_size: u32, logical: u64) -> Option<PtrSlot> {
+    if logical < DIRECT_POINTERS {
+        return Some(PtrSlot::Direct(logical as usize));
     }
 
-    // Escribir tabla de inodos de vuelta
-    for (i, chunk) in buf.chunks(block_size).enumerate() {
-        let block_idx = first_block + i;
-        let mut file = File::create(&entries[block_idx])
-            .with_context(|| format!("No se pudo abrir el bloque de inodos {:?} para escritura", entries[block_idx]))?;
-        file.write_all(chunk)
-            .with_context(|| format!("No se pudo escribir completamente el bloque {:?}", entries[block_idx]))?;
+    let ptrs = (block_size / 4) as u64;
+    let idx = logical - DIRECT_POINTERS;
+    if idx < ptrs {
+        return Some(PtrSlot::Indirect(idx as usize));
     }
 
-    Ok(())
+    let idx = idx - ptrs;
+    (idx < ptrs * ptrs).then(|| PtrSlot::Double {
+        outer: (idx / ptrs) as usize,
+        entry: (idx % ptrs) as usize,
+    })
 }
 
-fn write_fs_block(qr_folder: &Path, block_index: u32, data: &[u8]) -> Result<()> {
-    let entries = get_qr_entries(qr_folder)?;
-    let idx = block_index as usize;
-
-    if idx >= entries.len() {
-        return Err(anyhow::anyhow!(
-            "Índice de bloque fuera de rango en write_fs_block: {} (hay {} archivos QR)",
-            idx,
-            entries.len()
-        ));
+/// Traduce el bloque lógico `logical` de un archivo (offset / block_size) al
+/// bloque físico que lo guarda: primero los 12 punteros directos, después los
+/// del bloque indirecto simple y al final los del doble indirecto. `None` =
+/// hueco (bloque no asignado) o fuera de lo que el inodo puede direccionar.
+pub(crate) fn logical_to_physical(
+    qr_folder: &Path,
+    superblock: &SuperblockDisk,
+    inode_disk: &InodeDisk,
+    logical: u64,
+) -> Result<Option<u32>> {
+    if inode_disk.is_inline() {
+        return Ok(None);
     }
 
-    let block_size = QRFS_BLOCK_SIZE as usize;
-    let mut buf = vec![0u8; block_size];
-    let len = std::cmp::min(block_size, data.len());
-    buf[..len].copy_from_slice(&data[..len]);
-
-    let mut file = File::create(&entries[idx])
-        .with_context(|| format!("No se pudo abrir el bloque {:?} para escritura", entries[idx]))?;
-    file.write_all(&buf)
-        .with_context(|| format!("No se pudo escribir completamente el bloque {:?}", entries[idx]))?;
-
-    Ok(())
+    let b = match ptr_slot(superblock.block_size, logical) {
+        None => 0,
+        Some(PtrSlot::Direct(i)) => inode_disk.direct_blocks[i],
+        Some(PtrSlot::Indirect(i)) => {
+            if inode_disk.indirect_block == 0 {
+                return Ok(None);
+            }
+            block_ptr(&read_fs_block(qr_folder, inode_disk.indirect_block)?, i)
+        }
+        Some(PtrSlot::Double { outer, entry }) => {
+            if inode_disk.double_indirect_block == 0 {
+                return Ok(None);
+            }
+            let mid = block_ptr(&read_fs_block(qr_folder, inode_disk.double_indirect_block)?, outer);
+            if mid == 0 {
+                return Ok(None);
+            }
+            block_ptr(&read_fs_block(qr_folder, mid)?, entry)
+        }
+    };
+    Ok((b != 0).then_some(b))
 }
 
-/// Asigna un bloque de datos libre en el bitmap (versión mínima: busca desde data_blocks_start)
-fn alloc_block(inner: &mut QrfsInner) -> Result<u32> {
-    let qr_folder = inner.qr_folder.clone();
+/// Asigna un bloque de datos libre en el bitmap. La búsqueda arranca en
+/// `next_free_hint` y da una sola vuelta por el área de datos, así en una imagen
+/// grande no se recorre desde el principio en cada asignación.
+pub(crate) fn alloc_block(inner: &mut QrfsInner) -> Result<u32> {
     let sb = &mut inner.superblock;
+    let bitmap = &mut inner.bitmap;
 
-    let mut bitmap = load_bitmap(&qr_folder, sb)?;
-
-    for b in sb.data_blocks_start..sb.total_blocks {
-        if !bitmap_test(&bitmap, b) {
+    let hint = if (sb.data_blocks_start..sb.total_blocks).contains(&inner.next_free_hint) {
+        inner.next_free_hint
+    } else {
+        sb.data_blocks_start
+    };
+    for b in (hint..sb.total_blocks).chain(sb.data_blocks_start..hint) {
+        // Un bloque dañado queda marcado usado, pero un bitmap reparado a mano
+        // podría haberlo liberado
+        if !bitmap_test(bitmap, b) && !sb.is_bad_block(b) {
             // Encontramos un bloque libre
-            bitmap_set(&mut bitmap, b, true);
+            bitmap_set(bitmap, b, true);
 
             if inner.free_blocks > 0 {
                 inner.free_blocks -= 1;
@@ -748,8 +1820,17 @@ fn alloc_block(inner: &mut QrfsInner) -> Result<u32> {
                 sb.free_blocks -= 1;
             }
 
-            write_bitmap(&qr_folder, sb, &bitmap)?;
-            write_superblock(&qr_folder, sb)?;
+            // Si no se puede guardar, el bloque vuelve a quedar libre en memoria
+            let store = inner.store();
+            if let Err(e) = write_bitmap_block(inner, b).and_then(|()| store_superblock(&store, &inner.superblock)) {
+                bitmap_set(&mut inner.bitmap, b, false);
+                inner.free_blocks += 1;
+                inner.superblock.free_blocks += 1;
+                return Err(e);
+            }
+            inner.stats.record_alloc();
+            inner.next_free_hint = b + 1;
+            forget_deleted_owner(inner, b)?;
             return Ok(b);
         }
     }
@@ -757,58 +1838,2244 @@ fn alloc_block(inner: &mut QrfsInner) -> Result<u32> {
     Err(anyhow::anyhow!("No hay bloques de datos libres disponibles"))
 }
 
-fn read_directory_from_disk(
-    qr_folder: &Path,
-    superblock: &SuperblockDisk,
-    ino: u64,
-) -> Result<Vec<dir::DirEntry>> {
-    // Cargar el inodo del directorio
-    let inode_disk = load_inode_disk(qr_folder, superblock, ino)?;
+/// Índice de bloques de los inodos borrados (ver `QrfsInner::deleted_blocks`);
+/// la primera vez recorre la tabla de inodos.
+fn deleted_blocks(inner: &mut QrfsInner) -> Result<&mut HashMap<u32, u64>> {
+    if inner.deleted_blocks.is_none() {
+        let mut index = HashMap::new();
+        for (ino, disk_inode) in InodeTable::new(inner.store(), inner.superblock).iter()? {
+            if !disk_inode.is_deleted() {
+                continue;
+            }
+            // Con punteros ilegibles ya no es recuperable: no hay nada que indexar
+            for b in file_blocks_disk(&inner.qr_folder, &inner.superblock, &disk_inode).unwrap_or_default() {
+                index.insert(b, ino);
+            }
+        }
+        inner.deleted_blocks = Some(index);
+    }
+    Ok(inner.deleted_blocks.get_or_insert_with(HashMap::new))
+}
 
-    if inode_disk.file_type != 2 {
-        return Err(anyhow::anyhow!(
-            "Inodo {} no es un directorio (file_type = {})",
-            ino,
-            inode_disk.file_type
-        ));
+/// `block` acaba de reservarse: el inodo borrado que lo listaba se escribe
+/// vacío (un slot libre), así undelete no le devuelve datos de otro archivo,
+/// ni siquiera si el bloque se vuelve a liberar después. El bitmap solo no
+/// alcanza para saberlo.
+fn forget_deleted_owner(inner: &mut QrfsInner, block: u32) -> Result<()> {
+    let Some(ino) = deleted_blocks(inner)?.remove(&block) else {
+        return Ok(());
+    };
+
+    // El slot pudo reusarse o recuperarse desde que se indexó
+    let disk_inode = inner.load_inode(ino)?;
+    if !disk_inode.is_deleted() {
+        return Ok(());
+    }
+    let blocks = file_blocks_disk(&inner.qr_folder, &inner.superblock, &disk_inode).unwrap_or_default();
+    if !blocks.contains(&block) {
+        return Ok(());
     }
 
-    // Versión mínima: suponemos que el directorio cabe en el primer bloque directo
-    let data_block = inode_disk.direct_blocks[0];
-    if data_block == 0 {
-        // Directorio vacío
-        return Ok(Vec::new());
+    write_inode_disk(&inner.qr_folder, &inner.superblock, ino, &InodeDisk::empty())?;
+    let index = deleted_blocks(inner)?;
+    for b in blocks {
+        if index.get(&b) == Some(&ino) {
+            index.remove(&b);
+        }
     }
+    Ok(())
+}
 
-    // Leer el bloque de datos correspondiente al directorio
-    let buf = read_fs_block(qr_folder, data_block)?;
+/// Elige un número de inodo libre, o `None` si la tabla de inodos está llena.
+/// Primero sigue el contador monótono (así un inodo recién borrado no se reusa
+/// enseguida y `undelete` conserva su ventana); agotado el contador, reusa
+/// slots nunca usados o liberados y, como último recurso, inodos borrados.
+///
+/// Deja el registro del inodo en disco en cero y avanza `next_ino`: el que
+/// llama debe escribir el inodo nuevo y registrarlo en `inodes` sin soltar el
+/// lock de escritura.
+pub(crate) fn alloc_inode(inner: &mut QrfsInner) -> Option<(u64, u32)> {
+    let max_inodes = inner.superblock.max_inodes as u64;
+
+    let ino = if inner.next_ino <= max_inodes && !inner.inodes.contains_key(&inner.next_ino) {
+        Some(inner.next_ino)
+    } else {
+        let mut deleted = None;
+        let mut free = None;
 
-    // Usar el helper del módulo dir para desempaquetar las entradas DirEntryDisk
-    let entries = dir::unpack_dir_entries(&buf);
+        for ino in 1..=max_inodes {
+            if inner.inodes.contains_key(&ino) {
+                continue;
+            }
+            // Un slot ilegible no se reusa: podría estar en uso
+            let Ok(disk_inode) = inner.load_inode(ino) else {
+                continue;
+            };
+            if disk_inode.is_deleted() {
+                deleted.get_or_insert(ino);
+            } else if disk_inode.id == 0 || disk_inode.nlink == 0 {
+                free = Some(ino);
+                break;
+            }
+        }
 
-    Ok(entries)
+        free.or(deleted)
+    }?;
+
+    // El slot puede venir de un archivo borrado o de uno que quedó a medias
+    // tras un corte: se pisa con un registro en cero antes de entregarlo, así
+    // el archivo nuevo nunca hereda punteros a bloques que ya son de otro. Sólo
+    // se conserva la generación, que avanza con cada entrega
+    let generation = inner
+        .load_inode(ino)
+        .map_or(0, |old| old.generation)
+        .wrapping_add(1);
+    let qr_folder = inner.qr_folder.clone();
+    let cleared = InodeDisk {
+        generation,
+        ..InodeDisk::empty()
+    };
+    if let Err(e) = write_inode_disk(&qr_folder, &inner.superblock, ino, &cleared) {
+        eprintln!("No se pudo limpiar el inodo {} antes de reusarlo: {e:?}", ino);
+        return None;
+    }
+    inner.files.remove(&ino);
+
+    inner.next_ino = inner.next_ino.max(ino + 1);
+    Some((ino, generation))
 }
 
+/// Libera un bloque de datos (contraparte de `alloc_block`): limpia su bit en el
+/// bitmap, repone los contadores de bloques libres y persiste ambos. Con
+/// `zero_on_free` además borra su contenido.
+pub(crate) fn free_block(inner: &mut QrfsInner, block: u32) -> Result<()> {
+    let sb = &mut inner.superblock;
 
+    if block < sb.data_blocks_start || block >= sb.total_blocks {
+        return Err(anyhow::anyhow!(
+            "No se puede liberar el bloque {}: fuera del área de datos ({}..{})",
+            block,
+            sb.data_blocks_start,
+            sb.total_blocks
+        ));
+    }
 
-// -----------------------------------------------------------------------------
-// Implementación FUSE 
-// -----------------------------------------------------------------------------
+    // Ya estaba libre: no tocamos los contadores. Un bloque dañado no vuelve a
+    // quedar libre aunque lo suelte su archivo
+    if !bitmap_test(&inner.bitmap, block) || sb.is_bad_block(block) {
+        return Ok(());
+    }
 
-impl Filesystem for QrfsFilesystem {
-    
-    // getattr: info de un inodo
-    fn getattr(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyAttr) {
-        println!("getattr llamado: ino = {ino}");
-        let inner = self.inner.read().unwrap();
+    bitmap_set(&mut inner.bitmap, block, false);
+    inner.free_blocks += 1;
+    sb.free_blocks += 1;
 
-        if let Some(inode) = inner.inodes.get(&ino) {
-            let attr = inode_to_attr(inode);
-            let ttl = Duration::from_secs(1);
-            reply.attr(&ttl, &attr);
-        } else {
-            reply.error(ENOENT);
-        }
+    let store = inner.store();
+    if let Err(e) = write_bitmap_block(inner, block).and_then(|()| store_superblock(&store, &inner.superblock)) {
+        bitmap_set(&mut inner.bitmap, block, true);
+        inner.free_blocks -= 1;
+        inner.superblock.free_blocks -= 1;
+        return Err(e);
+    }
+    if inner.zero_on_free {
+        store.write_block(block, &vec![0u8; QRFS_BLOCK_SIZE as usize])?;
+    }
+    inner.stats.record_free();
+    // Un hueco por debajo de la pista se aprovecha en la próxima asignación
+    inner.next_free_hint = inner.next_free_hint.min(block);
+    Ok(())
+}
+
+/// El kernel soltó `nlookup` referencias a `ino`. Sin referencias, el inodo sale
+/// de `inodes` (y su contenido de `files`, volcado antes a sus bloques) hasta
+/// el próximo lookup, que lo vuelve a leer de disco. Así la memoria depende de
+/// los archivos que el kernel tiene en uso y no de todos los que se tocaron.
+/// Quedan siempre en memoria la raíz, los directorios (el árbol se arma al
+/// montar) y los archivos con locks.
+pub(crate) fn forget_inode(inner: &mut QrfsInner, ino: u64, nlookup: u64) {
+    let lookups = inner.lookups.get_mut().unwrap();
+    match lookups.get_mut(&ino) {
+        Some(count) if *count > nlookup => {
+            *count -= nlookup;
+            return;
+        }
+        Some(_) => {
+            lookups.remove(&ino);
+        }
+        None => {}
+    }
+
+    if ino == inner.root_ino || inner.directories.contains_key(&ino) || inner.locks.contains_key(&ino) {
+        return;
+    }
+
+    // Un archivo residente puede tener en RAM datos que no están en disco
+    if let Err(e) = flush_resident(inner, ino) {
+        eprintln!("Error al volcar el archivo {} a disco antes de olvidarlo: {e:?}", ino);
+        return;
+    }
+
+    inner.files.remove(&ino);
+    inner.inodes.remove(&ino);
+}
+
+/// Vuelca a sus bloques el contenido residente de `ino` si tiene escrituras
+/// pendientes (está en `dirty_files`). El archivo sigue en `files`. Con la
+/// extensión inline-data, un archivo que entra en el inodo y no tiene bloques
+/// se guarda inline.
+pub(crate) fn flush_resident(inner: &mut QrfsInner, ino: u64) -> Result<()> {
+    if !inner.dirty_files.contains(&ino) {
+        return Ok(());
+    }
+    // Borrado mientras era residente: no queda nada que volcar
+    let Some(buf) = inner.files.get(&ino) else {
+        inner.dirty_files.remove(&ino);
+        return Ok(());
+    };
+    let size = inner.inodes.get(&ino).map_or(buf.len(), |i| (i.size as usize).min(buf.len()));
+    let data = buf[..size].to_vec();
+
+    #[cfg(feature = "inline-data")]
+    {
+        let mut disk_inode = inner.load_inode(ino)?;
+        if data.len() <= QRFS_INLINE_MAX
+            && !disk_inode.is_compressed()
+            && (disk_inode.is_inline() || file_blocks_disk(&inner.qr_folder, &inner.superblock, &disk_inode)?.is_empty())
+        {
+            let now = SystemTime::now();
+            disk_inode.set_inline_data(&data);
+            disk_inode.set_mtime(now);
+            disk_inode.set_ctime(now);
+            write_inode_disk(&inner.qr_folder, &inner.superblock, ino, &disk_inode)?;
+            inner.dirty_files.remove(&ino);
+            return Ok(());
+        }
+    }
+
+    write_range_disk(inner, ino, 0, &data)?;
+    inner.dirty_files.remove(&ino);
+    Ok(())
+}
+
+/// Convierte `offset..offset + len` de un archivo en un hueco sin cambiar su
+/// tamaño: los bloques cubiertos por completo vuelven al bitmap y su puntero
+/// queda en 0 (se leen como ceros); los bordes parciales se llenan con ceros.
+/// Los bloques se liberan recién después de persistir los punteros, así nunca
+/// queda el inodo apuntando a un bloque libre.
+pub(crate) fn punch_hole(inner: &mut QrfsInner, ino: u64, offset: u64, len: u64) -> Result<()> {
+    let qr_folder = inner.qr_folder.clone();
+    let mut disk_inode = inner.load_inode(ino)?;
+    let mut to_free = Vec::new();
+
+    let size = disk_inode.size;
+    let end = offset.saturating_add(len).min(size);
+    if offset >= end {
+        return Ok(());
+    }
+
+    // Un archivo comprimido no tiene huecos: el rango se reescribe con ceros,
+    // que ocupan casi nada una vez comprimidos
+    if disk_inode.is_compressed() {
+        return write_range_disk(inner, ino, offset, &vec![0u8; (end - offset) as usize]);
+    }
+
+    // El buffer en RAM tiene prioridad en read: también se pone en cero
+    if let Some(buf) = inner.files.get_mut(&ino) {
+        let buf_end = (end as usize).min(buf.len());
+        if (offset as usize) < buf_end {
+            buf[offset as usize..buf_end].fill(0);
+        }
+    }
+
+    if disk_inode.is_inline() {
+        let mut data = disk_inode.inline_data().to_vec();
+        data[offset as usize..end as usize].fill(0);
+        disk_inode.set_inline_data(&data);
+    } else {
+        let block_size = inner.superblock.block_size as u64;
+        // Bloques de punteros leídos (cada uno una sola vez) y los que cambiaron
+        let mut tables: HashMap<u32, Vec<u8>> = HashMap::new();
+        let mut dirty: HashSet<u32> = HashSet::new();
+
+        for logical in offset / block_size..=(end - 1) / block_size {
+            let block_start = logical * block_size;
+            let from = offset.max(block_start);
+            let to = end.min(block_start + block_size);
+
+            // Bloque de punteros y entrada que apuntan al bloque (None = directo).
+            // Sin bloque de punteros, o más allá de lo direccionable, ya es un hueco
+            let (table, entry) = match ptr_slot(inner.superblock.block_size, logical) {
+                Some(PtrSlot::Direct(i)) => (None, i),
+                Some(PtrSlot::Indirect(i)) if disk_inode.indirect_block != 0 => {
+                    (Some(disk_inode.indirect_block), i)
+                }
+                Some(PtrSlot::Double { outer, entry }) if disk_inode.double_indirect_block != 0 => {
+                    let outer_table = cached_table(&mut tables, &qr_folder, disk_inode.double_indirect_block)?;
+                    match block_ptr(outer_table, outer) {
+                        0 => continue,
+                        mid => (Some(mid), entry),
+                    }
+                }
+                _ => continue,
+            };
+            let b = match table {
+                None => disk_inode.direct_blocks[entry],
+                Some(t) => block_ptr(cached_table(&mut tables, &qr_folder, t)?, entry),
+            };
+            if b == 0 {
+                continue; // ya es un hueco
+            }
+
+            // El último bloque cuenta como cubierto si el hueco llega hasta el EOF
+            let covered = from == block_start && (to == block_start + block_size || to == size);
+            if covered {
+                match table {
+                    None => disk_inode.direct_blocks[entry] = 0,
+                    Some(t) => {
+                        set_block_ptr(cached_table(&mut tables, &qr_folder, t)?, entry, 0);
+                        dirty.insert(t);
+                    }
+                }
+                to_free.push(b);
+            } else {
+                let mut data = read_fs_block(&qr_folder, b)?;
+                data[(from - block_start) as usize..(to - block_start) as usize].fill(0);
+                write_fs_block(&qr_folder, b, &data)?;
+            }
+        }
+
+        for t in dirty {
+            write_fs_block(&qr_folder, t, &tables[&t])?;
+        }
+    }
+
+    let now = SystemTime::now();
+    disk_inode.set_mtime(now);
+    disk_inode.set_ctime(now);
+    write_inode_disk(&qr_folder, &inner.superblock, ino, &disk_inode)?;
+
+    for b in to_free {
+        free_block(inner, b)?;
+    }
+
+    if let Some(inode) = inner.inodes.get_mut(&ino) {
+        inode.mtime = now;
+        inode.ctime = now;
+    }
+    Ok(())
+}
+
+/// Bloque de punteros `block`, leído de disco la primera vez que se pide.
+fn cached_table<'a>(tables: &'a mut HashMap<u32, Vec<u8>>, qr_folder: &Path, block: u32) -> Result<&'a mut Vec<u8>> {
+    Ok(match tables.entry(block) {
+        Entry::Occupied(e) => e.into_mut(),
+        Entry::Vacant(e) => e.insert(read_fs_block(qr_folder, block)?),
+    })
+}
+
+/// Bloques que ocupa un archivo en disco: los de `data_blocks` más, si tiene,
+/// el bloque indirecto y los bloques de datos que lista, y el doble indirecto
+/// con cada bloque de punteros que lista y los datos de cada uno.
+pub(crate) fn file_blocks_disk(
+    qr_folder: &Path,
+    superblock: &SuperblockDisk,
+    disk_inode: &InodeDisk,
+) -> Result<Vec<u32>> {
+    let mut blocks = disk_inode.data_blocks();
+    if disk_inode.is_inline() {
+        return Ok(blocks);
+    }
+
+    if disk_inode.indirect_block != 0 {
+        let buf = read_fs_block(qr_folder, disk_inode.indirect_block)?;
+        blocks.push(disk_inode.indirect_block);
+        blocks.extend(table_ptrs(&buf, superblock.block_size));
+    }
+
+    if disk_inode.double_indirect_block != 0 {
+        let outer = read_fs_block(qr_folder, disk_inode.double_indirect_block)?;
+        blocks.push(disk_inode.double_indirect_block);
+        for mid in table_ptrs(&outer, superblock.block_size) {
+            let buf = read_fs_block(qr_folder, mid)?;
+            blocks.push(mid);
+            blocks.extend(table_ptrs(&buf, superblock.block_size));
+        }
+    }
+    Ok(blocks)
+}
+
+/// Permisos (`R_OK`/`W_OK`) que necesita un `open` con `flags`: los del modo
+/// de apertura, y escritura también con `O_TRUNC`.
+pub(crate) fn open_access_mask(flags: i32) -> i32 {
+    let mask = match flags & libc::O_ACCMODE {
+        libc::O_WRONLY => libc::W_OK,
+        libc::O_RDWR => libc::R_OK | libc::W_OK,
+        _ => libc::R_OK,
+    };
+    if flags & libc::O_TRUNC != 0 {
+        mask | libc::W_OK
+    } else {
+        mask
+    }
+}
+
+/// Cambios de atributos que pide `setattr`; `None` deja el valor como está
+/// (para los tiempos, `UTIME_OMIT`).
+#[derive(Default)]
+pub(crate) struct AttrChanges {
+    pub mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub size: Option<u64>,
+    pub atime: Option<TimeOrNow>,
+    pub mtime: Option<TimeOrNow>,
+}
+
+/// Aplica `changes` al inodo `ino` en memoria y en disco (un tamaño distinto
+/// trunca el archivo) y devuelve los atributos nuevos, con el número de inodo
+/// interno. `ctime` pasa a ser ahora.
+pub(crate) fn set_attr(inner: &mut QrfsInner, ino: u64, changes: &AttrChanges) -> Result<FileAttr, libc::c_int> {
+    let (kind, old_size) = match inner.inodes.get(&ino) {
+        Some(i) => (i.kind, i.size),
+        None => return Err(ENOENT),
+    };
+
+    // Cambiar el tamaño (truncate): sólo archivos regulares. Los bloques que
+    // sobran vuelven al bitmap y a los contadores de libres
+    if let Some(new_size) = changes.size {
+        if new_size != old_size {
+            if kind != FileType::RegularFile {
+                return Err(libc::EISDIR);
+            }
+            if new_size > max_file_size(inner.superblock.block_size) {
+                return Err(libc::EFBIG);
+            }
+            if let Err(e) = truncate_disk(inner, ino, new_size) {
+                eprintln!("Error al truncar el inodo {} a {} bytes: {e:?}", ino, new_size);
+                return Err(libc::EIO);
+            }
+        }
+    }
+
+    let inode = inner.inodes.get_mut(&ino).ok_or(ENOENT)?;
+
+    // UTIME_OMIT llega como None (se conserva el valor), UTIME_NOW como Now
+    let now = SystemTime::now();
+    let resolve = |t: TimeOrNow| match t {
+        TimeOrNow::SpecificTime(t) => t,
+        TimeOrNow::Now => now,
+    };
+
+    if let Some(mode) = changes.mode {
+        inode.perm = (mode & 0o7777) as u16;
+    }
+    if let Some(uid) = changes.uid {
+        inode.uid = uid;
+    }
+    if let Some(gid) = changes.gid {
+        inode.gid = gid;
+    }
+    if let Some(t) = changes.atime {
+        inode.atime = resolve(t);
+    }
+    if let Some(t) = changes.mtime {
+        inode.mtime = resolve(t);
+    }
+    inode.ctime = now;
+
+    let attr = inode_to_attr(inode);
+    let updated = inode.clone();
+
+    // Persistir en el inodo de disco
+    if ino <= inner.superblock.max_inodes as u64 {
+        let res = inner.load_inode(ino).and_then(|mut disk_inode| {
+            disk_inode.perm = updated.perm;
+            disk_inode.uid = updated.uid;
+            disk_inode.gid = updated.gid;
+            disk_inode.set_atime(updated.atime);
+            disk_inode.set_mtime(updated.mtime);
+            disk_inode.set_ctime(updated.ctime);
+            write_inode_disk(&inner.qr_folder, &inner.superblock, ino, &disk_inode)
+        });
+
+        if let Err(e) = res {
+            eprintln!("Error al actualizar atributos del inodo {} en disco: {e:?}", ino);
+        }
+    }
+
+    Ok(attr)
+}
+
+/// Resultado de `lseek` con `SEEK_DATA` o `SEEK_HOLE` (`whence`) desde
+/// `offset`: el próximo byte con datos o el comienzo del próximo hueco, que a
+/// lo sumo es el EOF. `ENXIO` si `offset` está en el EOF o más allá, o si no
+/// quedan datos.
+pub(crate) fn seek_data_or_hole(inner: &QrfsInner, ino: u64, offset: u64, whence: i32) -> Result<u64, libc::c_int> {
+    let inode_disk = inner.load_inode(ino).map_err(|e| {
+        eprintln!("Error en lseek al cargar inodo {ino} desde disco: {e:?}");
+        libc::EIO
+    })?;
+
+    let size = inode_disk.size;
+    if offset >= size {
+        return Err(libc::ENXIO);
+    }
+
+    // Datos inline o comprimidos: todo el archivo es dato, el único hueco es el EOF
+    if inode_disk.is_inline() || inode_disk.is_compressed() {
+        return Ok(if whence == libc::SEEK_DATA { offset } else { size });
+    }
+
+    let block_size = inner.superblock.block_size as u64;
+    let last_block = (size - 1) / block_size;
+
+    for logical in offset / block_size..=last_block {
+        let mapped = logical_to_physical(&inner.qr_folder, &inner.superblock, &inode_disk, logical).map_err(|e| {
+            eprintln!("Error en lseek al resolver el bloque {logical} del inodo {ino}: {e:?}");
+            libc::EIO
+        })?;
+
+        let is_data = mapped.is_some();
+        if is_data == (whence == libc::SEEK_DATA) {
+            return Ok((logical * block_size).max(offset));
+        }
+    }
+
+    // Sin más datos: ENXIO. Sin más huecos: el EOF cuenta como hueco.
+    if whence == libc::SEEK_DATA {
+        Err(libc::ENXIO)
+    } else {
+        Ok(size)
+    }
+}
+
+/// Lectura de `read` sobre un archivo (no los virtuales): hasta `size` bytes
+/// desde `offset`, nunca más allá del tamaño lógico. Un archivo residente sale
+/// entero de RAM; uno que no lo es, entero de disco. `offset + size` no debe
+/// pasar de `i64::MAX` (`read` lo verifica antes).
+pub(crate) fn read_file_data(inner: &QrfsInner, ino: u64, fh: u64, offset: u64, size: u32) -> Result<Vec<u8>, libc::c_int> {
+    let qr_folder = &inner.qr_folder;
+    let superblock = inner.superblock;
+
+    // 1) Archivo residente: todo sale de RAM, sin mirar el disco
+    if let Some(data) = inner.files.get(&ino) {
+        // El buffer puede ser más largo que el archivo (un truncate sólo baja
+        // inode.size): lo que manda es el tamaño lógico
+        let len = inner
+            .inodes
+            .get(&ino)
+            .map_or(data.len(), |i| usize::try_from(i.size).unwrap_or(usize::MAX).min(data.len()));
+        // Más allá del EOF => start == len y to_read == 0
+        let start = usize::try_from(offset).unwrap_or(usize::MAX).min(len);
+        // Nunca más de lo pedido ni más de lo que queda del archivo
+        let to_read = (size as usize).min(len - start);
+
+        inner.stats.record_read(to_read, true);
+        return Ok(data[start..start + to_read].to_vec());
+    }
+
+    // 2) Archivo no residente: todo sale de disco usando InodeDisk + bloques
+    //    (directos y los de los indirectos)
+    let inode_disk = inner.load_inode(ino).map_err(|e| {
+        eprintln!("Error en read al cargar inodo {ino} desde disco: {e:?}");
+        libc::EIO
+    })?;
+
+    // Si es directorio, no lo tratamos como archivo de datos
+    if inode_disk.is_dir() {
+        return Err(libc::EISDIR);
+    }
+
+    // Datos inline: viven dentro del propio inodo
+    if inode_disk.is_inline() {
+        let inline = inode_disk.inline_data();
+        let start = usize::try_from(offset).unwrap_or(usize::MAX).min(inline.len());
+        let end = start.saturating_add(size as usize).min(inline.len());
+        inner.stats.record_read(end - start, false);
+        return Ok(inline[start..end].to_vec());
+    }
+
+    // Datos comprimidos: se desempaqueta el archivo entero y se corta el rango
+    if inode_disk.is_compressed() {
+        let content = read_compressed(qr_folder, &superblock, &inode_disk).map_err(|e| {
+            eprintln!("Error leyendo los datos comprimidos del inodo {ino}: {e:?}");
+            libc::EIO
+        })?;
+        let start = usize::try_from(offset).unwrap_or(usize::MAX).min(content.len());
+        let end = start.saturating_add(size as usize).min(content.len());
+        inner.stats.record_read(end - start, false);
+        return Ok(content[start..end].to_vec());
+    }
+
+    let file_size = inode_disk.size;
+    if offset >= file_size {
+        // Más allá del EOF
+        return Ok(Vec::new());
+    }
+
+    let to_read = (size as u64).min(file_size - offset) as usize;
+
+    let block_size = superblock.block_size as u64;
+    let start = offset;
+    // No desborda: to_read <= file_size - offset
+    let end = offset + to_read as u64;
+
+    let first_block_idx = start / block_size;
+    let last_block_idx = (end - 1) / block_size;
+
+    let mut result = Vec::with_capacity(to_read);
+
+    for i in first_block_idx..=last_block_idx {
+        // Parte válida de este bloque: desde el offset pedido (primer bloque)
+        // hasta el final del rango, que nunca pasa de file_size (último bloque)
+        let block_start = i * block_size;
+        let in_block_start = (start.max(block_start) - block_start) as usize;
+        let in_block_end = (end.min(block_start + block_size) - block_start) as usize;
+
+        // Directo o de un indirecto; más allá de lo direccionable también es hueco
+        let b = match logical_to_physical(qr_folder, &superblock, &inode_disk, i) {
+            Ok(Some(b)) => b,
+            Ok(None) => {
+                // Bloque no asignado (hueco): ceros, sólo por la parte válida
+                result.resize(result.len() + (in_block_end - in_block_start), 0);
+                continue;
+            }
+            Err(e) => {
+                eprintln!("Error leyendo un bloque indirecto del inodo {ino}: {e:?}");
+                return Err(libc::EIO);
+            }
+        };
+
+        let block_data = inner.read_data_block(b).map_err(|e| {
+            eprintln!("Error leyendo bloque de datos {b} para inodo {ino}: {e:?}");
+            libc::EIO
+        })?;
+
+        let in_block_end = in_block_end.min(block_data.len());
+        if in_block_start < in_block_end {
+            result.extend_from_slice(&block_data[in_block_start..in_block_end]);
+        }
+    }
+
+    debug_assert!(result.len() <= to_read);
+
+    inner.read_ahead_after(ino, fh, &inode_disk, offset, end);
+    inner.stats.record_read(result.len(), false);
+    Ok(result)
+}
+
+/// Escritura de `write` sobre un archivo regular (no los virtuales). Devuelve
+/// los bytes escritos o el errno: `EINVAL` si `offset + len` desborda, `EFBIG`
+/// si ningún inodo lo puede direccionar.
+pub(crate) fn write_file_data(inner: &mut QrfsInner, ino: u64, offset: u64, data: &[u8]) -> Result<u32, libc::c_int> {
+    // Escribir 0 bytes no cambia nada: ni el tamaño (aunque el offset esté más
+    // allá del final), ni mtime, ni bloques
+    if data.is_empty() {
+        return Ok(0);
+    }
+
+    // El archivo debe existir: en memoria o, si no está residente, en disco
+    let resident = inner.files.contains_key(&ino);
+    if !resident && !inner.inodes.get(&ino).is_some_and(|i| i.kind == FileType::RegularFile) {
+        return Err(libc::ENOENT);
+    }
+
+    // El buffer crece hasta offset + len: se rechaza lo que desbordaría y lo que
+    // ningún inodo puede direccionar (directos e indirectos), en vez de
+    // intentar reservar memoria para un offset cerca de i64::MAX
+    let addressable = max_file_size(inner.superblock.block_size);
+    let needed_len = usize::try_from(offset)
+        .ok()
+        .and_then(|off| off.checked_add(data.len()))
+        .ok_or(libc::EINVAL)?;
+    if needed_len as u64 > addressable {
+        return Err(libc::EFBIG);
+    }
+    inner.stats.record_write(data.len());
+
+    // Un archivo residente que pasaría --resident-limit se vuelca entero a sus
+    // bloques y sale de memoria; los no residentes se escriben directo en disco,
+    // así la memoria usada no depende del tamaño del archivo
+    let spill = resident && inner.resident_limit.is_some_and(|limit| needed_len as u64 > limit);
+    if spill {
+        if let Err(e) = flush_resident(inner, ino) {
+            eprintln!("Error al volcar el archivo {} a disco: {e:?}", ino);
+            return Err(libc::EIO);
+        }
+        inner.files.remove(&ino);
+    }
+    if spill || !resident {
+        return match write_range_disk(inner, ino, offset, data) {
+            Ok(()) => Ok(data.len() as u32),
+            Err(e) => {
+                eprintln!("Error al escribir en disco el archivo {}: {e:?}", ino);
+                Err(libc::EIO)
+            }
+        };
+    }
+
+    let buf = inner.files.get_mut(&ino).ok_or(libc::ENOENT)?;
+    let offset_usize = offset as usize;
+
+    if buf.len() < needed_len {
+        buf.resize(needed_len, 0);
+    }
+
+    buf[offset_usize..offset_usize + data.len()].copy_from_slice(data);
+
+    // Actualizar inodo lógico (tamaño y tiempos)
+    if let Some(inode) = inner.inodes.get_mut(&ino) {
+        let new_size = needed_len as u64;
+        if new_size > inode.size {
+            inode.size = new_size;
+        }
+        let now = SystemTime::now();
+        inode.mtime = now;
+        inode.ctime = now;
+    }
+
+    // Queda en RAM hasta el próximo flush_resident (sync, fsync o forget)
+    inner.dirty_files.insert(ino);
+
+    Ok(data.len() as u32)
+}
+
+/// Escribe `data` en `offset` directamente en los bloques del archivo `ino`,
+/// sin pasar por `inner.files`: lee y reescribe sólo los bloques tocados y
+/// reserva los que falten (los 12 directos, después los del indirecto y los del
+/// doble indirecto). Un inodo inline pasa antes a bloques. Al final actualiza
+/// tamaño y tiempos del inodo en disco y en memoria.
+pub(crate) fn write_range_disk(inner: &mut QrfsInner, ino: u64, offset: u64, data: &[u8]) -> Result<()> {
+    let qr_folder = inner.qr_folder.clone();
+    let mut disk_inode = inner.load_inode(ino)?;
+
+    let mut to_free = Vec::new();
+    if disk_inode.is_compressed() {
+        to_free = rewrite_compressed_disk(inner, &mut disk_inode, offset, data)?;
+    } else {
+        if disk_inode.is_inline() {
+            let inline = disk_inode.inline_data().to_vec();
+            disk_inode.clear_inline_data();
+            write_blocks_disk(inner, &mut disk_inode, 0, &inline)?;
+        }
+        write_blocks_disk(inner, &mut disk_inode, offset, data)?;
+    }
+
+    let end = offset + data.len() as u64;
+    let now = SystemTime::now();
+    disk_inode.size = disk_inode.size.max(end);
+    disk_inode.set_mtime(now);
+    disk_inode.set_ctime(now);
+    write_inode_disk(&qr_folder, &inner.superblock, ino, &disk_inode)?;
+
+    // Los bloques que el archivo comprimido dejó de usar, recién con el inodo escrito
+    for b in to_free {
+        free_block(inner, b)?;
+    }
+
+    if let Some(inode) = inner.inodes.get_mut(&ino) {
+        inode.size = disk_inode.size;
+        inode.mtime = now;
+        inode.ctime = now;
+    }
+    Ok(())
+}
+
+/// Tipo en disco de un archivo regular nuevo: con `--compress` lleva la marca
+/// `QRFS_INODE_COMPRESSED` y el superblock en memoria pasa a anunciar la
+/// extensión (el que llama escribe el superblock).
+pub(crate) fn new_file_type(inner: &mut QrfsInner) -> u16 {
+    if !inner.compress {
+        return 1;
+    }
+    inner.superblock.features |= QRFS_FEATURE_COMPRESSION;
+    1 | QRFS_INODE_COMPRESSED
+}
+
+/// Si un archivo recién creado con `file_type` guarda su contenido en
+/// `inner.files`. Uno comprimido no (cada write lo reescribe en disco), y con
+/// --no-page-cache-buffer ninguno.
+pub(crate) fn starts_resident(inner: &QrfsInner, file_type: u16) -> bool {
+    file_type & QRFS_INODE_COMPRESSED == 0 && !inner.no_page_cache_buffer
+}
+
+/// Contenido completo de un archivo comprimido: desempaqueta sus bloques en
+/// orden hasta juntar `size` bytes. Un hueco antes de eso es un error (un
+/// archivo comprimido no tiene huecos). Un inodo inline se lee tal cual.
+pub(crate) fn read_compressed(
+    qr_folder: &Path,
+    superblock: &SuperblockDisk,
+    disk_inode: &InodeDisk,
+) -> Result<Vec<u8>> {
+    if disk_inode.is_inline() {
+        return Ok(disk_inode.inline_data());
+    }
+
+    let size = usize::try_from(disk_inode.size).unwrap_or(usize::MAX);
+    let mut data = Vec::new();
+    let mut logical = 0;
+    while data.len() < size {
+        let block = logical_to_physical(qr_folder, superblock, disk_inode, logical)?.ok_or_else(|| {
+            anyhow::anyhow!(
+                "Al inodo {} le falta el bloque comprimido {} ({} de {} bytes leídos)",
+                disk_inode.id,
+                logical,
+                data.len(),
+                size
+            )
+        })?;
+        compress::unpack_block(&read_file_block(qr_folder, superblock, block)?, &mut data)
+            .with_context(|| format!("Bloque {} del inodo {}", block, disk_inode.id))?;
+        logical += 1;
+    }
+    data.truncate(size);
+    Ok(data)
+}
+
+/// Parte de `write_range_disk` para un archivo comprimido: aplica la escritura
+/// al contenido completo, lo vuelve a empaquetar en los bloques lógicos
+/// 0..n (reusando los que ya tiene) y quita los punteros que sobran. Devuelve
+/// esos bloques para liberarlos después de escribir el inodo.
+fn rewrite_compressed_disk(
+    inner: &mut QrfsInner,
+    disk_inode: &mut InodeDisk,
+    offset: u64,
+    data: &[u8],
+) -> Result<Vec<u32>> {
+    let qr_folder = inner.qr_folder.clone();
+
+    let mut content = read_compressed(&qr_folder, &inner.superblock, disk_inode)?;
+    if disk_inode.is_inline() {
+        disk_inode.clear_inline_data();
+    }
+    let start = offset as usize;
+    let end = start + data.len();
+    if content.len() < end {
+        content.resize(end, 0);
+    }
+    content[start..end].copy_from_slice(data);
+
+    repack_compressed_disk(inner, disk_inode, &content)
+}
+
+/// Empaqueta `content` en los bloques lógicos 0..n de un archivo comprimido
+/// (reusando los que ya tiene) y quita los punteros que sobran. Devuelve esos
+/// bloques para liberarlos después de escribir el inodo.
+fn repack_compressed_disk(inner: &mut QrfsInner, disk_inode: &mut InodeDisk, content: &[u8]) -> Result<Vec<u32>> {
+    let qr_folder = inner.qr_folder.clone();
+    let block_size = inner.superblock.block_size;
+
+    let packed = compress::pack(content, block_size as usize);
+    for (logical, buf) in packed.iter().enumerate() {
+        let (block, _) = block_for_write(inner, disk_inode, logical as u64)?;
+        write_fs_block(&qr_folder, block, buf)?;
+    }
+
+    // El contenido nuevo puede ocupar menos bloques que el anterior
+    release_tail_blocks(&qr_folder, block_size, disk_inode, packed.len() as u64)
+}
+
+/// Cambia el tamaño del archivo `ino` en disco (truncate). Al achicarlo, los
+/// bloques que quedan enteros más allá del final (y los bloques de punteros
+/// vacíos) vuelven al bitmap con `free_block` y el resto del último bloque se
+/// pone en cero, así un crecimiento posterior lee ceros. Al agrandarlo sólo
+/// cambia el tamaño: lo nuevo es un hueco. Como en `punch_hole`, los bloques se
+/// liberan recién después de escribir el inodo.
+pub(crate) fn truncate_disk(inner: &mut QrfsInner, ino: u64, new_size: u64) -> Result<()> {
+    let qr_folder = inner.qr_folder.clone();
+    let block_size = inner.superblock.block_size as u64;
+    let mut disk_inode = inner.load_inode(ino)?;
+    let mut to_free = Vec::new();
+
+    if new_size > max_file_size(inner.superblock.block_size) {
+        return Err(anyhow::anyhow!(
+            "El inodo {} no puede direccionar {} bytes",
+            ino,
+            new_size
+        ));
+    }
+
+    if disk_inode.is_compressed() {
+        // Sin huecos: el contenido se recorta o se completa con ceros y se reempaqueta
+        let mut content = read_compressed(&qr_folder, &inner.superblock, &disk_inode)?;
+        content.resize(new_size as usize, 0);
+        if disk_inode.is_inline() {
+            disk_inode.clear_inline_data();
+        }
+        to_free = repack_compressed_disk(inner, &mut disk_inode, &content)?;
+    } else if disk_inode.is_inline() {
+        let mut data = disk_inode.inline_data();
+        data.resize(new_size as usize, 0);
+        if data.len() <= QRFS_INLINE_MAX {
+            disk_inode.set_inline_data(&data);
+        } else {
+            disk_inode.clear_inline_data();
+            write_blocks_disk(inner, &mut disk_inode, 0, &data[..QRFS_INLINE_MAX])?;
+        }
+    } else if new_size < disk_inode.size {
+        let keep = new_size.div_ceil(block_size);
+        to_free = release_tail_blocks(&qr_folder, block_size as u32, &mut disk_inode, keep)?;
+
+        let tail = new_size % block_size;
+        if tail != 0 {
+            if let Some(b) = logical_to_physical(&qr_folder, &inner.superblock, &disk_inode, keep - 1)? {
+                let mut data = read_fs_block(&qr_folder, b)?;
+                data[tail as usize..].fill(0);
+                write_fs_block(&qr_folder, b, &data)?;
+            }
+        }
+    }
+
+    let now = SystemTime::now();
+    disk_inode.size = new_size;
+    disk_inode.set_mtime(now);
+    disk_inode.set_ctime(now);
+    write_inode_disk(&qr_folder, &inner.superblock, ino, &disk_inode)?;
+
+    for b in to_free {
+        free_block(inner, b)?;
+    }
+
+    // El buffer en RAM tiene prioridad en read: se recorta igual que el disco
+    if let Some(buf) = inner.files.get_mut(&ino) {
+        buf.resize(new_size as usize, 0);
+    }
+    if let Some(inode) = inner.inodes.get_mut(&ino) {
+        inode.size = new_size;
+        inode.mtime = now;
+        inode.ctime = now;
+    }
+    Ok(())
+}
+
+/// Quita los punteros de los bloques lógicos desde `keep` en adelante, y los
+/// bloques de punteros que quedan sin ningún bloque que listar. Los bloques de
+/// punteros que siguen en uso se escriben ya; el inodo cambia sólo en memoria.
+/// Devuelve los bloques a liberar una vez escrito el inodo.
+fn release_tail_blocks(
+    qr_folder: &Path,
+    block_size: u32,
+    disk_inode: &mut InodeDisk,
+    keep: u64,
+) -> Result<Vec<u32>> {
+    let mut to_free = Vec::new();
+    for slot in disk_inode.direct_blocks.iter_mut().skip(keep as usize) {
+        if *slot != 0 {
+            to_free.push(*slot);
+            *slot = 0;
+        }
+    }
+
+    let ptrs = (block_size / 4) as u64;
+    let keep = keep.saturating_sub(DIRECT_POINTERS);
+    if disk_inode.indirect_block != 0 {
+        to_free.extend(release_table_tail(qr_folder, &mut disk_inode.indirect_block, keep)?);
+    }
+
+    let keep = keep.saturating_sub(ptrs);
+    if disk_inode.double_indirect_block != 0 {
+        let mut outer = read_fs_block(qr_folder, disk_inode.double_indirect_block)?;
+        let mut changed = false;
+        for i in 0..ptrs.min(outer.len() as u64 / 4) {
+            let mut mid = block_ptr(&outer, i as usize);
+            let keep_here = keep.saturating_sub(i * ptrs);
+            if mid == 0 || keep_here >= ptrs {
+                continue;
+            }
+            to_free.extend(release_table_tail(qr_folder, &mut mid, keep_here)?);
+            if mid == 0 {
+                set_block_ptr(&mut outer, i as usize, 0);
+                changed = true;
+            }
+        }
+
+        if keep == 0 {
+            to_free.push(disk_inode.double_indirect_block);
+            disk_inode.double_indirect_block = 0;
+        } else if changed {
+            write_fs_block(qr_folder, disk_inode.double_indirect_block, &outer)?;
+        }
+    }
+    Ok(to_free)
+}
+
+/// Como `release_tail_blocks` para un bloque de punteros: quita las entradas
+/// desde `keep`. Si no queda ninguna (`keep == 0`) también se libera el bloque
+/// de punteros y `*table` queda en 0; si no, se escribe.
+fn release_table_tail(qr_folder: &Path, table: &mut u32, keep: u64) -> Result<Vec<u32>> {
+    let mut to_free = Vec::new();
+    let mut buf = read_fs_block(qr_folder, *table)?;
+    for ptr in buf.chunks_exact_mut(4).skip(keep as usize) {
+        let b = u32::from_le_bytes([ptr[0], ptr[1], ptr[2], ptr[3]]);
+        if b != 0 {
+            to_free.push(b);
+            ptr.fill(0);
+        }
+    }
+
+    if keep == 0 {
+        to_free.push(*table);
+        *table = 0;
+    } else if !to_free.is_empty() {
+        write_fs_block(qr_folder, *table, &buf)?;
+    }
+    Ok(to_free)
+}
+
+/// Parte de `write_range_disk` que escribe los bloques (el inodo sólo cambia
+/// en memoria: punteros nuevos).
+fn write_blocks_disk(inner: &mut QrfsInner, disk_inode: &mut InodeDisk, offset: u64, data: &[u8]) -> Result<()> {
+    let qr_folder = inner.qr_folder.clone();
+    let block_size = inner.superblock.block_size as usize;
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let at = offset + pos as u64;
+        let logical = at / block_size as u64;
+        let in_block = (at % block_size as u64) as usize;
+        let len = (block_size - in_block).min(data.len() - pos);
+
+        // Un bloque recién reservado o cubierto entero no se lee
+        let (block, fresh) = block_for_write(inner, disk_inode, logical)?;
+        let mut buf = if fresh || len == block_size {
+            vec![0u8; block_size]
+        } else {
+            read_fs_block(&qr_folder, block)?
+        };
+        buf.resize(block_size, 0);
+        buf[in_block..in_block + len].copy_from_slice(&data[pos..pos + len]);
+        write_fs_block(&qr_folder, block, &buf)?;
+
+        pos += len;
+    }
+    Ok(())
+}
+
+/// Escribe `chunks` (un bloque cada uno, en orden lógico) en bloques recién
+/// reservados y los engancha en `disk_inode`, que llega sin punteros: primero
+/// los directos, después el indirecto simple y el doble indirecto, como los lee
+/// `logical_to_physical`. Los bloques de punteros se arman en memoria y se
+/// escriben al final. Todo bloque reservado (de datos o de punteros) queda en
+/// `allocated`, así el que llama puede devolverlos si algo falla. El inodo no
+/// se escribe.
+pub(crate) fn write_new_blocks(
+    inner: &mut QrfsInner,
+    disk_inode: &mut InodeDisk,
+    chunks: &[Vec<u8>],
+    allocated: &mut Vec<u32>,
+) -> Result<()> {
+    let qr_folder = inner.qr_folder.clone();
+    let block_size = inner.superblock.block_size;
+    let empty_table = || vec![0u8; block_size as usize];
+
+    if !chunks.is_empty() && ptr_slot(block_size, chunks.len() as u64 - 1).is_none() {
+        return Err(anyhow::anyhow!(
+            "{} bloques no entran en los punteros de un inodo",
+            chunks.len()
+        ));
+    }
+
+    let mut indirect: Option<Vec<u8>> = None;
+    let mut double: BTreeMap<usize, Vec<u8>> = BTreeMap::new();
+    for (logical, chunk) in chunks.iter().enumerate() {
+        let b = alloc_block(inner)?;
+        allocated.push(b);
+        write_fs_block(&qr_folder, b, chunk)?;
+
+        match ptr_slot(block_size, logical as u64) {
+            Some(PtrSlot::Direct(i)) => disk_inode.direct_blocks[i] = b,
+            Some(PtrSlot::Indirect(i)) => set_block_ptr(indirect.get_or_insert_with(empty_table), i, b),
+            Some(PtrSlot::Double { outer, entry }) => {
+                set_block_ptr(double.entry(outer).or_insert_with(empty_table), entry, b)
+            }
+            None => unreachable!("el último bloque lógico ya se comprobó"),
+        }
+    }
+
+    if let Some(table) = indirect {
+        let b = alloc_block(inner)?;
+        allocated.push(b);
+        write_fs_block(&qr_folder, b, &table)?;
+        disk_inode.indirect_block = b;
+    }
+    if !double.is_empty() {
+        let mut outer_table = empty_table();
+        for (outer, table) in double {
+            let b = alloc_block(inner)?;
+            allocated.push(b);
+            write_fs_block(&qr_folder, b, &table)?;
+            set_block_ptr(&mut outer_table, outer, b);
+        }
+        let b = alloc_block(inner)?;
+        allocated.push(b);
+        write_fs_block(&qr_folder, b, &outer_table)?;
+        disk_inode.double_indirect_block = b;
+    }
+    Ok(())
+}
+
+/// Bloque físico donde escribir el bloque lógico `logical`, reservándolo (y los
+/// bloques de punteros que falten) cuando es un hueco. `true` = recién reservado.
+fn block_for_write(inner: &mut QrfsInner, disk_inode: &mut InodeDisk, logical: u64) -> Result<(u32, bool)> {
+    match ptr_slot(inner.superblock.block_size, logical) {
+        Some(PtrSlot::Direct(i)) => {
+            let b = disk_inode.direct_blocks[i];
+            if b != 0 {
+                return Ok((b, false));
+            }
+            let b = alloc_block(inner)?;
+            disk_inode.direct_blocks[i] = b;
+            Ok((b, true))
+        }
+        Some(PtrSlot::Indirect(i)) => {
+            let fresh = disk_inode.indirect_block == 0;
+            if fresh {
+                disk_inode.indirect_block = alloc_block(inner)?;
+            }
+            ptr_for_write(inner, disk_inode.indirect_block, fresh, i)
+        }
+        Some(PtrSlot::Double { outer, entry }) => {
+            let fresh = disk_inode.double_indirect_block == 0;
+            if fresh {
+                disk_inode.double_indirect_block = alloc_block(inner)?;
+            }
+            let (mid, mid_fresh) = ptr_for_write(inner, disk_inode.double_indirect_block, fresh, outer)?;
+            ptr_for_write(inner, mid, mid_fresh, entry)
+        }
+        None => Err(anyhow::anyhow!(
+            "El bloque lógico {} no entra en los punteros directos ni en los indirectos",
+            logical
+        )),
+    }
+}
+
+/// Bloque al que apunta la entrada `index` del bloque de punteros `table`,
+/// reservándolo si la entrada está en cero. Un bloque de punteros recién
+/// reservado (`table_fresh`) todavía tiene basura: se toma como todo ceros.
+fn ptr_for_write(inner: &mut QrfsInner, table: u32, table_fresh: bool, index: usize) -> Result<(u32, bool)> {
+    let qr_folder = inner.qr_folder.clone();
+    let block_size = inner.superblock.block_size as usize;
+
+    let mut buf = if table_fresh {
+        vec![0u8; block_size]
+    } else {
+        read_fs_block(&qr_folder, table)?
+    };
+    buf.resize(block_size, 0);
+
+    let b = block_ptr(&buf, index);
+    if b != 0 {
+        return Ok((b, false));
+    }
+    let b = alloc_block(inner)?;
+    set_block_ptr(&mut buf, index, b);
+    write_fs_block(&qr_folder, table, &buf)?;
+    Ok((b, true))
+}
+
+/// Borrado suave de un archivo ya desenlazado de su directorio: el inodo queda
+/// marcado con `QRFS_INODE_DELETED` y `nlink = 0`, y sus bloques vuelven al bitmap
+/// sin tocar los punteros. Primero se escribe el inodo, así nunca queda un inodo
+/// vivo apuntando a bloques libres.
+///
+/// Con `zero_on_free` los bloques se llenan de ceros y no hay nada que
+/// recuperar: el inodo se escribe vacío (un slot libre) en vez de borrado, así
+/// ni `list_deleted` ni `undelete` lo ofrecen.
+pub(crate) fn soft_delete_inode(inner: &mut QrfsInner, ino: u64) -> Result<()> {
+    let qr_folder = inner.qr_folder.clone();
+    let mut disk_inode = inner.load_inode(ino)?;
+
+    let blocks = file_blocks_disk(&qr_folder, &inner.superblock, &disk_inode)?;
+    if inner.zero_on_free {
+        disk_inode = InodeDisk::empty();
+    } else {
+        disk_inode.nlink = 0;
+        disk_inode.file_type |= QRFS_INODE_DELETED;
+        disk_inode.set_ctime(SystemTime::now());
+    }
+    write_inode_disk(&qr_folder, &inner.superblock, ino, &disk_inode)?;
+    if let (false, Some(index)) = (inner.zero_on_free, inner.deleted_blocks.as_mut()) {
+        for &b in &blocks {
+            index.insert(b, ino);
+        }
+    }
+
+    for b in blocks {
+        free_block(inner, b)?;
+    }
+
+    inner.free_inodes += 1;
+    inner.superblock.free_inodes += 1;
+    write_superblock(&qr_folder, &inner.superblock)?;
+
+    inner.inodes.remove(&ino);
+    inner.files.remove(&ino);
+    inner.locks.remove(&ino);
+    Ok(())
+}
+
+/// Quita un enlace al archivo `ino` (su entrada ya se sacó del directorio). Si
+/// le quedan otros enlaces sólo baja `nlink`; con el último pasa por el borrado
+/// suave, que libera sus bloques y el inodo.
+pub(crate) fn drop_link_disk(inner: &mut QrfsInner, ino: u64) -> Result<()> {
+    let mut disk_inode = inner.load_inode(ino)?;
+    if disk_inode.nlink <= 1 {
+        return soft_delete_inode(inner, ino);
+    }
+
+    disk_inode.nlink -= 1;
+    disk_inode.set_ctime(SystemTime::now());
+    write_inode_disk(&inner.qr_folder, &inner.superblock, ino, &disk_inode)?;
+
+    if let Some(inode) = inner.inodes.get_mut(&ino) {
+        inode.nlink = disk_inode.nlink;
+        inode.ctime = disk_inode.ctime();
+    }
+    Ok(())
+}
+
+/// Crea en disco el directorio vacío `ino` dentro de `parent`: su bloque de
+/// datos con "." y "..", el inodo apuntando a ese bloque y la entrada en el
+/// padre. `block` ya tiene que estar reservado (ver `alloc_block`).
+pub(crate) fn create_dir_disk(
+    inner: &mut QrfsInner,
+    ino: u64,
+    parent: u64,
+    name: &OsStr,
+    inode: &Inode,
+    block: u32,
+) -> Result<()> {
+    let qr_folder = inner.qr_folder.clone();
+
+    let data = crate::mkfs::make_dir_block(ino, parent, &[])?;
+    write_fs_block(&qr_folder, block, &data)?;
+
+    let mut direct_blocks = [0u32; 12];
+    direct_blocks[0] = block;
+    let mut disk_inode = InodeDisk {
+        id: ino as u32,
+        file_type: 2, // directorio
+        perm: inode.perm,
+        uid: inode.uid,
+        gid: inode.gid,
+        size: data.len() as u64,
+        atime: 0,
+        mtime: 0,
+        ctime: 0,
+        nlink: inode.nlink,
+        direct_blocks,
+        indirect_block: 0,
+        double_indirect_block: 0,
+        generation: inode.generation,
+    };
+    disk_inode.set_times(inode.ctime);
+    write_inode_disk(&qr_folder, &inner.superblock, ino, &disk_inode)?;
+
+    inner.free_inodes = inner.free_inodes.saturating_sub(1);
+    inner.superblock.free_inodes = inner.superblock.free_inodes.saturating_sub(1);
+    write_superblock(&qr_folder, &inner.superblock)?;
+
+    ensure_dir_block(inner, parent)?;
+    add_dir_entry_disk(&qr_folder, &inner.superblock, parent, name, ino)
+}
+
+/// Libera en disco un directorio ya desenlazado (contraparte de
+/// `create_dir_disk`): el inodo queda en cero y sus bloques vuelven al bitmap.
+/// A diferencia de los archivos no pasa por el borrado suave: un directorio
+/// vacío no tiene nada que recuperar.
+pub(crate) fn free_dir_disk(inner: &mut QrfsInner, ino: u64) -> Result<()> {
+    let qr_folder = inner.qr_folder.clone();
+    let mut disk_inode = inner.load_inode(ino)?;
+    let blocks = disk_inode.data_blocks();
+
+    disk_inode.id = 0;
+    disk_inode.nlink = 0;
+    disk_inode.size = 0;
+    disk_inode.direct_blocks = [0u32; 12];
+    disk_inode.indirect_block = 0;
+    disk_inode.double_indirect_block = 0;
+    write_inode_disk(&qr_folder, &inner.superblock, ino, &disk_inode)?;
+
+    for b in blocks {
+        free_block(inner, b)?;
+    }
+
+    inner.free_inodes += 1;
+    inner.superblock.free_inodes += 1;
+    write_superblock(&qr_folder, &inner.superblock)
+}
+
+/// Segundos desde epoch de un `SystemTime` (los anteriores a 1970 quedan en 0).
+pub(crate) fn system_time_to_secs(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// Inodo borrado que todavía aparece en la tabla de inodos.
+#[derive(Debug, Clone)]
+pub struct DeletedInode {
+    pub ino: u64,
+    pub size: u64,
+    /// Momento del borrado (ctime, segundos desde epoch).
+    pub deleted_at: u64,
+    /// `false` si alguno de sus bloques ya fue reutilizado por otro archivo.
+    pub recoverable: bool,
+}
+
+/// Un inodo borrado es recuperable si ninguno de sus bloques volvió a marcarse
+/// como usado en el bitmap. Un bloque que se reusó y se volvió a liberar no se
+/// ve acá: a ese inodo ya lo vació `forget_deleted_owner` al reservarlo.
+fn blocks_still_free(superblock: &SuperblockDisk, bitmap: &[u8], blocks: &[u32]) -> bool {
+    blocks.iter().all(|&b| {
+        b >= superblock.data_blocks_start
+            && b < superblock.total_blocks
+            && !bitmap_test(bitmap, b)
+    })
+}
+
+/// Lista los inodos borrados con `unlink` que siguen en la tabla de inodos.
+pub fn list_deleted(qr_folder: &Path) -> Result<Vec<DeletedInode>> {
+    let superblock = load_superblock(qr_folder)?;
+    let bitmap = load_bitmap(qr_folder, &superblock)?;
+    let mut deleted = Vec::new();
+
+    for (ino, disk_inode) in InodeTable::open(qr_folder, &superblock)?.iter()? {
+        if !disk_inode.is_deleted() {
+            continue;
+        }
+
+        deleted.push(DeletedInode {
+            ino,
+            size: disk_inode.size,
+            deleted_at: system_time_to_secs(disk_inode.ctime()),
+            recoverable: file_blocks_disk(qr_folder, &superblock, &disk_inode)
+                .is_ok_and(|blocks| blocks_still_free(&superblock, &bitmap, &blocks)),
+        });
+    }
+
+    Ok(deleted)
+}
+
+/// Recupera un inodo borrado y lo enlaza como `name` dentro de `parent`.
+/// Falla si el inodo no está borrado o si alguno de sus bloques ya se reutilizó.
+pub(crate) fn undelete_inode(inner: &mut QrfsInner, ino: u64, parent: u64, name: &OsStr) -> Result<()> {
+    if !inner.directories.contains_key(&parent) {
+        return Err(anyhow::anyhow!("El inodo {} no es un directorio", parent));
+    }
+    dir::check_name(name).with_context(|| format!("No se puede enlazar {:?}", name))?;
+    if dir::find_entry(inner, parent, name).is_some() {
+        return Err(anyhow::anyhow!("Ya existe {:?} en el directorio {}", name, parent));
+    }
+
+    let qr_folder = inner.qr_folder.clone();
+    let mut disk_inode = inner.load_inode(ino)?;
+    if !disk_inode.is_deleted() {
+        return Err(anyhow::anyhow!("El inodo {} no es un inodo borrado", ino));
+    }
+
+    // 1) Volver a reservar sus bloques (sólo si siguen libres)
+    let blocks = file_blocks_disk(&qr_folder, &inner.superblock, &disk_inode)?;
+    if !blocks_still_free(&inner.superblock, &inner.bitmap, &blocks) {
+        return Err(anyhow::anyhow!(
+            "El inodo {} no se puede recuperar: sus bloques ya fueron reutilizados",
+            ino
+        ));
+    }
+
+    for &b in &blocks {
+        bitmap_set(&mut inner.bitmap, b, true);
+    }
+    inner.free_blocks = inner.free_blocks.saturating_sub(blocks.len() as u32);
+    inner.superblock.free_blocks = inner.superblock.free_blocks.saturating_sub(blocks.len() as u32);
+    inner.free_inodes = inner.free_inodes.saturating_sub(1);
+    inner.superblock.free_inodes = inner.superblock.free_inodes.saturating_sub(1);
+    // Un bloque del bitmap por cada tramo de bloques que cambió
+    let mut changed = blocks.clone();
+    changed.sort_unstable();
+    changed.dedup_by_key(|b| *b / (8 * QRFS_BLOCK_SIZE));
+    for b in changed {
+        write_bitmap_block(inner, b)?;
+    }
+    write_superblock(&qr_folder, &inner.superblock)?;
+
+    if let Some(index) = inner.deleted_blocks.as_mut() {
+        for b in &blocks {
+            index.remove(b);
+        }
+    }
+
+    // 2) Revivir el inodo y enlazarlo en el directorio
+    disk_inode.file_type &= !QRFS_INODE_DELETED;
+    disk_inode.nlink = 1;
+    disk_inode.set_ctime(SystemTime::now());
+    write_inode_disk(&qr_folder, &inner.superblock, ino, &disk_inode)?;
+    ensure_dir_block(inner, parent)?;
+    add_dir_entry_disk(&qr_folder, &inner.superblock, parent, name, ino)?;
+
+    // 3) Estado en memoria
+    inner.inodes.insert(ino, Inode::from_disk(ino, &disk_inode));
+    if let Some(dir) = inner.directories.get_mut(&parent) {
+        dir.entries.insert(name.to_os_string(), ino);
+    }
+
+    Ok(())
+}
+
+fn read_directory_from_disk(
+    qr_folder: &Path,
+    superblock: &SuperblockDisk,
+    ino: u64,
+) -> Result<Vec<dir::DirEntry>> {
+    // Cargar el inodo del directorio
+    let inode_disk = load_inode_disk(qr_folder, superblock, ino)?;
+
+    if !inode_disk.is_dir() {
+        return Err(anyhow::anyhow!(
+            "Inodo {} no es un directorio (file_type = {})",
+            ino,
+            inode_disk.file_type
+        ));
+    }
+
+    // Versión mínima: suponemos que el directorio cabe en el primer bloque directo
+    let data_block = inode_disk.direct_blocks[0];
+    if data_block == 0 {
+        // Directorio vacío
+        return Ok(Vec::new());
+    }
+
+    // Leer el bloque de datos correspondiente al directorio
+    let buf = read_fs_block(qr_folder, data_block)?;
+
+    // Usar el helper del módulo dir para desempaquetar las entradas DirEntryDisk
+    let entries = dir::unpack_dir_entries(&buf);
+
+    Ok(entries)
+}
+
+/// Carga el inodo de un directorio junto con su bloque de datos (versión mínima:
+/// sólo el primer bloque directo).
+fn load_dir_block(
+    qr_folder: &Path,
+    superblock: &SuperblockDisk,
+    ino: u64,
+) -> Result<(InodeDisk, Vec<u8>)> {
+    let inode_disk = load_inode_disk(qr_folder, superblock, ino)?;
+
+    if !inode_disk.is_dir() {
+        return Err(anyhow::anyhow!(
+            "Inodo {} no es un directorio (file_type = {})",
+            ino,
+            inode_disk.file_type
+        ));
+    }
+
+    let data_block = inode_disk.direct_blocks[0];
+    if data_block == 0 {
+        return Err(anyhow::anyhow!(
+            "El directorio {} no tiene bloque de datos asignado",
+            ino
+        ));
+    }
+
+    let buf = read_fs_block(qr_folder, data_block)?;
+    Ok((inode_disk, buf))
+}
+
+/// Le da un bloque de datos al directorio `dir_ino` si no tiene uno, antes de
+/// agregarle una entrada. Una imagen puede traer un directorio (sobre todo la
+/// raíz) con `direct_blocks[0] = 0`: el montaje lo carga vacío, pero
+/// `add_dir_entry_disk` no tendría dónde escribir. Se reserva un bloque con "."
+/// y ".." y el inodo pasa a apuntarlo.
+pub(crate) fn ensure_dir_block(inner: &mut QrfsInner, dir_ino: u64) -> Result<()> {
+    let mut disk_inode = inner.load_inode(dir_ino)?;
+    if !disk_inode.is_dir() || disk_inode.direct_blocks[0] != 0 {
+        return Ok(());
+    }
+
+    let parent = dir::parent_inode(inner, dir_ino).unwrap_or(dir_ino);
+    let data = crate::mkfs::make_dir_block(dir_ino, parent, &[])?;
+    let block = alloc_block(inner)?;
+    let qr_folder = inner.qr_folder.clone();
+    if let Err(e) = write_fs_block(&qr_folder, block, &data) {
+        free_block(inner, block)?;
+        return Err(e);
+    }
+
+    disk_inode.direct_blocks[0] = block;
+    disk_inode.size = data.len() as u64;
+    write_inode_disk(&qr_folder, &inner.superblock, dir_ino, &disk_inode)?;
+    eprintln!("El directorio {} no tenía bloque de datos: se le asignó el bloque {}", dir_ino, block);
+    Ok(())
+}
+
+pub(crate) fn read_dir_slot(buf: &[u8], slot: usize) -> DirEntryDisk {
+    let entry_size = mem::size_of::<DirEntryDisk>();
+    unsafe {
+        let ptr = buf[slot * entry_size..].as_ptr() as *const DirEntryDisk;
+        ptr.read_unaligned()
+    }
+}
+
+fn write_dir_slot(buf: &mut [u8], slot: usize, entry: &DirEntryDisk) {
+    let entry_size = mem::size_of::<DirEntryDisk>();
+    unsafe {
+        let ptr = entry as *const DirEntryDisk as *const u8;
+        let slice = std::slice::from_raw_parts(ptr, entry_size);
+        buf[slot * entry_size..(slot + 1) * entry_size].copy_from_slice(slice);
+    }
+}
+
+/// Agrega una entrada al directorio en disco, reutilizando la primera lápida
+/// (slot con inode == 0) o el primer slot vacío.
+pub(crate) fn add_dir_entry_disk(
+    qr_folder: &Path,
+    superblock: &SuperblockDisk,
+    dir_ino: u64,
+    name: &OsStr,
+    child_ino: u64,
+) -> Result<()> {
+    let (mut inode_disk, mut buf) = load_dir_block(qr_folder, superblock, dir_ino)?;
+
+    if inode_disk.is_sorted_dir() {
+        let mut entries = sorted_dir_entries(&inode_disk, &buf);
+        let pos = match entries.binary_search_by(|(n, _)| dir_sort_key(n).cmp(&dir_sort_key(name))) {
+            Ok(_) => {
+                return Err(anyhow::anyhow!("{:?} ya existe en el directorio {}", name, dir_ino));
+            }
+            Err(pos) => pos,
+        };
+        entries.insert(pos, (name.to_os_string(), dir::pack_dir_entry(child_ino, name)));
+        return write_sorted_dir(qr_folder, superblock, dir_ino, &mut inode_disk, &mut buf, &entries);
+    }
+
+    let entry_size = mem::size_of::<DirEntryDisk>();
+    let slots = buf.len() / entry_size;
+
+    let slot = (0..slots)
+        .find(|&i| read_dir_slot(&buf, i).inode == 0)
+        .ok_or_else(|| anyhow::anyhow!("El bloque del directorio {} está lleno", dir_ino))?;
+
+    write_dir_slot(&mut buf, slot, &dir::pack_dir_entry(child_ino, name));
+    write_fs_block(qr_folder, inode_disk.direct_blocks[0], &buf)?;
+
+    let used_bytes = ((slot + 1) * entry_size) as u64;
+    if used_bytes > inode_disk.size {
+        inode_disk.size = used_bytes;
+        write_inode_disk(qr_folder, superblock, dir_ino, &inode_disk)?;
+    }
+
+    // Al pasar el umbral, el directorio se convierte al formato ordenado
+    #[cfg(feature = "sorted-dirs")]
+    {
+        let used_slots = (inode_disk.size as usize / entry_size).min(slots);
+        let live = (0..used_slots).filter(|&i| read_dir_slot(&buf, i).inode != 0).count();
+        if live > QRFS_SORTED_DIR_THRESHOLD {
+            sort_dir_disk(qr_folder, superblock, dir_ino)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Borra una entrada del directorio en disco dejando una lápida: sólo se pone
+/// `inode = 0` en su slot, sin compactar, para que el resto de entradas conserve
+/// su posición. Devuelve `false` si la entrada no estaba en disco.
+pub(crate) fn remove_dir_entry_disk(
+    qr_folder: &Path,
+    superblock: &SuperblockDisk,
+    dir_ino: u64,
+    name: &OsStr,
+) -> Result<bool> {
+    let (mut inode_disk, mut buf) = load_dir_block(qr_folder, superblock, dir_ino)?;
+
+    // Directorio ordenado: búsqueda binaria y se corre el resto (no quedan lápidas)
+    if inode_disk.is_sorted_dir() {
+        let mut entries = sorted_dir_entries(&inode_disk, &buf);
+        return match entries.binary_search_by(|(n, _)| dir_sort_key(n).cmp(&dir_sort_key(name))) {
+            Ok(pos) => {
+                entries.remove(pos);
+                write_sorted_dir(qr_folder, superblock, dir_ino, &mut inode_disk, &mut buf, &entries)?;
+                Ok(true)
+            }
+            Err(_) => Ok(false),
+        };
+    }
+
+    let entry_size = mem::size_of::<DirEntryDisk>();
+    let slots = buf.len() / entry_size;
+
+    for i in 0..slots {
+        let mut entry = read_dir_slot(&buf, i);
+        if entry.inode != 0 && dir::entry_name(&entry) == name {
+            entry.inode = 0;
+            write_dir_slot(&mut buf, i, &entry);
+            write_fs_block(qr_folder, inode_disk.direct_blocks[0], &buf)?;
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Cambia el nombre de una entrada sin moverla de directorio: se reescribe el
+/// nombre en su mismo slot (o se reordena, si el directorio está ordenado) con
+/// una sola escritura del bloque, así la entrada nunca desaparece a medias. Si
+/// `replaced` es el nombre de otra entrada (el destino que se pisa), se borra en
+/// la misma escritura. Devuelve `false` si `old_name` no estaba en disco.
+pub(crate) fn rename_dir_entry_disk(
+    qr_folder: &Path,
+    superblock: &SuperblockDisk,
+    dir_ino: u64,
+    old_name: &OsStr,
+    new_name: &OsStr,
+    replaced: Option<&OsStr>,
+) -> Result<bool> {
+    let (mut inode_disk, mut buf) = load_dir_block(qr_folder, superblock, dir_ino)?;
+
+    if inode_disk.is_sorted_dir() {
+        let mut entries = sorted_dir_entries(&inode_disk, &buf);
+        let Some(pos) = entries.iter().position(|(n, _)| n == old_name) else {
+            return Ok(false);
+        };
+        let (_, entry) = entries.remove(pos);
+        entries.retain(|(n, _)| Some(n.as_os_str()) != replaced && n != new_name);
+
+        let pos = entries
+            .binary_search_by(|(n, _)| dir_sort_key(n).cmp(&dir_sort_key(new_name)))
+            .unwrap_or_else(|pos| pos);
+        entries.insert(pos, (new_name.to_os_string(), dir::pack_dir_entry(entry.inode as u64, new_name)));
+        write_sorted_dir(qr_folder, superblock, dir_ino, &mut inode_disk, &mut buf, &entries)?;
+        return Ok(true);
+    }
+
+    let entry_size = mem::size_of::<DirEntryDisk>();
+    let slots = buf.len() / entry_size;
+
+    let Some(slot) = (0..slots).find(|&i| {
+        let entry = read_dir_slot(&buf, i);
+        entry.inode != 0 && dir::entry_name(&entry) == old_name
+    }) else {
+        return Ok(false);
+    };
+
+    for i in (0..slots).filter(|&i| i != slot) {
+        let mut entry = read_dir_slot(&buf, i);
+        let name = dir::entry_name(&entry);
+        if entry.inode != 0 && (Some(name.as_os_str()) == replaced || name == new_name) {
+            entry.inode = 0;
+            write_dir_slot(&mut buf, i, &entry);
+        }
+    }
+
+    let ino = read_dir_slot(&buf, slot).inode as u64;
+    write_dir_slot(&mut buf, slot, &dir::pack_dir_entry(ino, new_name));
+    write_fs_block(qr_folder, inode_disk.direct_blocks[0], &buf)?;
+    Ok(true)
+}
+
+/// Orden de las entradas de un directorio ordenado: "." y ".." primero y el resto
+/// por los bytes del nombre.
+fn dir_sort_key(name: &OsStr) -> (u8, &OsStr) {
+    match name.as_bytes() {
+        b"." => (0, name),
+        b".." => (1, name),
+        _ => (2, name),
+    }
+}
+
+/// Entradas de un directorio ordenado (ocupan los primeros `size / 60` slots).
+fn sorted_dir_entries(inode_disk: &InodeDisk, buf: &[u8]) -> Vec<(OsString, DirEntryDisk)> {
+    let entry_size = mem::size_of::<DirEntryDisk>();
+    let used_slots = (inode_disk.size as usize / entry_size).min(buf.len() / entry_size);
+
+    (0..used_slots)
+        .map(|i| read_dir_slot(buf, i))
+        .map(|e| (dir::entry_name(&e), e))
+        .collect()
+}
+
+/// Reescribe el bloque de un directorio con `entries` (ya ordenadas) al inicio,
+/// ajusta su tamaño y lo marca como ordenado.
+fn write_sorted_dir(
+    qr_folder: &Path,
+    superblock: &SuperblockDisk,
+    dir_ino: u64,
+    inode_disk: &mut InodeDisk,
+    buf: &mut [u8],
+    entries: &[(OsString, DirEntryDisk)],
+) -> Result<()> {
+    let entry_size = mem::size_of::<DirEntryDisk>();
+    if entries.len() > buf.len() / entry_size {
+        return Err(anyhow::anyhow!("El bloque del directorio {} está lleno", dir_ino));
+    }
+
+    buf.fill(0);
+    for (i, (_, entry)) in entries.iter().enumerate() {
+        write_dir_slot(buf, i, entry);
+    }
+    write_fs_block(qr_folder, inode_disk.direct_blocks[0], buf)?;
+
+    inode_disk.size = (entries.len() * entry_size) as u64;
+    inode_disk.file_type |= QRFS_DIR_SORTED;
+    write_inode_disk(qr_folder, superblock, dir_ino, inode_disk)
+}
+
+/// Convierte un directorio lineal (con lápidas) al formato ordenado.
+#[cfg(feature = "sorted-dirs")]
+fn sort_dir_disk(qr_folder: &Path, superblock: &SuperblockDisk, dir_ino: u64) -> Result<()> {
+    let (mut inode_disk, mut buf) = load_dir_block(qr_folder, superblock, dir_ino)?;
+
+    let mut entries: Vec<(OsString, DirEntryDisk)> = sorted_dir_entries(&inode_disk, &buf)
+        .into_iter()
+        .filter(|(_, e)| e.inode != 0)
+        .collect();
+    entries.sort_by(|(a, _), (b, _)| dir_sort_key(a).cmp(&dir_sort_key(b)));
+
+    write_sorted_dir(qr_folder, superblock, dir_ino, &mut inode_disk, &mut buf, &entries)
+}
+
+/// Compacta el bloque de un directorio: mueve las entradas vivas al inicio y
+/// descarta las lápidas. Devuelve cuántas lápidas se eliminaron.
+fn compact_dir_disk(qr_folder: &Path, superblock: &SuperblockDisk, dir_ino: u64) -> Result<usize> {
+    let (mut inode_disk, buf) = load_dir_block(qr_folder, superblock, dir_ino)?;
+
+    let entry_size = mem::size_of::<DirEntryDisk>();
+    let used_slots = ((inode_disk.size as usize) / entry_size).min(buf.len() / entry_size);
+
+    let live: Vec<DirEntryDisk> = (0..used_slots)
+        .map(|i| read_dir_slot(&buf, i))
+        .filter(|e| e.inode != 0)
+        .collect();
+
+    let removed = used_slots - live.len();
+    if removed == 0 {
+        return Ok(0);
+    }
+
+    let mut new_buf = vec![0u8; buf.len()];
+    for (i, entry) in live.iter().enumerate() {
+        write_dir_slot(&mut new_buf, i, entry);
+    }
+    write_fs_block(qr_folder, inode_disk.direct_blocks[0], &new_buf)?;
+
+    inode_disk.size = (live.len() * entry_size) as u64;
+    write_inode_disk(qr_folder, superblock, dir_ino, &inode_disk)?;
+
+    Ok(removed)
+}
+
+pub(crate) fn load_superblock(qr_folder: &Path) -> Result<SuperblockDisk> {
+    let buf = read_fs_block(qr_folder, 0)?;
+    let superblock = SuperblockDisk::from_block_bytes(&buf)?;
+
+    superblock.check_magic()?;
+
+    if !superblock.verify_checksum() {
+        return Err(superblock_checksum_error(&superblock));
+    }
+
+    superblock.check_features()?;
+    superblock.check_layout()?;
+
+    Ok(superblock)
+}
+
+fn superblock_checksum_error(superblock: &SuperblockDisk) -> anyhow::Error {
+    anyhow::anyhow!(
+        "El checksum del superblock no coincide (superblock checksum mismatch: guardado = {:#010X}, calculado = {:#010X})",
+        superblock.checksum,
+        superblock.compute_checksum()
+    )
+}
+
+/// Mantenimiento: compacta todos los directorios de la imagen eliminando las
+/// lápidas dejadas por los borrados. No debe correrse con el FS montado.
+/// Devuelve el total de lápidas eliminadas.
+pub fn compact_directories(qr_folder: &Path) -> Result<usize> {
+    let superblock = load_superblock(qr_folder)?;
+    let mut total = 0;
+
+    for (ino, inode_disk) in InodeTable::open(qr_folder, &superblock)?.iter()? {
+        if inode_disk.id == 0 || inode_disk.nlink == 0 || !inode_disk.is_dir() {
+            continue;
+        }
+        if inode_disk.direct_blocks[0] == 0 {
+            continue;
+        }
+        total += compact_dir_disk(qr_folder, &superblock, ino)?;
+    }
+
+    Ok(total)
+}
+
+/// Directorio cuyo bloque no empieza con "." -> sí mismo y ".." -> su padre.
+#[derive(Debug, Clone)]
+pub struct DotEntryProblem {
+    pub ino: u64,
+    pub bad_dot: bool,
+    pub bad_dotdot: bool,
+    /// Padre según el árbol (el directorio que lo lista); la raíz si ninguno lo lista.
+    pub parent: u64,
+    /// Se reescribió el bloque. Queda en `false` sin `repair` o si no había
+    /// slots libres para mover las entradas que ocupaban los dos primeros.
+    pub repaired: bool,
+}
+
+/// Verifica que los dos primeros slots de cada directorio sean "." y "..". Con
+/// `repair` los reescribe a partir del árbol (quién lista a quién). No debe
+/// correrse con el FS montado.
+pub fn check_dot_entries(qr_folder: &Path, repair: bool) -> Result<Vec<DotEntryProblem>> {
+    let superblock = load_superblock(qr_folder)?;
+    let root = superblock.root_inode as u64;
+
+    // 1. Directorios vivos con su bloque
+    let mut dirs = Vec::new();
+    for (ino, inode_disk) in InodeTable::open(qr_folder, &superblock)?.iter()? {
+        if inode_disk.id == 0 || inode_disk.nlink == 0 || inode_disk.is_deleted() || !inode_disk.is_dir() {
+            continue;
+        }
+        if inode_disk.direct_blocks[0] == 0 {
+            continue;
+        }
+        let buf = read_fs_block(qr_folder, inode_disk.direct_blocks[0])?;
+        dirs.push((ino, inode_disk, buf));
+    }
+
+    // 2. Padre de cada directorio: el primero que lo lista como hijo
+    let dir_inos: HashSet<u64> = dirs.iter().map(|(ino, _, _)| *ino).collect();
+    let mut parents = HashMap::new();
+    parents.insert(root, root);
+    for (ino, inode_disk, buf) in &dirs {
+        for (name, entry) in live_dir_slots(inode_disk, buf) {
+            let child = entry.inode as u64;
+            if name != "." && name != ".." && child != *ino && dir_inos.contains(&child) {
+                parents.entry(child).or_insert(*ino);
+            }
+        }
+    }
+
+    // 3. Revisar los slots 0 y 1
+    let mut problems = Vec::new();
+    for (ino, mut inode_disk, mut buf) in dirs {
+        let parent = parents.get(&ino).copied().unwrap_or(root);
+        let dot = read_dir_slot(&buf, 0);
+        let dotdot = read_dir_slot(&buf, 1);
+
+        let bad_dot = dot.inode as u64 != ino || dir::entry_name(&dot) != ".";
+        let bad_dotdot = dotdot.inode as u64 != parent || dir::entry_name(&dotdot) != "..";
+        if !bad_dot && !bad_dotdot {
+            continue;
+        }
+
+        let repaired = repair
+            && rewrite_dot_entries(qr_folder, &superblock, ino, parent, &mut inode_disk, &mut buf)?;
+        problems.push(DotEntryProblem {
+            ino,
+            bad_dot,
+            bad_dotdot,
+            parent,
+            repaired,
+        });
+    }
+
+    Ok(problems)
+}
+
+/// Inodo cuyo `file_type` contradice el lugar que ocupa en el árbol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeConflict {
+    /// Dice ser un directorio pero no tiene un bloque de directorio que se
+    /// pueda leer.
+    DirWithoutBlock { ino: u64 },
+    /// Es un archivo regular pero el árbol lo usa como directorio: es la raíz
+    /// o el ".." de otro directorio (`child`).
+    FileUsedAsDir { ino: u64, child: Option<u64> },
+}
+
+impl std::fmt::Display for TypeConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypeConflict::DirWithoutBlock { ino } => {
+                write!(f, "el inodo {} es un directorio sin bloque de directorio legible", ino)
+            }
+            TypeConflict::FileUsedAsDir { ino, child: None } => {
+                write!(f, "el inodo {} es la raíz pero no es un directorio", ino)
+            }
+            TypeConflict::FileUsedAsDir { ino, child: Some(child) } => {
+                write!(f, "el inodo {} es el \"..\" del directorio {} pero no es un directorio", ino, child)
+            }
+        }
+    }
+}
+
+/// Cruza el `file_type` de cada inodo vivo con su papel en el árbol. Los
+/// directorios se cargan suponiendo que el tipo es correcto: una contradicción
+/// termina más tarde en un `EISDIR` o `ENOTDIR` que no dice qué pasó.
+pub(crate) fn inode_type_conflicts(qr_folder: &Path, superblock: &SuperblockDisk) -> Result<Vec<TypeConflict>> {
+    let root = superblock.root_inode as u64;
+
+    let mut live = HashMap::new();
+    for (ino, inode_disk) in InodeTable::open(qr_folder, superblock)?.iter()? {
+        if inode_disk.id != 0 && inode_disk.nlink != 0 && !inode_disk.is_deleted() {
+            live.insert(ino, inode_disk);
+        }
+    }
+
+    let mut conflicts = Vec::new();
+    if live.get(&root).is_some_and(|i| !i.is_dir()) {
+        conflicts.push(TypeConflict::FileUsedAsDir { ino: root, child: None });
+    }
+
+    let mut dir_inos: Vec<u64> = live.iter().filter(|(_, i)| i.is_dir()).map(|(&ino, _)| ino).collect();
+    dir_inos.sort();
+
+    for ino in dir_inos {
+        let block = live[&ino].direct_blocks[0];
+        let buf = match block {
+            0 => None,
+            b => read_fs_block(qr_folder, b).ok(),
+        };
+        let Some(buf) = buf else {
+            conflicts.push(TypeConflict::DirWithoutBlock { ino });
+            continue;
+        };
+
+        let parent = dir::unpack_dir_entries(&buf)
+            .into_iter()
+            .find(|e| e.name == "..")
+            .map(|e| e.ino);
+        if let Some(parent) = parent {
+            if parent != ino && live.get(&parent).is_some_and(|i| !i.is_dir()) {
+                conflicts.push(TypeConflict::FileUsedAsDir { ino: parent, child: Some(ino) });
+            }
+        }
+    }
+
+    Ok(conflicts)
+}
+
+/// Slots vivos de un bloque de directorio (nombre y entrada), en orden.
+fn live_dir_slots(inode_disk: &InodeDisk, buf: &[u8]) -> Vec<(OsString, DirEntryDisk)> {
+    if inode_disk.is_sorted_dir() {
+        return sorted_dir_entries(inode_disk, buf);
+    }
+
+    let entry_size = mem::size_of::<DirEntryDisk>();
+    (0..buf.len() / entry_size)
+        .map(|i| read_dir_slot(buf, i))
+        .filter(|e| e.inode != 0)
+        .map(|e| (dir::entry_name(&e), e))
+        .collect()
+}
+
+/// Apunta el ".." del directorio `ino` a `parent` en disco, después de moverlo
+/// a otro padre: el montaje arma el árbol a partir de "..". Un directorio sin
+/// bloque todavía no tiene ni "." ni "..".
+pub(crate) fn set_dotdot_disk(inner: &QrfsInner, ino: u64, parent: u64) -> Result<()> {
+    let mut inode_disk = inner.load_inode(ino)?;
+    if inode_disk.direct_blocks[0] == 0 {
+        return Ok(());
+    }
+
+    let mut buf = read_fs_block(&inner.qr_folder, inode_disk.direct_blocks[0])?;
+    if !rewrite_dot_entries(&inner.qr_folder, &inner.superblock, ino, parent, &mut inode_disk, &mut buf)? {
+        return Err(anyhow::anyhow!(
+            "El directorio {} no tiene lugar para reescribir \".\" y \"..\"",
+            ino
+        ));
+    }
+    Ok(())
+}
+
+/// Deja "." y ".." en los slots 0 y 1. Las entradas que los ocupaban (y no son
+/// un "." o ".." viejo) se mueven a un slot libre. Devuelve `false` sin tocar
+/// nada si no hay lugar para moverlas.
+fn rewrite_dot_entries(
+    qr_folder: &Path,
+    superblock: &SuperblockDisk,
+    ino: u64,
+    parent: u64,
+    inode_disk: &mut InodeDisk,
+    buf: &mut [u8],
+) -> Result<bool> {
+    let dot = dir::pack_dir_entry(ino, OsStr::new("."));
+    let dotdot = dir::pack_dir_entry(parent, OsStr::new(".."));
+
+    // Ordenado: se reconstruye la lista con los dos primeros al frente
+    if inode_disk.is_sorted_dir() {
+        let mut entries: Vec<(OsString, DirEntryDisk)> = sorted_dir_entries(inode_disk, buf)
+            .into_iter()
+            .filter(|(n, e)| e.inode != 0 && n != "." && n != "..")
+            .collect();
+        entries.insert(0, (OsString::from(".."), dotdot));
+        entries.insert(0, (OsString::from("."), dot));
+        if entries.len() > buf.len() / mem::size_of::<DirEntryDisk>() {
+            return Ok(false);
+        }
+        write_sorted_dir(qr_folder, superblock, ino, inode_disk, buf, &entries)?;
+        return Ok(true);
+    }
+
+    let entry_size = mem::size_of::<DirEntryDisk>();
+    let slots = buf.len() / entry_size;
+
+    // Hijos que quedaron en los slots 0 y 1
+    let displaced: Vec<DirEntryDisk> = (0..2)
+        .map(|i| read_dir_slot(buf, i))
+        .filter(|e| e.inode != 0 && !matches!(dir::entry_name(e).as_bytes(), b"." | b".."))
+        .collect();
+    let free: Vec<usize> = (2..slots)
+        .filter(|&i| read_dir_slot(buf, i).inode == 0)
+        .take(displaced.len())
+        .collect();
+    if free.len() < displaced.len() {
+        return Ok(false);
+    }
+
+    let mut used_slots = (inode_disk.size as usize / entry_size).max(2);
+    for (entry, slot) in displaced.iter().zip(free) {
+        write_dir_slot(buf, slot, entry);
+        used_slots = used_slots.max(slot + 1);
+    }
+    write_dir_slot(buf, 0, &dot);
+    write_dir_slot(buf, 1, &dotdot);
+    write_fs_block(qr_folder, inode_disk.direct_blocks[0], buf)?;
+
+    let used_bytes = (used_slots * entry_size) as u64;
+    if used_bytes != inode_disk.size {
+        inode_disk.size = used_bytes;
+        write_inode_disk(qr_folder, superblock, ino, inode_disk)?;
+    }
+    Ok(true)
+}
+
+
+/// Resultado de `repair_counters`: contadores del superblock antes y después.
+#[derive(Debug, Clone, Copy)]
+pub struct CounterRepair {
+    pub old_free_blocks: u32,
+    pub free_blocks: u32,
+    pub old_free_inodes: u32,
+    pub free_inodes: u32,
+}
+
+impl CounterRepair {
+    pub fn changed(&self) -> bool {
+        self.old_free_blocks != self.free_blocks || self.old_free_inodes != self.free_inodes
+    }
+}
+
+/// Mantenimiento rápido: recalcula `free_blocks` desde el bitmap (área de datos)
+/// y `free_inodes` desde la tabla de inodos (libres, nunca usados o borrados) y
+/// los escribe en el superblock. No recorre directorios. No debe correrse con el
+/// FS montado.
+pub fn repair_counters(qr_folder: &Path) -> Result<CounterRepair> {
+    let superblock = load_superblock(qr_folder)?;
+    let bitmap = load_bitmap(qr_folder, &superblock)?;
+
+    let free_blocks = (superblock.data_blocks_start..superblock.total_blocks)
+        .filter(|&b| !bitmap_test(&bitmap, b))
+        .count() as u32;
+
+    let free_inodes = InodeTable::open(qr_folder, &superblock)?
+        .iter()?
+        .filter(|(_, inode_disk)| inode_disk.id == 0 || inode_disk.nlink == 0)
+        .count() as u32;
+
+    let repair = CounterRepair {
+        old_free_blocks: superblock.free_blocks,
+        free_blocks,
+        old_free_inodes: superblock.free_inodes,
+        free_inodes,
+    };
+
+    if repair.changed() {
+        let new_sb = SuperblockDisk {
+            free_blocks,
+            free_inodes,
+            ..superblock
+        };
+        write_superblock(qr_folder, &new_sb)?;
+    }
+
+    Ok(repair)
+}
+
+/// Resultado de `upgrade_image`.
+#[derive(Debug, Clone)]
+pub struct UpgradeReport {
+    pub old_version: u32,
+    pub version: u32,
+    /// Lo que se cambió, una línea por cambio (vacío: la imagen ya estaba al día).
+    pub changes: Vec<String>,
+}
+
+/// Lleva una imagen de una versión anterior del formato a `QRFS_VERSION`. Antes
+/// de escribir nada guarda el bloque 0 original en `QRFS_SUPERBLOCK_BACKUP`.
+/// Nunca baja de versión y rechaza imágenes con extensiones desconocidas o con
+/// el checksum roto (eso es trabajo del fsck). No debe correrse con el FS montado.
+pub fn upgrade_image(qr_folder: &Path) -> Result<UpgradeReport> {
+    let original = read_fs_block(qr_folder, 0)?;
+    let mut superblock = SuperblockDisk::from_block_bytes(&original)?;
+
+    superblock.check_magic()?;
+    if superblock.version > QRFS_VERSION {
+        return Err(anyhow::anyhow!(
+            "La imagen es de la versión {} y esta versión de QRFS sólo llega a la {}: no se baja de versión",
+            superblock.version,
+            QRFS_VERSION
+        ));
+    }
+    if superblock.version == 0 {
+        return Err(anyhow::anyhow!("Versión de FS desconocida: 0"));
+    }
+    if !superblock.verify_checksum() {
+        return Err(superblock_checksum_error(&superblock));
+    }
+    superblock.check_features()?;
+
+    let old_version = superblock.version;
+    let mut changes = Vec::new();
+
+    // Un cambio de formato agrega acá el paso de su versión anterior a la nueva
+    // (`if superblock.version < N { ... }`). Hoy sólo existe la v1.
+
+    // Las imágenes de antes del checksum lo tienen en 0; las de antes de la
+    // marca tienen checksum pero no `QRFS_FEATURE_CHECKSUM` (la pone `seal`)
+    if superblock.checksum == 0 {
+        changes.push("Se calculó el checksum del superblock".to_string());
+    } else if superblock.features & QRFS_FEATURE_CHECKSUM == 0 {
+        changes.push("Se marcó el superblock con QRFS_FEATURE_CHECKSUM".to_string());
+    }
+    if superblock.version != QRFS_VERSION {
+        changes.push(format!("Versión {} -> {}", superblock.version, QRFS_VERSION));
+        superblock.version = QRFS_VERSION;
+    }
+
+    if !changes.is_empty() {
+        let backup = qr_folder.join(QRFS_SUPERBLOCK_BACKUP);
+        fs::write(&backup, &original)
+            .with_context(|| format!("No se pudo guardar la copia del superblock en {:?}", backup))?;
+        write_superblock(qr_folder, &superblock)?;
+    }
+
+    Ok(UpgradeReport {
+        old_version,
+        version: superblock.version,
+        changes,
+    })
+}
+
+
+// -----------------------------------------------------------------------------
+// Implementación FUSE 
+// -----------------------------------------------------------------------------
+
+impl Filesystem for QrfsFilesystem {
+
+    // destroy: al desmontar, última sincronización de la imagen
+    fn destroy(&mut self) {
+        println!("destroy llamado");
+        if let Err(e) = self.sync() {
+            eprintln!("Error al sincronizar la imagen al desmontar: {e:?}");
+        }
+    }
+
+    // forget: el kernel soltó referencias a un inodo (ver `forget_inode`)
+    fn forget(&mut self, _req: &Request<'_>, ino: u64, nlookup: u64) {
+        println!("forget llamado: ino = {ino}, nlookup = {nlookup}");
+        let mut inner = self.inner.write().unwrap();
+        let ino = inner.fuse_ino(ino);
+        forget_inode(&mut inner, ino, nlookup);
+    }
+
+    // getattr: info de un inodo
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyAttr) {
+        println!("getattr llamado: ino = {ino}");
+        let inner = self.inner.read().unwrap();
+        let ino = inner.fuse_ino(ino);
+
+        if ino == QRFS_STATS_INO {
+            let size = inner.stats.snapshot(inner.free_blocks, inner.free_inodes).len() as u64;
+            reply.attr(&Duration::from_secs(0), &stats::stats_attr(size));
+            return;
+        }
+        if ino == QRFS_CONTROL_INO {
+            let size = inner.control_output.len() as u64;
+            reply.attr(&Duration::from_secs(0), &control::control_attr(size));
+            return;
+        }
+        if ino == QRFS_META_INO {
+            reply.attr(&Duration::from_secs(0), &meta::meta_dir_attr());
+            return;
+        }
+        if let Some(file) = MetaFile::from_ino(ino) {
+            let size = file.render(&inner).len() as u64;
+            reply.attr(&Duration::from_secs(0), &meta::meta_file_attr(file, size));
+            return;
+        }
+
+        if let Some(inode) = inner.inodes.get(&ino) {
+            let attr = inner.fuse_attr(inode);
+            let ttl = Duration::from_secs(1);
+            reply.attr(&ttl, &attr);
+        } else {
+            reply.error(ENOENT);
+        }
+    }
+
+    // setattr: chmod/chown y tiempos explícitos (utimensat, touch -d)
+    fn setattr(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        mode: Option<u32>,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        size: Option<u64>,
+        atime: Option<TimeOrNow>,
+        mtime: Option<TimeOrNow>,
+        _ctime: Option<SystemTime>,
+        _fh: Option<u64>,
+        _crtime: Option<SystemTime>,
+        _chgtime: Option<SystemTime>,
+        _bkuptime: Option<SystemTime>,
+        _flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        println!(
+            "setattr llamado: ino = {ino}, mode = {:?}, uid = {:?}, gid = {:?}, size = {:?}, atime = {:?}, mtime = {:?}",
+            mode, uid, gid, size, atime, mtime
+        );
+
+        if ino == QRFS_STATS_INO || meta::is_meta_ino(ino) {
+            reply.error(libc::EACCES);
+            return;
+        }
+
+        // `echo fsck > .qrfs-control` trunca antes de escribir: se acepta (el
+        // resultado se reemplaza con el próximo comando) y nada más
+        if ino == QRFS_CONTROL_INO {
+            if mode.is_some() || uid.is_some() || gid.is_some() || size.is_some_and(|s| s != 0) {
+                reply.error(libc::EACCES);
+            } else {
+                let size = self.inner.read().unwrap().control_output.len() as u64;
+                reply.attr(&Duration::from_secs(0), &control::control_attr(size));
+            }
+            return;
+        }
+
+        let mut guard = self.inner.write().unwrap();
+        let inner = &mut *guard;
+        let ino = inner.fuse_ino(ino);
+        let changes = AttrChanges { mode, uid, gid, size, atime, mtime };
+        match set_attr(inner, ino, &changes) {
+            Ok(mut attr) => {
+                attr.ino = inner.fuse_ino(attr.ino);
+                reply.attr(&Duration::from_secs(1), &attr);
+            }
+            Err(errno) => reply.error(errno),
+        }
     }
 
     // lookup: resolver (parent, nombre) -> inodo
@@ -822,36 +4089,76 @@ impl Filesystem for QrfsFilesystem {
         println!("lookup llamado: parent = {parent}, name = {:?}", name);
         let inner = self.inner.read().unwrap();
 
-        let name_str = name.to_string_lossy().to_string();
+        let parent = inner.fuse_ino(parent);
 
-        // Buscar el directorio padre
-        let dir = match inner.directories.get(&parent) {
-            Some(d) => d,
-            None => {
-                reply.error(ENOENT);
-                return;
+        // Archivo virtual de estadísticas: no tiene entrada en el directorio raíz
+        if parent == inner.root_ino && dir::names_equal(name, OsStr::new(QRFS_STATS_NAME), inner.case_insensitive) {
+            let size = inner.stats.snapshot(inner.free_blocks, inner.free_inodes).len() as u64;
+            reply.entry(&Duration::from_secs(0), &stats::stats_attr(size), 0);
+            return;
+        }
+        if parent == inner.root_ino && dir::names_equal(name, OsStr::new(QRFS_CONTROL_NAME), inner.case_insensitive) {
+            let size = inner.control_output.len() as u64;
+            reply.entry(&Duration::from_secs(0), &control::control_attr(size), 0);
+            return;
+        }
+        if inner.meta_dir
+            && parent == inner.root_ino
+            && dir::names_equal(name, OsStr::new(QRFS_META_NAME), inner.case_insensitive)
+        {
+            reply.entry(&Duration::from_secs(0), &meta::meta_dir_attr(), 0);
+            return;
+        }
+        if parent == QRFS_META_INO {
+            match name.to_str().and_then(MetaFile::from_name) {
+                Some(file) => {
+                    let size = file.render(&inner).len() as u64;
+                    reply.entry(&Duration::from_secs(0), &meta::meta_file_attr(file, size), 0);
+                }
+                None => reply.error(ENOENT),
             }
-        };
+            return;
+        }
 
-        let child_ino = match dir.entries.get(&name_str) {
-            Some(ino) => ino,
+        // Buscar la entrada en el directorio padre (respeta --case-insensitive)
+        let child_ino = match dir::find_entry(&inner, parent, name) {
+            Some((_, ino)) => ino,
             None => {
                 reply.error(ENOENT);
                 return;
             }
         };
 
-        let inode = match inner.inodes.get(child_ino) {
-            Some(i) => i,
-            None => {
+        // Lo común: el inodo está en memoria y alcanza con el lock de lectura
+        if let Some(inode) = inner.inodes.get(&child_ino) {
+            let attr = inner.fuse_attr(inode);
+            inner.remember(child_ino);
+            reply.entry(&Duration::from_secs(1), &attr, inode.generation as u64);
+            return;
+        }
+        drop(inner);
+
+        // Si el kernel lo había olvidado, se vuelve a leer de disco con el lock de
+        // escritura. Entre los dos locks la entrada pudo cambiar: se busca de nuevo
+        let mut guard = self.inner.write().unwrap();
+        let inner = &mut *guard;
+        let Some((_, child_ino)) = dir::find_entry(inner, parent, name) else {
+            reply.error(ENOENT);
+            return;
+        };
+        let inode = match inner.inode_or_load(child_ino) {
+            Ok(i) => i.clone(),
+            Err(e) => {
+                eprintln!("Error al cargar el inodo {} de {:?}: {e:?}", child_ino, name);
                 reply.error(ENOENT);
                 return;
             }
         };
 
-        let attr = inode_to_attr(inode);
+        let attr = inner.fuse_attr(&inode);
+        inner.remember(child_ino);
         let ttl = Duration::from_secs(1);
-        reply.entry(&ttl, &attr, 0);
+        reply.entry(&ttl, &attr, inode.generation as u64);
     }
 
     // access: por ahora sólo dejamos pasar el root, resto ENOENT
@@ -864,7 +4171,8 @@ impl Filesystem for QrfsFilesystem {
     ) {
         println!("access llamado: ino = {ino}");
 
-        if ino == ROOT_INO {
+        let inner = self.inner.read().unwrap();
+        if inner.fuse_ino(ino) == inner.root_ino {
             reply.ok();
         } else {
             reply.error(ENOENT);
@@ -880,15 +4188,41 @@ impl Filesystem for QrfsFilesystem {
         reply: ReplyOpen,
     ) {
         println!("opendir llamado");
+        if ino == QRFS_META_INO {
+            reply.opened(ino, 0);
+            return;
+        }
+
         let inner = self.inner.read().unwrap();
-        if !dir::is_directory(&inner, ino) {
+        if !dir::is_directory(&inner, inner.fuse_ino(ino)) {
             reply.error(libc::ENOTDIR);
             return;
         }
 
-        // Versión mínima: aceptamos siempre y usamos el propio ino como "file handle"
-        let fh = ino;
-        reply.opened(fh, 0);
+        // El handle es una copia del listado de ahora: readdir pagina sobre ella
+        match dir::snapshot_directory(&inner, inner.fuse_ino(ino)) {
+            Ok(entries) => {
+                let fh = inner.dir_streams.lock().unwrap().insert(entries);
+                reply.opened(fh, 0);
+            }
+            Err(e) => {
+                eprintln!("Error en opendir: {}", e);
+                reply.error(e.as_errno());
+            }
+        }
+    }
+
+    // releasedir: el listado del handle ya no se usa
+    fn releasedir(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        _flags: i32,
+        reply: ReplyEmpty,
+    ) {
+        self.inner.read().unwrap().dir_streams.lock().unwrap().remove(fh);
+        reply.ok();
     }
 
     // readdir
@@ -896,49 +4230,49 @@ impl Filesystem for QrfsFilesystem {
         &mut self,
         _req: &Request<'_>,
         ino: u64,
-        _fh: u64,
+        fh: u64,
         offset: i64,
         mut reply: ReplyDirectory,
     ) {
         println!("readdir llamado: ino = {ino}, offset = {offset}");
-        let inner = self.inner.read().unwrap();
-
-        let entries = match dir::list_directory(&inner, ino) {
-            Ok(e) => e,
-            Err(_) => {
-                reply.error(ENOENT);
-                return;
-            }
-        };
 
-        let mut offset_i = offset as usize;
-
-        // "." (offset 0)
-        if offset_i == 0 {
-            let full = reply.add(ino, 1, FileType::Directory, ".");
-            if full {
-                reply.ok();
-                return;
+        // Directorio virtual: ".", ".." y sus archivos, con offsets fijos
+        if ino == QRFS_META_INO {
+            let entries = [(QRFS_META_INO, FileType::Directory, "."), (ROOT_INO, FileType::Directory, "..")]
+                .into_iter()
+                .chain(MetaFile::ALL.iter().map(|f| (f.ino(), FileType::RegularFile, f.name())));
+            for (i, (entry_ino, kind, name)) in entries.enumerate().skip(offset as usize) {
+                if reply.add(entry_ino, (i + 1) as i64, kind, name) {
+                    break;
+                }
             }
-            offset_i = 1;
+            reply.ok();
+            return;
         }
 
-        // ".." (offset 1)
-        if offset_i == 1 {
-            let parent = dir::parent_inode(&inner, ino).unwrap_or(ino);
-            let full = reply.add(parent, 2, FileType::Directory, "..");
-            if full {
-                reply.ok();
-                return;
-            }
-            offset_i = 2;
-        }
+        let inner = self.inner.read().unwrap();
+        let streams = inner.dir_streams.lock().unwrap();
+
+        // Se pagina sobre la copia que tomó opendir; un handle que no es de un
+        // opendir nuestro lista el directorio como está ahora
+        let fresh;
+        let entries: &[dir::DirEntry] = match streams.get(fh) {
+            Some(entries) => entries,
+            None => match dir::snapshot_directory(&inner, inner.fuse_ino(ino)) {
+                Ok(entries) => {
+                    fresh = entries;
+                    &fresh
+                }
+                Err(_) => {
+                    reply.error(ENOENT);
+                    return;
+                }
+            },
+        };
 
-        // Resto de entradas (offset >= 2)
-        for (i, e) in entries.iter().enumerate().skip(offset_i - 2) {
-            let next_offset = (i + 3) as i64;
-            let full = reply.add(e.ino, next_offset, e.file_type, &e.name);
-            if full {
+        // La entrada i ("." es la 0, ".." la 1) tiene el offset i + 1
+        for (i, e) in entries.iter().enumerate().skip(offset as usize) {
+            if reply.add(e.ino, (i + 1) as i64, e.file_type, &e.name) {
                 break;
             }
         }
@@ -953,13 +4287,19 @@ impl Filesystem for QrfsFilesystem {
         parent: u64,
         name: &OsStr,
         mode: u32,
-        _umask: u32,
+        umask: u32,
         reply: ReplyEntry,
     ) {
         println!("mkdir llamado: parent = {parent}, name = {:?}", name);
         let mut inner = self.inner.write().unwrap();
-        match dir::create_directory(&mut inner, parent, name, mode) {
-            Ok(attr) => reply.entry(&Duration::from_secs(1), &attr, 0),
+        let parent = inner.fuse_ino(parent);
+        match dir::create_directory(&mut inner, parent, name, masked_perm(mode, umask)) {
+            Ok(mut attr) => {
+                inner.remember(attr.ino);
+                let generation = inner.inodes.get(&attr.ino).map_or(0, |i| i.generation);
+                attr.ino = inner.fuse_ino(attr.ino);
+                reply.entry(&Duration::from_secs(1), &attr, generation as u64)
+            }
             Err(e) => reply.error(e.as_errno()),
         }
     }
@@ -974,12 +4314,30 @@ impl Filesystem for QrfsFilesystem {
     ) {
         println!("rmdir llamado: parent = {parent}, name = {:?}", name);
         let mut inner = self.inner.write().unwrap();
+        let parent = inner.fuse_ino(parent);
         match dir::remove_directory(&mut inner, parent, name) {
             Ok(()) => reply.ok(),
             Err(e) => reply.error(e.as_errno()),
         }
     }
 
+    // unlink (delegado a dir.rs): borrado suave, recuperable con `undelete`
+    fn unlink(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        reply: ReplyEmpty,
+    ) {
+        println!("unlink llamado: parent = {parent}, name = {:?}", name);
+        let mut inner = self.inner.write().unwrap();
+        let parent = inner.fuse_ino(parent);
+        match dir::unlink_entry(&mut inner, parent, name) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(e.as_errno()),
+        }
+    }
+
     // rename (delegado a dir.rs)
     fn rename(
         &mut self,
@@ -988,85 +4346,345 @@ impl Filesystem for QrfsFilesystem {
         name: &OsStr,
         newparent: u64,
         newname: &OsStr,
-        _flags: u32,
+        flags: u32,
         reply: ReplyEmpty,
     ) {
         println!(
             "rename llamado: parent = {parent}, name = {:?}, newparent = {newparent}, newname = {:?}",
             name, newname
         );
+
+        // RENAME_NOREPLACE / RENAME_EXCHANGE no están soportados
+        if flags != 0 {
+            reply.error(dir::DirError::NotSupported.as_errno());
+            return;
+        }
+
         let mut inner = self.inner.write().unwrap();
+        let (parent, newparent) = (inner.fuse_ino(parent), inner.fuse_ino(newparent));
         match dir::rename_entry(&mut inner, parent, name, newparent, newname) {
             Ok(()) => reply.ok(),
             Err(e) => reply.error(e.as_errno()),
         }
     }
 
-    // statfs: estadísticas del FS (usa el superblock)
-    fn statfs(
+    // lseek: SEEK_DATA / SEEK_HOLE sobre los huecos (bloques sin asignar) del archivo.
+    // SEEK_SET/CUR/END los resuelve el kernel sin llegar hasta aquí.
+    fn lseek(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        whence: i32,
+        reply: ReplyLseek,
+    ) {
+        println!("lseek llamado: ino = {ino}, offset = {offset}, whence = {whence}");
+
+        if whence != libc::SEEK_DATA && whence != libc::SEEK_HOLE {
+            reply.error(libc::EINVAL);
+            return;
+        }
+        if offset < 0 {
+            reply.error(libc::ENXIO);
+            return;
+        }
+
+        let inner = self.inner.read().unwrap();
+        let ino = inner.fuse_ino(ino);
+        match seek_data_or_hole(&inner, ino, offset as u64, whence) {
+            Ok(pos) => reply.offset(pos as i64),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    // fallocate: sólo FALLOC_FL_PUNCH_HOLE (siempre junto con KEEP_SIZE)
+    fn fallocate(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        length: i64,
+        mode: i32,
+        reply: ReplyEmpty,
+    ) {
+        println!("fallocate llamado: ino = {ino}, offset = {offset}, length = {length}, mode = {mode:#x}");
+
+        if mode != libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE {
+            reply.error(libc::EOPNOTSUPP);
+            return;
+        }
+        if offset < 0 || length <= 0 || offset.checked_add(length).is_none() {
+            reply.error(libc::EINVAL);
+            return;
+        }
+        if ino == QRFS_STATS_INO || ino == QRFS_CONTROL_INO || meta::is_meta_ino(ino) {
+            reply.error(libc::EACCES);
+            return;
+        }
+
+        let mut guard = self.inner.write().unwrap();
+        let inner = &mut *guard;
+        let ino = inner.fuse_ino(ino);
+
+        match inner.inodes.get(&ino) {
+            Some(inode) if inode.kind == FileType::RegularFile => {}
+            Some(_) => {
+                reply.error(libc::ENODEV);
+                return;
+            }
+            None => {
+                reply.error(ENOENT);
+                return;
+            }
+        }
+
+        match punch_hole(inner, ino, offset as u64, length as u64) {
+            Ok(()) => reply.ok(),
+            Err(e) => {
+                eprintln!("Error al perforar el rango {offset}+{length} del inodo {ino}: {e:?}");
+                reply.error(libc::EIO);
+            }
+        }
+    }
+
+    // statfs: estadísticas del FS (usa el superblock)
+    fn statfs(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        reply: ReplyStatfs,
+    ) {
+        let inner = self.inner.read().unwrap();
+        let sb = &inner.superblock;
+
+        let blocks  = sb.total_blocks as u64;
+        let bfree   = inner.free_blocks as u64;
+        let bavail  = bfree;
+        let files   = sb.max_inodes as u64;
+        let ffree   = inner.free_inodes as u64;
+        let bsize   = sb.block_size as u32;
+        let namelen = QRFS_MAX_NAME as u32;
+        let frsize  = sb.block_size as u32;
+
+        reply.statfs(
+            blocks,
+            bfree,
+            bavail,
+            files,
+            ffree,
+            bsize,
+            namelen,
+            frsize,
+        );
+    }
+
+    // fsync: vuelca el contenido residente del archivo a sus bloques y hace
+    // fsync de los archivos de bloque
+    fn fsync(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        _datasync: bool,
+        reply: ReplyEmpty,
+    ) {
+        println!("fsync llamado: ino = {ino}");
+        let mut inner = self.inner.write().unwrap();
+        let ino = inner.fuse_ino(ino);
+
+        let result = flush_resident(&mut inner, ino)
+            .and_then(|()| BlockStore::open(&inner.qr_folder)?.sync_all());
+        match result {
+            Ok(()) => reply.ok(),
+            Err(e) => {
+                eprintln!("Error en fsync del archivo {}: {e:?}", ino);
+                reply.error(libc::EIO);
+            }
+        }
+    }
+
+    // poll: en QRFS nada bloquea, así que todo archivo está siempre listo para
+    // leer (y para escribir, salvo los virtuales de sólo lectura). Sobre
+    // .qrfs-stats el kernel además puede pedir un aviso cuando cambien los
+    // contadores; lo manda `notify_stats`
+    fn poll(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        kh: u64,
+        events: u32,
+        flags: u32,
+        reply: ReplyPoll,
+    ) {
+        if ino == QRFS_STATS_INO && flags & fuser::consts::FUSE_POLL_SCHEDULE_NOTIFY != 0 {
+            self.inner.write().unwrap().stats_pollers.insert(kh);
+        }
+        reply.poll(events & poll_ready(ino));
+    }
+
+    // getlk: informa el primer lock que impediría tomar el pedido
+    fn getlk(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        lock_owner: u64,
+        start: u64,
+        end: u64,
+        typ: i32,
+        pid: u32,
+        reply: ReplyLock,
+    ) {
+        println!("getlk llamado: ino = {ino}, fh = {fh}, owner = {lock_owner}, rango = {start}..={end}, typ = {typ}");
+        let inner = self.inner.read().unwrap();
+        let ino = inner.fuse_ino(ino);
+
+        let conflict = inner
+            .locks
+            .get(&ino)
+            .and_then(|locks| locks.iter().find(|l| l.conflicts_with(lock_owner, start, end, typ)));
+
+        match conflict {
+            Some(l) => reply.locked(l.start, l.end, l.typ, l.pid),
+            None => reply.locked(start, end, libc::F_UNLCK, pid),
+        }
+    }
+
+    // setlk: toma o libera un lock advisorio. Como no bloqueamos el hilo de FUSE,
+    // un pedido que choca devuelve EAGAIN aunque se haya pedido esperar (F_SETLKW).
+    fn setlk(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        lock_owner: u64,
+        start: u64,
+        end: u64,
+        typ: i32,
+        pid: u32,
+        sleep: bool,
+        reply: ReplyEmpty,
+    ) {
+        println!(
+            "setlk llamado: ino = {ino}, fh = {fh}, owner = {lock_owner}, rango = {start}..={end}, typ = {typ}, sleep = {sleep}"
+        );
+        let mut inner = self.inner.write().unwrap();
+        let ino = inner.fuse_ino(ino);
+
+        if !inner.inodes.contains_key(&ino) {
+            reply.error(ENOENT);
+            return;
+        }
+
+        let locks = inner.locks.entry(ino).or_default();
+
+        if typ != libc::F_UNLCK
+            && locks.iter().any(|l| l.conflicts_with(lock_owner, start, end, typ))
+        {
+            reply.error(libc::EAGAIN);
+            return;
+        }
+
+        set_lock_range(locks, lock_owner, start, end, typ, pid);
+
+        if locks.is_empty() {
+            inner.locks.remove(&ino);
+        }
+
+        reply.ok();
+    }
+
+    // release: el handle se cerró, su posición de lectura ya no sirve
+    fn release(
         &mut self,
         _req: &Request<'_>,
-        _ino: u64,
-        reply: ReplyStatfs,
+        ino: u64,
+        fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: ReplyEmpty,
     ) {
         let inner = self.inner.read().unwrap();
-        let sb = &inner.superblock;
-
-        let blocks  = sb.total_blocks as u64;
-        let bfree   = inner.free_blocks as u64;
-        let bavail  = bfree;
-        let files   = sb.max_inodes as u64;
-        let ffree   = inner.free_inodes as u64;
-        let bsize   = sb.block_size as u32;
-        let namelen = 255;
-        let frsize  = sb.block_size as u32;
-
-        reply.statfs(
-            blocks,
-            bfree,
-            bavail,
-            files,
-            ffree,
-            bsize,
-            namelen,
-            frsize,
-        );
+        inner.read_ahead.forget(inner.fuse_ino(ino), fh);
+        reply.ok();
     }
 
-    // fsync: por ahora, sólo trazamos y respondemos ok
-    fn fsync(
+    // flush: al cerrar un descriptor se liberan los locks de su dueño
+    fn flush(
         &mut self,
         _req: &Request<'_>,
         ino: u64,
-        _fh: u64,
-        _datasync: bool,
+        fh: u64,
+        lock_owner: u64,
         reply: ReplyEmpty,
     ) {
-        println!("fsync llamado: ino = {ino}");
-        // Más adelante: forzar flush real hacia los QRs físicos.
+        println!("flush llamado: ino = {ino}, fh = {fh}, owner = {lock_owner}");
+        let mut inner = self.inner.write().unwrap();
+        let ino = inner.fuse_ino(ino);
+
+        if let Some(locks) = inner.locks.get_mut(&ino) {
+            locks.retain(|l| l.owner != lock_owner);
+            if locks.is_empty() {
+                inner.locks.remove(&ino);
+            }
+        }
+
         reply.ok();
     }
 
     // open
     fn open(
         &mut self,
-        _req: &Request<'_>,
+        req: &Request<'_>,
         ino: u64,
         flags: i32,
         reply: ReplyOpen,
     ) {
         println!("open llamado: ino = {ino}, flags = {flags}");
 
-        // Versión mínima: comprobamos que el inodo exista.
+        // El archivo de estadísticas es de sólo lectura y su tamaño cambia en cada
+        // lectura: DIRECT_IO evita que el kernel lo corte con un tamaño viejo
+        if ino == QRFS_STATS_INO {
+            if flags & libc::O_ACCMODE != libc::O_RDONLY {
+                reply.error(libc::EACCES);
+            } else {
+                reply.opened(ino, fuser::consts::FOPEN_DIRECT_IO);
+            }
+            return;
+        }
+        if ino == QRFS_CONTROL_INO {
+            reply.opened(ino, fuser::consts::FOPEN_DIRECT_IO);
+            return;
+        }
+        if MetaFile::from_ino(ino).is_some() {
+            if flags & libc::O_ACCMODE != libc::O_RDONLY {
+                reply.error(libc::EACCES);
+            } else {
+                reply.opened(ino, fuser::consts::FOPEN_DIRECT_IO);
+            }
+            return;
+        }
+
         let inner = self.inner.read().unwrap();
-        if !inner.inodes.contains_key(&ino) {
+        let Some(inode) = inner.inodes.get(&inner.fuse_ino(ino)) else {
             reply.error(ENOENT);
             return;
+        };
+
+        if !inode.permits(req.uid(), req.gid(), open_access_mask(flags)) {
+            reply.error(libc::EACCES);
+            return;
         }
 
-        // Versión mínima: aceptamos siempre y usamos el propio ino como "file handle"
+        // Versión mínima: aceptamos siempre y usamos el propio ino como "file handle".
+        // Con --no-page-cache-buffer cada read del kernel llega hasta el disco
         let fh = ino;
-        reply.opened(fh, 0);
+        let open_flags = if inner.no_page_cache_buffer { fuser::consts::FOPEN_DIRECT_IO } else { 0 };
+        reply.opened(fh, open_flags);
     }
 
     // create
@@ -1076,7 +4694,7 @@ impl Filesystem for QrfsFilesystem {
     parent: u64,
     name: &OsStr,
     mode: u32,
-    _umask: u32,
+    umask: u32,
     flags: i32,
     reply: ReplyCreate,
 ) {
@@ -1085,95 +4703,113 @@ impl Filesystem for QrfsFilesystem {
         name
     );
 
-    let name_str = name.to_string_lossy().to_string();
-
-    let mut inner = self.inner.write().unwrap();
+    let mut guard = self.inner.write().unwrap();
+    let inner = &mut *guard;
+    let parent = inner.fuse_ino(parent);
 
     // 1) Verificar que el padre existe y es directorio
-    let parent_dir = match inner.directories.get_mut(&parent) {
-        Some(d) => d,
-        None => {
-            reply.error(libc::ENOTDIR);
-            return;
-        }
-    };
+    if !inner.directories.contains_key(&parent) {
+        reply.error(libc::ENOTDIR);
+        return;
+    }
+
+    if let Err(e) = dir::check_name(name) {
+        reply.error(e.as_errno());
+        return;
+    }
 
     // 2) Verificar que no exista ya una entrada con ese nombre
-    if parent_dir.entries.contains_key(&name_str) {
+    //    (con --case-insensitive, tampoco una que sólo difiera en mayúsculas)
+    if dir::find_entry(inner, parent, name).is_some() {
         reply.error(libc::EEXIST);
         return;
     }
 
-    // 3) Reservar un nuevo inodo lógico
-    let ino = inner.next_ino;
-    inner.next_ino += 1;
+    // 3) Reservar un inodo libre antes de tocar nada: si la tabla está llena
+    //    no debe quedar ninguna entrada fantasma en memoria
+    let (ino, generation) = match alloc_inode(inner) {
+        Some(slot) => slot,
+        None => {
+            reply.error(libc::ENOSPC);
+            return;
+        }
+    };
 
-    let inode = Inode::file(ino, 0);
+    let mut inode = Inode::file(ino, 0);
+    inode.perm = masked_perm(mode, umask);
+    inode.generation = generation;
     inner.inodes.insert(ino, inode.clone());
 
     // 4) Agregar la entrada al directorio padre
-    parent_dir.entries.insert(name_str.clone(), ino);
+    if let Some(parent_dir) = inner.directories.get_mut(&parent) {
+        parent_dir.entries.insert(name.to_os_string(), ino);
+    }
 
-    // 5) Inicializar el contenido del archivo vacío
-    inner.files.insert(ino, Vec::new());
+    // 5) Inicializar el contenido del archivo vacío. Uno comprimido (o con
+    //    --no-page-cache-buffer) no queda en memoria: cada write va a disco
+    let file_type = new_file_type(inner);
+    if starts_resident(inner, file_type) {
+        inner.files.insert(ino, Vec::new());
+    }
 
     // 6-bis) Crear también el inodo en disco (versión mínima)
     {
         let qr_folder = inner.qr_folder.clone();
         let sb = &mut inner.superblock;
 
-        if ino <= sb.max_inodes as u64 {
-            // Actualizar contador de inodos libres
-            if inner.free_inodes > 0 {
-                inner.free_inodes -= 1;
-            }
-            if sb.free_inodes > 0 {
-                sb.free_inodes -= 1;
-            }
-
-            let now = SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs() as u64;
-
-            let disk_inode = InodeDisk {
-                id: ino as u32,
-                file_type: 1, // archivo regular
-                perm: inode.perm,
-                uid: inode.uid,
-                gid: inode.gid,
-                size: 0,
-                atime: now,
-                mtime: now,
-                ctime: now,
-                nlink: 1,
-                direct_blocks: [0u32; 12],
-                indirect_block: 0,
-                double_indirect_block: 0,
-                _padding: 0,
-            };
+        // Actualizar contador de inodos libres
+        if inner.free_inodes > 0 {
+            inner.free_inodes -= 1;
+        }
+        if sb.free_inodes > 0 {
+            sb.free_inodes -= 1;
+        }
 
-            if let Err(e) = write_inode_disk(&qr_folder, sb, ino, &disk_inode) {
-                eprintln!("Error al escribir inodo {} en disco: {e:?}", ino);
-            }
+        let mut disk_inode = InodeDisk {
+            id: ino as u32,
+            file_type, // archivo regular (comprimido o no)
+            perm: inode.perm,
+            uid: inode.uid,
+            gid: inode.gid,
+            size: 0,
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+            nlink: 1,
+            direct_blocks: [0u32; 12],
+            indirect_block: 0,
+            double_indirect_block: 0,
+            generation: inode.generation,
+        };
+        disk_inode.set_times(inode.ctime);
 
-            if let Err(e) = write_superblock(&qr_folder, sb) {
-                eprintln!("Error al actualizar superblock tras crear inodo {}: {e:?}", ino);
-            }
-        } else {
-            eprintln!(
-                "Advertencia: ino {} excede max_inodes {}: no se crea inodo en disco",
-                ino, sb.max_inodes
-            );
+        if let Err(e) = write_inode_disk(&qr_folder, sb, ino, &disk_inode) {
+            eprintln!("Error al escribir inodo {} en disco: {e:?}", ino);
+        }
+
+        if let Err(e) = write_superblock(&qr_folder, sb) {
+            eprintln!("Error al actualizar superblock tras crear inodo {}: {e:?}", ino);
+        }
+
+        let added = ensure_dir_block(inner, parent)
+            .and_then(|()| add_dir_entry_disk(&qr_folder, &inner.superblock, parent, name, ino));
+        if let Err(e) = added {
+            eprintln!("Error al agregar {:?} al directorio {} en disco: {e:?}", name, parent);
         }
     }
 
     // 6) Construir atributos FUSE y responder
-    let attr = inode_to_attr(&inode);
+    let attr = inner.fuse_attr(&inode);
+    inner.remember(ino);
     let ttl = Duration::from_secs(1);
     let fh = 0; // no llevamos manejo especial de file handles
+    let open_flags = if inner.no_page_cache_buffer {
+        flags as u32 | fuser::consts::FOPEN_DIRECT_IO
+    } else {
+        flags as u32
+    };
 
-    reply.created(&ttl, &attr, fh, 0, flags as u32);
+    reply.created(&ttl, &attr, fh, inode.generation as u64, open_flags);
 }
 
 
@@ -1193,122 +4829,52 @@ impl Filesystem for QrfsFilesystem {
             lock_owner
         );
 
-        if offset < 0 {
+        // offset + size debe caber en un i64: el kernel no lo garantiza para
+        // llamadores rotos, y todo el cálculo de rangos de abajo parte de eso
+        if offset < 0 || offset.checked_add(size as i64).is_none() {
             reply.error(libc::EINVAL);
             return;
         }
 
-        // Tomamos lo que necesitamos del estado interno y soltamos el lock
-        let (qr_folder, superblock, maybe_data) = {
-            let inner = self.inner.read().unwrap();
-            (
-                inner.qr_folder.clone(),
-                inner.superblock,                   // SuperblockDisk: Copy
-                inner.files.get(&ino).cloned(),    // copia opcional del buffer en RAM
-            )
-        };
-
-        // 1) Si tenemos el archivo en memoria, leemos desde RAM (como antes)
-        if let Some(data) = maybe_data {
-            let offset_usize = offset as usize;
-
-            if offset_usize >= data.len() {
-                // Más allá del EOF
-                reply.data(&[]);
-                return;
-            }
-
-            let end = std::cmp::min(offset_usize + size as usize, data.len());
-            reply.data(&data[offset_usize..end]);
+        // Nada que leer: no hace falta ni tomar el lock
+        if size == 0 {
+            reply.data(&[]);
             return;
         }
 
-        // 2) Si no está en RAM, leemos desde disco usando InodeDisk + bloques
-        //    (versión mínima: sólo bloques directos)
-        let inode_disk = match load_inode_disk(&qr_folder, &superblock, ino) {
-            Ok(inode) => inode,
-            Err(e) => {
-                eprintln!("Error en read al cargar inodo {ino} desde disco: {e:?}");
-                reply.error(libc::EIO);
-                return;
-            }
-        };
-
-        // Si es directorio, no lo tratamos como archivo de datos
-        if inode_disk.file_type == 2 {
-            reply.error(libc::EISDIR);
+        if ino == QRFS_STATS_INO {
+            let inner = self.inner.read().unwrap();
+            let snapshot = inner.stats.snapshot(inner.free_blocks, inner.free_inodes);
+            let start = (offset as usize).min(snapshot.len());
+            let end = start.saturating_add(size as usize).min(snapshot.len());
+            reply.data(&snapshot[start..end]);
             return;
         }
-
-        let file_size = inode_disk.size as i64;
-        if offset >= file_size {
-            // Más allá del EOF
-            reply.data(&[]);
+        if ino == QRFS_CONTROL_INO {
+            let inner = self.inner.read().unwrap();
+            let output = &inner.control_output;
+            let start = (offset as usize).min(output.len());
+            let end = start.saturating_add(size as usize).min(output.len());
+            reply.data(&output[start..end]);
             return;
         }
-
-        let max_len = (file_size - offset) as u32;
-        let to_read = std::cmp::min(size, max_len) as usize;
-
-        let block_size = superblock.block_size as i64;
-        let start = offset;
-        let end = offset + to_read as i64;
-
-        let first_block_idx = (start / block_size) as usize;
-        let last_block_idx = ((end - 1) / block_size) as usize;
-
-        let mut result = Vec::with_capacity(to_read);
-
-        for i in first_block_idx..=last_block_idx {
-            if i >= inode_disk.direct_blocks.len() {
-                break;
-            }
-
-            let b = inode_disk.direct_blocks[i];
-            if b == 0 {
-                // Bloque no asignado: lo tratamos como ceros
-                let remaining = to_read - result.len();
-                if remaining == 0 {
-                    break;
-                }
-                let zeros = vec![0u8; remaining.min(block_size as usize)];
-                result.extend_from_slice(&zeros);
-                continue;
-            }
-
-            let block_data = match read_fs_block(&qr_folder, b) {
-                Ok(buf) => buf,
-                Err(e) => {
-                    eprintln!("Error leyendo bloque de datos {b} para inodo {ino}: {e:?}");
-                    reply.error(libc::EIO);
-                    return;
-                }
-            };
-
-            let block_start = i as i64 * block_size;
-            let in_block_start = if i == first_block_idx {
-                (start - block_start) as usize
-            } else {
-                0
-            };
-
-            let in_block_end = if i == last_block_idx {
-                let end_in_block = (end - block_start) as usize;
-                end_in_block.min(block_data.len())
-            } else {
-                block_data.len()
-            };
-
-            if in_block_start < in_block_end && in_block_start < block_data.len() {
-                result.extend_from_slice(&block_data[in_block_start..in_block_end]);
-            }
+        if let Some(file) = MetaFile::from_ino(ino) {
+            let content = file.render(&self.inner.read().unwrap());
+            let start = (offset as usize).min(content.len());
+            let end = start.saturating_add(size as usize).min(content.len());
+            reply.data(&content[start..end]);
+            return;
         }
 
-        if result.len() > to_read {
-            result.truncate(to_read);
+        // El lock de lectura se mantiene toda la llamada: un write (que toma el de
+        // escritura) no puede cambiar el archivo a mitad de la lectura, así un
+        // rango pedido por mmap siempre sale de una sola versión del contenido
+        let inner = self.inner.read().unwrap();
+        let ino = inner.fuse_ino(ino);
+        match read_file_data(&inner, ino, fh, offset as u64, size) {
+            Ok(data) => reply.data(&data),
+            Err(errno) => reply.error(errno),
         }
-
-        reply.data(&result);
     }
 
     // write
@@ -1335,115 +4901,703 @@ impl Filesystem for QrfsFilesystem {
         return;
     }
 
-    let mut inner = self.inner.write().unwrap();
+    if ino == QRFS_STATS_INO || meta::is_meta_ino(ino) {
+        reply.error(libc::EACCES);
+        return;
+    }
 
-    // Archivo debe existir en memoria
-    let buf = match inner.files.get_mut(&ino) {
-        Some(b) => b,
-        None => {
-            reply.error(libc::ENOENT);
-            return;
+    // Cada escritura en el archivo de control es un comando completo
+    if ino == QRFS_CONTROL_INO {
+        let mut guard = self.inner.write().unwrap();
+        match control::run_command(&mut guard, data) {
+            Ok(()) => reply.written(data.len() as u32),
+            Err(errno) => reply.error(errno),
         }
-    };
+        return;
+    }
 
-    let offset_usize = offset as usize;
-    let needed_len = offset_usize + data.len();
+    let mut guard = self.inner.write().unwrap();
+    let inner = &mut *guard;
+    let ino = inner.fuse_ino(ino);
+    match write_file_data(inner, ino, offset as u64, data) {
+        Ok(written) => reply.written(written),
+        Err(errno) => reply.error(errno),
+    }
+}
 
-    if buf.len() < needed_len {
-        buf.resize(needed_len, 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::QrfsImage;
+    use crate::test_util::{fresh_image, pattern};
+
+    #[test]
+    fn sync_flushes_resident_files_past_the_first_block() {
+        let dir = fresh_image(64);
+        let path = Path::new("/residente.bin");
+        let ino = QrfsImage::open(dir.path()).unwrap().create_file(path).unwrap();
+        let data = pattern(3 * QRFS_BLOCK_SIZE as usize + 7);
+
+        // Lo que deja write en un archivo residente: el buffer y el tamaño en memoria
+        let fs = QrfsFilesystem::mount_from_folder(dir.path(), None, None).unwrap();
+        {
+            let mut inner = fs.inner.write().unwrap();
+            inner.files.insert(ino, data.clone());
+            inner.inodes.get_mut(&ino).unwrap().size = data.len() as u64;
+            inner.dirty_files.insert(ino);
+        }
+        fs.sync().unwrap();
+        assert!(fs.inner.read().unwrap().dirty_files.is_empty());
+        drop(fs);
+
+        assert_eq!(QrfsImage::open(dir.path()).unwrap().read_file(path).unwrap(), data);
     }
 
-    buf[offset_usize..offset_usize + data.len()].copy_from_slice(data);
+    /// Rangos de los locks, ordenados, para comparar.
+    fn lock_ranges(locks: &[FileLock]) -> Vec<(u64, u64, i32)> {
+        let mut ranges: Vec<_> = locks.iter().map(|l| (l.start, l.end, l.typ)).collect();
+        ranges.sort();
+        ranges
+    }
 
-    // Actualizar inodo lógico (tamaño y tiempos)
-    if let Some(inode) = inner.inodes.get_mut(&ino) {
-        let new_size = needed_len as u64;
-        if new_size > inode.size {
-            inode.size = new_size;
+    #[test]
+    fn unlocking_the_middle_of_a_lock_splits_it() {
+        let mut locks = Vec::new();
+        set_lock_range(&mut locks, 1, 0, 99, libc::F_WRLCK, 10);
+        set_lock_range(&mut locks, 2, 200, 299, libc::F_RDLCK, 20);
+        set_lock_range(&mut locks, 1, 40, 59, libc::F_UNLCK, 10);
+
+        assert_eq!(
+            lock_ranges(&locks),
+            [(0, 39, libc::F_WRLCK), (60, 99, libc::F_WRLCK), (200, 299, libc::F_RDLCK)]
+        );
+    }
+
+    #[test]
+    fn replacing_part_of_a_lock_trims_the_old_one() {
+        let mut locks = Vec::new();
+        set_lock_range(&mut locks, 1, 0, 99, libc::F_WRLCK, 10);
+        set_lock_range(&mut locks, 1, 50, u64::MAX, libc::F_RDLCK, 10);
+
+        assert_eq!(lock_ranges(&locks), [(0, 49, libc::F_WRLCK), (50, u64::MAX, libc::F_RDLCK)]);
+
+        set_lock_range(&mut locks, 1, 0, u64::MAX, libc::F_UNLCK, 10);
+        assert!(locks.is_empty());
+    }
+
+    #[test]
+    fn write_new_blocks_follows_logical_to_physical() {
+        let dir = fresh_image(400);
+        let fs = QrfsFilesystem::mount_from_folder(dir.path(), None, None).unwrap();
+        let mut inner = fs.inner.write().unwrap();
+        let block_size = inner.superblock.block_size as usize;
+        let data = pattern(300 * block_size);
+        let chunks: Vec<Vec<u8>> = data.chunks(block_size).map(<[u8]>::to_vec).collect();
+
+        let mut disk_inode = inner.load_inode(inner.root_ino).unwrap();
+        disk_inode.direct_blocks = [0; 12];
+        disk_inode.indirect_block = 0;
+        disk_inode.double_indirect_block = 0;
+        let mut allocated = Vec::new();
+        write_new_blocks(&mut inner, &mut disk_inode, &chunks, &mut allocated).unwrap();
+
+        // Datos más el indirecto simple, el doble y un bloque de punteros
+        assert_eq!(allocated.len(), chunks.len() + 3);
+        for (logical, chunk) in chunks.iter().enumerate() {
+            let block = logical_to_physical(&inner.qr_folder, &inner.superblock, &disk_inode, logical as u64)
+                .unwrap()
+                .unwrap();
+            assert_eq!(&read_fs_block(&inner.qr_folder, block).unwrap(), chunk, "bloque lógico {logical}");
         }
-        let now = SystemTime::now();
-        inode.mtime = now;
-        inode.ctime = now;
     }
 
-        // Persistir versión mínima en disco: un solo bloque directo [0]
-    {
-        let qr_folder = inner.qr_folder.clone();
-        let sb = inner.superblock; // copia
+    #[test]
+    fn sparse_write_in_the_double_indirect_region() {
+        let dir = fresh_image(64);
+        let path = Path::new("/disperso.bin");
+        let ino = QrfsImage::open(dir.path()).unwrap().create_file(path).unwrap();
+        let fs = QrfsFilesystem::mount_from_folder(dir.path(), None, None).unwrap();
+        let block_size = fs.inner.read().unwrap().superblock.block_size as u64;
+        // Más allá de los 12 directos y los 256 del indirecto simple
+        let offset = 300 * block_size + 5;
+        let data = pattern(2 * block_size as usize);
+        {
+            let mut inner = fs.inner.write().unwrap();
+            let free_before = inner.free_blocks;
+            write_range_disk(&mut inner, ino, offset, &data).unwrap();
+            // Tres bloques de datos, el doble indirecto y un bloque de punteros
+            assert_eq!(free_before - inner.free_blocks, 5);
+
+            truncate_disk(&mut inner, ino, 0).unwrap();
+            assert_eq!(inner.free_blocks, free_before);
+        }
+        drop(fs);
+        assert!(crate::check(dir.path()).errors.is_empty());
+    }
+
+    /// Imagen con `/datos.bin` escrito en disco y montada: la carpeta, el FS,
+    /// el inodo y el contenido.
+    fn mounted_file(len: usize) -> (tempfile::TempDir, QrfsFilesystem, u64, Vec<u8>) {
+        let dir = fresh_image(64);
+        let path = Path::new("/datos.bin");
+        let data = pattern(len);
+        let image = QrfsImage::open(dir.path()).unwrap();
+        let ino = image.create_file(path).unwrap();
+        image.write_file(path, &data).unwrap();
+        drop(image);
+
+        let fs = QrfsFilesystem::mount_from_folder(dir.path(), None, None).unwrap();
+        (dir, fs, ino, data)
+    }
 
-        // Tomamos el contenido completo actual del archivo
-        if let Some(full_data) = inner.files.get(&ino) {
-            let block_size = sb.block_size as usize;
-            let to_write = std::cmp::min(block_size, full_data.len());
-            let data = &full_data[..to_write];
+    #[test]
+    fn read_larger_than_the_rest_of_the_file() {
+        let (_dir, fs, ino, data) = mounted_file(2500);
+        let mut inner = fs.inner.write().unwrap();
 
-            // Cargar el inodo de disco (puede estar en cero si nunca se inicializó bien)
-            let mut disk_inode = match load_inode_disk(&qr_folder, &sb, ino) {
-                Ok(inode) => inode,
-                Err(e) => {
-                    eprintln!("Error al cargar inodo {} desde disco en write: {e:?}", ino);
-                    // Creamos uno desde cero como fallback
-                    InodeDisk {
-                        id: ino as u32,
-                        file_type: 1,
-                        perm: 0o644,
-                        uid: 0,
-                        gid: 0,
-                        size: 0,
-                        atime: 0,
-                        mtime: 0,
-                        ctime: 0,
-                        nlink: 1,
-                        direct_blocks: [0u32; 12],
-                        indirect_block: 0,
-                        double_indirect_block: 0,
-                        _padding: 0,
-                    }
-                }
-            };
+        assert_eq!(read_file_data(&inner, ino, 0, 100, 1 << 20).unwrap(), &data[100..]);
+        assert_eq!(read_file_data(&inner, ino, 0, 1030, 1 << 20).unwrap(), &data[1030..]);
+        assert!(read_file_data(&inner, ino, 0, 2500, 10).unwrap().is_empty());
+        assert!(read_file_data(&inner, ino, 0, 9000, 10).unwrap().is_empty());
 
-            // Si no tiene bloque de datos asignado en direct_blocks[0], lo asignamos ahora
-            if disk_inode.direct_blocks[0] == 0 {
-                match alloc_block(&mut inner) {
-                    Ok(b) => {
-                        disk_inode.direct_blocks[0] = b;
-                    }
-                    Err(e) => {
-                        eprintln!("Sin bloques libres para archivo {}: {e:?}", ino);
-                        // No podemos persistir, pero el write en memoria ya se hizo
-                        reply.written(data.len() as u32);
-                        return;
-                    }
-                }
+        // Lo mismo con el archivo residente
+        inner.files.insert(ino, data.clone());
+        assert_eq!(read_file_data(&inner, ino, 0, 100, 1 << 20).unwrap(), &data[100..]);
+        assert!(read_file_data(&inner, ino, 0, 9000, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn setattr_times_persist_and_omit_keeps_the_old_value() {
+        let (dir, fs, ino, _) = mounted_file(10);
+        let mtime = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        let atime_before = fs.inner.read().unwrap().inodes[&ino].atime;
+        {
+            let mut inner = fs.inner.write().unwrap();
+            let changes = AttrChanges { mtime: Some(TimeOrNow::SpecificTime(mtime)), ..Default::default() };
+            let attr = set_attr(&mut inner, ino, &changes).unwrap();
+            assert_eq!(attr.mtime, mtime);
+            assert_eq!(attr.atime, atime_before);
+        }
+        drop(fs);
+
+        let disk = QrfsImage::open(dir.path()).unwrap().read_inode_raw(ino).unwrap();
+        assert_eq!(disk.mtime(), mtime);
+
+        // UTIME_NOW usa el reloj
+        let fs = QrfsFilesystem::mount_from_folder(dir.path(), None, None).unwrap();
+        let mut inner = fs.inner.write().unwrap();
+        let before = SystemTime::now() - Duration::from_secs(1);
+        let changes = AttrChanges { atime: Some(TimeOrNow::Now), ..Default::default() };
+        let attr = set_attr(&mut inner, ino, &changes).unwrap();
+        assert!(attr.atime >= before);
+        assert_eq!(attr.mtime, mtime);
+    }
+
+    #[test]
+    fn seek_data_and_hole_around_a_hole_in_the_middle() {
+        let dir = fresh_image(64);
+        let path = Path::new("/hueco.bin");
+        let ino = QrfsImage::open(dir.path()).unwrap().create_file(path).unwrap();
+        let fs = QrfsFilesystem::mount_from_folder(dir.path(), None, None).unwrap();
+        let mut inner = fs.inner.write().unwrap();
+        let bs = inner.superblock.block_size as u64;
+
+        // Datos en los bloques 0 y 3, hueco en 1 y 2; el archivo termina a mitad del 3
+        write_range_disk(&mut inner, ino, 0, &pattern(bs as usize)).unwrap();
+        write_range_disk(&mut inner, ino, 3 * bs, &pattern(100)).unwrap();
+        let size = 3 * bs + 100;
+
+        assert_eq!(seek_data_or_hole(&inner, ino, 0, libc::SEEK_DATA), Ok(0));
+        assert_eq!(seek_data_or_hole(&inner, ino, 10, libc::SEEK_HOLE), Ok(bs));
+        assert_eq!(seek_data_or_hole(&inner, ino, bs + 5, libc::SEEK_HOLE), Ok(bs + 5));
+        assert_eq!(seek_data_or_hole(&inner, ino, bs + 5, libc::SEEK_DATA), Ok(3 * bs));
+        assert_eq!(seek_data_or_hole(&inner, ino, 3 * bs + 1, libc::SEEK_DATA), Ok(3 * bs + 1));
+        // Después de los últimos datos el único hueco es el EOF
+        assert_eq!(seek_data_or_hole(&inner, ino, 3 * bs, libc::SEEK_HOLE), Ok(size));
+        assert_eq!(seek_data_or_hole(&inner, ino, size, libc::SEEK_DATA), Err(libc::ENXIO));
+    }
+
+    #[test]
+    fn stats_file_counts_reads_writes_and_blocks() {
+        let (_dir, fs, ino, _) = mounted_file(0);
+        let data = pattern(3000);
+        {
+            let mut inner = fs.inner.write().unwrap();
+            assert_eq!(write_file_data(&mut inner, ino, 0, &data), Ok(3000));
+            assert_eq!(read_file_data(&inner, ino, 0, 0, 4096).unwrap(), data);
+        }
+
+        let inner = fs.inner.read().unwrap();
+        let snapshot = inner.stats.snapshot(inner.free_blocks, inner.free_inodes);
+        let json: serde_json::Value = serde_json::from_slice(&snapshot).unwrap();
+        assert_eq!(json["reads"], 1);
+        assert_eq!(json["bytes_read"], 3000);
+        assert_eq!(json["writes"], 1);
+        assert_eq!(json["bytes_written"], 3000);
+        assert_eq!(json["blocks_allocated"], 3);
+        assert_eq!(json["blocks_freed"], 0);
+        assert_eq!(json["free_blocks"], inner.free_blocks);
+        assert_eq!(json["free_inodes"], inner.free_inodes);
+    }
+
+    #[test]
+    fn offsets_near_the_limits_are_clean_errors() {
+        let (_dir, fs, ino, data) = mounted_file(100);
+        let mut inner = fs.inner.write().unwrap();
+
+        assert_eq!(write_file_data(&mut inner, ino, i64::MAX as u64 - 1, b"xy"), Err(libc::EFBIG));
+        assert_eq!(write_file_data(&mut inner, ino, u64::MAX - 1, b"xy"), Err(libc::EINVAL));
+        assert_eq!(inner.inodes[&ino].size, 100);
+
+        assert!(read_file_data(&inner, ino, 0, i64::MAX as u64 - u32::MAX as u64, u32::MAX).unwrap().is_empty());
+        inner.files.insert(ino, data);
+        assert!(read_file_data(&inner, ino, 0, i64::MAX as u64 - u32::MAX as u64, u32::MAX).unwrap().is_empty());
+        assert_eq!(write_file_data(&mut inner, ino, u64::MAX - 1, b"xy"), Err(libc::EINVAL));
+        assert_eq!(inner.files[&ino].len(), 100);
+    }
+
+    #[test]
+    fn resident_read_stops_at_the_logical_size() {
+        let (_dir, fs, ino, data) = mounted_file(4096);
+        let mut inner = fs.inner.write().unwrap();
+        inner.files.insert(ino, data.clone());
+
+        let changes = AttrChanges { size: Some(1024), ..Default::default() };
+        set_attr(&mut inner, ino, &changes).unwrap();
+        assert_eq!(read_file_data(&inner, ino, 0, 512, 2048).unwrap(), &data[512..1024]);
+
+        // Un buffer más largo que el tamaño lógico no deja ver lo que sobra
+        inner.files.insert(ino, data.clone());
+        assert_eq!(read_file_data(&inner, ino, 0, 512, 2048).unwrap(), &data[512..1024]);
+        assert!(read_file_data(&inner, ino, 0, 1024, 2048).unwrap().is_empty());
+    }
+
+    #[test]
+    fn alloc_block_starts_at_the_hint_and_wraps_around() {
+        let (_dir, fs, _, _) = mounted_file(0);
+        let mut inner = fs.inner.write().unwrap();
+        let total = inner.superblock.total_blocks;
+
+        // Cada asignación sigue a la anterior sin volver a recorrer desde el principio
+        let first = alloc_block(&mut inner).unwrap();
+        let mut last = first;
+        while inner.free_blocks > 0 {
+            let b = alloc_block(&mut inner).unwrap();
+            assert_eq!(b, last + 1);
+            assert_eq!(inner.next_free_hint, b + 1);
+            last = b;
+        }
+        assert_eq!(last, total - 1);
+        assert!(alloc_block(&mut inner).is_err());
+
+        // Un bloque liberado por debajo de la pista es el próximo en salir
+        free_block(&mut inner, first + 3).unwrap();
+        assert_eq!(inner.next_free_hint, first + 3);
+        assert_eq!(alloc_block(&mut inner).unwrap(), first + 3);
+
+        // Con la pista al final, la búsqueda da la vuelta
+        free_block(&mut inner, first).unwrap();
+        inner.next_free_hint = total - 1;
+        assert_eq!(alloc_block(&mut inner).unwrap(), first);
+    }
+
+    #[test]
+    fn allocation_cost_does_not_grow_with_the_image() {
+        // 64 bloques: un bloque de bitmap; 10000: dos, y el primer y el último
+        // bloque de datos tienen su bit en bloques distintos
+        for total in [64, 10_000] {
+            let dir = fresh_image(total);
+            let fs = QrfsFilesystem::mount_from_folder(dir.path(), None, None).unwrap();
+            let mut inner = fs.inner.write().unwrap();
+            // La primera asignación arma el índice de bloques de inodos borrados
+            let first = alloc_block(&mut inner).unwrap();
+
+            // Con el manifiesto roto, listar la carpeta falla: asignar y liberar
+            // no la vuelven a recorrer
+            let manifest = dir.path().join(crate::block_store::QRFS_MANIFEST_NAME);
+            let saved = std::fs::read(&manifest).unwrap();
+            std::fs::write(&manifest, b"{").unwrap();
+            inner.next_free_hint = total - 1;
+            let last = alloc_block(&mut inner).unwrap();
+            assert_eq!(last, total - 1);
+            free_block(&mut inner, first).unwrap();
+            std::fs::write(&manifest, saved).unwrap();
+
+            // Cada una escribe el superblock y el único bloque del bitmap que cambió
+            crate::block_store::begin_deferred(dir.path()).unwrap();
+            alloc_block(&mut inner).unwrap();
+            let written = crate::block_store::discard_deferred(dir.path());
+            let bitmap_block = inner.superblock.free_bitmap_start + first / 8 / QRFS_BLOCK_SIZE;
+            assert_eq!(written.keys().copied().collect::<Vec<_>>(), [0, bitmap_block]);
+
+            crate::block_store::begin_deferred(dir.path()).unwrap();
+            free_block(&mut inner, last).unwrap();
+            let written = crate::block_store::discard_deferred(dir.path());
+            let bitmap_block = inner.superblock.free_bitmap_start + last / 8 / QRFS_BLOCK_SIZE;
+            assert_eq!(written.keys().copied().collect::<Vec<_>>(), [0, bitmap_block]);
+        }
+    }
+
+    #[test]
+    fn punch_hole_frees_covered_blocks_and_zeroes_the_edges() {
+        let (_dir, fs, ino, data) = mounted_file(5000);
+        let mut inner = fs.inner.write().unwrap();
+        let free_before = inner.free_blocks;
+
+        // 1500..4000: el bloque 2 entero, y parte del 1 y del 3
+        punch_hole(&mut inner, ino, 1500, 2500).unwrap();
+        assert_eq!(inner.free_blocks, free_before + 1);
+        assert_eq!(inner.inodes[&ino].size, 5000);
+
+        let read = read_file_data(&inner, ino, 0, 0, 8192).unwrap();
+        assert_eq!(read.len(), 5000);
+        assert_eq!(&read[..1500], &data[..1500]);
+        assert!(read[1500..4000].iter().all(|&b| b == 0));
+        assert_eq!(&read[4000..], &data[4000..]);
+        assert_eq!(seek_data_or_hole(&inner, ino, 0, libc::SEEK_HOLE), Ok(2048));
+    }
+
+    #[test]
+    fn open_of_a_private_file_by_another_user() {
+        let mut inode = Inode::file(2, 0);
+        inode.perm = 0o600;
+        inode.uid = 1000;
+        inode.gid = 1000;
+
+        let read = open_access_mask(libc::O_RDONLY);
+        let write = open_access_mask(libc::O_WRONLY);
+        assert_eq!(open_access_mask(libc::O_RDWR), libc::R_OK | libc::W_OK);
+        assert_eq!(open_access_mask(libc::O_RDONLY | libc::O_TRUNC), libc::R_OK | libc::W_OK);
+
+        assert!(inode.permits(1000, 1000, read));
+        assert!(inode.permits(1000, 1000, write));
+        assert!(!inode.permits(1001, 1000, read));
+        assert!(!inode.permits(1001, 1001, write));
+        // root pasa por encima de los bits rw, no de los x
+        assert!(inode.permits(0, 0, read | write));
+        assert!(!inode.permits(0, 0, libc::X_OK));
+
+        inode.perm = 0o640;
+        assert!(inode.permits(1001, 1000, read));
+        assert!(!inode.permits(1001, 1000, write));
+        assert!(!inode.permits(1001, 1001, read));
+    }
+
+    #[test]
+    fn tail_reads_have_no_padding() {
+        let (_dir, fs, ino, data) = mounted_file(2500);
+        let inner = fs.inner.read().unwrap();
+        assert_eq!(read_file_data(&inner, ino, 0, 2048, 1024).unwrap(), &data[2048..]);
+
+        // Huecos hasta el último bloque, que queda a medias
+        let (_hole_dir, hole_fs, ino, _) = mounted_file(0);
+        let mut inner = hole_fs.inner.write().unwrap();
+        assert_eq!(write_file_data(&mut inner, ino, 5000, b"cola"), Ok(4));
+        assert_eq!(inner.inodes[&ino].size, 5004);
+
+        let read = read_file_data(&inner, ino, 0, 1000, 1 << 20).unwrap();
+        assert_eq!(read.len(), 4004);
+        assert!(read[..4000].iter().all(|&b| b == 0));
+        assert_eq!(&read[4000..], b"cola");
+        assert_eq!(read_file_data(&inner, ino, 0, 2100, 10).unwrap(), vec![0; 10]);
+        assert_eq!(read_file_data(&inner, ino, 0, 5002, 1024).unwrap(), b"la");
+    }
+
+    #[test]
+    fn root_inode_other_than_one_is_fuse_ino_one() {
+        let dir = fresh_image(64);
+
+        // La raíz pasa del slot 1 al 2: inodo, "." y ".." del bloque y superblock
+        let mut sb = load_superblock(dir.path()).unwrap();
+        let table = InodeTable::open(dir.path(), &sb).unwrap();
+        let mut root = table.get(1).unwrap();
+        root.id = 2;
+        table.put(2, &root).unwrap();
+        table.put(1, &InodeDisk::empty()).unwrap();
+
+        let mut buf = read_fs_block(dir.path(), root.direct_blocks[0]).unwrap();
+        let slot = mem::size_of::<DirEntryDisk>();
+        for entry in [0, slot] {
+            buf[entry..entry + 4].copy_from_slice(&2u32.to_le_bytes());
+        }
+        write_fs_block(dir.path(), root.direct_blocks[0], &buf).unwrap();
+        sb.root_inode = 2;
+        write_superblock(dir.path(), &sb).unwrap();
+
+        let file = QrfsImage::open(dir.path()).unwrap().create_file(Path::new("/f")).unwrap();
+        {
+            let fs = QrfsFilesystem::mount_from_folder(dir.path(), None, None).unwrap();
+            let mut inner = fs.inner.write().unwrap();
+            assert_eq!(inner.root_ino, 2);
+            assert_eq!(inner.fuse_ino(ROOT_INO), 2);
+            assert_eq!(inner.fuse_ino(2), ROOT_INO);
+
+            let root = inner.fuse_ino(ROOT_INO);
+            assert_eq!(dir::find_entry(&inner, root, OsStr::new("f")).map(|(_, ino)| ino), Some(file));
+            dir::create_directory(&mut inner, root, OsStr::new("sub"), 0o755).unwrap();
+        }
+
+        let image = QrfsImage::open(dir.path()).unwrap();
+        assert_eq!(image.resolve(Path::new("/")).unwrap(), 2);
+        assert!(image.stat(Path::new("/sub")).is_ok());
+        drop(image);
+        assert!(crate::check(dir.path()).errors.is_empty(), "{:?}", crate::check(dir.path()).errors);
+    }
+
+    #[test]
+    fn reads_match_whether_the_file_is_resident_or_not() {
+        let (_dir, fs, ino, mut data) = mounted_file(5000);
+        let mut inner = fs.inner.write().unwrap();
+        inner.files.insert(ino, data.clone());
+        assert_eq!(write_file_data(&mut inner, ino, 1000, b"cambio"), Ok(6));
+        data[1000..1006].copy_from_slice(b"cambio");
+
+        // Rangos alineados a página y cruzando bordes de bloque, como los de mmap
+        let ranges = [(0, 4096), (4096, 4096), (1020, 8), (2040, 1000)];
+        let resident: Vec<Vec<u8>> =
+            ranges.iter().map(|&(off, len)| read_file_data(&inner, ino, 0, off, len).unwrap()).collect();
+
+        flush_resident(&mut inner, ino).unwrap();
+        inner.files.remove(&ino);
+        for (&(off, len), from_ram) in ranges.iter().zip(&resident) {
+            let end = (off as usize + len as usize).min(data.len());
+            assert_eq!(from_ram, &data[off as usize..end]);
+            assert_eq!(&read_file_data(&inner, ino, 0, off, len).unwrap(), from_ram);
+        }
+    }
+
+    #[test]
+    fn writes_past_the_resident_limit_stream_to_disk() {
+        let (dir, fs, ino, _) = mounted_file(0);
+        let fs = fs.resident_limit(Some(4096));
+        let data = pattern(40 * 1024);
+        {
+            let mut inner = fs.inner.write().unwrap();
+            inner.files.insert(ino, Vec::new());
+
+            for (i, chunk) in data.chunks(1024).enumerate() {
+                assert_eq!(write_file_data(&mut inner, ino, (i * 1024) as u64, chunk), Ok(1024));
+                assert!(inner.files.get(&ino).map_or(0, Vec::len) <= 4096);
             }
+            assert!(!inner.files.contains_key(&ino));
+            assert_eq!(inner.inodes[&ino].size, data.len() as u64);
+            assert_eq!(read_file_data(&inner, ino, 0, 0, 1 << 20).unwrap(), data);
+        }
+        drop(fs);
+        assert_eq!(QrfsImage::open(dir.path()).unwrap().read_file(Path::new("/datos.bin")).unwrap(), data);
+    }
 
-            let data_block = disk_inode.direct_blocks[0];
+    #[test]
+    fn both_time_formats_decode_with_or_without_the_feature() {
+        let mut inode = InodeDisk::empty();
+        inode.mtime = 1_700_000_000;
+        assert_eq!(inode.mtime(), UNIX_EPOCH + Duration::from_secs(1_700_000_000));
 
-            if let Err(e) = write_fs_block(&qr_folder, data_block, data) {
-                eprintln!(
-                    "Error al escribir bloque de datos {} para inodo {}: {e:?}",
-                    data_block, ino
-                );
-            } else {
-                // Actualizamos tamaño en disco y tiempos básicos
-                disk_inode.size = to_write as u64;
-
-                let now = SystemTime::now()
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap_or_default()
-                    .as_secs() as u64;
-                disk_inode.mtime = now;
-                disk_inode.ctime = now;
-
-                if let Err(e) = write_inode_disk(&qr_folder, &sb, ino, &disk_inode) {
-                    eprintln!("Error al actualizar inodo {} en disco: {e:?}", ino);
+        // Lo que escribe una versión con nsec-time se lee igual sin ella
+        inode.file_type |= QRFS_INODE_NSEC_TIME;
+        inode.mtime = 1_700_000_000 << NSEC_BITS | 123_456_789;
+        assert_eq!(inode.mtime(), UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789));
+    }
+
+    #[test]
+    fn stats_pollers_are_woken_after_an_operation() {
+        let (_dir, fs, ino, _) = mounted_file(0);
+        let mut last = Vec::new();
+        // La primera foto siempre es un cambio; después, sin operaciones, no hay aviso
+        assert!(fs.stats_changed(&mut last).is_some());
+        assert!(fs.stats_changed(&mut last).is_none());
+
+        // Lo que hace poll con FUSE_POLL_SCHEDULE_NOTIFY
+        fs.inner.write().unwrap().stats_pollers.insert(42);
+        assert!(fs.stats_changed(&mut last).is_none());
+
+        assert_eq!(write_file_data(&mut fs.inner.write().unwrap(), ino, 0, b"hola"), Ok(4));
+        assert_eq!(fs.stats_changed(&mut last), Some(HashSet::from([42])));
+        // Cada handle sirve para un solo aviso
+        assert!(fs.inner.read().unwrap().stats_pollers.is_empty());
+
+        let readable = (libc::POLLIN | libc::POLLRDNORM) as u32;
+        assert_eq!(poll_ready(QRFS_STATS_INO), readable);
+        assert_eq!(poll_ready(ino) & readable, readable);
+        assert_ne!(poll_ready(ino) & libc::POLLOUT as u32, 0);
+    }
+
+    #[test]
+    fn forget_evicts_files_once_every_lookup_is_returned() {
+        let dir = fresh_image(64);
+        let (sub, files) = {
+            let image = QrfsImage::open(dir.path()).unwrap();
+            let sub = image.mkdir(Path::new("/sub")).unwrap();
+            let files: Vec<u64> =
+                (0..12).map(|i| image.create_file(&Path::new("/sub").join(format!("f{}", i))).unwrap()).collect();
+            (sub, files)
+        };
+        let data = pattern(5000);
+
+        let fs = QrfsFilesystem::mount_from_folder(dir.path(), None, None).unwrap();
+        {
+            let mut inner = fs.inner.write().unwrap();
+            // Lo que hace lookup: cargar el inodo (si hace falta) y contar la referencia
+            for &ino in &files {
+                for _ in 0..1000 {
+                    inner.inode_or_load(ino).unwrap();
+                    inner.remember(ino);
                 }
             }
+            inner.remember(sub);
+            inner.files.insert(files[0], data.clone());
+            inner.inodes.get_mut(&files[0]).unwrap().size = data.len() as u64;
+            inner.dirty_files.insert(files[0]);
+
+            for &ino in &files {
+                forget_inode(&mut inner, ino, 999);
+            }
+            assert!(files.iter().all(|ino| inner.inodes.contains_key(ino)));
+
+            for &ino in &files {
+                forget_inode(&mut inner, ino, 1);
+            }
+            forget_inode(&mut inner, sub, 1);
+            assert!(files.iter().all(|ino| !inner.inodes.contains_key(ino)));
+            assert!(inner.files.is_empty() && inner.lookups.lock().unwrap().is_empty());
+            // Los directorios quedan: el árbol vive en memoria
+            assert!(inner.inodes.contains_key(&sub));
+
+            // El próximo lookup lo vuelve a leer, con lo que tenía el buffer ya en disco
+            assert_eq!(inner.inode_or_load(files[0]).unwrap().size, data.len() as u64);
+            assert_eq!(read_file_data(&inner, files[0], 0, 0, 1 << 20).unwrap(), data);
         }
+        drop(fs);
+        assert_eq!(QrfsImage::open(dir.path()).unwrap().read_file(Path::new("/sub/f0")).unwrap(), data);
     }
 
+    #[test]
+    fn umask_is_applied_to_new_files_and_directories() {
+        assert_eq!(masked_perm(0o666, 0o022), 0o644);
+        assert_eq!(masked_perm(libc::S_IFREG | 0o666, 0o022), 0o644);
+        assert_eq!(masked_perm(0o4777, 0o077), 0o700);
+
+        let dir = fresh_image(64);
+        let fs = QrfsFilesystem::mount_from_folder(dir.path(), None, None).unwrap();
+        let ino = {
+            let mut inner = fs.inner.write().unwrap();
+            let root = inner.root_ino;
+            dir::create_directory(&mut inner, root, OsStr::new("sub"), masked_perm(0o777, 0o027)).unwrap().ino
+        };
+        drop(fs);
+        assert_eq!(QrfsImage::open(dir.path()).unwrap().read_inode_raw(ino).unwrap().perm, 0o750);
+    }
 
-    reply.written(data.len() as u32);
-}
+    #[test]
+    fn reused_inode_slot_starts_empty() {
+        let dir = fresh_image(64);
+        let path = Path::new("/viejo.bin");
+        let (old_ino, old) = {
+            let image = QrfsImage::open(dir.path()).unwrap();
+            let ino = image.create_file(path).unwrap();
+            image.write_file(path, &pattern(14 * QRFS_BLOCK_SIZE as usize)).unwrap();
+            image.remove(path).unwrap();
+            (ino, image.read_inode_raw(ino).unwrap())
+        };
+        // El borrado suave conserva los punteros viejos en el registro
+        assert!(old.is_deleted() && old.direct_blocks[0] != 0 && old.indirect_block != 0);
+
+        let fs = QrfsFilesystem::mount_from_folder(dir.path(), None, None).unwrap();
+        let mut inner = fs.inner.write().unwrap();
+        inner.next_ino = old_ino;
+        inner.files.insert(old_ino, b"viejo".to_vec());
+
+        let (ino, generation) = alloc_inode(&mut inner).unwrap();
+        assert_eq!(ino, old_ino);
+        assert_eq!(generation, old.generation.wrapping_add(1));
+        assert!(!inner.files.contains_key(&ino));
+
+        let fresh = inner.load_inode(ino).unwrap();
+        assert_eq!(fresh.size, 0);
+        assert_eq!(fresh.direct_blocks, [0; 12]);
+        assert_eq!((fresh.indirect_block, fresh.double_indirect_block), (0, 0));
+    }
+
+    #[test]
+    fn max_file_size_with_1024_byte_blocks() {
+        // 12 directos, 256 por el indirecto y 256 * 256 por el doble indirecto
+        assert_eq!(max_file_size(1024), (12 + 256 + 256 * 256) * 1024);
+        assert_eq!(max_file_size(1024), 67_383_296);
+    }
+
+    #[test]
+    fn no_page_cache_buffer_keeps_contents_on_disk() {
+        let (dir, fs, ino, _) = mounted_file(0);
+        let fs = fs.no_page_cache_buffer(true);
+        let data = pattern(10 * 1024);
+        {
+            let mut inner = fs.inner.write().unwrap();
+            let file_type = new_file_type(&mut inner);
+            assert!(!starts_resident(&inner, file_type));
+            inner.no_page_cache_buffer = false;
+            assert!(starts_resident(&inner, file_type));
+            inner.no_page_cache_buffer = true;
+
+            for (i, chunk) in data.chunks(1000).enumerate() {
+                assert_eq!(write_file_data(&mut inner, ino, (i * 1000) as u64, chunk), Ok(chunk.len() as u32));
+            }
+            assert!(inner.files.is_empty());
+            assert_eq!(read_file_data(&inner, ino, 0, 0, 1 << 20).unwrap(), data);
+            assert_eq!(read_file_data(&inner, ino, 0, 5000, 100).unwrap(), &data[5000..5100]);
+        }
+        drop(fs);
+        assert_eq!(QrfsImage::open(dir.path()).unwrap().read_file(Path::new("/datos.bin")).unwrap(), data);
+    }
 
+    #[test]
+    fn stats_accessor_counts_known_operations() {
+        let (_dir, fs, ino, _) = mounted_file(0);
+        let before = fs.stats();
+        let data = pattern(3000);
+        {
+            let mut inner = fs.inner.write().unwrap();
+            assert_eq!(write_file_data(&mut inner, ino, 0, &data), Ok(3000));
+            read_file_data(&inner, ino, 0, 0, 1000).unwrap();
+            inner.files.insert(ino, data.clone());
+            read_file_data(&inner, ino, 0, 0, 500).unwrap();
+            inner.files.remove(&ino);
+
+            let changes = AttrChanges { size: Some(0), ..Default::default() };
+            set_attr(&mut inner, ino, &changes).unwrap();
+        }
+
+        let after = fs.stats();
+        assert_eq!(after.writes - before.writes, 1);
+        assert_eq!(after.bytes_written - before.bytes_written, 3000);
+        assert_eq!(after.reads - before.reads, 2);
+        assert_eq!(after.bytes_read - before.bytes_read, 1500);
+        assert_eq!(after.cache_hits - before.cache_hits, 1);
+        assert_eq!(after.cache_misses - before.cache_misses, 1);
+        assert_eq!(after.blocks_allocated - before.blocks_allocated, 3);
+        assert_eq!(after.blocks_freed - before.blocks_freed, 3);
+        assert!(after.inode_reads > before.inode_reads);
+    }
+
+    #[test]
+    fn zero_length_reads_and_writes_change_nothing() {
+        let (_dir, fs, ino, data) = mounted_file(100);
+        let mut inner = fs.inner.write().unwrap();
+        let free_blocks = inner.free_blocks;
+        let mtime = inner.inodes[&ino].mtime;
+
+        for resident in [false, true] {
+            if resident {
+                inner.files.insert(ino, data.clone());
+            }
+            assert_eq!(write_file_data(&mut inner, ino, 1 << 20, &[]), Ok(0));
+            assert_eq!(inner.inodes[&ino].size, 100);
+            assert_eq!(inner.inodes[&ino].mtime, mtime);
+            assert_eq!(inner.free_blocks, free_blocks);
+            assert!(read_file_data(&inner, ino, 0, 10, 0).unwrap().is_empty());
+        }
+        assert_eq!(inner.files[&ino].len(), 100);
+        assert!(!inner.dirty_files.contains(&ino));
+    }
 }
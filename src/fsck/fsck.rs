@@ -0,0 +1,777 @@
+/*EL ARCHIVO PRINCIPAL DE FSCK. Aquí esta la función principal, 
+validaciones básicas como leer superblock, validar inodos, validar bloques,
+recopilar errores. Ahora mismo es simple */
+use std::collections::{HashMap, HashSet};
+
+use crate::compress::QRFS_COMPRESSED_HEADER;
+use super::{fsck_backend::FsckBackend, fsck_types::*};
+
+fn check_superblock<B: FsckBackend>(
+    backend: &B,
+    report: &mut FsckReport
+) {
+    let sb = backend.load_superblock();
+    let inodes = backend.load_all_inodes();
+    let bitmap = backend.load_block_bitmap();
+
+    // 1. Magic number
+    if sb.magic != 0x1234 {
+        report.errors.push("Superblock: magic inválido".into());
+    }
+
+    // 1a. Regiones del layout
+    if let Some(problem) = &sb.layout_error {
+        report.errors.push(problem.clone());
+        report.blocks_ok = false;
+    }
+
+    // 1b. Checksum (detecta corrupción parcial que deja el magic intacto)
+    if !sb.checksum_ok {
+        report.errors.push("Superblock: checksum no coincide (superblock checksum mismatch)".into());
+    }
+
+    // 2. Coincidencia del número de inodos
+    if sb.num_inodes as usize != inodes.len() {
+        report.errors.push(format!(
+            "Superblock: num_inodes = {}, pero hay {} inodos reales",
+            sb.num_inodes,
+            inodes.len()
+        ));
+        report.inodes_ok = false;
+    }
+
+    // 3. Coincidencia del número de bloques
+    if sb.num_blocks as usize != bitmap.len() {
+        report.errors.push(format!(
+            "Superblock: num_blocks = {}, pero bitmap tiene {} entradas",
+            sb.num_blocks,
+            bitmap.len()
+        ));
+        report.blocks_ok = false;
+    }
+
+    // 4. root_inode válido
+    if sb.root_inode as usize >= inodes.len() {
+        report.errors.push(format!(
+            "Superblock: root_inode ({}) fuera de rango",
+            sb.root_inode
+        ));
+        report.inodes_ok = false;
+    }
+
+    // 5. Reglas básicas que nunca deben violarse
+    if sb.num_blocks == 0 {
+        report.errors.push("Superblock: num_blocks no puede ser 0".into());
+        report.blocks_ok = false;
+    }
+
+    if sb.num_inodes == 0 {
+        report.errors.push("Superblock: num_inodes no puede ser 0".into());
+        report.inodes_ok = false;
+    }
+}
+
+fn check_bitmap_global<B: FsckBackend>(backend: &B, sb: &Superblock, report: &mut FsckReport) {
+    let bitmap = backend.load_block_bitmap();
+    let inodes = backend.load_all_inodes();
+
+    // 1. Tamaño incorrecto
+    if bitmap.len() != sb.num_blocks as usize {
+        report.errors.push(format!(
+            "Bitmap tiene tamaño incorrecto: {} en vez de {}",
+            bitmap.len(),
+            sb.num_blocks
+        ));
+        report.blocks_ok = false;
+        return;
+    }
+
+    // 2. Bloques realmente usados por inodos (también los que se alcanzan a
+    //    través de los bloques indirectos)
+    let mut used_by_inodes = vec![false; sb.num_blocks as usize];
+
+    for inode in &inodes {
+        for blk in inode_blocks(backend, sb, inode) {
+            if blk < sb.num_blocks {
+                used_by_inodes[blk as usize] = true;
+            }
+        }
+    }
+
+    // 3. Comparación bitmap <-> realidad (los bloques de metadatos no son de ningún inodo)
+    for block in sb.first_data_block as usize..sb.num_blocks as usize {
+        let bitmap_says_used = bitmap[block];
+        let inode_says_used = used_by_inodes[block];
+        let bad = sb.bad_blocks.contains(&(block as u32));
+
+        if bad && inode_says_used {
+            report.notes.push(format!(
+                "El bloque {} está marcado como dañado y lo usa un inodo: esa parte sólo se lee si la paridad lo reconstruye",
+                block
+            ));
+        }
+
+        if bitmap_says_used && !inode_says_used && !bad {
+            report.errors.push(format!(
+                "Bitmap marca usado el bloque {}, pero ningún inodo lo usa",
+                block
+            ));
+            report.blocks_ok = false;
+        }
+
+        if !bitmap_says_used && inode_says_used {
+            report.errors.push(format!(
+                "Bitmap marca libre el bloque {}, pero algún inodo lo usa",
+                block
+            ));
+            report.blocks_ok = false;
+        }
+    }
+}
+
+
+fn check_dirs<B: FsckBackend>(backend: &B, scope: Option<&HashSet<u32>>, report: &mut FsckReport) {
+    let sb = backend.load_superblock();
+    let inodes = backend.load_all_inodes();
+
+    // Si el root inode es inválido, no tiene sentido seguir
+    if report.errors.iter().any(|e| e.contains("Superblock: root_inode")) {
+        return;
+    }
+
+    // Validar que root sea directorio
+    if sb.root_inode as usize >= inodes.len() {
+        report.errors.push("Root inode fuera de rango".into());
+        report.inodes_ok = false;
+        return;
+    }
+    if !inodes[sb.root_inode as usize].is_dir {
+        report.errors.push("Root inode no es un directorio".into());
+        report.inodes_ok = false;
+    }
+
+    // Validar cada directorio (sólo los del subárbol pedido, si hay uno)
+    for (ino_id, inode) in inodes.iter().enumerate() {
+        if scope.is_some_and(|s| !s.contains(&(ino_id as u32))) {
+            continue;
+        }
+        if inode.is_dir {
+            let entries = backend.read_dir(ino_id as u32);
+
+            for entry in entries {
+                // Nombre vacío
+                if entry.name.is_empty() {
+                    report.errors.push(format!(
+                        "Inodo {}: dirent con nombre vacío",
+                        ino_id
+                    ));
+                }
+
+                // Inodo fuera de rango
+                if entry.inode as usize >= inodes.len() {
+                    report.errors.push(format!(
+                        "Inodo {}: dirent '{}' apunta a inodo inexistente ({})",
+                        ino_id,
+                        entry.name,
+                        entry.inode
+                    ));
+                    continue; // ← ¡Evita que leamos un índice inválido!
+                }
+
+                // Tipo no concuerda
+                let target = &inodes[entry.inode as usize];
+                    if entry.is_dir != target.is_dir {
+                        report.errors.push(format!(
+                            "Dirent '{}' en inodo {} declara tipo incorrecto",
+                            entry.name,
+                            ino_id
+                        ));
+                    }
+            }
+        }
+    }
+}
+
+/// El ".." de cada directorio tiene que apuntar al directorio que lo lista
+/// (la raíz, a sí misma): el montaje arma el árbol a partir de "..". Los
+/// directorios sin ".." (un backend que no los guarda) no se revisan.
+fn check_dotdot<B: FsckBackend>(backend: &B, scope: Option<&HashSet<u32>>, report: &mut FsckReport) {
+    let sb = backend.load_superblock();
+    let inodes = backend.load_all_inodes();
+
+    // Padre de cada directorio: el primero que lo lista con un nombre propio
+    let mut dotdots = Vec::new();
+    let mut parents: HashMap<u32, u32> = HashMap::new();
+    parents.insert(sb.root_inode, sb.root_inode);
+    for (ino_id, inode) in inodes.iter().enumerate() {
+        if !inode.in_use || !inode.is_dir {
+            continue;
+        }
+        for entry in backend.read_dir(ino_id as u32) {
+            match entry.name.as_str() {
+                "." => {}
+                ".." => dotdots.push((ino_id as u32, entry.inode)),
+                _ if entry.is_dir && entry.inode != ino_id as u32 => {
+                    parents.entry(entry.inode).or_insert(ino_id as u32);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    for (ino, dotdot) in dotdots {
+        if scope.is_some_and(|s| !s.contains(&ino)) {
+            continue;
+        }
+        // Un directorio que nadie lista ya lo reporta la búsqueda de huérfanos
+        let Some(&parent) = parents.get(&ino) else {
+            continue;
+        };
+        if dotdot != parent {
+            report.errors.push(format!(
+                "Directorio {}: \"..\" apunta al inodo {} y no a su padre {} (fsck.qrfs --repair-dots lo corrige)",
+                ino, dotdot, parent
+            ));
+        }
+    }
+}
+
+fn check_orphan_inodes<B: FsckBackend>(backend: &B, report: &mut FsckReport) {
+    let sb = backend.load_superblock();
+    let inodes = backend.load_all_inodes();
+
+    // Mapa: inodos referenciados por algún directorio
+    let mut referenced = vec![false; inodes.len()];
+
+    // El root SIEMPRE se considera referenciado
+    if sb.root_inode < inodes.len() as u32 {
+        referenced[sb.root_inode as usize] = true;
+    }
+
+    // Recorrer los directorios para marcar referencias. "." y ".." no cuentan:
+    // un directorio suelto se apunta a sí mismo y a su antiguo padre
+    for (ino_id, inode) in inodes.iter().enumerate() {
+        if inode.is_dir {
+            for entry in backend.read_dir(ino_id as u32) {
+                if entry.name == "." || entry.name == ".." {
+                    continue;
+                }
+                if entry.inode < inodes.len() as u32 {
+                    referenced[entry.inode as usize] = true;
+                }
+            }
+        }
+    }
+
+    // Finalmente: detectar huérfanos (los slots libres no cuentan)
+    for ino in 0..inodes.len() {
+        if inodes[ino].in_use && !referenced[ino] {
+            report.errors.push(format!("Inodo {} huérfano", ino));
+            report.inodes_ok = false;
+        }
+    }
+}
+
+
+/// Recalcula el CRC32 de cada bloque de datos que tiene checksum guardado y
+/// reporta los que no coinciden, con el inodo que los usa. Devuelve `false` si
+/// ningún bloque tenía checksum (no hubo nada que revisar).
+fn check_block_checksums<B: FsckBackend>(backend: &B, sb: &Superblock, report: &mut FsckReport) -> bool {
+    // Dueño de cada bloque (el primero que lo referencia)
+    let mut owner = HashMap::new();
+    for (ino_id, inode) in backend.load_all_inodes().iter().enumerate() {
+        for blk in inode_blocks(backend, sb, inode) {
+            owner.entry(blk).or_insert(ino_id);
+        }
+    }
+
+    let mut checked = 0;
+    for block in sb.first_data_block..sb.num_blocks {
+        let Some(stored) = backend.block_checksum(block) else {
+            continue;
+        };
+        checked += 1;
+
+        let used_by = match owner.get(&block) {
+            Some(ino) => format!("inodo {}", ino),
+            None => "ningún inodo".to_string(),
+        };
+        match backend.read_block(block) {
+            Some(data) => {
+                let actual = crate::fs::crc32(&data);
+                if actual != stored {
+                    report.errors.push(format!(
+                        "Bloque {}: checksum {:#010X} guardado, {:#010X} calculado (usado por {})",
+                        block, stored, actual, used_by
+                    ));
+                    report.blocks_ok = false;
+                }
+            }
+            None => {
+                report.errors.push(format!("Bloque {}: no se pudo leer (usado por {})", block, used_by));
+                report.blocks_ok = false;
+            }
+        }
+    }
+
+    checked > 0
+}
+
+fn check_blocks_global<B: FsckBackend>(backend: &B, sb: &Superblock, report: &mut FsckReport) {
+    let mut seen = std::collections::HashSet::new();
+
+    for (ino_id, inode) in backend.load_all_inodes().iter().enumerate() {
+
+        // Recorrer directos
+        for &blk in &inode.direct {
+            if !seen.insert(blk) {
+                report.errors.push(format!(
+                    "Inodo {}: bloque duplicado globalmente ({})",
+                    ino_id, blk
+                ));
+                report.blocks_ok = false;
+            }
+        }
+
+        // indirecto 1
+        if let Some(blk) = inode.indirect1 {
+            if !seen.insert(blk) {
+                report.errors.push(format!(
+                    "Inodo {}: indirect1 duplicado globalmente ({})",
+                    ino_id, blk
+                ));
+                report.blocks_ok = false;
+            }
+        }
+
+        // indirecto 2
+        if let Some(blk) = inode.indirect2 {
+            if !seen.insert(blk) {
+                report.errors.push(format!(
+                    "Inodo {}: indirect2 duplicado globalmente ({})",
+                    ino_id, blk
+                ));
+                report.blocks_ok = false;
+            }
+        }
+
+        // Lo que cuelga de los indirectos: datos y, en el doble, sus tablas
+        for blk in indirect_blocks(backend, sb, inode) {
+            if !seen.insert(blk) {
+                report.errors.push(format!(
+                    "Inodo {}: bloque {} (a través de un indirecto) duplicado globalmente",
+                    ino_id, blk
+                ));
+                report.blocks_ok = false;
+            }
+        }
+    }
+}
+
+
+
+fn check_inodes_basic<B: FsckBackend>(backend: &B, scope: Option<&HashSet<u32>>, report: &mut FsckReport) {
+    let sb = backend.load_superblock();
+    let total_blocks = sb.num_blocks;
+
+    // Recorremos todos los inodos que el backend expone
+    for (idx, inode) in backend.load_all_inodes().iter().enumerate() {
+        if scope.is_some_and(|s| !s.contains(&(idx as u32))) {
+            continue;
+        }
+
+        // 1. Valida tamaño
+        if inode.size == u32::MAX {
+            report.errors.push(format!("Inodo {} tiene tamaño inválido", idx));
+            report.inodes_ok = false;
+        }
+
+        // 2. Valida punteros directos
+        for &blk in &inode.direct {
+            if blk >= total_blocks {
+                report.errors.push(format!(
+                    "Inodo {}: bloque directo fuera de rango ({})",
+                    idx, blk
+                ));
+                report.inodes_ok = false;
+            }
+        }
+
+        // 3. Valida puntero indirecto 1
+        if let Some(blk) = inode.indirect1 {
+            if blk >= total_blocks {
+                report.errors.push(format!(
+                    "Inodo {}: indirect1 fuera de rango ({})",
+                    idx, blk
+                ));
+                report.inodes_ok = false;
+            }
+        }
+
+        // 4. Valida puntero indirecto 2
+        if let Some(blk) = inode.indirect2 {
+            if blk >= total_blocks {
+                report.errors.push(format!(
+                    "Inodo {}: indirect2 fuera de rango ({})",
+                    idx, blk
+                ));
+                report.inodes_ok = false;
+            }
+        }
+
+        // 5. Detectar duplicados dentro del mismo inodo
+        let mut seen = std::collections::HashSet::new();
+        for &blk in &inode.direct {
+            if !seen.insert(blk) {
+                report.errors.push(format!(
+                    "Inodo {}: bloque duplicado ({})",
+                    idx, blk
+                ));
+                report.inodes_ok = false;
+            }
+        }
+    }
+}
+
+
+
+/// Punteros distintos de cero de un bloque de punteros; uno que no se puede
+/// leer no tiene ninguno.
+fn pointer_table<B: FsckBackend>(backend: &B, block: u32) -> Vec<u32> {
+    backend
+        .read_block(block)
+        .map(|buf| {
+            buf.chunks_exact(4)
+                .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+                .filter(|&b| b != 0)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Bloques de datos asignados a un inodo: sus punteros directos más los
+/// punteros distintos de cero de sus bloques indirectos (simple y doble). Los
+/// bloques de punteros no cuentan, y uno que no se puede leer no aporta nada.
+pub(crate) fn allocated_data_blocks<B: FsckBackend>(backend: &B, inode: &Inode) -> u64 {
+    let mut count = inode.direct.len() as u64;
+    if let Some(blk) = inode.indirect1 {
+        count += pointer_table(backend, blk).len() as u64;
+    }
+    if let Some(blk) = inode.indirect2 {
+        count += pointer_table(backend, blk)
+            .into_iter()
+            .map(|mid| pointer_table(backend, mid).len() as u64)
+            .sum::<u64>();
+    }
+    count
+}
+
+/// Bloques que se alcanzan a través de los indirectos de un inodo: los de
+/// datos del simple, y las tablas intermedias del doble con los datos de cada
+/// una. Sólo se leen bloques de punteros dentro del área de datos (uno fuera
+/// de rango ya lo reporta `check_inodes_basic`, y leerlo sería leer basura).
+pub(crate) fn indirect_blocks<B: FsckBackend>(backend: &B, sb: &Superblock, inode: &Inode) -> Vec<u32> {
+    let in_data_area = |b: u32| b >= sb.first_data_block && b < sb.num_blocks;
+    let mut blocks = Vec::new();
+
+    if let Some(blk) = inode.indirect1.filter(|&b| in_data_area(b)) {
+        blocks.extend(pointer_table(backend, blk));
+    }
+    if let Some(blk) = inode.indirect2.filter(|&b| in_data_area(b)) {
+        for mid in pointer_table(backend, blk) {
+            blocks.push(mid);
+            if in_data_area(mid) {
+                blocks.extend(pointer_table(backend, mid));
+            }
+        }
+    }
+    blocks
+}
+
+/// Todos los bloques que ocupa un inodo: los directos, sus bloques de
+/// punteros y lo que cuelga de ellos (ver `indirect_blocks`).
+pub(crate) fn inode_blocks<B: FsckBackend>(backend: &B, sb: &Superblock, inode: &Inode) -> Vec<u32> {
+    let mut blocks: Vec<u32> = inode.direct.iter().chain(&inode.indirect1).chain(&inode.indirect2).copied().collect();
+    blocks.extend(indirect_blocks(backend, sb, inode));
+    blocks
+}
+
+/// Bloques que como máximo puede ocupar un inodo de `size` bytes. Un archivo
+/// comprimido guarda en cada bloque al menos `block_size - encabezado` bytes
+/// (menos el último). Con menos bloques asignados no hay error: son huecos.
+pub(crate) fn blocks_for_size(inode: &Inode, block_size: u32) -> u64 {
+    let per_block = if inode.compressed {
+        block_size.saturating_sub(QRFS_COMPRESSED_HEADER as u32)
+    } else {
+        block_size
+    };
+    (inode.size as u64).div_ceil(per_block.max(1) as u64)
+}
+
+/// Tamaño contra bloques asignados: un inodo con más bloques de datos de los
+/// que su `size` puede ocupar tiene el tamaño mal (o bloques de más).
+fn check_sizes<B: FsckBackend>(backend: &B, sb: &Superblock, scope: Option<&HashSet<u32>>, report: &mut FsckReport) {
+    if sb.block_size == 0 {
+        return;
+    }
+
+    for (idx, inode) in backend.load_all_inodes().iter().enumerate() {
+        if !inode.in_use || scope.is_some_and(|s| !s.contains(&(idx as u32))) {
+            continue;
+        }
+
+        let implied = blocks_for_size(inode, sb.block_size);
+        let allocated = allocated_data_blocks(backend, inode);
+        if allocated > implied {
+            report.errors.push(format!(
+                "Inodo {}: el tamaño ({} bytes) implica {} bloques pero tiene {} asignados",
+                idx, inode.size, implied, allocated
+            ));
+            report.inodes_ok = false;
+        }
+    }
+}
+
+/// Inodos del subárbol que cuelga de `path` (incluido el propio `path`),
+/// resolviendo la ruta desde la raíz con `read_dir`. `None` si no existe.
+fn subtree_inodes<B: FsckBackend>(backend: &B, sb: &Superblock, path: &str) -> Option<HashSet<u32>> {
+    let mut ino = sb.root_inode;
+    for component in path.split('/').filter(|c| !c.is_empty() && *c != ".") {
+        ino = backend
+            .read_dir(ino)
+            .into_iter()
+            .find(|e| e.name == component)?
+            .inode;
+    }
+
+    let mut scope = HashSet::new();
+    let mut pending = vec![ino];
+    while let Some(dir) = pending.pop() {
+        if !scope.insert(dir) {
+            continue; // ciclo o enlace repetido
+        }
+        for entry in backend.read_dir(dir) {
+            if entry.name == "." || entry.name == ".." {
+                continue;
+            }
+            if entry.is_dir {
+                pending.push(entry.inode);
+            } else {
+                scope.insert(entry.inode);
+            }
+        }
+    }
+    Some(scope)
+}
+
+/// `true` (y marca el reporte como truncado) si ya hay más errores que el
+/// límite: se conservan los primeros `limit` y el resto de pasos no se corre.
+fn limit_reached(report: &mut FsckReport, opts: &FsckOptions) -> bool {
+    match opts.limit {
+        Some(limit) if report.errors.len() > limit => {
+            report.errors.truncate(limit);
+            report.truncated = true;
+            true
+        }
+        _ => false,
+    }
+}
+
+pub fn run_fsck<B: FsckBackend>(backend: &B) -> FsckReport {
+    run_fsck_with(backend, &FsckOptions::default())
+}
+
+/// Como `run_fsck`, pero con límite de errores y/o restringido a un subárbol.
+pub fn run_fsck_with<B: FsckBackend>(backend: &B, opts: &FsckOptions) -> FsckReport {
+    let mut report = FsckReport::new();
+    let sb = backend.load_superblock();
+
+    // --- Paso 1: Validación del superblock ---
+    check_superblock(backend, &mut report);
+    if limit_reached(&mut report, opts) {
+        return report;
+    }
+
+    // Sin un superblock usable los demás campos no significan nada: recorrer
+    // `num_blocks` o `num_inodes` basura sólo llenaría el reporte (o la memoria)
+    if sb.magic != 0x1234 || sb.layout_error.is_some() {
+        return report;
+    }
+
+    // Subárbol pedido con --path: se resuelve una vez y acota los pasos 2 y 4
+    let scope = match &opts.path {
+        Some(path) => match subtree_inodes(backend, &sb, path) {
+            Some(scope) => Some(scope),
+            None => {
+                report.errors.push(format!("Ruta no encontrada: {}", path));
+                return report;
+            }
+        },
+        None => None,
+    };
+
+    // --- Paso 2: Validación básica de inodos ---
+    check_inodes_basic(backend, scope.as_ref(), &mut report);
+    if limit_reached(&mut report, opts) {
+        return report;
+    }
+
+    // --- Paso 2b: Tamaño contra bloques asignados ---
+    check_sizes(backend, &sb, scope.as_ref(), &mut report);
+    if limit_reached(&mut report, opts) {
+        return report;
+    }
+
+    // --- Paso 3: Validación global de bloques (sólo con la imagen completa) ---
+    if scope.is_none() {
+        check_blocks_global(backend, &sb, &mut report);
+        if limit_reached(&mut report, opts) {
+            return report;
+        }
+    }
+
+    // --- Paso 4: Validación de directorios ---
+    check_dirs(backend, scope.as_ref(), &mut report);
+    check_dotdot(backend, scope.as_ref(), &mut report);
+    if limit_reached(&mut report, opts) {
+        return report;
+    }
+
+    if scope.is_none() {
+        // --- Paso 5: Validación del bitmap global ---
+        check_bitmap_global(backend, &sb, &mut report);
+        if limit_reached(&mut report, opts) {
+            return report;
+        }
+
+        // --- Paso 6: Detección de inodos huérfanos ---
+        check_orphan_inodes(backend, &mut report);
+        if limit_reached(&mut report, opts) {
+            return report;
+        }
+    }
+
+    // --- Paso 7 (opcional): checksums de los bloques de datos ---
+    if opts.block_checksums && !check_block_checksums(backend, &sb, &mut report) {
+        report
+            .notes
+            .push("Checksums por bloque: omitido, la imagen no guarda checksums de sus bloques".into());
+    }
+    limit_reached(&mut report, opts);
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fsck::mock::MockBackend;
+    use crate::fsck::qrfs_backend::QrfsBackend;
+    use crate::fsck::repair::{find_repairs, run_repairs, Fix, RepairMode};
+    use crate::test_util::fresh_image;
+
+    #[test]
+    fn block_checksum_scan_finds_a_flipped_byte() {
+        let opts = FsckOptions { block_checksums: true, ..Default::default() };
+        let mut backend = MockBackend::sample();
+        backend.blocks[4] = vec![b'x'; 1024];
+        backend.checksums = backend.blocks.iter().map(|b| Some(crate::fs::crc32(b))).collect();
+
+        let report = run_fsck_with(&backend, &opts);
+        assert!(report.errors.is_empty() && report.notes.is_empty(), "{:?}", report.errors);
+
+        // Un byte cambiado en el bloque de la raíz
+        backend.blocks[4][100] ^= 0xFF;
+        let report = run_fsck_with(&backend, &opts);
+        assert_eq!(report.errors.len(), 1, "{:?}", report.errors);
+        assert!(report.errors[0].starts_with("Bloque 4:") && report.errors[0].contains("inodo 1"));
+        assert!(!report.blocks_ok);
+
+        // Sin la pasada pedida no se lee nada
+        assert!(run_fsck_with(&backend, &FsckOptions::default()).errors.is_empty());
+    }
+
+    #[test]
+    fn size_that_does_not_cover_the_blocks_is_reported_and_repaired() {
+        // Un archivo de 100 bytes con tres bloques de datos
+        let mut backend = MockBackend::sample();
+        backend.inodes[2] = Inode {
+            in_use: true,
+            is_dir: false,
+            size: 100,
+            compressed: false,
+            direct: vec![5, 6, 7],
+            indirect1: None,
+            indirect2: None,
+        };
+        backend.dirs[1].push(Dirent { inode: 2, name: "f".into(), is_dir: false, valid: true });
+        backend.bitmap[5..8].fill(true);
+
+        let report = run_fsck(&backend);
+        assert_eq!(
+            report.errors,
+            ["Inodo 2: el tamaño (100 bytes) implica 1 bloques pero tiene 3 asignados"]
+        );
+        assert!(!report.inodes_ok);
+
+        let repairs = find_repairs(&backend);
+        assert_eq!(repairs.iter().map(|r| r.fix.clone()).collect::<Vec<_>>(), [Fix::SetSize { ino: 2, size: 3072 }]);
+        run_repairs(&mut backend, &repairs, RepairMode::Yes, "".as_bytes(), &mut Vec::new()).unwrap();
+        assert!(run_fsck(&backend).errors.is_empty());
+
+        // Menos bloques que los que implica el tamaño es un archivo con huecos
+        backend.inodes[2].size = 50_000;
+        assert!(run_fsck(&backend).errors.is_empty());
+    }
+
+    #[test]
+    fn blocks_behind_the_indirect_pointers_count_as_used() {
+        // Un archivo con un directo, un indirecto simple (6 -> 7, 8) y un
+        // doble (9 -> 10 -> 11) en una imagen de 16 bloques
+        let mut backend = MockBackend::sample();
+        backend.superblock.num_blocks = 16;
+        backend.blocks = vec![vec![0; 1024]; 16];
+        let pointers = |list: &[u32]| {
+            let mut block = vec![0u8; 1024];
+            for (i, p) in list.iter().enumerate() {
+                block[i * 4..i * 4 + 4].copy_from_slice(&p.to_le_bytes());
+            }
+            block
+        };
+        backend.blocks[6] = pointers(&[7, 8]);
+        backend.blocks[9] = pointers(&[10]);
+        backend.blocks[10] = pointers(&[11]);
+        backend.inodes[2] = Inode {
+            in_use: true,
+            is_dir: false,
+            size: 300 * 1024,
+            compressed: false,
+            direct: vec![5],
+            indirect1: Some(6),
+            indirect2: Some(9),
+        };
+        backend.dirs[1].push(Dirent { inode: 2, name: "f".into(), is_dir: false, valid: true });
+        backend.bitmap = (0..16).map(|b| b <= 11).collect();
+
+        let report = run_fsck(&backend);
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert!(report.blocks_ok);
+
+        // Un bloque de datos del doble indirecto libre en el bitmap sí se reporta
+        backend.bitmap[11] = false;
+        let report = run_fsck(&backend);
+        assert_eq!(report.errors.len(), 1, "{:?}", report.errors);
+        assert!(report.errors[0].contains("11"), "{:?}", report.errors);
+    }
+
+    #[test]
+    fn block_checksum_scan_without_checksums_leaves_a_note() {
+        // Los checksums por bloque son los del manifiesto
+        let dir = fresh_image(64);
+        std::fs::remove_file(dir.path().join(crate::block_store::QRFS_MANIFEST_NAME)).unwrap();
+        let opts = FsckOptions { block_checksums: true, ..Default::default() };
+        let report = run_fsck_with(&QrfsBackend::new(dir.path().to_path_buf()), &opts);
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert_eq!(report.notes.len(), 1);
+    }
+}
@@ -0,0 +1,45 @@
+/*Define la interfaz para el backend del fsck.
+Define un trait que describe cómo el fsck debe leer:
+bloques, inodos, el directorio raíz
+Existe para permitir múltiples backends, por ejemplo:
+Un mock (lo que usa ahorita), el FS real de compañeros (cuando esté listo), pruebas de fragmentación
+
+Las escrituras son opcionales: un backend de sólo lectura no las implementa y
+las reparaciones fallan con un error en vez de tocar nada.
+*/
+
+use anyhow::{anyhow, Result};
+
+use super::fsck_types::{Dirent, Inode, Superblock};
+
+pub trait FsckBackend {
+    fn load_superblock(&self) -> Superblock;
+    fn read_inode(&self, ino: u32) -> Option<Inode>;
+    fn read_block(&self, block: u32) -> Option<Vec<u8>>;
+    fn read_dir(&self, ino: u32) -> Vec<Dirent>;
+    fn load_all_inodes(&self) -> Vec<Inode>;
+    fn load_block_bitmap(&self) -> Vec<bool>;
+
+    /// CRC32 guardado para el bloque, si la imagen guarda checksums por bloque.
+    fn block_checksum(&self, _block: u32) -> Option<u32> {
+        None
+    }
+
+    // --------- Escritura (para las reparaciones) ---------
+
+    fn write_superblock(&mut self, _sb: &Superblock) -> Result<()> {
+        Err(anyhow!("Este backend no soporta escribir el superblock"))
+    }
+
+    fn write_inode(&mut self, _ino: u32, _inode: &Inode) -> Result<()> {
+        Err(anyhow!("Este backend no soporta escribir inodos"))
+    }
+
+    fn write_block(&mut self, _block: u32, _data: &[u8]) -> Result<()> {
+        Err(anyhow!("Este backend no soporta escribir bloques"))
+    }
+
+    fn write_bitmap(&mut self, _bitmap: &[bool]) -> Result<()> {
+        Err(anyhow!("Este backend no soporta escribir el bitmap"))
+    }
+}
@@ -0,0 +1,121 @@
+/*Define todas las estructuras básicas del fsck, incluyendo:
+Superblock simplificado
+Inode simplificado
+Dirent
+FsckReport (donde se reportan errores) */
+
+use serde::Serialize;
+
+#[derive(Debug, Clone)]
+pub struct Superblock {
+    pub magic: u32,
+    pub num_inodes: u32,
+    pub num_blocks: u32,
+    pub root_inode: u32,
+    pub checksum_ok: bool,
+    /// Bytes por bloque (0 si no se conoce: no se compara tamaño con bloques).
+    pub block_size: u32,
+    /// Primer bloque de datos: los anteriores (superblock, tabla de inodos,
+    /// bitmap) están marcados como usados sin que ningún inodo los referencie.
+    pub first_data_block: u32,
+    /// Por qué las regiones del layout no tienen sentido (fuera de orden o más
+    /// allá de la imagen) o la imagen usa extensiones que esta versión no lee,
+    /// si es el caso. Con un layout inválido o el magic mal, el fsck no sigue
+    /// después del superblock.
+    pub layout_error: Option<String>,
+    /// Bloques marcados como dañados: quedan usados en el bitmap aunque ningún
+    /// inodo los tenga.
+    pub bad_blocks: Vec<u32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Inode {
+    /// `false` para los slots libres de la tabla (y el dummy del índice 0).
+    pub in_use: bool,
+    pub is_dir: bool,
+    pub size: u32,
+    /// Datos comprimidos: cada bloque guarda un tramo empaquetado con su
+    /// encabezado, así que el tamaño no dice cuántos bloques ocupa.
+    pub compressed: bool,
+    /// Punteros directos asignados (sin los ceros de los no usados).
+    pub direct: Vec<u32>,
+    pub indirect1: Option<u32>,
+    pub indirect2: Option<u32>,
+}
+
+impl Inode {
+    /// Slot libre: sin bloques y sin que nadie deba referenciarlo.
+    pub fn unused() -> Self {
+        Self {
+            in_use: false,
+            is_dir: false,
+            size: 0,
+            compressed: false,
+            direct: Vec::new(),
+            indirect1: None,
+            indirect2: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Dirent {
+    pub inode: u32,
+    pub name: String,
+    pub is_dir: bool,
+    pub valid: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Bitmap {
+    pub blocks: Vec<bool>, // true = usado, false = libre
+}
+
+/// Resultado de una revisión. La imagen está sana si `errors` está vacío.
+#[derive(Debug, Serialize)]
+pub struct FsckReport {
+    /// `false` si algún bloque está mal: fuera de rango, usado por dos inodos
+    /// o con el bitmap en desacuerdo con los inodos.
+    pub blocks_ok: bool,
+    /// `false` si algún inodo está mal: punteros inválidos, raíz que no es
+    /// directorio, inodos huérfanos o contadores del superblock que no cierran.
+    pub inodes_ok: bool,
+    /// Un mensaje legible por cada problema encontrado, en el orden en que se detectó.
+    pub errors: Vec<String>,
+    /// El recorrido se cortó al llegar a `FsckOptions::limit` errores.
+    pub truncated: bool,
+    /// Avisos que no son errores (por ejemplo, un paso pedido que se omitió).
+    pub notes: Vec<String>,
+}
+
+impl FsckReport {
+    pub fn new() -> Self {
+        Self {
+            blocks_ok: true,
+            inodes_ok: true,
+            errors: Vec::new(),
+            truncated: false,
+            notes: Vec::new(),
+        }
+    }
+
+    /// El reporte en JSON (lo que devuelve el archivo `.qrfs-control`).
+    pub fn to_json(&self) -> String {
+        let mut json = serde_json::to_string_pretty(self).unwrap_or_default();
+        json.push('\n');
+        json
+    }
+}
+
+/// Opciones para revisar sólo una parte de imágenes grandes.
+#[derive(Debug, Clone, Default)]
+pub struct FsckOptions {
+    /// Cortar después de esta cantidad de errores.
+    pub limit: Option<usize>,
+    /// Revisar sólo el subárbol que cuelga de esta ruta (p. ej. "/fotos/2024").
+    /// Las verificaciones globales (bitmap, duplicados, huérfanos) se omiten
+    /// porque necesitan la tabla completa.
+    pub path: Option<String>,
+    /// Leer cada bloque de datos y compararlo con su checksum guardado.
+    pub block_checksums: bool,
+}
@@ -0,0 +1,148 @@
+/*Es una implementación temporal del backend real. Incluye:
+un superblock falso, unos inodos falsos, unos bloques falsos. Se usa sólo para que el fsck:
+compile, se ejecute, produzca un reporte*/
+
+use anyhow::{anyhow, Result};
+
+use super::{fsck_backend::FsckBackend, fsck_types::*};
+
+pub struct MockBackend {
+    pub superblock: Superblock,
+    pub inodes: Vec<Inode>,
+    pub blocks: Vec<Vec<u8>>,
+    pub dirs: Vec<Vec<Dirent>>,
+    pub bitmap: Vec<bool>,
+    /// Checksum guardado de cada bloque (vacío = imagen sin checksums por bloque).
+    pub checksums: Vec<Option<u32>>,
+}
+
+impl FsckBackend for MockBackend {
+    fn load_superblock(&self) -> Superblock {
+        self.superblock.clone()
+    }
+
+    fn read_inode(&self, ino: u32) -> Option<Inode> {
+        self.inodes.get(ino as usize).cloned()
+    }
+
+    fn read_block(&self, block: u32) -> Option<Vec<u8>> {
+        self.blocks.get(block as usize).cloned()
+    }
+
+    fn read_dir(&self, ino: u32) -> Vec<Dirent> {
+        self.dirs.get(ino as usize).cloned().unwrap_or_default()
+    }
+
+    fn load_all_inodes(&self) -> Vec<Inode> {
+        self.inodes.clone()
+    }
+    fn load_block_bitmap(&self) -> Vec<bool> {
+        self.bitmap.clone()
+    }
+
+    fn block_checksum(&self, block: u32) -> Option<u32> {
+        self.checksums.get(block as usize).copied().flatten()
+    }
+
+    // Las escrituras quedan en memoria: sirven para verificar reparaciones
+    fn write_superblock(&mut self, sb: &Superblock) -> Result<()> {
+        self.superblock = sb.clone();
+        Ok(())
+    }
+
+    fn write_inode(&mut self, ino: u32, inode: &Inode) -> Result<()> {
+        let slot = self
+            .inodes
+            .get_mut(ino as usize)
+            .ok_or_else(|| anyhow!("Inodo fuera de rango: {}", ino))?;
+        *slot = inode.clone();
+        Ok(())
+    }
+
+    fn write_block(&mut self, block: u32, data: &[u8]) -> Result<()> {
+        let slot = self
+            .blocks
+            .get_mut(block as usize)
+            .ok_or_else(|| anyhow!("Bloque fuera de rango: {}", block))?;
+        *slot = data.to_vec();
+        Ok(())
+    }
+
+    fn write_bitmap(&mut self, bitmap: &[bool]) -> Result<()> {
+        self.bitmap = bitmap.to_vec();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl MockBackend {
+    /// Imagen sana de 8 bloques (0-3 de metadatos) con la raíz en el inodo 1 y
+    /// su único bloque de datos en el 4.
+    pub(crate) fn sample() -> Self {
+        let root = Inode {
+            in_use: true,
+            is_dir: true,
+            size: 1024,
+            compressed: false,
+            direct: vec![4],
+            indirect1: None,
+            indirect2: None,
+        };
+        let dot = |name: &str| Dirent { inode: 1, name: name.into(), is_dir: true, valid: true };
+
+        Self {
+            superblock: Superblock {
+                magic: 0x1234,
+                num_inodes: 4,
+                num_blocks: 8,
+                root_inode: 1,
+                checksum_ok: true,
+                block_size: 1024,
+                first_data_block: 4,
+                layout_error: None,
+                bad_blocks: Vec::new(),
+            },
+            inodes: vec![Inode::unused(), root, Inode::unused(), Inode::unused()],
+            blocks: vec![vec![0; 1024]; 8],
+            dirs: vec![Vec::new(), vec![dot("."), dot("..")]],
+            bitmap: (0..8).map(|b| b <= 4).collect(),
+            checksums: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fsck::fsck::run_fsck;
+
+    #[test]
+    fn writes_persist_in_memory() {
+        let mut backend = MockBackend::sample();
+        assert!(run_fsck(&backend).errors.is_empty(), "{:?}", run_fsck(&backend).errors);
+
+        backend.superblock.checksum_ok = false;
+        assert!(run_fsck(&backend).errors.iter().any(|e| e.contains("checksum")));
+
+        // El superblock reparado es el que ve la próxima revisión
+        let mut repaired = backend.load_superblock();
+        repaired.checksum_ok = true;
+        backend.write_superblock(&repaired).unwrap();
+        assert!(run_fsck(&backend).errors.is_empty());
+
+        let mut root = backend.read_inode(1).unwrap();
+        root.size = 10;
+        backend.write_inode(1, &root).unwrap();
+        assert_eq!(backend.read_inode(1).unwrap().size, 10);
+        assert!(backend.write_inode(9, &root).is_err());
+
+        backend.write_block(5, &[7; 1024]).unwrap();
+        assert_eq!(backend.read_block(5).unwrap(), vec![7; 1024]);
+        assert!(backend.write_block(8, &[0; 1024]).is_err());
+
+        let mut bitmap = backend.load_block_bitmap();
+        bitmap[5] = true;
+        backend.write_bitmap(&bitmap).unwrap();
+        assert!(backend.load_block_bitmap()[5]);
+    }
+}
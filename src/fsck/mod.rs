@@ -0,0 +1,21 @@
+pub mod fsck_types;
+pub mod fsck_backend;
+pub mod fsck;
+pub mod mock;
+pub mod repair;
+
+pub mod qrfs_backend;
+
+/*Es el archivo índice de módulo fsck.
+Aquí simplemente declara qué submódulos existen.*/
+
+use std::path::Path;
+
+use self::fsck_types::FsckReport;
+use self::qrfs_backend::QrfsBackend;
+
+/// Revisa la imagen de `qr_folder` sin montarla y sin modificarla (lo mismo que
+/// `fsck.qrfs` sin opciones). La imagen está sana si `report.errors` está vacío.
+pub fn check(qr_folder: &Path) -> FsckReport {
+    fsck::run_fsck(&QrfsBackend::new(qr_folder.to_path_buf()))
+}
@@ -0,0 +1,318 @@
+/*Backend del fsck sobre una carpeta QRFS real.
+Traduce el formato en disco (SuperblockDisk, InodeDisk, DirEntryDisk) a los
+tipos simplificados del fsck y, para las reparaciones, de vuelta a disco con
+los mismos helpers de escritura que usa el montaje.
+*/
+
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+
+use crate::block_store::{manifest_checksums, BlockStore};
+use crate::dir;
+use crate::fs::{self, DirEntryDisk, InodeDisk, SuperblockDisk, QRFS_TYPE_MASK};
+use crate::inode_table::InodeTable;
+use super::fsck_backend::FsckBackend;
+use super::fsck_types::{Superblock, Inode, Dirent};
+
+pub struct QrfsBackend {
+    pub qr_folder: PathBuf,
+    /// CRC32 de los bloques según el manifiesto, leído una sola vez.
+    checksums: OnceCell<Option<HashMap<u32, u32>>>,
+}
+
+impl QrfsBackend {
+    pub fn new(qr_folder: PathBuf) -> Self {
+        Self {
+            qr_folder,
+            checksums: OnceCell::new(),
+        }
+    }
+
+    fn read_block_raw(&self, block_index: u32) -> Option<Vec<u8>> {
+        BlockStore::open(&self.qr_folder).ok()?.read_block(block_index).ok()
+    }
+
+    fn load_superblock_disk(&self) -> Option<SuperblockDisk> {
+        let buf = self.read_block_raw(0)?;
+        SuperblockDisk::from_block_bytes(&buf).ok()
+    }
+
+    /// El superblock sólo si se puede usar para recorrer la imagen: magic
+    /// correcto, extensiones soportadas y layout con sentido. Si no, los
+    /// recorridos quedan vacíos en vez de iterar sobre tamaños basura.
+    fn usable_superblock(&self) -> Option<SuperblockDisk> {
+        self.load_superblock_disk()
+            .filter(|sb| sb.check_magic().is_ok() && layout_problem(sb).is_none())
+    }
+
+    fn load_inode_disk(&self, ino: u32, sb: &SuperblockDisk) -> Option<InodeDisk> {
+        InodeTable::open(&self.qr_folder, sb).ok()?.get(ino as u64).ok()
+    }
+
+    /// Entradas de cualquier directorio: recorre todos sus bloques de datos
+    /// (directos y los del indirecto) hasta `size`, saltando huecos y bloques
+    /// ilegibles (esos los reporta el resto del fsck).
+    fn read_dir_disk(&self, ino: u32, sb: &SuperblockDisk) -> Vec<Dirent> {
+        let mut result = Vec::new();
+
+        let inode = match self.load_inode_disk(ino, sb) {
+            Some(i) if i.id != 0 && i.nlink != 0 && i.is_dir() => i,
+            _ => return result,
+        };
+
+        // Nunca más bloques de los que un inodo puede direccionar, aunque el
+        // tamaño esté corrupto
+        let block_size = sb.block_size as u64;
+        let addressable = fs::max_file_size(sb.block_size) / block_size;
+        let blocks = inode.size.div_ceil(block_size).min(addressable);
+
+        for logical in 0..blocks {
+            let block = match fs::logical_to_physical(&self.qr_folder, sb, &inode, logical) {
+                Ok(Some(b)) => b,
+                Ok(None) | Err(_) => continue,
+            };
+            let Some(buf) = self.read_block_raw(block) else {
+                continue;
+            };
+
+            // Convertir DirEntryDisk -> Dirent
+            let entry_size = std::mem::size_of::<DirEntryDisk>();
+            for slot in 0..buf.len() / entry_size {
+                let disk_entry = fs::read_dir_slot(&buf, slot);
+                if disk_entry.inode == 0 {
+                    continue;
+                }
+
+                // El fsck sólo muestra y compara nombres: alcanza con la versión con pérdida
+                let name = dir::entry_name(&disk_entry).to_string_lossy().into_owned();
+                if name.is_empty() {
+                    continue;
+                }
+
+                // El tipo sale del inodo al que apunta la entrada, no de la entrada
+                let is_dir = self
+                    .load_inode_disk(disk_entry.inode, sb)
+                    .is_some_and(|i| i.is_dir());
+
+                result.push(Dirent {
+                    inode: disk_entry.inode,
+                    name,
+                    is_dir,
+                    valid: true,
+                });
+            }
+        }
+
+        result
+    }
+}
+
+/// Por qué no se puede recorrer la imagen con el layout de esta versión:
+/// extensiones que no soporta (de una versión más nueva, por ejemplo) o regiones
+/// sin sentido.
+fn layout_problem(sb: &SuperblockDisk) -> Option<String> {
+    sb.check_features().and_then(|()| sb.check_layout()).err().map(|e| e.to_string())
+}
+
+impl FsckBackend for QrfsBackend {
+    fn load_superblock(&self) -> Superblock {
+        // Adaptamos SuperblockDisk al Superblock simplificado de fsck
+        if let Some(sb) = self.load_superblock_disk() {
+            Superblock {
+                magic: if sb.check_magic().is_ok() { 0x1234 } else { 0 }, // lo que espera fsck.rs
+                num_inodes: sb.max_inodes.saturating_add(1),
+                num_blocks: sb.total_blocks,
+                root_inode: sb.root_inode, // mismo índice que usamos en Dirent.inode
+                checksum_ok: sb.verify_checksum(),
+                block_size: sb.block_size,
+                first_data_block: sb.data_blocks_start,
+                layout_error: layout_problem(&sb),
+                bad_blocks: sb.bad_block_list(),
+            }
+        } else {
+            Superblock {
+                magic: 0,
+                num_inodes: 0,
+                num_blocks: 0,
+                root_inode: 0,
+                checksum_ok: false,
+                block_size: 0,
+                first_data_block: 0,
+                layout_error: None,
+                bad_blocks: Vec::new(),
+            }
+        }
+    }
+
+    fn load_all_inodes(&self) -> Vec<Inode> {
+        let sb_disk = match self.usable_superblock() {
+            Some(sb) => sb,
+            None => return Vec::new(),
+        };
+
+        // Índice 0 lo dejamos como "dummy" para que root=1 funcione bien
+        let mut result = vec![Inode::unused()];
+
+        // InodeTable no recorre más inodos de los que caben en la tabla aunque
+        // max_inodes diga otra cosa. Si no coinciden, check_superblock lo reporta.
+        let table = match InodeTable::open(&self.qr_folder, &sb_disk).and_then(|t| t.iter()) {
+            Ok(iter) => iter,
+            Err(_) => return result,
+        };
+
+        for (_, disk_inode) in table {
+            if disk_inode.id == 0 || disk_inode.nlink == 0 {
+                // Inodo libre (o borrado): no aporta bloques ni referencias
+                result.push(Inode::unused());
+                continue;
+            }
+
+            // Un inodo inline guarda datos (no punteros) en direct_blocks
+            let direct = if disk_inode.is_inline() {
+                Vec::new()
+            } else {
+                disk_inode.direct_blocks.iter().copied().filter(|&b| b != 0).collect()
+            };
+            let indirect1 = if disk_inode.indirect_block != 0 {
+                Some(disk_inode.indirect_block)
+            } else {
+                None
+            };
+            let indirect2 = if disk_inode.double_indirect_block != 0 {
+                Some(disk_inode.double_indirect_block)
+            } else {
+                None
+            };
+
+            result.push(Inode {
+                in_use: true,
+                is_dir: disk_inode.is_dir(),
+                size: disk_inode.size as u32,
+                compressed: disk_inode.is_compressed(),
+                direct,
+                indirect1,
+                indirect2,
+            });
+        }
+
+        result
+    }
+
+    fn read_inode(&self, ino: u32) -> Option<Inode> {
+        let all = self.load_all_inodes();
+        all.get(ino as usize).cloned()
+    }
+
+    fn read_block(&self, block: u32) -> Option<Vec<u8>> {
+        self.read_block_raw(block)
+    }
+
+    /// El checksum de cada bloque es el del manifiesto: sin manifiesto (o si
+    /// no se puede leer) la imagen no tiene checksums por bloque.
+    fn block_checksum(&self, block: u32) -> Option<u32> {
+        self.checksums
+            .get_or_init(|| manifest_checksums(&self.qr_folder).ok().flatten())
+            .as_ref()?
+            .get(&block)
+            .copied()
+    }
+
+    fn read_dir(&self, ino: u32) -> Vec<Dirent> {
+        match self.usable_superblock() {
+            Some(sb) => self.read_dir_disk(ino, &sb),
+            None => Vec::new(),
+        }
+    }
+
+    fn load_block_bitmap(&self) -> Vec<bool> {
+        let sb_disk = match self.usable_superblock() {
+            Some(sb) => sb,
+            None => return Vec::new(),
+        };
+
+        let buf = match fs::load_bitmap(&self.qr_folder, &sb_disk) {
+            Ok(buf) => buf,
+            Err(_) => return Vec::new(),
+        };
+
+        // Pasar a Vec<bool>
+        (0..sb_disk.total_blocks)
+            .map(|b| {
+                let byte = b as usize / 8;
+                byte < buf.len() && buf[byte] & (1 << (b % 8)) != 0
+            })
+            .collect()
+    }
+
+    fn write_superblock(&mut self, sb: &Superblock) -> Result<()> {
+        // El Superblock del fsck es un resumen: sólo se pisan los campos que
+        // representa y el resto se conserva tal como está en disco
+        let mut disk = self
+            .load_superblock_disk()
+            .ok_or_else(|| anyhow!("No se pudo leer el superblock de {:?}", self.qr_folder))?;
+        disk.total_blocks = sb.num_blocks;
+        disk.max_inodes = sb.num_inodes.saturating_sub(1); // el índice 0 es el dummy
+        disk.root_inode = sb.root_inode;
+        fs::write_superblock(&self.qr_folder, &disk)
+    }
+
+    fn write_inode(&mut self, ino: u32, inode: &Inode) -> Result<()> {
+        let sb = self
+            .load_superblock_disk()
+            .ok_or_else(|| anyhow!("No se pudo leer el superblock de {:?}", self.qr_folder))?;
+        let table = InodeTable::open(&self.qr_folder, &sb)?;
+
+        let mut disk = table.get(ino as u64)?;
+        let flags = disk.file_type & !QRFS_TYPE_MASK;
+        disk.file_type = flags | if inode.is_dir { 2 } else { 1 };
+        disk.size = inode.size as u64;
+        // En un inodo inline direct_blocks guarda datos: no se tocan
+        if !disk.is_inline() {
+            // `inode.direct` no tiene los ceros de los huecos: los punteros que
+            // siguen estando conservan su slot (su posición lógica en el archivo),
+            // los que ya no están se ponen en 0 y los nuevos van a slots libres
+            let mut remaining = inode.direct.clone();
+            for slot in disk.direct_blocks.iter_mut().filter(|b| **b != 0) {
+                match remaining.iter().position(|b| b == slot) {
+                    Some(pos) => {
+                        remaining.remove(pos);
+                    }
+                    None => *slot = 0,
+                }
+            }
+            for block in remaining {
+                let slot = disk
+                    .direct_blocks
+                    .iter_mut()
+                    .find(|b| **b == 0)
+                    .ok_or_else(|| anyhow!("El inodo {} tiene más de 12 punteros directos", ino))?;
+                *slot = block;
+            }
+        }
+        disk.indirect_block = inode.indirect1.unwrap_or(0);
+        disk.double_indirect_block = inode.indirect2.unwrap_or(0);
+
+        table.put(ino as u64, &disk)
+    }
+
+    fn write_block(&mut self, block: u32, data: &[u8]) -> Result<()> {
+        BlockStore::open(&self.qr_folder)?.write_block(block, data)
+    }
+
+    fn write_bitmap(&mut self, bitmap: &[bool]) -> Result<()> {
+        let sb = self
+            .load_superblock_disk()
+            .ok_or_else(|| anyhow!("No se pudo leer el superblock de {:?}", self.qr_folder))?;
+
+        let mut bytes = vec![0u8; bitmap.len().div_ceil(8)];
+        for (b, &used) in bitmap.iter().enumerate() {
+            if used {
+                bytes[b / 8] |= 1 << (b % 8);
+            }
+        }
+        fs::write_bitmap(&self.qr_folder, &sb, &bytes)
+    }
+}
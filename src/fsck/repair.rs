@@ -0,0 +1,254 @@
+/*Reparaciones del fsck como unidades independientes.
+Cada problema que tiene arreglo se describe con un `Repair` (qué está mal y
+qué se va a hacer) antes de tocar nada, así se pueden aplicar todas de una
+vez (--repair), mostrándolas (--yes) o preguntando una por una (--interactive).
+*/
+
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+
+use anyhow::{anyhow, Result};
+
+use super::fsck::{allocated_data_blocks, blocks_for_size, inode_blocks};
+use super::fsck_backend::FsckBackend;
+
+/// Cambio concreto que aplica una reparación.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fix {
+    /// Marcar el bloque como usado (`true`) o libre (`false`) en el bitmap.
+    SetBlockUsed { block: u32, used: bool },
+    /// Sacar una aparición del puntero `block` del inodo `ino` (directo o indirecto).
+    ClearPointer { ino: u32, block: u32 },
+    /// Cambiar el tamaño del inodo `ino`.
+    SetSize { ino: u32, size: u32 },
+}
+
+#[derive(Debug, Clone)]
+pub struct Repair {
+    /// Problema y arreglo en una línea, para mostrar antes de aplicarlo.
+    pub description: String,
+    pub fix: Fix,
+}
+
+/// Cómo se deciden las reparaciones en `run_repairs`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepairMode {
+    /// Todas, sin mostrar nada (`--repair`).
+    Batch,
+    /// Todas, mostrando cada una (`--yes`).
+    Yes,
+    /// Preguntar s/n por cada una (`--interactive`).
+    Interactive,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RepairSummary {
+    pub applied: usize,
+    pub skipped: usize,
+}
+
+/// Problemas con arreglo conocido. Primero los punteros inválidos de los
+/// inodos, después los tamaños que no cubren los bloques asignados y al final
+/// el bitmap, que se compara con los punteros que quedan.
+pub fn find_repairs<B: FsckBackend>(backend: &B) -> Vec<Repair> {
+    let sb = backend.load_superblock();
+    let inodes = backend.load_all_inodes();
+    let bitmap = backend.load_block_bitmap();
+    let mut repairs = Vec::new();
+
+    // 1. Punteros fuera del área de datos o repetidos dentro del mismo inodo
+    for (ino, inode) in inodes.iter().enumerate() {
+        let ino = ino as u32;
+        let mut seen = HashSet::new();
+        let pointers = inode.direct.iter().chain(&inode.indirect1).chain(&inode.indirect2);
+
+        for &blk in pointers {
+            let problem = if blk >= sb.num_blocks || blk < sb.first_data_block {
+                format!("Inodo {}: puntero a bloque fuera del área de datos ({})", ino, blk)
+            } else if !seen.insert(blk) {
+                format!("Inodo {}: bloque {} referenciado dos veces", ino, blk)
+            } else {
+                continue;
+            };
+            repairs.push(Repair {
+                description: format!("{}: se quita el puntero", problem),
+                fix: Fix::ClearPointer { ino, block: blk },
+            });
+        }
+    }
+
+    // 2. Tamaño que no cubre los bloques asignados: como hace e2fsck, se agranda
+    //    hasta cubrirlos. Lo que había más allá del final viejo pasa a ser parte
+    //    del archivo (puede ser basura), así que conviene revisarlo. En uno
+    //    comprimido el tamaño no sale de los bloques: sólo se reporta
+    for (ino, inode) in inodes.iter().enumerate() {
+        if !inode.in_use || inode.compressed || sb.block_size == 0 {
+            continue;
+        }
+        let allocated = allocated_data_blocks(backend, inode);
+        if allocated <= blocks_for_size(inode, sb.block_size) {
+            continue;
+        }
+        let Ok(size) = u32::try_from(allocated * sb.block_size as u64) else {
+            continue;
+        };
+        repairs.push(Repair {
+            description: format!(
+                "Inodo {}: {} bytes no alcanzan para sus {} bloques: el tamaño pasa a {} bytes (revisar el final del archivo)",
+                ino, inode.size, allocated, size
+            ),
+            fix: Fix::SetSize { ino: ino as u32, size },
+        });
+    }
+
+    // 3. Bitmap contra los punteros válidos (un bloque repetido sigue en uso
+    //    por su primera aparición)
+    if bitmap.len() != sb.num_blocks as usize {
+        return repairs; // tamaño incorrecto: lo reporta fsck, no hay arreglo bloque a bloque
+    }
+
+    let mut used = vec![false; bitmap.len()];
+    for inode in &inodes {
+        // También lo que cuelga de los indirectos: si no, sus datos se liberarían
+        for blk in inode_blocks(backend, &sb, inode) {
+            if blk >= sb.first_data_block && blk < sb.num_blocks {
+                used[blk as usize] = true;
+            }
+        }
+    }
+
+    for block in sb.first_data_block..sb.num_blocks {
+        let (in_bitmap, referenced) = (bitmap[block as usize], used[block as usize]);
+        // Un bloque dañado sigue usado aunque nadie lo tenga
+        if in_bitmap == referenced || (in_bitmap && sb.bad_blocks.contains(&block)) {
+            continue;
+        }
+        let description = if referenced {
+            format!("Bloque {}: en uso pero marcado libre en el bitmap: se marca usado", block)
+        } else {
+            format!("Bloque {}: marcado usado pero ningún inodo lo usa: se marca libre", block)
+        };
+        repairs.push(Repair {
+            description,
+            fix: Fix::SetBlockUsed { block, used: referenced },
+        });
+    }
+
+    repairs
+}
+
+pub fn apply_repair<B: FsckBackend>(backend: &mut B, repair: &Repair) -> Result<()> {
+    match repair.fix {
+        Fix::SetBlockUsed { block, used } => {
+            let mut bitmap = backend.load_block_bitmap();
+            let slot = bitmap
+                .get_mut(block as usize)
+                .ok_or_else(|| anyhow!("Bloque fuera del bitmap: {}", block))?;
+            *slot = used;
+            backend.write_bitmap(&bitmap)
+        }
+        Fix::ClearPointer { ino, block } => {
+            let mut inode = backend
+                .read_inode(ino)
+                .ok_or_else(|| anyhow!("No se pudo leer el inodo {}", ino))?;
+
+            // Se saca la última aparición: con un bloque repetido queda la primera
+            if let Some(pos) = inode.direct.iter().rposition(|&b| b == block) {
+                inode.direct.remove(pos);
+            } else if inode.indirect2 == Some(block) {
+                inode.indirect2 = None;
+            } else if inode.indirect1 == Some(block) {
+                inode.indirect1 = None;
+            } else {
+                return Ok(()); // ya no está (otra reparación lo quitó)
+            }
+            backend.write_inode(ino, &inode)
+        }
+        Fix::SetSize { ino, size } => {
+            let mut inode = backend
+                .read_inode(ino)
+                .ok_or_else(|| anyhow!("No se pudo leer el inodo {}", ino))?;
+            inode.size = size;
+            backend.write_inode(ino, &inode)
+        }
+    }
+}
+
+/// Recorre `repairs` según `mode`. En modo interactivo lee las respuestas de
+/// `input` (una por línea, "s"/"y" = sí); si la entrada se termina, el resto se
+/// saltea. Los mensajes y preguntas van a `out`.
+pub fn run_repairs<B: FsckBackend, R: BufRead, W: Write>(
+    backend: &mut B,
+    repairs: &[Repair],
+    mode: RepairMode,
+    mut input: R,
+    out: &mut W,
+) -> Result<RepairSummary> {
+    let mut summary = RepairSummary::default();
+
+    for repair in repairs {
+        let apply = match mode {
+            RepairMode::Batch => true,
+            RepairMode::Yes => {
+                writeln!(out, "{} [s/n] s", repair.description)?;
+                true
+            }
+            RepairMode::Interactive => {
+                write!(out, "{} ¿Reparar? [s/n] ", repair.description)?;
+                out.flush()?;
+                let mut answer = String::new();
+                let read = input.read_line(&mut answer)?;
+                if read == 0 {
+                    writeln!(out)?;
+                }
+                matches!(answer.trim().to_lowercase().as_str(), "s" | "si" | "sí" | "y" | "yes")
+            }
+        };
+
+        if apply {
+            apply_repair(backend, repair)?;
+            summary.applied += 1;
+        } else {
+            summary.skipped += 1;
+        }
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fsck::mock::MockBackend;
+
+    #[test]
+    fn interactive_mode_applies_only_the_accepted_fixes() {
+        // Dos bloques marcados usados que ningún inodo tiene
+        let mut backend = MockBackend::sample();
+        backend.bitmap[6] = true;
+        backend.bitmap[7] = true;
+
+        let repairs = find_repairs(&backend);
+        assert_eq!(
+            repairs.iter().map(|r| r.fix.clone()).collect::<Vec<_>>(),
+            [Fix::SetBlockUsed { block: 6, used: false }, Fix::SetBlockUsed { block: 7, used: false }]
+        );
+
+        let mut out = Vec::new();
+        let summary =
+            run_repairs(&mut backend, &repairs, RepairMode::Interactive, "s\nn\n".as_bytes(), &mut out).unwrap();
+        assert_eq!((summary.applied, summary.skipped), (1, 1));
+        assert!(!backend.bitmap[6] && backend.bitmap[7]);
+        assert_eq!(String::from_utf8(out).unwrap().matches("¿Reparar? [s/n]").count(), 2);
+
+        // Sin más respuestas lo que queda se saltea
+        let repairs = find_repairs(&backend);
+        let summary = run_repairs(&mut backend, &repairs, RepairMode::Interactive, "".as_bytes(), &mut Vec::new())
+            .unwrap();
+        assert_eq!((summary.applied, summary.skipped), (0, 1));
+
+        let summary = run_repairs(&mut backend, &repairs, RepairMode::Yes, "".as_bytes(), &mut Vec::new()).unwrap();
+        assert_eq!(summary.applied, 1);
+        assert!(find_repairs(&backend).is_empty());
+    }
+}
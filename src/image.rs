@@ -0,0 +1,544 @@
+// Acceso "headless" (sin FUSE) a una imagen QRFS, pensado para scripts y
+// herramientas que llenan o inspeccionan una carpeta de QRs.
+
+use std::ffi::OsString;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Context, Result};
+use fuser::{FileAttr, FileType};
+
+use crate::block_store;
+use crate::compress;
+use crate::dir;
+use crate::readahead::ReadAhead;
+use crate::fs::{self, Inode, InodeDisk, QrfsFilesystem, QrfsInner, QRFS_INLINE_MAX};
+use crate::inode_table::InodeTable;
+
+pub struct QrfsImage {
+    inner: Arc<RwLock<QrfsInner>>,
+}
+
+impl QrfsImage {
+    /// Abre la imagen de la carpeta indicada (misma carga que el montaje FUSE).
+    pub fn open(qr_folder: &Path) -> Result<Self> {
+        let fs = QrfsFilesystem::mount_from_folder(qr_folder, None, None)?;
+        Ok(Self { inner: fs.inner })
+    }
+
+    /// Bloques que se cargan por adelantado cuando un archivo se lee en orden
+    /// con `read_at` (igual que `mount_qrfs --read-ahead`; 0 la desactiva).
+    pub fn read_ahead(self, blocks: usize) -> Self {
+        self.inner.write().unwrap().read_ahead = Arc::new(ReadAhead::new(blocks));
+        self
+    }
+
+    /// Los archivos creados desde ahora guardan sus datos comprimidos (igual
+    /// que `mount_qrfs --compress`).
+    pub fn compress(self, enabled: bool) -> Self {
+        self.inner.write().unwrap().compress = enabled;
+        self
+    }
+
+    /// Los bloques que se liberan se llenan de ceros (igual que
+    /// `mount_qrfs --zero-on-free`).
+    pub fn zero_on_free(self, enabled: bool) -> Self {
+        self.inner.write().unwrap().zero_on_free = enabled;
+        self
+    }
+
+    /// Crea un archivo vacío en `path` (el directorio padre debe existir).
+    pub fn create_file(&self, path: &Path) -> Result<u64> {
+        self.locked(|tx| tx.create_file(path))
+    }
+
+    /// Atributos del archivo o directorio en `path`.
+    pub fn stat(&self, path: &Path) -> Result<FileAttr> {
+        stat_path(&self.inner.read().unwrap(), path)
+    }
+
+    /// Número de inodo de `path`, recorriendo los directorios desde la raíz. El
+    /// error dice qué componente no existe o no es un directorio.
+    pub fn resolve(&self, path: &Path) -> Result<u64> {
+        lookup_path(&self.inner.read().unwrap(), path)
+    }
+
+    /// Lee el contenido completo de un archivo.
+    pub fn read_file(&self, path: &Path) -> Result<Vec<u8>> {
+        self.read_at(path, 0, usize::MAX)
+    }
+
+    /// Lee hasta `size` bytes desde `offset` (nunca más allá del final del archivo).
+    pub fn read_at(&self, path: &Path, offset: u64, size: usize) -> Result<Vec<u8>> {
+        read_range(&self.inner.read().unwrap(), path, offset, size)
+    }
+
+    /// Borra un archivo (borrado suave, igual que `unlink` en el montaje FUSE).
+    pub fn remove(&self, path: &Path) -> Result<()> {
+        self.locked(|tx| tx.remove(path))
+    }
+
+    /// Crea un directorio vacío en `path` con permisos 0755 (el padre debe
+    /// existir) y devuelve su inodo.
+    pub fn mkdir(&self, path: &Path) -> Result<u64> {
+        self.locked(|tx| tx.mkdir(path))
+    }
+
+    /// Borra un directorio vacío (igual que `rmdir` en el montaje FUSE).
+    pub fn rmdir(&self, path: &Path) -> Result<()> {
+        self.locked(|tx| tx.rmdir(path))
+    }
+
+    /// Mueve o renombra `from` a `to` (igual que `rename` en el montaje FUSE:
+    /// si `to` existe, se reemplaza).
+    pub fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.locked(|tx| tx.rename(from, to))
+    }
+
+    /// Cambia el tamaño de un archivo (igual que `truncate` en el montaje FUSE):
+    /// al achicarlo, los bloques que sobran vuelven a estar libres.
+    pub fn truncate(&self, path: &Path, size: u64) -> Result<()> {
+        self.locked(|tx| tx.truncate(path, size))
+    }
+
+    /// Reemplaza atómicamente el contenido de un archivo existente.
+    ///
+    /// Primero se reservan y escriben bloques nuevos; recién después se actualiza
+    /// el inodo en disco (punteros y tamaño) con una sola escritura, y al final se
+    /// liberan los bloques viejos. Si algo falla antes de actualizar el inodo, los
+    /// bloques nuevos se devuelven y el contenido anterior queda intacto.
+    pub fn write_file(&self, path: &Path, data: &[u8]) -> Result<()> {
+        self.locked(|tx| tx.write_file(path, data))
+    }
+
+    /// Recupera un inodo borrado (ver `qrfs::list_deleted`) y lo enlaza en `path`.
+    /// El directorio padre debe existir y el nombre no debe estar ocupado.
+    pub fn undelete(&self, ino: u64, path: &Path) -> Result<()> {
+        self.locked(|tx| tx.undelete(ino, path))
+    }
+
+    /// Corre varias operaciones con el lock tomado una sola vez y las escrituras
+    /// de bloques diferidas: la tabla de inodos, el bitmap y el superblock (que
+    /// cada operación suelta reescribe) se escriben una sola vez, al final, junto
+    /// con los bloques de datos. Pensado para cargar muchos archivos de golpe.
+    ///
+    /// Si `f` devuelve un error o entra en pánico no se escribe nada: la imagen
+    /// en disco queda como antes y la abierta vuelve a cargarse desde ahí (el
+    /// pánico sigue después). Mientras dura, otra `QrfsImage` del mismo proceso
+    /// sobre la misma carpeta ya ve los bloques diferidos.
+    pub fn batch<T>(&self, f: impl FnOnce(&mut QrfsBatch) -> Result<T>) -> Result<T> {
+        let mut guard = self.inner.write().unwrap();
+        let qr_folder = guard.qr_folder.clone();
+        block_store::begin_deferred(&qr_folder)?;
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(&mut QrfsBatch { inner: &mut guard })));
+        match result {
+            Ok(Ok(value)) => {
+                block_store::commit_deferred(&qr_folder)
+                    .with_context(|| format!("No se pudieron escribir los bloques del lote en {:?}", qr_folder))?;
+                Ok(value)
+            }
+            Ok(Err(e)) => {
+                rollback(&mut guard)?;
+                Err(e)
+            }
+            Err(payload) => {
+                if let Err(e) = rollback(&mut guard) {
+                    eprintln!("Error al recargar {:?} tras un pánico en el lote: {e:?}", qr_folder);
+                }
+                // Sin el lock tomado, así no queda envenenado
+                drop(guard);
+                panic::resume_unwind(payload)
+            }
+        }
+    }
+
+    /// Registro del inodo `ino` tal como está en la tabla en disco (también los
+    /// libres o borrados), o `None` si está fuera de la tabla o no se puede leer.
+    /// Es lo que hay en disco, no lo que tiene en memoria la imagen abierta.
+    pub fn read_inode_raw(&self, ino: u64) -> Option<InodeDisk> {
+        self.inner.read().unwrap().load_inode(ino).ok()
+    }
+
+    /// Contenido del bloque `block` sin interpretar, o `None` si está fuera de
+    /// la imagen o no se puede leer.
+    pub fn read_block_raw(&self, block: u32) -> Option<Vec<u8>> {
+        let inner = self.inner.read().unwrap();
+        fs::read_fs_block(&inner.qr_folder, block).ok()
+    }
+
+    /// Bloques que hacen falta para leer `path`: el superblock, los de la tabla
+    /// que guardan su inodo y los de datos (con los bloques de punteros), en
+    /// orden y sin repetir.
+    pub(crate) fn backing_blocks(&self, path: &Path) -> Result<Vec<u32>> {
+        let inner = self.inner.read().unwrap();
+        let ino = lookup_path(&inner, path)?;
+
+        let mut blocks = vec![0];
+        blocks.extend(InodeTable::open(&inner.qr_folder, &inner.superblock)?.blocks_of(ino)?);
+        let disk_inode = inner.load_inode(ino)?;
+        blocks.extend(fs::file_blocks_disk(&inner.qr_folder, &inner.superblock, &disk_inode)?);
+
+        blocks.sort_unstable();
+        blocks.dedup();
+        Ok(blocks)
+    }
+
+    /// Corre `f` con el lock de escritura tomado, sin diferir escrituras (lo
+    /// que hace cada operación suelta).
+    fn locked<T>(&self, f: impl FnOnce(&mut QrfsBatch) -> T) -> T {
+        let mut guard = self.inner.write().unwrap();
+        f(&mut QrfsBatch { inner: &mut guard })
+    }
+}
+
+/// Operaciones sobre una imagen con el lock ya tomado: lo que recibe la
+/// closure de `QrfsImage::batch`.
+pub struct QrfsBatch<'a> {
+    inner: &'a mut QrfsInner,
+}
+
+impl QrfsBatch<'_> {
+    /// Como `QrfsImage::stat`.
+    pub fn stat(&self, path: &Path) -> Result<FileAttr> {
+        stat_path(self.inner, path)
+    }
+
+    /// Como `QrfsImage::resolve`.
+    pub fn resolve(&self, path: &Path) -> Result<u64> {
+        lookup_path(self.inner, path)
+    }
+
+    /// Como `QrfsImage::read_file`.
+    pub fn read_file(&self, path: &Path) -> Result<Vec<u8>> {
+        read_range(self.inner, path, 0, usize::MAX)
+    }
+
+    /// Como `QrfsImage::create_file`.
+    pub fn create_file(&mut self, path: &Path) -> Result<u64> {
+        let inner = &mut *self.inner;
+
+        let (parent, name) = split_parent(inner, path)?;
+        dir::check_name(&name).with_context(|| format!("No se puede crear {:?}", path))?;
+        if dir::find_entry(inner, parent, &name).is_some() {
+            return Err(anyhow!("{:?} ya existe", path));
+        }
+
+        let (ino, generation) = fs::alloc_inode(inner)
+            .ok_or_else(|| anyhow!("No quedan inodos libres para crear {:?}", path))?;
+
+        let mut inode = Inode::file(ino, 0);
+        inode.generation = generation;
+
+        let mut disk_inode = InodeDisk {
+            id: ino as u32,
+            file_type: fs::new_file_type(inner), // archivo regular (comprimido o no)
+            perm: inode.perm,
+            uid: inode.uid,
+            gid: inode.gid,
+            size: 0,
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+            nlink: 1,
+            direct_blocks: [0u32; 12],
+            indirect_block: 0,
+            double_indirect_block: 0,
+            generation,
+        };
+        disk_inode.set_times(inode.ctime);
+
+        let qr_folder = inner.qr_folder.clone();
+        fs::write_inode_disk(&qr_folder, &inner.superblock, ino, &disk_inode)?;
+        fs::ensure_dir_block(inner, parent)
+            .and_then(|()| fs::add_dir_entry_disk(&qr_folder, &inner.superblock, parent, &name, ino))
+            .with_context(|| format!("No se pudo agregar {:?} a su directorio", path))?;
+
+        inner.free_inodes = inner.free_inodes.saturating_sub(1);
+        inner.superblock.free_inodes = inner.superblock.free_inodes.saturating_sub(1);
+        fs::write_superblock(&qr_folder, &inner.superblock)?;
+
+        inner.inodes.insert(ino, inode);
+        if let Some(dir) = inner.directories.get_mut(&parent) {
+            dir.entries.insert(name, ino);
+        }
+
+        Ok(ino)
+    }
+
+    /// Como `QrfsImage::remove`.
+    pub fn remove(&mut self, path: &Path) -> Result<()> {
+        let inner = &mut *self.inner;
+
+        let (parent, name) = split_parent(inner, path)?;
+        dir::unlink_entry(inner, parent, name.as_ref())
+            .with_context(|| format!("No se pudo borrar {:?}", path))
+    }
+
+    /// Como `QrfsImage::mkdir`.
+    pub fn mkdir(&mut self, path: &Path) -> Result<u64> {
+        let inner = &mut *self.inner;
+
+        let (parent, name) = split_parent(inner, path)?;
+        let attr = dir::create_directory(inner, parent, &name, 0o755)
+            .with_context(|| format!("No se pudo crear el directorio {:?}", path))?;
+        Ok(attr.ino)
+    }
+
+    /// Como `QrfsImage::rmdir`.
+    pub fn rmdir(&mut self, path: &Path) -> Result<()> {
+        let inner = &mut *self.inner;
+
+        let (parent, name) = split_parent(inner, path)?;
+        dir::remove_directory(inner, parent, &name)
+            .with_context(|| format!("No se pudo borrar el directorio {:?}", path))
+    }
+
+    /// Como `QrfsImage::rename`.
+    pub fn rename(&mut self, from: &Path, to: &Path) -> Result<()> {
+        let inner = &mut *self.inner;
+
+        let (parent, name) = split_parent(inner, from)?;
+        let (newparent, newname) = split_parent(inner, to)?;
+        dir::rename_entry(inner, parent, &name, newparent, &newname)
+            .with_context(|| format!("No se pudo mover {:?} a {:?}", from, to))
+    }
+
+    /// Como `QrfsImage::truncate`.
+    pub fn truncate(&mut self, path: &Path, size: u64) -> Result<()> {
+        let inner = &mut *self.inner;
+
+        let ino = lookup_path(inner, path)?;
+        match inner.inodes.get(&ino) {
+            Some(inode) if inode.kind == FileType::RegularFile => {}
+            Some(_) => return Err(anyhow!("{:?} no es un archivo regular", path)),
+            None => return Err(anyhow!("{:?}: el inodo {} no está cargado", path, ino)),
+        }
+
+        fs::truncate_disk(inner, ino, size).with_context(|| format!("No se pudo truncar {:?}", path))
+    }
+
+    /// Como `QrfsImage::write_file`.
+    pub fn write_file(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        let inner = &mut *self.inner;
+
+        let ino = lookup_path(inner, path)?;
+        match inner.inodes.get(&ino) {
+            Some(inode) if inode.kind == FileType::RegularFile => {}
+            Some(_) => return Err(anyhow!("{:?} no es un archivo regular", path)),
+            None => return Err(anyhow!("{:?}: el inodo {} no está cargado", path, ino)),
+        }
+
+        let qr_folder = inner.qr_folder.clone();
+        let sb = inner.superblock;
+        let block_size = sb.block_size as usize;
+
+        let old_inode = inner.load_inode(ino)?;
+        // El inodo nuevo arranca sin punteros: ni directos ni indirectos viejos
+        let mut new_inode = old_inode;
+        new_inode.clear_inline_data();
+        new_inode.indirect_block = 0;
+        new_inode.double_indirect_block = 0;
+
+        let use_inline = cfg!(feature = "inline-data") && data.len() <= QRFS_INLINE_MAX;
+        // Un archivo comprimido guarda bloques empaquetados, con su encabezado
+        let chunks: Vec<Vec<u8>> = if old_inode.is_compressed() {
+            compress::pack(data, block_size)
+        } else {
+            data.chunks(block_size).map(<[u8]>::to_vec).collect()
+        };
+        // Todos los bloques del archivo viejo, también los de punteros
+        let old_blocks = fs::file_blocks_disk(&qr_folder, &sb, &old_inode)?;
+
+        // 1) Reservar y escribir los bloques nuevos (el inodo todavía apunta a los viejos)
+        let mut new_blocks: Vec<u32> = Vec::new();
+        if !use_inline {
+            if let Err(e) = fs::write_new_blocks(inner, &mut new_inode, &chunks, &mut new_blocks) {
+                release_blocks(inner, &new_blocks);
+                return Err(e.context(format!("No se pudo escribir el contenido nuevo de {:?}", path)));
+            }
+        }
+
+        // 2) Cambiar punteros y tamaño en una sola escritura del inodo
+        let now = SystemTime::now();
+
+        if use_inline {
+            new_inode.set_inline_data(data);
+        } else {
+            new_inode.size = data.len() as u64;
+        }
+        new_inode.set_mtime(now);
+        new_inode.set_ctime(now);
+
+        if let Err(e) = fs::write_inode_disk(&qr_folder, &sb, ino, &new_inode) {
+            release_blocks(inner, &new_blocks);
+            return Err(e.context(format!("No se pudo actualizar el inodo {} de {:?}", ino, path)));
+        }
+
+        // 3) Recién ahora se liberan los bloques viejos
+        release_blocks(inner, &old_blocks);
+
+        // 4) Estado en memoria
+        if let Some(inode) = inner.inodes.get_mut(&ino) {
+            inode.size = data.len() as u64;
+            inode.mtime = now;
+            inode.ctime = now;
+        }
+        if let Some(buf) = inner.files.get_mut(&ino) {
+            *buf = data.to_vec();
+        }
+
+        Ok(())
+    }
+
+    /// Como `QrfsImage::undelete`.
+    pub fn undelete(&mut self, ino: u64, path: &Path) -> Result<()> {
+        let inner = &mut *self.inner;
+
+        let (parent, name) = split_parent(inner, path)?;
+
+        fs::undelete_inode(inner, ino, parent, &name)
+            .with_context(|| format!("No se pudo recuperar el inodo {} en {:?}", ino, path))
+    }
+}
+
+/// Devuelve bloques al bitmap; los errores sólo se reportan (quedarían "perdidos"
+/// hasta el próximo fsck, pero no afectan el contenido de ningún archivo).
+fn release_blocks(inner: &mut QrfsInner, blocks: &[u32]) {
+    for &b in blocks {
+        if let Err(e) = fs::free_block(inner, b) {
+            eprintln!("Error al liberar el bloque {}: {e:?}", b);
+        }
+    }
+}
+
+/// Atributos del archivo o directorio en `path`.
+fn stat_path(inner: &QrfsInner, path: &Path) -> Result<FileAttr> {
+    let ino = lookup_path(inner, path)?;
+    let inode = inner
+        .inodes
+        .get(&ino)
+        .with_context(|| format!("{:?}: el inodo {} no está cargado", path, ino))?;
+    Ok(fs::inode_to_attr(inode))
+}
+
+/// Hasta `size` bytes de `path` desde `offset`.
+fn read_range(inner: &QrfsInner, path: &Path, offset: u64, size: usize) -> Result<Vec<u8>> {
+    let ino = lookup_path(inner, path)?;
+
+    if let Some(data) = inner.files.get(&ino) {
+        // Sólo hasta el tamaño lógico: el buffer puede tener bytes viejos al final
+        let len = inner
+            .inodes
+            .get(&ino)
+            .map_or(data.len(), |i| usize::try_from(i.size).unwrap_or(usize::MAX).min(data.len()));
+        let start = (offset as usize).min(len);
+        let end = start + size.min(len - start);
+        return Ok(data[start..end].to_vec());
+    }
+
+    let inode_disk = inner.load_inode(ino)?;
+    if inode_disk.is_dir() {
+        return Err(anyhow!("{:?} es un directorio", path));
+    }
+
+    if inode_disk.is_inline() {
+        let inline = inode_disk.inline_data();
+        let start = (offset as usize).min(inline.len());
+        let end = start + size.min(inline.len() - start);
+        return Ok(inline[start..end].to_vec());
+    }
+
+    if inode_disk.is_compressed() {
+        let content = fs::read_compressed(&inner.qr_folder, &inner.superblock, &inode_disk)?;
+        let start = (offset as usize).min(content.len());
+        let end = start + size.min(content.len() - start);
+        return Ok(content[start..end].to_vec());
+    }
+
+    let file_size = inode_disk.size as usize;
+    let start = (offset as usize).min(file_size);
+    let end = start + size.min(file_size - start);
+    let block_size = inner.superblock.block_size as usize;
+
+    let mut result = Vec::with_capacity(end - start);
+    let mut pos = start;
+    while pos < end {
+        let i = (pos / block_size) as u64;
+        let in_block = pos % block_size;
+        let chunk = (block_size - in_block).min(end - pos);
+        match fs::logical_to_physical(&inner.qr_folder, &inner.superblock, &inode_disk, i)? {
+            // Bloque no asignado: se lee como ceros
+            None => result.resize(result.len() + chunk, 0),
+            Some(b) => {
+                let data = inner.read_data_block(b)?;
+                result.extend_from_slice(&data[in_block..in_block + chunk]);
+            }
+        }
+        pos += chunk;
+    }
+
+    // Sin handles: todas las lecturas del archivo cuentan como una sola secuencia
+    inner.read_ahead_after(ino, 0, &inode_disk, start as u64, end as u64);
+    Ok(result)
+}
+
+/// Descarta las escrituras diferidas de un lote y vuelve a cargar la imagen
+/// desde disco, conservando las opciones de la `QrfsImage`.
+fn rollback(inner: &mut QrfsInner) -> Result<()> {
+    block_store::discard_deferred(&inner.qr_folder);
+
+    let fs = QrfsFilesystem::mount_from_folder(&inner.qr_folder, None, None)?;
+    let mut fresh = Arc::try_unwrap(fs.inner)
+        .map_err(|_| anyhow!("La imagen recargada de {:?} sigue compartida", inner.qr_folder))?
+        .into_inner()
+        .unwrap();
+    fresh.read_ahead = Arc::clone(&inner.read_ahead);
+    fresh.compress = inner.compress;
+    fresh.zero_on_free = inner.zero_on_free;
+    *inner = fresh;
+    Ok(())
+}
+
+/// Separa `path` en el inodo de su directorio padre y el nombre final.
+fn split_parent(inner: &QrfsInner, path: &Path) -> Result<(u64, OsString)> {
+    let name = path
+        .file_name()
+        .with_context(|| format!("{:?} no tiene nombre de archivo", path))?
+        .to_os_string();
+
+    let parent = lookup_path(inner, path.parent().unwrap_or(Path::new("/")))?;
+    if !inner.directories.contains_key(&parent) {
+        return Err(anyhow!("{:?}: el padre no es un directorio", path));
+    }
+
+    Ok((parent, name))
+}
+
+/// Resuelve una ruta (relativa a la raíz del FS) a su número de inodo. Lo
+/// usan todas las operaciones por ruta (ver `QrfsImage::resolve`).
+fn lookup_path(inner: &QrfsInner, path: &Path) -> Result<u64> {
+    let mut ino = inner.superblock.root_inode as u64;
+    // Lo recorrido hasta ahora, para decir en el error dónde se cortó
+    let mut walked = PathBuf::from("/");
+
+    for component in path.components() {
+        match component {
+            Component::RootDir | Component::CurDir => continue,
+            Component::Normal(name) => {
+                if !inner.directories.contains_key(&ino) {
+                    return Err(anyhow!("{:?}: {:?} no es un directorio", path, walked));
+                }
+                walked.push(name);
+                ino = dir::find_entry(inner, ino, name)
+                    .map(|(_, child)| child)
+                    .with_context(|| format!("{:?}: no existe {:?}", path, walked))?;
+            }
+            _ => return Err(anyhow!("Ruta no soportada: {:?}", path)),
+        }
+    }
+
+    Ok(ino)
+}
@@ -0,0 +1,169 @@
+// Acceso a la tabla de inodos en disco.
+//
+// Los inodos se guardan uno detrás de otro desde `inode_table_start`; el inodo N
+// ocupa el slot N-1. `get` y `put` sólo leen (y reescriben) los bloques donde
+// cae el inodo pedido, incluso si queda partido entre dos bloques; `iter` lee la
+// tabla entera una sola vez para los recorridos completos (montaje, fsck).
+
+use std::mem;
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::block_store::BlockStore;
+use crate::fs::{InodeDisk, SuperblockDisk};
+
+pub struct InodeTable {
+    store: BlockStore,
+    superblock: SuperblockDisk,
+}
+
+impl InodeTable {
+    pub fn new(store: BlockStore, superblock: SuperblockDisk) -> Self {
+        Self { store, superblock }
+    }
+
+    pub fn open(qr_folder: &Path, superblock: &SuperblockDisk) -> Result<Self> {
+        Ok(Self::new(BlockStore::open(qr_folder)?, *superblock))
+    }
+
+    /// Cantidad de inodos recorribles: `max_inodes`, pero nunca más de los que
+    /// caben en los bloques de la tabla (un superblock corrupto podría mentir).
+    pub fn len(&self) -> u64 {
+        (self.superblock.max_inodes as u64).min(self.superblock.inode_table_capacity())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, ino: u64) -> Result<InodeDisk> {
+        let (first_block, count, offset) = self.locate(ino)?;
+        let buf = self
+            .store
+            .read_blocks(first_block, count)
+            .with_context(|| format!("No se pudo leer el inodo {} de la tabla", ino))?;
+        InodeDisk::from_bytes(&buf[offset..])
+    }
+
+    /// Bloques de la tabla que guardan el inodo `ino`: uno, o dos si queda partido.
+    pub fn blocks_of(&self, ino: u64) -> Result<Range<u32>> {
+        let (first_block, count, _) = self.locate(ino)?;
+        Ok(first_block..first_block + count)
+    }
+
+    pub fn put(&self, ino: u64, inode: &InodeDisk) -> Result<()> {
+        let (first_block, count, offset) = self.locate(ino)?;
+        let mut buf = self
+            .store
+            .read_blocks(first_block, count)
+            .with_context(|| format!("No se pudo leer el inodo {} de la tabla", ino))?;
+
+        buf[offset..offset + mem::size_of::<InodeDisk>()].copy_from_slice(inode_bytes(inode));
+
+        self.store
+            .write_blocks(first_block, &buf)
+            .with_context(|| format!("No se pudo escribir el inodo {} en la tabla", ino))
+    }
+
+    /// Todos los inodos de la tabla (usados o no) en orden, con su número.
+    pub fn iter(&self) -> Result<impl Iterator<Item = (u64, InodeDisk)>> {
+        let buf = self
+            .store
+            .read_blocks(self.superblock.inode_table_start, self.superblock.inode_table_blocks)
+            .with_context(|| "No se pudo leer la tabla de inodos")?;
+
+        let inode_size = mem::size_of::<InodeDisk>();
+        // `len` nunca pasa de lo que cabe en los bloques leídos: `map_while` sólo
+        // cortaría si eso dejara de valer, en vez de entrar en pánico
+        Ok((1..=self.len()).map_while(move |ino| {
+            let offset = (ino as usize - 1) * inode_size;
+            Some((ino, InodeDisk::from_bytes(buf.get(offset..)?).ok()?))
+        }))
+    }
+
+    /// Bloques que hay que leer para el inodo `ino` (primero y cantidad: 1, o 2
+    /// si el inodo queda partido) y su desplazamiento dentro de ellos.
+    fn locate(&self, ino: u64) -> Result<(u32, u32, usize)> {
+        if ino == 0 || ino > self.len() {
+            return Err(anyhow!(
+                "Inodo fuera de rango: {} (max_inodes = {}, la tabla admite {})",
+                ino,
+                self.superblock.max_inodes,
+                self.superblock.inode_table_capacity()
+            ));
+        }
+
+        let block_size = self.superblock.block_size as u64;
+        let start = (ino - 1) * mem::size_of::<InodeDisk>() as u64;
+        let end = start + mem::size_of::<InodeDisk>() as u64;
+
+        let first = start / block_size;
+        let last = (end - 1) / block_size;
+        Ok((
+            self.superblock.inode_table_start + first as u32,
+            (last - first + 1) as u32,
+            (start - first * block_size) as usize,
+        ))
+    }
+}
+
+fn inode_bytes(inode: &InodeDisk) -> &[u8] {
+    unsafe {
+        std::slice::from_raw_parts(inode as *const InodeDisk as *const u8, mem::size_of::<InodeDisk>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::fresh_image;
+
+    fn inode(ino: u64) -> InodeDisk {
+        InodeDisk {
+            id: ino as u32,
+            file_type: 1,
+            size: ino * 1000,
+            nlink: 1,
+            direct_blocks: [ino as u32; 12],
+            generation: ino as u32,
+            ..InodeDisk::empty()
+        }
+    }
+
+    #[test]
+    fn first_last_and_straddling_inodes_round_trip() {
+        let dir = fresh_image(64);
+        let sb = SuperblockDisk::from_block_bytes(&BlockStore::open(dir.path()).unwrap().read_block(0).unwrap())
+            .unwrap();
+        let table = InodeTable::open(dir.path(), &sb).unwrap();
+
+        let last = table.len();
+        let straddling = (1..=last)
+            .find(|&ino| table.blocks_of(ino).unwrap().len() == 2)
+            .expect("con este tamaño de inodo alguno queda partido");
+        let before = table.get(straddling + 1).unwrap();
+
+        for ino in [1, straddling, last] {
+            table.put(ino, &inode(ino)).unwrap();
+        }
+        for ino in [1, straddling, last] {
+            assert_eq!(table.get(ino).unwrap().size, ino * 1000);
+            assert_eq!(table.get(ino).unwrap().direct_blocks, [ino as u32; 12]);
+        }
+        // Escribir el partido no pisa al vecino
+        assert_eq!(table.get(straddling + 1).unwrap().id, before.id);
+
+        let all: Vec<(u64, InodeDisk)> = table.iter().unwrap().collect();
+        assert_eq!(all.len() as u64, last);
+        for ino in [1, straddling, last] {
+            assert_eq!(all[ino as usize - 1].0, ino);
+            assert_eq!(all[ino as usize - 1].1.size, ino * 1000);
+        }
+
+        assert!(table.get(0).is_err());
+        assert!(table.get(last + 1).is_err());
+        assert!(table.put(last + 1, &inode(last + 1)).is_err());
+    }
+}
@@ -1,8 +1,62 @@
 mod fs;
 mod dir;
-pub mod fsck; // <- descomentar
+mod image;
+mod block_store;
+mod recover;
+mod mkfs;
+mod stats;
+mod control;
+mod meta;
+mod config;
+mod inode_table;
+mod compress;
+mod readahead;
+mod zip_store;
+mod locate;
+mod print;
+mod scan;
+mod bad_blocks;
+mod parity;
+mod folder_lock;
+mod dry_run;
+pub mod fsck;
+#[cfg(test)]
+mod test_util;
 
 pub use crate::fs::QrfsFilesystem;
+pub use crate::image::{QrfsBatch, QrfsImage};
+pub use crate::inode_table::InodeTable;
+pub use crate::fsck::check;
+pub use crate::fsck::fsck_types::FsckReport;
+pub use crate::config::{MountConfig, QRFS_CONFIG_NAME};
+pub use crate::compress::QRFS_COMPRESSED_HEADER;
+pub use crate::readahead::QRFS_READ_AHEAD_DEFAULT;
+pub use crate::zip_store::is_zip_image;
+pub use crate::locate::files_backing_path;
+pub use crate::print::{block_payload, parse_block_payload, print_pdf, PrintReport, QRFS_PRINT_PER_PAGE_DEFAULT};
+pub use crate::scan::{import_scans, ScanReport};
+pub use crate::bad_blocks::{bad_blocks, mark_bad_block, BadBlockMark};
+pub use crate::parity::{add_parity, rebuild_parity, QRFS_PARITY_MAX_SHARDS};
+pub use crate::folder_lock::{lock_folder, FolderLock, QRFS_LOCK_NAME};
+pub use crate::dry_run::{dry_run, PlannedChange};
+pub use crate::stats::{MountStats, QrfsStats, QRFS_STATS_INO, QRFS_STATS_NAME};
+pub use crate::control::{QRFS_CONTROL_INO, QRFS_CONTROL_NAME};
+pub use crate::meta::{QRFS_META_INO, QRFS_META_NAME};
+pub use crate::mkfs::{
+    build_layout, capacity_summary, create_block_files, init_fresh_fs, make_dir_block, write_fresh_fs,
+    CapacitySummary, FileCapacity, FsLayout, SUMMARY_FILE_SIZES,
+};
+pub use crate::recover::{rebuild_inode_table, RecoveryReport};
+pub use crate::block_store::{
+    block_file_index, block_file_name, block_file_name_with_ext, block_file_writes, list_block_files,
+    manifest_mismatches, refresh_manifest, write_manifest, BlockStore, QRFS_BLOCK_PREFIX,
+    QRFS_DEFAULT_BLOCK_EXT, QRFS_MANIFEST_NAME, QRFS_OVERLAY_MARKER,
+};
+pub use crate::fs::{
+    bitmap_set, bitmap_test, check_dot_entries, check_mountpoint, compact_directories, file_blocks_for_size, list_deleted, max_file_size, repair_counters, upgrade_image,
+    CounterRepair,
+    DeletedInode, DotEntryProblem, TypeConflict, UpgradeReport,
+};
 pub use crate::fs::{
     SuperblockDisk,
     InodeDisk,
@@ -11,4 +65,20 @@ pub use crate::fs::{
     QRFS_MAGIC,
     QRFS_VERSION,
     QRFS_NAME_LEN,
+    QRFS_MAX_NAME,
+    QRFS_INODE_INLINE,
+    QRFS_INLINE_MAX,
+    QRFS_INODE_DELETED,
+    QRFS_INODE_COMPRESSED,
+    QRFS_INODE_NSEC_TIME,
+    QRFS_TYPE_MASK,
+    QRFS_DIR_SORTED,
+    QRFS_SORTED_DIR_THRESHOLD,
+    QRFS_FEATURE_COMPRESSION,
+    QRFS_FEATURE_BAD_BLOCKS,
+    QRFS_FEATURE_PARITY,
+    QRFS_FEATURE_CHECKSUM,
+    QRFS_MAX_BAD_BLOCKS,
+    QRFS_SUPPORTED_FEATURES,
+    QRFS_SUPERBLOCK_BACKUP,
 };
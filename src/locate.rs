@@ -0,0 +1,28 @@
+// Qué archivos de bloque guardan un archivo de la imagen.
+//
+// Para imprimir sólo las páginas de QRs de un archivo (o volver a escanearlas)
+// hay que saber en qué bloques vive: el superblock, el bloque de la tabla con
+// su inodo y sus bloques de datos. `files_backing_path` los traduce a los
+// archivos de la carpeta con `BlockStore`, así que respeta el manifiesto y la
+// capa superior de un overlay.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::block_store::BlockStore;
+use crate::image::QrfsImage;
+
+/// Bloques (y el archivo de cada uno) que hacen falta para leer `path` en la
+/// imagen de `qr_folder`, ordenados por número de bloque. `path` es relativa a
+/// la raíz del FS (`/docs/report.pdf` o `docs/report.pdf`).
+pub fn files_backing_path(qr_folder: &Path, path: &Path) -> Result<Vec<(u32, PathBuf)>> {
+    let image = QrfsImage::open(qr_folder)?;
+    let blocks = image.backing_blocks(path)?;
+
+    let store = BlockStore::open(qr_folder)?;
+    blocks
+        .into_iter()
+        .map(|b| Ok((b, store.block_path(b)?)))
+        .collect()
+}
@@ -0,0 +1,247 @@
+// Directorio virtual `.qrfs-meta` con los metadatos de la imagen como texto.
+//
+// Con `mount_qrfs --meta-dir` la raíz tiene un directorio `.qrfs-meta` (que no
+// aparece en `ls`, igual que `.qrfs-stats`) con tres archivos de sólo lectura:
+// `superblock.txt`, `bitmap.txt` e `inodes.txt`. Nada de esto ocupa inodos ni
+// bloques: sus números salen de un rango reservado al final de los u64 y el
+// contenido se genera en cada lectura desde el superblock en memoria y la tabla
+// de inodos y el bitmap en disco.
+
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use anyhow::Result;
+use fuser::{FileAttr, FileType};
+
+use crate::fs::{bitmap_test, QrfsInner};
+use crate::inode_table::InodeTable;
+
+/// Nombre del directorio virtual en la raíz.
+pub const QRFS_META_NAME: &str = ".qrfs-meta";
+
+/// Inodo reservado para el directorio; los archivos usan los siguientes hacia
+/// abajo (ver `QRFS_STATS_INO`).
+pub const QRFS_META_INO: u64 = u64::MAX - 3;
+
+/// Bloques por línea de `bitmap.txt`.
+const BITMAP_ROW: u32 = 64;
+
+/// Archivos del directorio `.qrfs-meta`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MetaFile {
+    Superblock,
+    Bitmap,
+    Inodes,
+}
+
+impl MetaFile {
+    pub(crate) const ALL: [MetaFile; 3] = [MetaFile::Superblock, MetaFile::Bitmap, MetaFile::Inodes];
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            MetaFile::Superblock => "superblock.txt",
+            MetaFile::Bitmap => "bitmap.txt",
+            MetaFile::Inodes => "inodes.txt",
+        }
+    }
+
+    pub(crate) fn ino(self) -> u64 {
+        let index = MetaFile::ALL.iter().position(|&f| f == self).unwrap_or(0);
+        QRFS_META_INO - 1 - index as u64
+    }
+
+    pub(crate) fn from_ino(ino: u64) -> Option<Self> {
+        MetaFile::ALL.into_iter().find(|f| f.ino() == ino)
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        MetaFile::ALL.into_iter().find(|f| f.name() == name)
+    }
+
+    /// Contenido actual del archivo. Un error de lectura del disco queda escrito
+    /// en el texto en vez de fallar la lectura.
+    pub(crate) fn render(self, inner: &QrfsInner) -> Vec<u8> {
+        let text = match self {
+            MetaFile::Superblock => Ok(render_superblock(inner)),
+            MetaFile::Bitmap => render_bitmap(inner),
+            MetaFile::Inodes => render_inodes(inner),
+        };
+        text.unwrap_or_else(|e| format!("error: {:#}\n", e)).into_bytes()
+    }
+}
+
+/// `true` para el directorio virtual y sus archivos.
+pub(crate) fn is_meta_ino(ino: u64) -> bool {
+    ino == QRFS_META_INO || MetaFile::from_ino(ino).is_some()
+}
+
+fn render_superblock(inner: &QrfsInner) -> String {
+    let sb = &inner.superblock;
+    let mut out = String::new();
+    let _ = writeln!(out, "magic: {:#010x}", sb.magic);
+    let _ = writeln!(out, "version: {}", sb.version);
+    let _ = writeln!(out, "block_size: {}", sb.block_size);
+    let _ = writeln!(out, "total_blocks: {}", sb.total_blocks);
+    let _ = writeln!(
+        out,
+        "inode_table: bloques {}..{} ({})",
+        sb.inode_table_start,
+        sb.inode_table_start + sb.inode_table_blocks,
+        sb.inode_table_blocks
+    );
+    let _ = writeln!(
+        out,
+        "free_bitmap: bloques {}..{} ({})",
+        sb.free_bitmap_start,
+        sb.free_bitmap_start + sb.free_bitmap_blocks,
+        sb.free_bitmap_blocks
+    );
+    let _ = writeln!(out, "data_blocks_start: {}", sb.data_blocks_start);
+    let _ = writeln!(out, "max_inodes: {}", sb.max_inodes);
+    let _ = writeln!(out, "root_inode: {}", sb.root_inode);
+    // Los contadores de memoria: los de disco se actualizan al sincronizar
+    let _ = writeln!(out, "free_blocks: {}", inner.free_blocks);
+    let _ = writeln!(out, "free_inodes: {}", inner.free_inodes);
+    let _ = writeln!(out, "features: {:#010x}", sb.features);
+    let _ = writeln!(out, "bad_blocks: {:?}", sb.bad_block_list());
+    let _ = writeln!(
+        out,
+        "parity: {}:{} ({} bloques)",
+        sb.parity_group,
+        sb.parity_per_group,
+        sb.parity_blocks()
+    );
+    // Se recalcula al sincronizar, así que sólo vale para la copia en disco
+    let _ = writeln!(out, "checksum: {:#010x}", sb.checksum);
+    out
+}
+
+/// Una línea por cada `BITMAP_ROW` bloques: el primero de la fila y un `#` por
+/// bloque usado o un `.` por bloque libre.
+fn render_bitmap(inner: &QrfsInner) -> Result<String> {
+    let sb = &inner.superblock;
+    let bitmap = &inner.bitmap;
+
+    let mut out = String::new();
+    for row in (0..sb.total_blocks).step_by(BITMAP_ROW as usize) {
+        let _ = write!(out, "{:>8} ", row);
+        for block in row..(row + BITMAP_ROW).min(sb.total_blocks) {
+            out.push(if bitmap_test(bitmap, block) { '#' } else { '.' });
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Una línea por inodo en uso (o borrado y todavía recuperable) de la tabla en
+/// disco.
+fn render_inodes(inner: &QrfsInner) -> Result<String> {
+    let mut out = String::from("ino\ttipo\tpermisos\tnlink\ttamaño\tdirectos\tindirecto\tdoble\n");
+
+    for (ino, inode) in InodeTable::open(&inner.qr_folder, &inner.superblock)?.iter()? {
+        if inode.id == 0 || (inode.nlink == 0 && !inode.is_deleted()) {
+            continue;
+        }
+
+        let kind = if inode.is_deleted() {
+            "borrado"
+        } else if inode.is_dir() {
+            "dir"
+        } else {
+            "archivo"
+        };
+        let direct = if inode.is_inline() {
+            "inline".to_string()
+        } else {
+            let used: Vec<String> = inode
+                .direct_blocks
+                .iter()
+                .filter(|&&b| b != 0)
+                .map(|b| b.to_string())
+                .collect();
+            if used.is_empty() { "-".to_string() } else { used.join(",") }
+        };
+
+        let _ = writeln!(
+            out,
+            "{}\t{}\t{:o}\t{}\t{}\t{}\t{}\t{}",
+            ino,
+            kind,
+            inode.perm,
+            inode.nlink,
+            inode.size,
+            direct,
+            inode.indirect_block,
+            inode.double_indirect_block
+        );
+    }
+    Ok(out)
+}
+
+/// Atributos del directorio virtual.
+pub fn meta_dir_attr() -> FileAttr {
+    meta_attr(QRFS_META_INO, FileType::Directory, 0, 0o555, 2)
+}
+
+/// Atributos de un archivo del directorio, con el tamaño del contenido actual.
+pub(crate) fn meta_file_attr(file: MetaFile, size: u64) -> FileAttr {
+    meta_attr(file.ino(), FileType::RegularFile, size, 0o444, 1)
+}
+
+fn meta_attr(ino: u64, kind: FileType, size: u64, perm: u16, nlink: u32) -> FileAttr {
+    let now = SystemTime::now();
+    FileAttr {
+        ino,
+        size,
+        blocks: 0,
+        atime: now,
+        mtime: now,
+        ctime: now,
+        crtime: now,
+        kind,
+        perm,
+        nlink,
+        uid: 0,
+        gid: 0,
+        rdev: 0,
+        blksize: 512,
+        flags: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::QRFS_CONTROL_INO;
+    use crate::fs::QrfsFilesystem;
+    use crate::stats::QRFS_STATS_INO;
+    use crate::test_util::fresh_image;
+
+    #[test]
+    fn superblock_file_renders_the_mounted_superblock() {
+        let dir = fresh_image(64);
+        let fs = QrfsFilesystem::mount_from_folder(dir.path(), None, None).unwrap();
+        let inner = fs.inner.read().unwrap();
+
+        let file = MetaFile::from_name("superblock.txt").unwrap();
+        assert!(is_meta_ino(file.ino()));
+        assert!(MetaFile::from_ino(file.ino()) == Some(file));
+        let text = String::from_utf8(file.render(&inner)).unwrap();
+
+        let sb = &inner.superblock;
+        for line in [
+            format!("magic: {:#010x}", sb.magic),
+            format!("total_blocks: {}", sb.total_blocks),
+            format!("max_inodes: {}", sb.max_inodes),
+            format!("root_inode: {}", sb.root_inode),
+            format!("free_blocks: {}", inner.free_blocks),
+        ] {
+            assert!(text.lines().any(|l| l == line), "falta {:?} en:\n{}", line, text);
+        }
+
+        // Los números reservados no chocan con los otros archivos virtuales
+        let inos: Vec<u64> = MetaFile::ALL.iter().map(|f| f.ino()).chain([QRFS_META_INO]).collect();
+        assert!(!inos.contains(&QRFS_STATS_INO) && !inos.contains(&QRFS_CONTROL_INO));
+        assert!(!is_meta_ino(inner.root_ino));
+    }
+}
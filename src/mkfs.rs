@@ -0,0 +1,531 @@
+// Formateo de imágenes QRFS: cálculo del layout y escritura de los metadatos
+// iniciales. Lo usan mkfs.qrfs y las herramientas que arman una imagen entera
+// de una vez (from_dir.qrfs).
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::ffi::{OsStr, OsString};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::block_store::{block_file_index, block_file_name_with_ext, list_block_files};
+use crate::dir;
+use crate::fs::{
+    file_blocks_for_size, DirEntryDisk, InodeDisk, SuperblockDisk, QRFS_BLOCK_SIZE, QRFS_MAGIC, QRFS_MAX_BAD_BLOCKS, QRFS_MAX_NAME,
+    QRFS_NAME_LEN, QRFS_VERSION,
+};
+
+/// Crea `count` archivos de bloque vacíos (`block_00000000.<ext>`, ...). La
+/// carpeta no debe tener bloques previos para no mezclar imágenes.
+pub fn create_block_files(qr_folder: &Path, count: u32, ext: &str) -> Result<()> {
+    // Una extensión que el formato no acepta (vacía, con puntos, `.tmp`) dejaría
+    // archivos que después nadie reconoce como bloques
+    if block_file_index(&block_file_name_with_ext(0, ext)) != Some(0) {
+        return Err(anyhow!(
+            "Extensión de bloque inválida {:?}: debe ser alfanumérica y no de archivo temporal",
+            ext
+        ));
+    }
+
+    fs::create_dir_all(qr_folder)
+        .with_context(|| format!("No se pudo crear el directorio {:?}", qr_folder))?;
+
+    if !list_block_files(qr_folder)?.is_empty() {
+        return Err(anyhow!(
+            "La carpeta {:?} ya tiene archivos de bloque; no se crean bloques nuevos",
+            qr_folder
+        ));
+    }
+
+    for i in 0..count {
+        File::create(qr_folder.join(block_file_name_with_ext(i, ext)))
+            .with_context(|| format!("No se pudo crear el bloque {} en {:?}", i, qr_folder))?;
+    }
+    Ok(())
+}
+
+/// Estructura auxiliar para el layout calculado.
+pub struct FsLayout {
+    pub total_blocks: u32,
+    pub inode_table_start: u32,
+    pub inode_table_blocks: u32,
+    pub free_bitmap_start: u32,
+    pub free_bitmap_blocks: u32,
+    pub data_blocks_start: u32,
+    pub max_inodes: u32,
+}
+
+/// Cantidad mínima de bloques de una imagen: superblock, tabla de inodos,
+/// bitmap y el bloque del directorio raíz.
+const QRFS_MIN_BLOCKS: u32 = 4;
+
+/// Cálculo del layout básico del filesystem dentro de los bloques QR. Siempre
+/// deja lugar para al menos un inodo (la raíz) y un bloque de datos (el suyo);
+/// una imagen más chica es un error.
+pub fn build_layout(total_blocks: u32) -> Result<FsLayout> {
+    if total_blocks < QRFS_MIN_BLOCKS {
+        return Err(anyhow!(
+            "Se requieren al menos {} bloques para crear el filesystem (se tienen {}).",
+            QRFS_MIN_BLOCKS,
+            total_blocks
+        ));
+    }
+
+    let block_size = QRFS_BLOCK_SIZE as usize;
+    let inode_size = mem::size_of::<InodeDisk>();
+
+    if inode_size == 0 || inode_size > block_size {
+        return Err(anyhow!(
+            "InodeDisk no cabe en un bloque: inode_size={}, block_size={}",
+            inode_size,
+            block_size
+        ));
+    }
+
+    // Heurística simple:
+    // - Reservar ~10% de los bloques para la tabla de inodos (al menos 1).
+    // - Los inodos van uno detrás de otro sin relleno (un inodo puede quedar
+    //   partido entre dos bloques, ver `InodeTable`), así que entran todos los
+    //   que caben en los bytes de la tabla: igual que `inode_table_capacity`.
+    let mut inode_table_blocks = (total_blocks / 10).max(1);
+    if inode_table_blocks > total_blocks - 2 {
+        inode_table_blocks = 1;
+    }
+    let max_inodes = (inode_table_blocks as usize * block_size / inode_size) as u32;
+    if max_inodes == 0 {
+        return Err(anyhow!(
+            "La tabla de inodos ({} bloques) no alcanza ni para el inodo raíz",
+            inode_table_blocks
+        ));
+    }
+
+    // Bitmap: 1 bit por bloque.
+    let bitmap_bits = total_blocks as usize;
+    let bitmap_bytes = (bitmap_bits + 7) / 8;
+    let free_bitmap_blocks =
+        ((bitmap_bytes as u32) + QRFS_BLOCK_SIZE - 1) / QRFS_BLOCK_SIZE;
+
+    let inode_table_start = 1;
+    let free_bitmap_start = inode_table_start + inode_table_blocks;
+    let data_blocks_start = free_bitmap_start + free_bitmap_blocks;
+
+    if data_blocks_start >= total_blocks {
+        return Err(anyhow!(
+            "No hay espacio para bloques de datos: total_blocks={}, data_blocks_start={}",
+            total_blocks,
+            data_blocks_start
+        ));
+    }
+
+    Ok(FsLayout {
+        total_blocks,
+        inode_table_start,
+        inode_table_blocks,
+        free_bitmap_start,
+        free_bitmap_blocks,
+        data_blocks_start,
+        max_inodes,
+    })
+}
+
+/// Tamaños promedio de archivo para los que `capacity_summary` estima cuántos
+/// archivos entran.
+pub const SUMMARY_FILE_SIZES: [u64; 3] = [1024, 4096, 64 * 1024];
+
+/// Capacidad de una imagen recién formateada, derivada de su layout.
+pub struct CapacitySummary {
+    /// Bytes de datos disponibles (bloques de datos menos el de la raíz).
+    pub data_bytes: u64,
+    /// Bloques de metadatos (superblock, tabla de inodos y bitmap).
+    pub metadata_blocks: u32,
+    /// Porcentaje de la imagen que ocupan los metadatos.
+    pub metadata_percent: f64,
+    /// Para cada tamaño de `SUMMARY_FILE_SIZES`, cuántos archivos de ese tamaño
+    /// entran.
+    pub files: Vec<FileCapacity>,
+}
+
+pub struct FileCapacity {
+    pub file_size: u64,
+    pub max_files: u64,
+    /// `true` si se acaban antes los inodos que los bloques.
+    pub limited_by_inodes: bool,
+}
+
+/// Cuántos archivos de cada tamaño típico entran en una imagen con este layout,
+/// limitados tanto por los inodos libres como por los bloques de datos libres
+/// (contando los de punteros). No cuenta directorios: cada uno gasta además un
+/// inodo y al menos un bloque, y admite pocas entradas.
+pub fn capacity_summary(layout: &FsLayout) -> CapacitySummary {
+    // La raíz ya usa un inodo y el primer bloque de datos
+    let free_inodes = layout.max_inodes.saturating_sub(1) as u64;
+    let free_blocks = (layout.total_blocks - layout.data_blocks_start).saturating_sub(1) as u64;
+
+    let files = SUMMARY_FILE_SIZES
+        .iter()
+        .map(|&file_size| {
+            let by_blocks = free_blocks / file_blocks_for_size(file_size, QRFS_BLOCK_SIZE);
+            FileCapacity {
+                file_size,
+                max_files: by_blocks.min(free_inodes),
+                limited_by_inodes: free_inodes < by_blocks,
+            }
+        })
+        .collect();
+
+    let metadata_blocks = layout.data_blocks_start;
+    CapacitySummary {
+        data_bytes: free_blocks * QRFS_BLOCK_SIZE as u64,
+        metadata_blocks,
+        metadata_percent: metadata_blocks as f64 * 100.0 / layout.total_blocks as f64,
+        files,
+    }
+}
+
+/// Inicializa un filesystem vacío: superblock, inodos (incluyendo root) y bitmap.
+pub fn init_fresh_fs(layout: &FsLayout) -> Result<(SuperblockDisk, Vec<InodeDisk>, Vec<u8>)> {
+    // Sin lugar para la raíz el montaje inventaría otra (ver `build_layout`)
+    if layout.max_inodes == 0 || layout.data_blocks_start >= layout.total_blocks {
+        return Err(anyhow!(
+            "El layout no tiene lugar para el directorio raíz ({} inodos, datos desde el bloque {} de {})",
+            layout.max_inodes,
+            layout.data_blocks_start,
+            layout.total_blocks
+        ));
+    }
+
+    // Bloque de datos que vamos a usar para el directorio raíz
+    let root_data_block = layout.data_blocks_start;
+
+    // Total de bloques de datos, y dejamos 1 ocupado por el root
+    let total_data_blocks = layout.total_blocks - layout.data_blocks_start;
+    let data_blocks_after_root = total_data_blocks.saturating_sub(1);
+
+    let superblock = SuperblockDisk {
+        magic: QRFS_MAGIC,
+        version: QRFS_VERSION,
+        block_size: QRFS_BLOCK_SIZE,
+        total_blocks: layout.total_blocks,
+        inode_table_start: layout.inode_table_start,
+        inode_table_blocks: layout.inode_table_blocks,
+        free_bitmap_start: layout.free_bitmap_start,
+        free_bitmap_blocks: layout.free_bitmap_blocks,
+        data_blocks_start: layout.data_blocks_start,
+        max_inodes: layout.max_inodes,
+        root_inode: 1,
+        free_blocks: data_blocks_after_root, // << antes usabas todos como libres
+        free_inodes: layout.max_inodes - 1,
+        checksum: 0, // se calcula al escribir el bloque 0
+        features: 0,
+        bad_blocks: [0; QRFS_MAX_BAD_BLOCKS],
+        parity_group: 0,
+        parity_per_group: 0,
+    };
+
+    // Crear vector de inodos vacíos.
+    let mut inodes = vec![InodeDisk::empty(); layout.max_inodes as usize];
+
+    // Inodo 1 = directorio raíz
+if !inodes.is_empty() {
+    // tamaño del directorio raíz con "." y ".."
+    let dir_entry_size = std::mem::size_of::<DirEntryDisk>();
+    let root_dir_size = (2 * dir_entry_size) as u64;
+
+    inodes[0] = InodeDisk {
+        id: 1,
+        file_type: 2, // 2 = directorio
+        perm: 0o755,
+        uid: 0,
+        gid: 0,
+        size: root_dir_size, // << antes 0
+        atime: 0,
+        mtime: 0,
+        ctime: 0,
+        nlink: 2, // "." y ".."
+        direct_blocks: {
+            let mut blocks = [0u32; 12];
+            blocks[0] = root_data_block; // << bloque de datos usado por el root
+            blocks
+        },
+        indirect_block: 0,
+        double_indirect_block: 0,
+        generation: 0,
+    };
+    inodes[0].set_times(SystemTime::now());
+}
+
+    // Bitmap: 1 bit por bloque, 1 = usado, 0 = libre.
+    let bitmap_bits = layout.total_blocks as usize;
+    let bitmap_bytes = (bitmap_bits + 7) / 8;
+    let mut bitmap = vec![0u8; bitmap_bytes];
+
+    // Marcar como usados todos los bloques de metadata:
+    // [0 .. data_blocks_start)
+    for b in 0..layout.data_blocks_start {
+        let idx = b as usize;
+        let byte = idx / 8;
+        let bit = (idx % 8) as u8;
+        bitmap[byte] |= 1 << bit;
+    }
+    // Marcar como usado el bloque de datos del directorio raíz
+    {
+        let idx = root_data_block as usize;
+        let byte = idx / 8;
+        let bit = (idx % 8) as u8;
+        bitmap[byte] |= 1 << bit;
+    }
+
+    Ok((superblock, inodes, bitmap))
+}
+
+/// Escribe una imagen recién formateada sobre los archivos de bloque `entries`
+/// (en orden lógico): superblock, tabla de inodos, bitmap, área de datos en
+/// ceros y el bloque del directorio raíz con "." y "..".
+pub fn write_fresh_fs(
+    entries: &[PathBuf],
+    layout: &FsLayout,
+    superblock: &SuperblockDisk,
+    inodes: &[InodeDisk],
+    bitmap: &[u8],
+) -> Result<()> {
+    write_superblock(entries, superblock)?;
+    write_inode_table(entries, layout, inodes)?;
+    write_bitmap(entries, layout, bitmap)?;
+    // Primero cero todo el área de datos
+    zero_data_blocks(entries, layout)?;
+    // Luego escribo el contenido real del directorio raíz en su bloque
+    write_root_directory_block(entries, layout)
+}
+
+/// Escribe el superblock en el bloque 0.
+fn write_superblock(entries: &[PathBuf], sb: &SuperblockDisk) -> Result<()> {
+    let mut sealed = *sb;
+    sealed.seal();
+    let data = struct_to_bytes(&sealed);
+    write_block(&entries[0], &data)
+}
+
+/// Escribe la tabla de inodos a partir de inode_table_start.
+fn write_inode_table(
+    entries: &[PathBuf],
+    layout: &FsLayout,
+    inodes: &[InodeDisk],
+) -> Result<()> {
+    let block_size = QRFS_BLOCK_SIZE as usize;
+    let max_bytes = (layout.inode_table_blocks as usize) * block_size;
+
+    // Serializamos todos los inodos
+    let mut data = slice_of_structs_to_bytes(inodes);
+
+    if data.len() > max_bytes {
+        // Opción 1: truncar silenciosamente (no recomendado en prod)
+        // data.truncate(max_bytes);
+
+        // Opción 2: fallar explícitamente:
+        return Err(anyhow!(
+            "La tabla de inodos ({:?} bytes) no cabe en los bloques reservados ({:?} bytes)",
+            data.len(),
+            max_bytes
+        ));
+    }
+
+    // Rellenar con ceros hasta ocupar exactamente la región
+    if data.len() < max_bytes {
+        data.resize(max_bytes, 0);
+    }
+
+    write_blocks(entries, layout.inode_table_start, &data)
+}
+
+/// Escribe el bitmap de bloques libres.
+fn write_bitmap(
+    entries: &[PathBuf],
+    layout: &FsLayout,
+    bitmap: &[u8],
+) -> Result<()> {
+    let block_size = QRFS_BLOCK_SIZE as usize;
+    let max_bytes = (layout.free_bitmap_blocks as usize) * block_size;
+
+    if bitmap.len() > max_bytes {
+        return Err(anyhow!(
+            "El bitmap ({:?} bytes) no cabe en los bloques reservados ({:?} bytes)",
+            bitmap.len(),
+            max_bytes
+        ));
+    }
+
+    let mut data = bitmap.to_vec();
+    if data.len() < max_bytes {
+        data.resize(max_bytes, 0);
+    }
+
+    write_blocks(entries, layout.free_bitmap_start, &data)
+}
+
+
+/// Rellena los bloques de datos con ceros.
+fn zero_data_blocks(entries: &[PathBuf], layout: &FsLayout) -> Result<()> {
+    let block_size = QRFS_BLOCK_SIZE as usize;
+    let zero_block = vec![0u8; block_size];
+
+    let start = layout.data_blocks_start as usize;
+    let end = layout.total_blocks as usize;
+
+    for i in start..end {
+        write_block(&entries[i], &zero_block)?;
+    }
+
+    Ok(())
+}
+
+/// Serializa una estructura arbitraria (repr(C), Copy) a bytes.
+fn struct_to_bytes<T: Copy>(val: &T) -> Vec<u8> {
+    let size = mem::size_of::<T>();
+    unsafe {
+        let ptr = val as *const T as *const u8;
+        std::slice::from_raw_parts(ptr, size).to_vec()
+    }
+}
+
+/// Serializa un slice de estructuras (repr(C), Copy) a bytes contiguos.
+fn slice_of_structs_to_bytes<T: Copy>(slice: &[T]) -> Vec<u8> {
+    let size = mem::size_of::<T>() * slice.len();
+    unsafe {
+        let ptr = slice.as_ptr() as *const u8;
+        std::slice::from_raw_parts(ptr, size).to_vec()
+    }
+}
+
+/// Escribe un bloque lógico completo sobre el archivo correspondiente.
+fn write_block(path: &PathBuf, data: &[u8]) -> Result<()> {
+    let block_size = QRFS_BLOCK_SIZE as usize;
+    let mut buf = vec![0u8; block_size];
+
+    let to_copy = data.len().min(block_size);
+    buf[..to_copy].copy_from_slice(&data[..to_copy]);
+
+    let mut file = File::create(path)
+        .with_context(|| format!("No se pudo crear/escribir el archivo {:?}", path))?;
+    file.write_all(&buf)?;
+    Ok(())
+}
+
+/// Escribe datos en varios bloques consecutivos, comenzando en `start_block`.
+fn write_blocks(
+    entries: &[PathBuf],
+    start_block: u32,
+    data: &[u8],
+) -> Result<()> {
+    let block_size = QRFS_BLOCK_SIZE as usize;
+    let mut offset = 0usize;
+    let mut block_index = start_block as usize;
+
+    while offset < data.len() {
+        if block_index >= entries.len() {
+            return Err(anyhow!(
+                "No hay suficientes bloques para escribir los metadatos (se quedó corto en el bloque {}).",
+                block_index
+            ));
+        }
+
+        let end = (offset + block_size).min(data.len());
+        write_block(&entries[block_index], &data[offset..end])?;
+        offset = end;
+        block_index += 1;
+    }
+
+    Ok(())
+}
+
+fn make_root_dir_block() -> Vec<u8> {
+    use std::mem;
+
+    let mut entries = Vec::new();
+
+    // "." → inode 1
+    let mut name_dot = [0u8; QRFS_NAME_LEN];
+    name_dot[0] = b'.';
+
+    let dot = DirEntryDisk {
+        inode: 1,
+        name: name_dot,
+    };
+    entries.push(dot);
+
+    // ".." → inode 1 (porque es raíz)
+    let mut name_dotdot = [0u8; QRFS_NAME_LEN];
+    name_dotdot[0] = b'.';
+    name_dotdot[1] = b'.';
+
+    let dotdot = DirEntryDisk {
+        inode: 1,
+        name: name_dotdot,
+    };
+    entries.push(dotdot);
+
+    let entry_size = mem::size_of::<DirEntryDisk>();
+    let mut buf = Vec::with_capacity(entries.len() * entry_size);
+
+    for e in &entries {
+        let ptr = e as *const DirEntryDisk as *const u8;
+        let slice = unsafe { std::slice::from_raw_parts(ptr, entry_size) };
+        buf.extend_from_slice(slice);
+    }
+
+    buf
+}
+
+fn write_root_directory_block(
+    entries: &[PathBuf],
+    layout: &FsLayout,
+) -> Result<()> {
+    let data = make_root_dir_block();
+
+    // El bloque de datos del root es layout.data_blocks_start
+    let root_block_index = layout.data_blocks_start as usize;
+
+    if root_block_index >= entries.len() {
+        return Err(anyhow!(
+            "Índice de bloque de datos raíz fuera de rango: {}",
+            root_block_index
+        ));
+    }
+
+    write_block(&entries[root_block_index], &data)
+}
+
+/// Bloque de un directorio: "." (`ino`), ".." (`parent`) y después los hijos en
+/// el orden dado. Falla si algún nombre no cabe o si no entran en un bloque.
+pub fn make_dir_block(ino: u64, parent: u64, children: &[(OsString, u64)]) -> Result<Vec<u8>> {
+    let entry_size = mem::size_of::<DirEntryDisk>();
+    let slots = QRFS_BLOCK_SIZE as usize / entry_size;
+    if children.len() + 2 > slots {
+        return Err(anyhow!(
+            "El directorio {} tiene {} entradas y un bloque admite {}",
+            ino,
+            children.len(),
+            slots - 2
+        ));
+    }
+
+    let mut buf = Vec::with_capacity((children.len() + 2) * entry_size);
+    buf.extend_from_slice(&struct_to_bytes(&dir::pack_dir_entry(ino, OsStr::new("."))));
+    buf.extend_from_slice(&struct_to_bytes(&dir::pack_dir_entry(parent, OsStr::new(".."))));
+
+    for (name, child) in children {
+        if name.is_empty() || name.len() > QRFS_MAX_NAME {
+            return Err(anyhow!(
+                "Nombre inválido en el directorio {}: {:?} (máximo {} bytes)",
+                ino,
+                name,
+                QRFS_MAX_NAME
+            ));
+        }
+        buf.extend_from_slice(&struct_to_bytes(&dir::pack_dir_entry(*child, name)));
+    }
+
+    Ok(buf)
+}
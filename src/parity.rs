@@ -0,0 +1,326 @@
+// Bloques de paridad Reed-Solomon para recuperar páginas perdidas.
+//
+// Con `mkfs.qrfs --parity k:m` (o `add_parity` sobre una imagen existente) cada
+// grupo de `k` bloques consecutivos de la imagen, del superblock al último
+// bloque de datos, tiene `m` bloques de paridad. Van después de los
+// `total_blocks` del FS, grupo por grupo, y son archivos de bloque como los
+// demás: se imprimen y se escanean igual. Si en un grupo se pierden hasta `m`
+// bloques (el archivo falta o no se puede leer, o está marcado como dañado),
+// `BlockStore::read_block` reconstruye su contenido con el resto del grupo.
+//
+// La paridad se mantiene al escribir: `BlockStore` pasa cada escritura por
+// `with_parity`, que recalcula la de los grupos tocados antes de escribir
+// nada. El superblock no se puede reconstruir, porque es el que dice la
+// geometría.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use reed_solomon_erasure::galois_8::ReedSolomon;
+
+use crate::block_store::{
+    block_file_name_with_ext, refresh_manifest, write_manifest, BlockStore, QRFS_DEFAULT_BLOCK_EXT, QRFS_MANIFEST_NAME,
+    QRFS_OVERLAY_MARKER,
+};
+use crate::fs::{load_superblock, write_superblock, SuperblockDisk, QRFS_BLOCK_SIZE, QRFS_FEATURE_PARITY};
+use crate::zip_store::is_zip_image;
+
+/// Máximo de bloques por grupo contando los de paridad (Reed-Solomon sobre
+/// GF(2^8)).
+pub const QRFS_PARITY_MAX_SHARDS: u32 = 256;
+
+/// Geometría de la paridad de una imagen.
+#[derive(Clone, Copy)]
+struct Geometry {
+    /// Bloques del FS por grupo (`k`).
+    group: u32,
+    /// Bloques de paridad por grupo (`m`).
+    per_group: u32,
+    /// Bloques cubiertos: los `total_blocks` del FS.
+    covered: u32,
+}
+
+impl Geometry {
+    fn of(sb: &SuperblockDisk) -> Option<Self> {
+        let enabled = sb.features & QRFS_FEATURE_PARITY != 0 && sb.parity_group != 0 && sb.parity_per_group != 0;
+        enabled.then_some(Self {
+            group: sb.parity_group as u32,
+            per_group: sb.parity_per_group as u32,
+            covered: sb.total_blocks,
+        })
+    }
+
+    fn groups(&self) -> u32 {
+        self.covered.div_ceil(self.group)
+    }
+
+    fn shards(&self) -> usize {
+        (self.group + self.per_group) as usize
+    }
+
+    /// Grupo y posición dentro del grupo de un bloque del FS o de paridad.
+    fn shard_of(&self, block: u32) -> Option<(u32, usize)> {
+        if block < self.covered {
+            return Some((block / self.group, (block % self.group) as usize));
+        }
+        let parity = block - self.covered;
+        (parity < self.groups() * self.per_group)
+            .then(|| (parity / self.per_group, (self.group + parity % self.per_group) as usize))
+    }
+
+    /// Bloque en la posición `shard` del grupo, o `None` para el relleno del
+    /// último grupo (más allá de `total_blocks`), que cuenta como ceros.
+    fn shard_block(&self, group: u32, shard: usize) -> Option<u32> {
+        let shard = shard as u32;
+        if shard < self.group {
+            let block = group * self.group + shard;
+            return (block < self.covered).then_some(block);
+        }
+        Some(self.covered + group * self.per_group + (shard - self.group))
+    }
+
+    fn codec(&self) -> Result<ReedSolomon> {
+        ReedSolomon::new(self.group as usize, self.per_group as usize)
+            .map_err(|e| anyhow::anyhow!("Paridad {}:{} inválida: {:?}", self.group, self.per_group, e))
+    }
+}
+
+/// Superblock y geometría de la imagen, leídos del archivo del bloque 0 sin
+/// pasar por la reconstrucción. `None` si la imagen no tiene paridad.
+fn image_geometry(store: &BlockStore) -> Option<(SuperblockDisk, Geometry)> {
+    let sb = SuperblockDisk::from_block_bytes(&store.read_block_file(0).ok()?).ok()?;
+    if sb.check_magic().is_err() || !sb.verify_checksum() {
+        return None;
+    }
+    Some((sb, Geometry::of(&sb)?))
+}
+
+/// Contenido de un bloque para la paridad: `None` si no se puede leer o está
+/// marcado como dañado.
+fn read_shard(store: &BlockStore, sb: &SuperblockDisk, block: u32) -> Option<Vec<u8>> {
+    if sb.is_bad_block(block) {
+        return None;
+    }
+    store.read_block_file(block).ok()
+}
+
+/// Reconstruye el bloque `block` con el resto de su grupo, sin leer su archivo.
+/// `None` si la imagen no tiene paridad o el bloque no está cubierto.
+pub(crate) fn reconstruct(store: &BlockStore, block: u32) -> Result<Option<Vec<u8>>> {
+    let Some((sb, geometry)) = image_geometry(store) else {
+        return Ok(None);
+    };
+    let Some((group, target)) = geometry.shard_of(block) else {
+        return Ok(None);
+    };
+
+    let mut shards: Vec<Option<Vec<u8>>> = (0..geometry.shards())
+        .map(|shard| match geometry.shard_block(group, shard) {
+            None => Some(vec![0u8; QRFS_BLOCK_SIZE as usize]),
+            Some(b) if b == block => None,
+            Some(b) => read_shard(store, &sb, b),
+        })
+        .collect();
+
+    geometry.codec()?.reconstruct(&mut shards).map_err(|e| {
+        anyhow::anyhow!(
+            "El grupo {} no tiene bloques suficientes para reconstruir el bloque {} ({:?})",
+            group,
+            block,
+            e
+        )
+    })?;
+    Ok(shards[target].take())
+}
+
+/// Los bloques a escribir más los de paridad de cada grupo que tocan, ya
+/// recalculados. Sin paridad devuelve `blocks` tal cual. Si un grupo tiene
+/// bloques perdidos, primero se reconstruyen con la paridad vieja; si no
+/// alcanza, falla sin que se haya escrito nada.
+pub(crate) fn with_parity(store: &BlockStore, mut blocks: BTreeMap<u32, Vec<u8>>) -> Result<BTreeMap<u32, Vec<u8>>> {
+    // Si se escribe el superblock, la geometría sale del nuevo
+    let image = match blocks.get(&0) {
+        Some(data) => SuperblockDisk::from_block_bytes(data)
+            .ok()
+            .and_then(|sb| Some((sb, Geometry::of(&sb)?))),
+        None => image_geometry(store),
+    };
+    let Some((sb, geometry)) = image else {
+        return Ok(blocks);
+    };
+
+    let groups: BTreeSet<u32> = blocks
+        .keys()
+        .filter(|&&b| b < geometry.covered)
+        .map(|&b| b / geometry.group)
+        .collect();
+    if groups.is_empty() {
+        return Ok(blocks);
+    }
+    let codec = geometry.codec()?;
+    let data_shards = geometry.group as usize;
+
+    for group in groups {
+        // Los que se van a escribir no hace falta leerlos salvo para reconstruir
+        let mut shards: Vec<Option<Vec<u8>>> = vec![None; geometry.shards()];
+        let mut lost = false;
+        for (shard, slot) in shards.iter_mut().enumerate().take(data_shards) {
+            match geometry.shard_block(group, shard) {
+                None => *slot = Some(vec![0u8; QRFS_BLOCK_SIZE as usize]),
+                Some(b) if blocks.contains_key(&b) => {}
+                Some(b) => {
+                    *slot = read_shard(store, &sb, b);
+                    lost |= slot.is_none();
+                }
+            }
+        }
+
+        if lost {
+            for (shard, slot) in shards.iter_mut().enumerate() {
+                if slot.is_none() {
+                    *slot = geometry.shard_block(group, shard).and_then(|b| read_shard(store, &sb, b));
+                }
+            }
+            codec.reconstruct_data(&mut shards).map_err(|e| {
+                anyhow::anyhow!(
+                    "No se puede actualizar la paridad del grupo {}: tiene más de {} bloques perdidos ({:?})",
+                    group,
+                    geometry.per_group,
+                    e
+                )
+            })?;
+        }
+
+        let mut full: Vec<Vec<u8>> = Vec::with_capacity(geometry.shards());
+        for (shard, slot) in shards.into_iter().enumerate() {
+            let written = if shard < data_shards {
+                geometry.shard_block(group, shard).and_then(|b| blocks.get(&b))
+            } else {
+                None
+            };
+            full.push(match (written, slot) {
+                (Some(data), _) => data.clone(),
+                (None, Some(data)) => data,
+                (None, None) => vec![0u8; QRFS_BLOCK_SIZE as usize],
+            });
+        }
+        codec
+            .encode(&mut full)
+            .map_err(|e| anyhow::anyhow!("No se pudo calcular la paridad del grupo {} ({:?})", group, e))?;
+
+        for (shard, data) in full.into_iter().enumerate().skip(data_shards) {
+            if let Some(b) = geometry.shard_block(group, shard) {
+                blocks.insert(b, data);
+            }
+        }
+    }
+    Ok(blocks)
+}
+
+/// Agrega paridad `group:per_group` a la imagen de `qr_folder`: crea los
+/// archivos de paridad después del último bloque, los anota en el manifiesto
+/// si la carpeta tiene uno y los calcula. Devuelve cuántos bloques agregó. La
+/// imagen no debe estar montada.
+pub fn add_parity(qr_folder: &Path, group: u16, per_group: u16) -> Result<u32> {
+    if is_zip_image(qr_folder) || qr_folder.join(QRFS_OVERLAY_MARKER).is_file() {
+        return Err(anyhow::anyhow!(
+            "Sólo se puede agregar paridad a una carpeta de bloques (no a un zip ni a un overlay)"
+        ));
+    }
+
+    let mut superblock = load_superblock(qr_folder)?;
+    if superblock.parity_group != 0 {
+        return Err(anyhow::anyhow!(
+            "La imagen ya tiene paridad {}:{}",
+            superblock.parity_group,
+            superblock.parity_per_group
+        ));
+    }
+    if group == 0 || per_group == 0 || group as u32 + per_group as u32 > QRFS_PARITY_MAX_SHARDS {
+        return Err(anyhow::anyhow!(
+            "Paridad inválida {}:{} (los dos mayores que 0 y a lo sumo {} en total)",
+            group,
+            per_group,
+            QRFS_PARITY_MAX_SHARDS
+        ));
+    }
+
+    let store = BlockStore::open(qr_folder)?;
+    if store.len() != superblock.total_blocks as usize {
+        return Err(anyhow::anyhow!(
+            "La carpeta {:?} tiene {} archivos de bloque pero el superblock indica {} bloques",
+            qr_folder,
+            store.len(),
+            superblock.total_blocks
+        ));
+    }
+    let mut entries = (0..store.len() as u32)
+        .map(|b| store.block_path(b))
+        .collect::<Result<Vec<_>>>()?;
+    let ext = entries[0]
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or(QRFS_DEFAULT_BLOCK_EXT)
+        .to_string();
+
+    superblock.parity_group = group;
+    superblock.parity_per_group = per_group;
+    superblock.features |= QRFS_FEATURE_PARITY;
+    let added = superblock.parity_blocks();
+
+    // Primero los archivos en cero, así la carpeta ya tiene todos los bloques
+    // cuando el superblock nuevo dice que los tiene
+    for index in superblock.total_blocks..superblock.image_blocks() {
+        let path = qr_folder.join(block_file_name_with_ext(index, &ext));
+        if path.exists() {
+            return Err(anyhow::anyhow!("Ya existe {:?}: no se puede crear el bloque de paridad {}", path, index));
+        }
+        fs::write(&path, vec![0u8; QRFS_BLOCK_SIZE as usize])
+            .with_context(|| format!("No se pudo crear el bloque de paridad {:?}", path))?;
+        entries.push(path);
+    }
+    if qr_folder.join(QRFS_MANIFEST_NAME).is_file() {
+        write_manifest(qr_folder, &entries)?;
+    }
+
+    write_superblock(qr_folder, &superblock)?;
+    rebuild_parity(qr_folder)?;
+    refresh_manifest(qr_folder)?;
+    Ok(added)
+}
+
+/// Recalcula todos los bloques de paridad a partir del contenido actual. Sirve
+/// después de modificar archivos de bloque por fuera de QRFS. Devuelve cuántos
+/// grupos calculó. Falla si algún bloque del FS no se puede leer: no hay con
+/// qué calcular su grupo.
+pub fn rebuild_parity(qr_folder: &Path) -> Result<u32> {
+    let store = BlockStore::open(qr_folder)?;
+    let Some((sb, geometry)) = image_geometry(&store) else {
+        return Err(anyhow::anyhow!("La imagen de {:?} no tiene paridad", qr_folder));
+    };
+    let codec = geometry.codec()?;
+
+    for group in 0..geometry.groups() {
+        let mut full = Vec::with_capacity(geometry.shards());
+        for shard in 0..geometry.shards() {
+            let data = match geometry.shard_block(group, shard) {
+                Some(b) if shard < geometry.group as usize => read_shard(&store, &sb, b)
+                    .with_context(|| format!("No se puede calcular la paridad: el bloque {} no se puede leer", b))?,
+                _ => vec![0u8; QRFS_BLOCK_SIZE as usize],
+            };
+            full.push(data);
+        }
+        codec
+            .encode(&mut full)
+            .map_err(|e| anyhow::anyhow!("No se pudo calcular la paridad del grupo {} ({:?})", group, e))?;
+
+        for (shard, data) in full.iter().enumerate().skip(geometry.group as usize) {
+            if let Some(b) = geometry.shard_block(group, shard) {
+                store.write_block(b, data)?;
+            }
+        }
+    }
+    Ok(geometry.groups())
+}
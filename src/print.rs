@@ -0,0 +1,285 @@
+// Hojas imprimibles con los bloques de la imagen como códigos QR.
+//
+// `print_pdf` arma un PDF A4 con un QR por bloque, `per_page` por hoja y en
+// orden de bloque, cada uno con su número y su archivo debajo. Los archivos de
+// bloque todavía guardan los bytes sin codificar, así que el QR se genera acá
+// a partir del contenido. Cada QR lleva delante del bloque una cabecera con el
+// magic de QRFS y el número de bloque (`block_payload`): al escanear las hojas
+// el orden sale del propio código, no de la posición en la hoja ni de la
+// etiqueta impresa.
+//
+// El PDF se escribe a mano (catálogo, páginas y un stream de contenido por
+// hoja comprimido con deflate). Los QR son rectángulos vectoriales, sin
+// imágenes, y no hay fechas en el archivo: la misma imagen da siempre el mismo
+// PDF.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use qrcode::{Color, EcLevel, QrCode};
+
+use crate::block_store::BlockStore;
+use crate::fs::QRFS_MAGIC;
+
+/// QRs por hoja si no se indica otra cosa (2 columnas x 3 filas).
+pub const QRFS_PRINT_PER_PAGE_DEFAULT: usize = 6;
+
+/// Hoja A4 en puntos.
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 36.0;
+/// Alto reservado para el título de la hoja y para la etiqueta de cada QR.
+const TITLE_HEIGHT: f32 = 24.0;
+const LABEL_HEIGHT: f32 = 16.0;
+/// Módulos en blanco alrededor de cada QR (el mínimo que pide el estándar).
+const QUIET_ZONE: usize = 4;
+
+/// Resultado de `print_pdf`.
+#[derive(Debug, Clone, Copy)]
+pub struct PrintReport {
+    pub blocks: usize,
+    pub pages: usize,
+}
+
+/// Contenido de un QR: el magic de QRFS y el número de bloque (u32 little
+/// endian cada uno) seguidos de los bytes del bloque.
+pub fn block_payload(block_index: u32, data: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(8 + data.len());
+    payload.extend_from_slice(&QRFS_MAGIC.to_le_bytes());
+    payload.extend_from_slice(&block_index.to_le_bytes());
+    payload.extend_from_slice(data);
+    payload
+}
+
+/// Inversa de `block_payload`: el número de bloque y sus bytes, o `None` si el
+/// contenido no empieza con la cabecera de QRFS.
+pub fn parse_block_payload(payload: &[u8]) -> Option<(u32, &[u8])> {
+    let (magic, rest) = payload.split_first_chunk::<4>()?;
+    let (index, data) = rest.split_first_chunk::<4>()?;
+    (u32::from_le_bytes(*magic) == QRFS_MAGIC).then(|| (u32::from_le_bytes(*index), data))
+}
+
+/// Escribe en `out` el PDF con todos los bloques de la imagen de `qr_folder`
+/// (carpeta, overlay o zip), `per_page` QRs por hoja. La hoja `k` tiene los
+/// bloques `k * per_page` a `(k + 1) * per_page - 1`.
+pub fn print_pdf(qr_folder: &Path, out: &Path, per_page: usize) -> Result<PrintReport> {
+    if per_page == 0 {
+        return Err(anyhow::anyhow!("Tiene que haber al menos un QR por hoja"));
+    }
+
+    let store = BlockStore::open(qr_folder)?;
+    if store.is_empty() {
+        return Err(anyhow::anyhow!("La carpeta {:?} no contiene bloques QRFS", qr_folder));
+    }
+
+    let blocks = store.len();
+    let pages = blocks.div_ceil(per_page);
+    let source = pdf_text(&qr_folder.display().to_string());
+
+    let mut contents = Vec::with_capacity(pages);
+    for page in 0..pages {
+        let first = page * per_page;
+        let last = (first + per_page).min(blocks) - 1;
+
+        let mut content = String::new();
+        let title = format!("QRFS {}  -  hoja {} de {}  -  bloques {} a {}", source, page + 1, pages, first, last);
+        text(&mut content, 12.0, MARGIN, PAGE_HEIGHT - MARGIN - 12.0, &title);
+
+        for (slot, block) in (first..=last).enumerate() {
+            let block = block as u32;
+            let data = store.read_block(block)?;
+            let code = QrCode::with_error_correction_level(block_payload(block, &data), EcLevel::M)
+                .map_err(|e| anyhow::anyhow!("No se pudo codificar el bloque {} como QR: {}", block, e))?;
+
+            let file = store
+                .block_path(block)?
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            draw_cell(&mut content, per_page, slot, &code, &format!("bloque {}  ({})", block, pdf_text(&file)));
+        }
+        contents.push(content);
+    }
+
+    std::fs::write(out, build_pdf(&contents)).with_context(|| format!("No se pudo escribir {:?}", out))?;
+    Ok(PrintReport { blocks, pages })
+}
+
+/// Columnas y filas de la grilla: tan cuadrada como se pueda, con más filas
+/// que columnas porque la hoja es vertical.
+fn grid(per_page: usize) -> (usize, usize) {
+    let cols = ((per_page as f32).sqrt().floor() as usize).max(1);
+    (cols, per_page.div_ceil(cols))
+}
+
+/// Dibuja el QR de la posición `slot` de la hoja con su etiqueta debajo.
+fn draw_cell(content: &mut String, per_page: usize, slot: usize, code: &QrCode, label: &str) {
+    let (cols, rows) = grid(per_page);
+    let cell_width = (PAGE_WIDTH - 2.0 * MARGIN) / cols as f32;
+    let cell_height = (PAGE_HEIGHT - 2.0 * MARGIN - TITLE_HEIGHT) / rows as f32;
+    let left = MARGIN + (slot % cols) as f32 * cell_width;
+    let top = PAGE_HEIGHT - MARGIN - TITLE_HEIGHT - (slot / cols) as f32 * cell_height;
+
+    let side = cell_width.min(cell_height - LABEL_HEIGHT);
+    let width = code.width();
+    let module = side / (width + 2 * QUIET_ZONE) as f32;
+    let origin_x = left + (cell_width - side) / 2.0 + QUIET_ZONE as f32 * module;
+    let origin_y = top - QUIET_ZONE as f32 * module;
+
+    // Un rectángulo por cada tramo de módulos oscuros seguidos de una fila
+    let colors = code.to_colors();
+    for (row, line) in colors.chunks(width).enumerate() {
+        let y = origin_y - (row + 1) as f32 * module;
+        let mut col = 0;
+        while col < width {
+            if line[col] != Color::Dark {
+                col += 1;
+                continue;
+            }
+            let start = col;
+            while col < width && line[col] == Color::Dark {
+                col += 1;
+            }
+            let _ = writeln!(
+                content,
+                "{:.2} {:.2} {:.2} {:.2} re",
+                origin_x + start as f32 * module,
+                y,
+                (col - start) as f32 * module,
+                module
+            );
+        }
+    }
+    content.push_str("f\n");
+
+    text(content, 10.0, left + (cell_width - side) / 2.0, top - side - LABEL_HEIGHT + 4.0, label);
+}
+
+fn text(content: &mut String, size: f32, x: f32, y: f32, text: &str) {
+    let _ = writeln!(content, "BT /F1 {} Tf {:.2} {:.2} Td ({}) Tj ET", size, x, y, text);
+}
+
+/// Texto para un string de PDF: sin los caracteres especiales de la sintaxis
+/// y sólo ASCII (la fuente estándar no tiene el resto).
+fn pdf_text(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '(' | ')' | '\\' => format!("\\{}", c),
+            c if c.is_ascii() && !c.is_ascii_control() => c.to_string(),
+            _ => "?".to_string(),
+        })
+        .collect()
+}
+
+/// Arma el archivo: catálogo (1), árbol de páginas (2), fuente (3) y un par
+/// página / contenido por hoja, con la tabla xref al final.
+fn build_pdf(contents: &[String]) -> Vec<u8> {
+    let mut objects: Vec<Vec<u8>> = Vec::new();
+
+    objects.push(b"<< /Type /Catalog /Pages 2 0 R >>".to_vec());
+    let kids: Vec<String> = (0..contents.len()).map(|k| format!("{} 0 R", 4 + 2 * k)).collect();
+    objects.push(format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), contents.len()).into_bytes());
+    objects.push(b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_vec());
+
+    for (k, content) in contents.iter().enumerate() {
+        objects.push(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+                PAGE_WIDTH,
+                PAGE_HEIGHT,
+                5 + 2 * k
+            )
+            .into_bytes(),
+        );
+
+        let stream = compress_to_vec_zlib(content.as_bytes(), 6);
+        let mut object = format!("<< /Length {} /Filter /FlateDecode >>\nstream\n", stream.len()).into_bytes();
+        object.extend_from_slice(&stream);
+        object.extend_from_slice(b"\nendstream");
+        objects.push(object);
+    }
+
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+        pdf.extend_from_slice(object);
+        pdf.extend_from_slice(b"\nendobj\n");
+    }
+
+    let xref = pdf.len();
+    let mut tail = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        let _ = writeln!(tail, "{:010} 00000 n ", offset);
+    }
+    let _ = write!(
+        tail,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref
+    );
+    pdf.extend_from_slice(tail.as_bytes());
+    pdf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::fresh_image;
+    use miniz_oxide::inflate::decompress_to_vec_zlib;
+
+    /// Contenido de cada hoja del PDF, descomprimido.
+    fn page_contents(pdf: &[u8]) -> Vec<String> {
+        let mut pages = Vec::new();
+        let mut rest = pdf;
+        while let Some(start) = rest.windows(7).position(|w| w == b"stream\n") {
+            let body = &rest[start + 7..];
+            let end = body.windows(10).position(|w| w == b"\nendstream").unwrap();
+            pages.push(String::from_utf8(decompress_to_vec_zlib(&body[..end]).unwrap()).unwrap());
+            rest = &body[end + 10..];
+        }
+        pages
+    }
+
+    #[test]
+    fn tiny_image_gives_one_label_per_block_in_order() {
+        let dir = fresh_image(20);
+        let out = tempfile::tempdir().unwrap();
+        let pdf_path = out.path().join("hojas.pdf");
+
+        let report = print_pdf(dir.path(), &pdf_path, 6).unwrap();
+        assert_eq!((report.blocks, report.pages), (20, 4));
+
+        let pdf = std::fs::read(&pdf_path).unwrap();
+        assert!(String::from_utf8_lossy(&pdf).contains("/Count 4"));
+        let pages = page_contents(&pdf);
+        assert_eq!(pages.len(), 4);
+
+        // Cada bloque aparece una sola vez, en su hoja y en orden
+        for (page, content) in pages.iter().enumerate() {
+            let labels: Vec<u32> = content
+                .split("(bloque ")
+                .skip(1)
+                .map(|rest| rest.split(' ').next().unwrap().parse().unwrap())
+                .collect();
+            let first = page as u32 * 6;
+            assert_eq!(labels, (first..(first + 6).min(20)).collect::<Vec<_>>());
+        }
+
+        // La misma imagen da el mismo archivo
+        let again = out.path().join("otra.pdf");
+        print_pdf(dir.path(), &again, 6).unwrap();
+        assert_eq!(std::fs::read(&again).unwrap(), pdf);
+    }
+
+    #[test]
+    fn block_payload_round_trips() {
+        let payload = block_payload(17, b"datos");
+        assert_eq!(parse_block_payload(&payload), Some((17, &b"datos"[..])));
+        assert_eq!(parse_block_payload(b"otra cosa"), None);
+        assert_eq!(parse_block_payload(&payload[..6]), None);
+    }
+}
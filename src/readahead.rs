@@ -0,0 +1,280 @@
+// Lectura anticipada de bloques para lecturas secuenciales.
+//
+// Leer un bloque implica abrir (y, cuando exista la codificación QR, decodificar)
+// un archivo entero, así que cuando un proceso recorre un archivo en orden
+// conviene tener listos los bloques siguientes antes de que los pida. Cada
+// lectura informa dónde terminó; si la siguiente del mismo handle empieza ahí,
+// se encargan los próximos bloques a un único hilo que los carga en un caché
+// chico. Los pedidos esperan en una cola acotada: si está llena, el pedido se
+// descarta (la lectura real lee el bloque igual), así una ráfaga de lecturas no
+// acumula trabajo ni hilos.
+//
+// Está desactivada por defecto: hoy leer un bloque es leer un archivo de 1 KB y
+// anticiparlo no gana nada.
+//
+// Una copia cacheada sólo se usa si no se escribió ningún bloque desde que se
+// cargó (ver `block_store::write_generation`): así ninguna escritura tiene que
+// avisarle al caché, venga del camino que venga.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::thread;
+
+use crate::block_store::{write_generation, BlockStore};
+use crate::fs::{logical_to_physical, InodeDisk, SuperblockDisk};
+
+/// Bloques que se anticipan por defecto (0 desactiva la lectura anticipada).
+pub const QRFS_READ_AHEAD_DEFAULT: usize = 0;
+
+/// Pedidos de lectura anticipada que pueden esperar al hilo a la vez.
+const QUEUE_CAPACITY: usize = 4;
+
+/// Máximo de bloques guardados en el caché de lectura anticipada; al llenarse
+/// se vacía entero (lo que importa son los próximos bloques, no los viejos).
+const CACHE_CAPACITY: usize = 256;
+
+struct CachedBlock {
+    /// `write_generation()` de antes de leerlo.
+    generation: u64,
+    data: Vec<u8>,
+}
+
+#[derive(Default)]
+struct Cache {
+    blocks: HashMap<u32, CachedBlock>,
+    /// Bloques que algún hilo está cargando en este momento.
+    loading: HashSet<u32>,
+}
+
+/// Bloques lógicos `first..first + window` del inodo a cargar.
+struct Prefetch {
+    qr_folder: PathBuf,
+    superblock: SuperblockDisk,
+    inode_disk: InodeDisk,
+    first: u64,
+}
+
+pub struct ReadAhead {
+    /// Bloques a anticipar tras una lectura secuencial.
+    window: usize,
+    cache: Mutex<Cache>,
+    /// (inodo, handle) -> offset donde terminó su última lectura.
+    streams: Mutex<HashMap<(u64, u64), u64>>,
+    /// Cola del hilo que carga los bloques; se crea con el primer pedido. El
+    /// hilo termina cuando cae el `ReadAhead` (y con él esta punta de la cola).
+    queue: OnceLock<SyncSender<Prefetch>>,
+}
+
+impl ReadAhead {
+    pub fn new(window: usize) -> Self {
+        Self {
+            window,
+            cache: Mutex::new(Cache::default()),
+            streams: Mutex::new(HashMap::new()),
+            queue: OnceLock::new(),
+        }
+    }
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Registra una lectura de `offset..end` y dice si continúa la anterior del
+    /// mismo handle (acceso secuencial).
+    pub fn record(&self, ino: u64, fh: u64, offset: u64, end: u64) -> bool {
+        let mut streams = self.streams.lock().unwrap();
+        let previous = streams.insert((ino, fh), end);
+        previous == Some(offset) && offset > 0
+    }
+
+    /// El handle se cerró: se olvida su posición.
+    pub fn forget(&self, ino: u64, fh: u64) {
+        self.streams.lock().unwrap().remove(&(ino, fh));
+    }
+
+    /// Copia cacheada del bloque si sigue valiendo (no hubo escrituras desde
+    /// que se cargó). Las copias viejas se descartan.
+    pub fn get(&self, block: u32) -> Option<Vec<u8>> {
+        let mut cache = self.cache.lock().unwrap();
+        let generation = write_generation();
+        cache.blocks.retain(|_, c| c.generation == generation);
+        cache.blocks.get(&block).map(|c| c.data.clone())
+    }
+
+    /// Encarga al hilo de lectura anticipada los bloques lógicos
+    /// `first..first + window` del inodo (los que existan y no estén ya en el
+    /// caché). Si la cola está llena el pedido se pierde.
+    pub fn prefetch(
+        self: &Arc<Self>,
+        qr_folder: &Path,
+        superblock: SuperblockDisk,
+        inode_disk: InodeDisk,
+        first: u64,
+    ) {
+        if self.window == 0 {
+            return;
+        }
+
+        let queue = self.queue.get_or_init(|| spawn_worker(Arc::downgrade(self)));
+        let job = Prefetch {
+            qr_folder: qr_folder.to_path_buf(),
+            superblock,
+            inode_disk,
+            first,
+        };
+        let _ = queue.try_send(job);
+    }
+
+    fn run(&self, job: Prefetch) {
+        let Ok(store) = BlockStore::open(&job.qr_folder) else {
+            return;
+        };
+        let last = job.first.saturating_add(self.window as u64);
+        let blocks_in_file = job.inode_disk.size.div_ceil(job.superblock.block_size as u64);
+
+        for logical in job.first..last.min(blocks_in_file) {
+            let Ok(Some(block)) = logical_to_physical(&job.qr_folder, &job.superblock, &job.inode_disk, logical)
+            else {
+                continue; // hueco o indirecto ilegible: la lectura real lo resuelve
+            };
+            if !self.claim(block) {
+                continue;
+            }
+            self.load(&store, block);
+        }
+    }
+
+    /// Marca el bloque como "en carga" si nadie lo tiene ni lo está cargando.
+    fn claim(&self, block: u32) -> bool {
+        let mut cache = self.cache.lock().unwrap();
+        !cache.blocks.contains_key(&block) && cache.loading.insert(block)
+    }
+
+    /// Lee el bloque y lo guarda. El contador se toma antes de leer: si hay una
+    /// escritura en el medio, la copia queda vieja y no se usa.
+    fn load(&self, store: &BlockStore, block: u32) {
+        let generation = write_generation();
+        let loaded = store.read_block(block).ok().map(|data| CachedBlock { generation, data });
+
+        let mut cache = self.cache.lock().unwrap();
+        cache.loading.remove(&block);
+        let Some(entry) = loaded else {
+            return;
+        };
+        if cache.blocks.len() >= CACHE_CAPACITY {
+            cache.blocks.clear();
+        }
+        cache.blocks.insert(block, entry);
+    }
+}
+
+/// Arranca el hilo que atiende los pedidos de `ahead` y devuelve la punta de
+/// la cola para encargarle trabajo. Guarda sólo una referencia débil: no
+/// mantiene vivo al `ReadAhead`.
+fn spawn_worker(ahead: Weak<ReadAhead>) -> SyncSender<Prefetch> {
+    let (tx, rx) = mpsc::sync_channel(QUEUE_CAPACITY);
+    thread::spawn(move || {
+        for job in rx {
+            let Some(ahead) = ahead.upgrade() else {
+                break;
+            };
+            ahead.run(job);
+        }
+    });
+    tx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::{load_inode_disk, load_superblock};
+    use crate::image::QrfsImage;
+    use crate::test_util::{fresh_image, pattern};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn record_detects_sequential_reads_per_handle() {
+        let ahead = ReadAhead::new(4);
+        assert!(!ahead.record(7, 1, 0, 1024));
+        assert!(ahead.record(7, 1, 1024, 2048));
+        // Otro handle del mismo archivo lleva su propia posición
+        assert!(!ahead.record(7, 2, 2048, 3072));
+        // Un salto corta la secuencia
+        assert!(!ahead.record(7, 1, 4096, 5120));
+        assert!(ahead.record(7, 1, 5120, 6144));
+
+        ahead.forget(7, 1);
+        assert!(!ahead.record(7, 1, 6144, 7168));
+    }
+
+    #[test]
+    fn prefetched_blocks_are_dropped_after_a_write() {
+        let dir = fresh_image(64);
+        let path = std::path::Path::new("/datos.bin");
+        let ino = {
+            let image = QrfsImage::open(dir.path()).unwrap();
+            let ino = image.create_file(path).unwrap();
+            image.write_file(path, &pattern(5 * 1024)).unwrap();
+            ino
+        };
+        let sb = load_superblock(dir.path()).unwrap();
+        let inode_disk = load_inode_disk(dir.path(), &sb, ino).unwrap();
+        let block = logical_to_physical(dir.path(), &sb, &inode_disk, 2).unwrap().unwrap();
+        let on_disk = BlockStore::open(dir.path()).unwrap().read_block(block).unwrap();
+
+        // El contador de escrituras es de todo el proceso: si otra prueba escribe
+        // mientras tanto la copia se descarta y se vuelve a pedir
+        let ahead = Arc::new(ReadAhead::new(4));
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let cached = loop {
+            ahead.prefetch(dir.path(), sb, inode_disk, 1);
+            thread::sleep(Duration::from_millis(20));
+            if let Some(data) = ahead.get(block) {
+                break data;
+            }
+            assert!(Instant::now() < deadline, "el bloque {} nunca llegó al caché", block);
+        };
+        assert_eq!(cached, on_disk);
+
+        BlockStore::open(dir.path()).unwrap().write_block(block, &on_disk).unwrap();
+        assert!(ahead.get(block).is_none());
+    }
+
+    #[test]
+    fn a_burst_of_requests_shares_one_bounded_worker() {
+        let dir = fresh_image(64);
+        let path = std::path::Path::new("/datos.bin");
+        let ino = {
+            let image = QrfsImage::open(dir.path()).unwrap();
+            let ino = image.create_file(path).unwrap();
+            image.write_file(path, &pattern(5 * 1024)).unwrap();
+            ino
+        };
+        let sb = load_superblock(dir.path()).unwrap();
+        let inode_disk = load_inode_disk(dir.path(), &sb, ino).unwrap();
+
+        // Desactivada (lo de por defecto): ni siquiera se arranca el hilo
+        let off = Arc::new(ReadAhead::new(QRFS_READ_AHEAD_DEFAULT));
+        off.prefetch(dir.path(), sb, inode_disk, 1);
+        assert!(off.queue.get().is_none());
+
+        // Con la cola llena los pedidos se descartan: encargar nunca espera
+        let ahead = Arc::new(ReadAhead::new(4));
+        let started = Instant::now();
+        for _ in 0..10_000 {
+            ahead.prefetch(dir.path(), sb, inode_disk, 1);
+        }
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // El hilo no mantiene vivo al ReadAhead
+        let weak = Arc::downgrade(&ahead);
+        drop(ahead);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while weak.upgrade().is_some() {
+            assert!(Instant::now() < deadline, "el hilo de lectura anticipada retiene el caché");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
@@ -0,0 +1,242 @@
+// Recuperación profunda: reconstruye la tabla de inodos cuando sus bloques se
+// dañaron pero los bloques de directorio y de datos siguen intactos.
+//
+// Cada bloque de directorio empieza con "." (que apunta al propio directorio) y
+// ".." (que apunta al padre), así que alcanza con recorrer el área de datos para
+// saber qué bloque pertenece a qué directorio y qué nombres cuelgan de cada uno.
+// Lo que no se puede deducir es qué bloques de datos son de qué archivo: esos
+// archivos se recrean vacíos y sus bloques quedan reservados y reportados.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::mem;
+use std::path::Path;
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Result};
+
+use crate::block_store::BlockStore;
+use crate::dir;
+use crate::fs::{self, DirEntryDisk, InodeDisk, SuperblockDisk, QRFS_NAME_LEN};
+
+/// Resultado de `rebuild_inode_table`.
+#[derive(Debug, Default)]
+pub struct RecoveryReport {
+    /// Directorios recuperados con su bloque (confianza alta: "." los identifica).
+    pub directories: Vec<(u64, u32)>,
+    /// Archivos de los que sólo se conoce el nombre: se recrean vacíos (confianza baja).
+    pub files: Vec<(u64, String)>,
+    /// Bloques en uso que no se pudieron asignar a ningún archivo. Siguen marcados
+    /// como usados para que nada los sobrescriba.
+    pub unplaced_blocks: Vec<u32>,
+    /// Inconsistencias encontradas (bloques duplicados, padres que no coinciden, ...).
+    pub warnings: Vec<String>,
+}
+
+/// Bloque de directorio encontrado en el área de datos.
+struct FoundDir {
+    block: u32,
+    parent: u64,
+    used_slots: usize,
+    children: Vec<(OsString, u64)>,
+}
+
+/// Reescribe la tabla de inodos a partir de los bloques de directorio que
+/// sobrevivieron. No debe correrse con el FS montado.
+pub fn rebuild_inode_table(qr_folder: &Path) -> Result<RecoveryReport> {
+    let superblock = fs::load_superblock(qr_folder)?;
+    let bitmap = fs::load_bitmap(qr_folder, &superblock)?;
+    let mut report = RecoveryReport::default();
+
+    // 1. Buscar bloques de directorio entre los bloques de datos en uso
+    let mut dirs: BTreeMap<u64, FoundDir> = BTreeMap::new();
+    let mut data_blocks = Vec::new();
+
+    for block in superblock.data_blocks_start..superblock.total_blocks {
+        if !fs::bitmap_test(&bitmap, block) {
+            continue;
+        }
+
+        let buf = fs::read_fs_block(qr_folder, block)?;
+        let Some((self_ino, found)) = parse_dir_block(&buf, block) else {
+            data_blocks.push(block);
+            continue;
+        };
+
+        if self_ino > superblock.max_inodes as u64 {
+            report.warnings.push(format!(
+                "El bloque {} parece el directorio {} pero excede max_inodes ({})",
+                block, self_ino, superblock.max_inodes
+            ));
+            data_blocks.push(block);
+        } else if let Some(previous) = dirs.get(&self_ino) {
+            report.warnings.push(format!(
+                "Los bloques {} y {} dicen ser el directorio {}: se usa el primero",
+                previous.block, block, self_ino
+            ));
+            data_blocks.push(block);
+        } else {
+            dirs.insert(self_ino, found);
+        }
+    }
+
+    let root_ino = superblock.root_inode as u64;
+    if !dirs.contains_key(&root_ino) {
+        return Err(anyhow!(
+            "No se encontró el bloque del directorio raíz (inodo {}): no se puede reconstruir el árbol",
+            root_ino
+        ));
+    }
+
+    // 2. Hijos que no tienen bloque de directorio propio son archivos
+    let mut files: BTreeMap<u64, String> = BTreeMap::new();
+    for (&dir_ino, found) in &dirs {
+        for (name, child) in &found.children {
+            if let Some(child_dir) = dirs.get(child) {
+                if child_dir.parent != dir_ino {
+                    report.warnings.push(format!(
+                        "El directorio {} aparece en {} pero su \"..\" apunta a {}",
+                        child, dir_ino, child_dir.parent
+                    ));
+                }
+            } else if *child > superblock.max_inodes as u64 {
+                report.warnings.push(format!(
+                    "{:?} en el directorio {} apunta al inodo {} (fuera de rango): se descarta",
+                    name, dir_ino, child
+                ));
+            } else {
+                files.entry(*child).or_insert_with(|| name.to_string_lossy().into_owned());
+            }
+        }
+    }
+
+    // 3. Sintetizar la tabla de inodos con permisos conservadores
+    let now = SystemTime::now();
+    let inode_size = mem::size_of::<InodeDisk>();
+    let mut table = vec![0u8; superblock.inode_table_blocks as usize * superblock.block_size as usize];
+
+    for (&ino, found) in &dirs {
+        let mut direct_blocks = [0u32; 12];
+        direct_blocks[0] = found.block;
+
+        let inode = synthesized_inode(ino, 2, 0o700, 2, now);
+        let inode = InodeDisk {
+            size: (found.used_slots * mem::size_of::<DirEntryDisk>()) as u64,
+            direct_blocks,
+            ..inode
+        };
+        put_inode(&mut table, inode_size, ino, &inode);
+        report.directories.push((ino, found.block));
+    }
+
+    for (&ino, name) in &files {
+        let inode = synthesized_inode(ino, 1, 0o600, 1, now);
+        put_inode(&mut table, inode_size, ino, &inode);
+        report.files.push((ino, name.clone()));
+    }
+
+    report.unplaced_blocks = data_blocks;
+
+    // 4. Escribir la tabla nueva y corregir el contador de inodos libres
+    BlockStore::open(qr_folder)?.write_blocks(superblock.inode_table_start, &table)?;
+
+    let used = (dirs.len() + files.len()) as u32;
+    let new_sb = SuperblockDisk {
+        free_inodes: superblock.max_inodes.saturating_sub(used),
+        ..superblock
+    };
+    fs::write_superblock(qr_folder, &new_sb)?;
+
+    Ok(report)
+}
+
+/// Interpreta un bloque como directorio: los dos primeros slots deben ser "." y
+/// ".." y todos los nombres deben ser válidos. Devuelve el inodo del propio
+/// directorio (el de ".") y su contenido.
+fn parse_dir_block(buf: &[u8], block: u32) -> Option<(u64, FoundDir)> {
+    let entry_size = mem::size_of::<DirEntryDisk>();
+    let slots = buf.len() / entry_size;
+
+    let dot = fs::read_dir_slot(buf, 0);
+    let dotdot = fs::read_dir_slot(buf, 1);
+    if dot.inode == 0 || dotdot.inode == 0 {
+        return None;
+    }
+    if dir::entry_name(&dot) != "." || dir::entry_name(&dotdot) != ".." {
+        return None;
+    }
+
+    let mut used_slots = 2;
+    let mut children = Vec::new();
+    let mut seen = BTreeSet::new();
+
+    for i in 2..slots {
+        let entry = fs::read_dir_slot(buf, i);
+        if entry.inode == 0 && entry.name.iter().all(|&b| b == 0) {
+            continue; // slot nunca usado
+        }
+        if !valid_name(&entry.name) {
+            return None;
+        }
+
+        used_slots = i + 1;
+        if entry.inode == 0 {
+            continue; // lápida
+        }
+
+        let name = dir::entry_name(&entry);
+        if seen.insert(name.clone()) {
+            children.push((name, entry.inode as u64));
+        }
+    }
+
+    Some((
+        dot.inode as u64,
+        FoundDir {
+            block,
+            parent: dotdot.inode as u64,
+            used_slots,
+            children,
+        },
+    ))
+}
+
+/// Nombre no vacío, sin '/' ni bytes de control y relleno con ceros después del
+/// primer nulo. No se exige UTF-8: los nombres se guardan con sus bytes tal cual.
+fn valid_name(raw: &[u8; QRFS_NAME_LEN]) -> bool {
+    let len = raw.iter().position(|&b| b == 0).unwrap_or(QRFS_NAME_LEN);
+    if len == 0 || raw[len..].iter().any(|&b| b != 0) {
+        return false;
+    }
+    !raw[..len].iter().any(|&b| b == b'/' || b < 0x20 || b == 0x7f)
+}
+
+fn synthesized_inode(ino: u64, file_type: u16, perm: u16, nlink: u32, now: SystemTime) -> InodeDisk {
+    let mut inode = InodeDisk {
+        id: ino as u32,
+        file_type,
+        perm,
+        uid: 0,
+        gid: 0,
+        size: 0,
+        atime: 0,
+        mtime: 0,
+        ctime: 0,
+        nlink,
+        direct_blocks: [0u32; 12],
+        indirect_block: 0,
+        double_indirect_block: 0,
+        generation: 0,
+    };
+    inode.set_times(now);
+    inode
+}
+
+fn put_inode(table: &mut [u8], inode_size: usize, ino: u64, inode: &InodeDisk) {
+    let offset = (ino as usize - 1) * inode_size;
+    unsafe {
+        let ptr = inode as *const InodeDisk as *const u8;
+        let bytes = std::slice::from_raw_parts(ptr, inode_size);
+        table[offset..offset + inode_size].copy_from_slice(bytes);
+    }
+}
@@ -0,0 +1,108 @@
+// Reconstrucción de una imagen a partir de sus QRs escaneados.
+//
+// Es el camino de vuelta de `print_pdf`: cada QR impreso lleva la cabecera de
+// `block_payload` con el número de bloque, así que los escaneos pueden llegar
+// en cualquier orden y con cualquier nombre. Decodificar las fotos no es parte
+// de QRFS: cada archivo de la carpeta de entrada es el contenido de un QR ya
+// decodificado (lo que deja un lector como `zbarimg --raw -Sbinary`).
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::block_store::{list_block_files, write_manifest, BlockStore, QRFS_DEFAULT_BLOCK_EXT};
+use crate::fs::{SuperblockDisk, QRFS_BLOCK_SIZE};
+use crate::mkfs::create_block_files;
+use crate::print::parse_block_payload;
+
+/// Resultado de `import_scans`.
+#[derive(Debug, Clone, Default)]
+pub struct ScanReport {
+    /// Bloques que tiene la imagen (según el superblock escaneado, o el mayor
+    /// número visto si falta el bloque 0).
+    pub total_blocks: u32,
+    /// Archivos que no son un bloque de esta imagen: sin la cabecera de QRFS,
+    /// con un tamaño que no es el de un bloque o con un número fuera de rango.
+    pub rejected: Vec<PathBuf>,
+    /// Números de bloque que no aparecieron en ningún escaneo.
+    pub missing: Vec<u32>,
+}
+
+/// Lee los QRs decodificados de `scans` (un archivo por QR, en cualquier orden
+/// y con cualquier nombre) y arma en `dest` la carpeta de bloques ordenada, con
+/// su manifiesto. Un mismo bloque escaneado dos veces es válido si las dos
+/// copias coinciden. Si falta algún bloque no se escribe nada: `missing` dice
+/// cuáles hay que volver a escanear.
+pub fn import_scans(scans: &Path, dest: &Path) -> Result<ScanReport> {
+    let mut files: Vec<PathBuf> = fs::read_dir(scans)
+        .with_context(|| format!("No se pudo leer la carpeta {:?}", scans))?
+        .map(|e| Ok(e?.path()))
+        .collect::<Result<_>>()?;
+    files.retain(|p| p.is_file());
+    files.sort();
+
+    let mut report = ScanReport::default();
+    let mut blocks: BTreeMap<u32, (Vec<u8>, PathBuf)> = BTreeMap::new();
+
+    for file in files {
+        let payload = fs::read(&file).with_context(|| format!("No se pudo leer {:?}", file))?;
+        let (index, data) = match parse_block_payload(&payload) {
+            Some((index, data)) if data.len() == QRFS_BLOCK_SIZE as usize => (index, data),
+            _ => {
+                report.rejected.push(file);
+                continue;
+            }
+        };
+
+        match blocks.get(&index) {
+            Some((seen, _)) if seen == data => {}
+            Some((_, other)) => {
+                return Err(anyhow::anyhow!(
+                    "El bloque {} aparece en {:?} y en {:?} con contenido distinto",
+                    index,
+                    other,
+                    file
+                ));
+            }
+            None => {
+                blocks.insert(index, (data.to_vec(), file));
+            }
+        }
+    }
+
+    // La cantidad de bloques sale del superblock; sin él, del mayor número visto
+    report.total_blocks = match blocks.get(&0) {
+        Some((data, file)) => {
+            let superblock = SuperblockDisk::from_block_bytes(data)?;
+            superblock
+                .check_magic()
+                .with_context(|| format!("El bloque 0 escaneado ({:?}) no es un superblock válido", file))?;
+            // Los de paridad también son bloques de la imagen
+            superblock.image_blocks()
+        }
+        None => blocks.keys().next_back().map_or(0, |&last| last + 1),
+    };
+
+    // Un número fuera de rango es de otra imagen
+    let out_of_range = blocks.split_off(&report.total_blocks);
+    report.rejected.extend(out_of_range.into_values().map(|(_, file)| file));
+    report.rejected.sort();
+
+    report.missing = (0..report.total_blocks).filter(|b| !blocks.contains_key(b)).collect();
+    if report.total_blocks == 0 || !report.missing.is_empty() {
+        return Ok(report);
+    }
+
+    create_block_files(dest, report.total_blocks, QRFS_DEFAULT_BLOCK_EXT)?;
+    let store = BlockStore::open(dest)?;
+    for (index, (data, _)) in &blocks {
+        store
+            .write_block(*index, data)
+            .with_context(|| format!("No se pudo escribir el bloque {} en {:?}", index, dest))?;
+    }
+    write_manifest(dest, &list_block_files(dest)?)?;
+
+    Ok(report)
+}
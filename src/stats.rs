@@ -0,0 +1,148 @@
+// Estadísticas del montaje expuestas como archivo virtual.
+//
+// `.qrfs-stats` aparece en la raíz pero no existe en disco: su inodo es
+// sintético y su contenido se genera en cada lectura a partir de los contadores
+// de `MountStats`. Así se pueden consultar desde un shell (`cat`) o desde un
+// script sin herramientas extra.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use fuser::{FileAttr, FileType};
+
+/// Nombre del archivo virtual de estadísticas en la raíz.
+pub const QRFS_STATS_NAME: &str = ".qrfs-stats";
+
+/// Inodo reservado para el archivo de estadísticas: nunca choca con uno real
+/// porque los inodos de disco están acotados por `max_inodes` (u32).
+pub const QRFS_STATS_INO: u64 = u64::MAX - 1;
+
+/// Contadores del montaje. Son atómicos para poder incrementarlos también
+/// desde las operaciones que sólo toman el lock de lectura.
+#[derive(Debug, Default)]
+pub struct MountStats {
+    pub reads: AtomicU64,
+    pub bytes_read: AtomicU64,
+    pub writes: AtomicU64,
+    pub bytes_written: AtomicU64,
+    pub blocks_allocated: AtomicU64,
+    pub blocks_freed: AtomicU64,
+    /// Inodos leídos de la tabla en disco.
+    pub inode_reads: AtomicU64,
+    /// Lecturas servidas desde el buffer en RAM.
+    pub cache_hits: AtomicU64,
+    /// Lecturas que tuvieron que ir a los bloques en disco.
+    pub cache_misses: AtomicU64,
+    /// Bloques servidos desde el caché de lectura anticipada.
+    pub read_ahead_hits: AtomicU64,
+}
+
+impl MountStats {
+    /// Una lectura de `bytes` bytes; `from_ram` indica si salió del buffer en memoria.
+    pub fn record_read(&self, bytes: usize, from_ram: bool) {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        self.bytes_read.fetch_add(bytes as u64, Ordering::Relaxed);
+        let cache = if from_ram { &self.cache_hits } else { &self.cache_misses };
+        cache.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_write(&self, bytes: usize) {
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        self.bytes_written.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_alloc(&self) {
+        self.blocks_allocated.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_free(&self) {
+        self.blocks_freed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_inode_read(&self) {
+        self.inode_reads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_read_ahead_hit(&self) {
+        self.read_ahead_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Valores actuales de los contadores.
+    pub fn counters(&self) -> QrfsStats {
+        let get = |c: &AtomicU64| c.load(Ordering::Relaxed);
+        QrfsStats {
+            reads: get(&self.reads),
+            bytes_read: get(&self.bytes_read),
+            writes: get(&self.writes),
+            bytes_written: get(&self.bytes_written),
+            blocks_allocated: get(&self.blocks_allocated),
+            blocks_freed: get(&self.blocks_freed),
+            inode_reads: get(&self.inode_reads),
+            cache_hits: get(&self.cache_hits),
+            cache_misses: get(&self.cache_misses),
+            read_ahead_hits: get(&self.read_ahead_hits),
+        }
+    }
+
+    /// Foto de los contadores en JSON (una clave por línea).
+    pub fn snapshot(&self, free_blocks: u32, free_inodes: u32) -> Vec<u8> {
+        let c = self.counters();
+        format!(
+            "{{\n  \"reads\": {},\n  \"bytes_read\": {},\n  \"writes\": {},\n  \"bytes_written\": {},\n  \
+             \"blocks_allocated\": {},\n  \"blocks_freed\": {},\n  \"inode_reads\": {},\n  \
+             \"cache_hits\": {},\n  \"cache_misses\": {},\n  \"read_ahead_hits\": {},\n  \
+             \"free_blocks\": {},\n  \"free_inodes\": {}\n}}\n",
+            c.reads,
+            c.bytes_read,
+            c.writes,
+            c.bytes_written,
+            c.blocks_allocated,
+            c.blocks_freed,
+            c.inode_reads,
+            c.cache_hits,
+            c.cache_misses,
+            c.read_ahead_hits,
+            free_blocks,
+            free_inodes,
+        )
+        .into_bytes()
+    }
+}
+
+/// Foto de los contadores de `MountStats` para usar desde código (benchmarks,
+/// programas que embeben QRFS), sin pasar por el archivo virtual.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QrfsStats {
+    pub reads: u64,
+    pub bytes_read: u64,
+    pub writes: u64,
+    pub bytes_written: u64,
+    pub blocks_allocated: u64,
+    pub blocks_freed: u64,
+    pub inode_reads: u64,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    pub read_ahead_hits: u64,
+}
+
+/// Atributos del archivo virtual: sólo lectura, con el tamaño de la foto actual.
+pub fn stats_attr(size: u64) -> FileAttr {
+    let now = SystemTime::now();
+    FileAttr {
+        ino: QRFS_STATS_INO,
+        size,
+        blocks: 0,
+        atime: now,
+        mtime: now,
+        ctime: now,
+        crtime: now,
+        kind: FileType::RegularFile,
+        perm: 0o444,
+        nlink: 1,
+        uid: 0,
+        gid: 0,
+        rdev: 0,
+        blksize: 512,
+        flags: 0,
+    }
+}
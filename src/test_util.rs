@@ -0,0 +1,25 @@
+//! Ayudas para las pruebas: imágenes recién formateadas en carpetas temporales.
+
+use tempfile::TempDir;
+
+use crate::block_store::{list_block_files, write_manifest, QRFS_DEFAULT_BLOCK_EXT};
+use crate::mkfs::{build_layout, create_block_files, init_fresh_fs, write_fresh_fs};
+
+/// Carpeta temporal formateada como `mkfs.qrfs --blocks blocks`. Se borra al
+/// soltar el `TempDir`.
+pub(crate) fn fresh_image(blocks: u32) -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    create_block_files(dir.path(), blocks, QRFS_DEFAULT_BLOCK_EXT).unwrap();
+
+    let entries = list_block_files(dir.path()).unwrap();
+    let layout = build_layout(entries.len() as u32).unwrap();
+    let (superblock, inodes, bitmap) = init_fresh_fs(&layout).unwrap();
+    write_fresh_fs(&entries, &layout, &superblock, &inodes, &bitmap).unwrap();
+    write_manifest(dir.path(), &entries).unwrap();
+    dir
+}
+
+/// Contenido de prueba de `len` bytes que no se repite cada bloque.
+pub(crate) fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
@@ -0,0 +1,165 @@
+// Imágenes QRFS empaquetadas en un archivo .zip (sólo lectura).
+//
+// Para distribuir una imagen lo natural es comprimir su carpeta de bloques en
+// un zip. `BlockStore::open` reconoce una ruta a un `.zip` y lee cada bloque de
+// su entrada en el archivo, así la imagen se monta sin descomprimirla. El orden
+// sale igual que en una carpeta: del `manifest.json` si el zip trae uno y si no
+// del número en el nombre de cada archivo. Los bloques pueden estar en la raíz
+// del zip o dentro de una carpeta (lo que deja `zip -r imagen.zip qrfolder/`).
+//
+// Escribir un bloque es un error: para modificar la imagen hay que extraerla.
+// Como `BlockStore` se abre en cada acceso a un bloque, el índice del zip (su
+// directorio central) se lee una sola vez por proceso y se comparte.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use zip::ZipArchive;
+
+use crate::block_store::{block_file_index, in_block_order, manifest_order, parse_manifest, QRFS_MANIFEST_NAME};
+use crate::fs::QRFS_BLOCK_SIZE;
+
+/// Extensión de una imagen empaquetada.
+const ZIP_EXT: &str = "zip";
+
+/// Zips ya abiertos por este proceso, por ruta.
+static OPEN_ZIPS: OnceLock<Mutex<HashMap<PathBuf, Arc<ZipImage>>>> = OnceLock::new();
+
+/// `true` si `path` es un archivo `.zip` (una imagen empaquetada) en vez de
+/// una carpeta de bloques.
+pub fn is_zip_image(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case(ZIP_EXT))
+}
+
+pub(crate) struct ZipImage {
+    path: PathBuf,
+    /// Fecha de modificación y tamaño al abrirlo: si cambian, el zip se reemplazó.
+    stamp: (SystemTime, u64),
+    archive: Mutex<ZipArchive<File>>,
+    /// Entrada de cada bloque dentro del zip, en orden lógico.
+    entries: Vec<String>,
+}
+
+/// El zip de `path`, abierto una sola vez mientras no cambie en disco.
+pub(crate) fn open(path: &Path) -> Result<Arc<ZipImage>> {
+    let stamp = file_stamp(path)?;
+    let mut open = OPEN_ZIPS.get_or_init(Default::default).lock().unwrap();
+
+    if let Some(image) = open.get(path) {
+        if image.stamp == stamp {
+            return Ok(Arc::clone(image));
+        }
+    }
+
+    let image = Arc::new(ZipImage::load(path, stamp)?);
+    open.insert(path.to_path_buf(), Arc::clone(&image));
+    Ok(image)
+}
+
+fn file_stamp(path: &Path) -> Result<(SystemTime, u64)> {
+    let meta = std::fs::metadata(path).with_context(|| format!("No se pudo leer {:?}", path))?;
+    Ok((meta.modified()?, meta.len()))
+}
+
+impl ZipImage {
+    fn load(path: &Path, stamp: (SystemTime, u64)) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("No se pudo abrir {:?}", path))?;
+        let mut archive = ZipArchive::new(file).with_context(|| format!("{:?} no es un zip válido", path))?;
+
+        let names: Vec<String> = archive
+            .file_names()
+            .filter(|n| !n.ends_with('/'))
+            .map(String::from)
+            .collect();
+
+        // Carpeta del zip con la imagen: la del manifiesto o, si no hay, la del
+        // primer archivo de bloque. Lo que esté en otras carpetas se ignora.
+        let dir = names
+            .iter()
+            .find(|n| base_name(n) == QRFS_MANIFEST_NAME)
+            .or_else(|| names.iter().find(|n| block_file_index(base_name(n)).is_some()))
+            .map(|n| dir_name(n).to_string())
+            .with_context(|| format!("El zip {:?} no tiene archivos de bloque", path))?;
+
+        let manifest_name = format!("{}{}", dir, QRFS_MANIFEST_NAME);
+        let entries = if names.contains(&manifest_name) {
+            let mut text = String::new();
+            archive
+                .by_name(&manifest_name)?
+                .read_to_string(&mut text)
+                .with_context(|| format!("No se pudo leer {} de {:?}", manifest_name, path))?;
+            let source = path.join(&manifest_name);
+            let manifest = parse_manifest(&source, &text)?;
+
+            let mut entries = Vec::new();
+            for (index, file) in manifest_order(&source, &manifest)?.into_iter().enumerate() {
+                let name = format!("{}{}", dir, file);
+                if !names.contains(&name) {
+                    return Err(anyhow::anyhow!(
+                        "El bloque {} del manifiesto de {:?} no está en el zip: {:?}",
+                        index,
+                        path,
+                        name
+                    ));
+                }
+                entries.push(name);
+            }
+            entries
+        } else {
+            let indexed = names
+                .iter()
+                .filter(|n| dir_name(n) == dir)
+                .filter_map(|n| Some((block_file_index(base_name(n))?, n.clone())))
+                .collect();
+            in_block_order(path, indexed)?
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            stamp,
+            archive: Mutex::new(archive),
+            entries,
+        })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Entrada de cada bloque, en orden lógico.
+    pub(crate) fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Lee el bloque guardado en la entrada `name`.
+    pub(crate) fn read_block(&self, name: &str) -> Result<Vec<u8>> {
+        let mut archive = self.archive.lock().unwrap();
+        let mut entry = archive
+            .by_name(name)
+            .with_context(|| format!("No se pudo abrir el bloque {:?} de {:?}", name, self.path))?;
+
+        let mut buf = vec![0u8; QRFS_BLOCK_SIZE as usize];
+        entry
+            .read_exact(&mut buf)
+            .with_context(|| format!("No se pudo leer el bloque completo de {:?} en {:?}", name, self.path))?;
+        Ok(buf)
+    }
+}
+
+/// Nombre de una entrada sin la carpeta (los zips siempre separan con `/`).
+fn base_name(name: &str) -> &str {
+    name.rsplit('/').next().unwrap_or(name)
+}
+
+/// Carpeta de una entrada, con la `/` final (vacía en la raíz del zip).
+fn dir_name(name: &str) -> &str {
+    &name[..name.len() - base_name(name).len()]
+}
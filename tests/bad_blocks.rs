@@ -0,0 +1,59 @@
+mod common;
+
+use std::path::Path;
+
+use common::{assert_fsck_clean, fresh_image, pattern, read_superblock};
+use qrfs::{bad_blocks, mark_bad_block, QrfsImage, QRFS_BLOCK_SIZE};
+
+#[test]
+fn allocation_skips_a_block_marked_bad() {
+    let dir = fresh_image(64);
+    let before = read_superblock(&dir);
+    // El primer bloque de datos es el del root: el siguiente es el primero libre
+    let bad = before.data_blocks_start + 1;
+
+    let mark = mark_bad_block(dir.path(), bad).unwrap();
+    assert!(mark.added);
+    assert!(!mark.in_use);
+    assert!(!mark_bad_block(dir.path(), bad).unwrap().added);
+    assert_eq!(bad_blocks(dir.path()).unwrap(), vec![bad]);
+    assert_eq!(read_superblock(&dir).free_blocks, before.free_blocks - 1);
+
+    let path = Path::new("/datos.bin");
+    let data = pattern(10 * QRFS_BLOCK_SIZE as usize);
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        let ino = image.create_file(path).unwrap();
+        image.write_file(path, &data).unwrap();
+        let inode = image.read_inode_raw(ino).unwrap();
+        let direct = inode.direct_blocks;
+        assert!(direct[..10].iter().all(|&b| b != 0 && b != bad), "{direct:?}");
+        assert_eq!(image.read_file(path).unwrap(), data);
+    }
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn reading_a_bad_block_of_a_file_fails() {
+    let dir = fresh_image(64);
+    let path = Path::new("/datos.bin");
+    let data = pattern(3 * QRFS_BLOCK_SIZE as usize);
+    let victim = {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        let ino = image.create_file(path).unwrap();
+        image.write_file(path, &data).unwrap();
+        let direct = image.read_inode_raw(ino).unwrap().direct_blocks;
+        direct[1]
+    };
+
+    let mark = mark_bad_block(dir.path(), victim).unwrap();
+    assert!(mark.added);
+    assert!(mark.in_use);
+
+    let image = QrfsImage::open(dir.path()).unwrap();
+    assert!(image.read_file(path).is_err());
+    // El resto del archivo se sigue pudiendo copiar
+    let block = QRFS_BLOCK_SIZE as usize;
+    assert_eq!(image.read_at(path, 0, block).unwrap(), data[..block]);
+    assert_eq!(image.read_at(path, 2 * block as u64, block).unwrap(), data[2 * block..]);
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+use anyhow::anyhow;
+use common::{assert_fsck_clean, folder_snapshot, fresh_image, pattern};
+use qrfs::{block_file_writes, QrfsBatch, QrfsImage};
+
+/// Árbol de prueba: 100 archivos repartidos en diez directorios, de tamaños
+/// distintos (de uno a cuatro bloques, ninguno inline).
+fn sample_tree() -> Vec<(String, Vec<u8>)> {
+    (0..100)
+        .map(|i| (format!("/d{}/f{}", i % 10, i), pattern(100 + i * 37)))
+        .collect()
+}
+
+/// Carga `tree` con las operaciones de un lote, en el mismo orden que la carga
+/// operación por operación.
+fn import_tree(b: &mut QrfsBatch, tree: &[(String, Vec<u8>)]) -> anyhow::Result<()> {
+    for d in 0..10 {
+        b.mkdir(Path::new(&format!("/d{}", d)))?;
+    }
+    for (path, data) in tree {
+        b.create_file(Path::new(path))?;
+        b.write_file(Path::new(path), data)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn batch_writes_everything_at_the_end() {
+    let tree = sample_tree();
+
+    // La misma carga operación por operación y en un solo lote
+    let per_op = fresh_image(1024);
+    let before = block_file_writes(per_op.path());
+    {
+        let image = QrfsImage::open(per_op.path()).unwrap();
+        for d in 0..10 {
+            image.mkdir(Path::new(&format!("/d{}", d))).unwrap();
+        }
+        for (path, data) in &tree {
+            image.create_file(Path::new(path)).unwrap();
+            image.write_file(Path::new(path), data).unwrap();
+        }
+    }
+    let per_op_writes = block_file_writes(per_op.path()) - before;
+
+    let batched = fresh_image(1024);
+    let before = block_file_writes(batched.path());
+    {
+        let image = QrfsImage::open(batched.path()).unwrap();
+        image.batch(|b| import_tree(b, &tree)).unwrap();
+    }
+    let batched_writes = block_file_writes(batched.path()) - before;
+
+    for dir in [&per_op, &batched] {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        for (path, data) in &tree {
+            assert_eq!(&image.read_file(Path::new(path)).unwrap(), data, "{}", path);
+        }
+        drop(image);
+        assert_fsck_clean(dir);
+    }
+
+    // Cada bloque del lote se escribe una sola vez
+    assert!(
+        batched_writes < per_op_writes,
+        "lote: {} escrituras, operación por operación: {}",
+        batched_writes,
+        per_op_writes
+    );
+}
+
+#[test]
+fn failed_batch_leaves_the_disk_unchanged() {
+    let dir = fresh_image(128);
+    let image = QrfsImage::open(dir.path()).unwrap();
+    image.create_file(Path::new("/viejo")).unwrap();
+    image.write_file(Path::new("/viejo"), &pattern(2000)).unwrap();
+    let before = folder_snapshot(&dir);
+
+    let result: anyhow::Result<()> = image.batch(|b| {
+        b.create_file(Path::new("/nuevo"))?;
+        b.write_file(Path::new("/nuevo"), &pattern(4000))?;
+        b.remove(Path::new("/viejo"))?;
+        Err(anyhow!("a mitad del lote"))
+    });
+    assert!(result.is_err());
+    assert_eq!(folder_snapshot(&dir), before);
+
+    // La imagen abierta volvió a lo que hay en disco
+    assert!(image.stat(Path::new("/nuevo")).is_err());
+    assert_eq!(image.read_file(Path::new("/viejo")).unwrap(), pattern(2000));
+    image.create_file(Path::new("/despues")).unwrap();
+    drop(image);
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn panicking_batch_leaves_the_disk_unchanged() {
+    let dir = fresh_image(128);
+    let image = QrfsImage::open(dir.path()).unwrap();
+    let before = folder_snapshot(&dir);
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        image.batch(|b| -> anyhow::Result<()> {
+            b.mkdir(Path::new("/d"))?;
+            panic!("a mitad del lote");
+        })
+    }));
+    assert!(result.is_err());
+    assert_eq!(folder_snapshot(&dir), before);
+
+    assert!(image.stat(Path::new("/d")).is_err());
+    image.mkdir(Path::new("/d")).unwrap();
+    drop(image);
+    assert_fsck_clean(&dir);
+}
@@ -0,0 +1,20 @@
+mod common;
+
+use std::process::Command;
+
+use common::{folder_snapshot, fresh_image};
+
+#[test]
+fn bench_runs_on_a_copy_of_the_image() {
+    let dir = fresh_image(128);
+    let before = folder_snapshot(&dir);
+
+    let out = Command::new(env!("CARGO_BIN_EXE_bench_qrfs")).arg(dir.path()).arg("2").output().unwrap();
+    assert!(out.status.success(), "bench_qrfs: {}", String::from_utf8_lossy(&out.stderr));
+
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    for name in ["escritura secuencial", "lectura secuencial", "lectura aleatoria", "create", "stat", "unlink"] {
+        assert!(stdout.contains(name), "falta {:?} en:\n{}", name, stdout);
+    }
+    assert_eq!(folder_snapshot(&dir), before);
+}
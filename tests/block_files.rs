@@ -0,0 +1,170 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::{assert_fsck_clean, fresh_image, pattern};
+use qrfs::{
+    block_file_name, create_block_files, list_block_files, manifest_mismatches, write_manifest, BlockStore, QrfsImage,
+    QRFS_DEFAULT_BLOCK_EXT, QRFS_MANIFEST_NAME,
+};
+
+#[test]
+fn stray_files_next_to_the_blocks_are_ignored() {
+    let dir = fresh_image(64);
+    fs::remove_file(dir.path().join(QRFS_MANIFEST_NAME)).unwrap();
+    fs::write(dir.path().join(".DS_Store"), b"basura").unwrap();
+    fs::write(dir.path().join("block_00000003.qr.swp"), b"basura").unwrap();
+    fs::write(dir.path().join("fsck.log"), b"basura").unwrap();
+
+    assert_eq!(list_block_files(dir.path()).unwrap().len(), 64);
+    QrfsImage::open(dir.path()).unwrap();
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn images_named_before_the_block_format_still_mount() {
+    let dir = fresh_image(64);
+    let data = pattern(3000);
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.create_file(Path::new("/viejo.bin")).unwrap();
+        image.write_file(Path::new("/viejo.bin"), &data).unwrap();
+    }
+
+    // Los nombres que aceptaba la versión original: cualquier archivo, en orden alfabético
+    fs::remove_file(dir.path().join(QRFS_MANIFEST_NAME)).unwrap();
+    for (i, file) in list_block_files(dir.path()).unwrap().iter().enumerate() {
+        fs::rename(file, dir.path().join(format!("qr_{:04}.png", i))).unwrap();
+    }
+    fs::write(dir.path().join(".DS_Store"), b"basura").unwrap();
+
+    let files = list_block_files(dir.path()).unwrap();
+    assert_eq!(files.len(), 64);
+    assert!(files[0].ends_with("qr_0000.png"));
+
+    let image = QrfsImage::open(dir.path()).unwrap();
+    assert_eq!(image.read_file(Path::new("/viejo.bin")).unwrap(), data);
+    drop(image);
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn other_files_are_not_an_old_image() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("LEAME.txt"), b"hojas de la imagen").unwrap();
+    assert!(list_block_files(dir.path()).unwrap().is_empty());
+
+    create_block_files(dir.path(), 8, QRFS_DEFAULT_BLOCK_EXT).unwrap();
+    assert_eq!(list_block_files(dir.path()).unwrap().len(), 8);
+}
+
+#[test]
+fn scan_finds_a_truncated_block_file() {
+    let dir = fresh_image(64);
+    assert!(BlockStore::open(dir.path()).unwrap().scan().is_empty());
+    assert!(manifest_mismatches(dir.path()).unwrap().is_empty());
+
+    let path = dir.path().join(block_file_name(10));
+    let data = fs::read(&path).unwrap();
+    fs::write(&path, &data[..100]).unwrap();
+
+    let bad = BlockStore::open(dir.path()).unwrap().scan();
+    assert_eq!(bad.iter().map(|(b, _)| *b).collect::<Vec<_>>(), [10]);
+    assert_eq!(manifest_mismatches(dir.path()).unwrap(), [10]);
+}
+
+#[test]
+fn shuffled_names_follow_the_manifest() {
+    let dir = fresh_image(64);
+    let data = pattern(5000);
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.create_file(Path::new("/datos.bin")).unwrap();
+        image.write_file(Path::new("/datos.bin"), &data).unwrap();
+    }
+
+    // El bloque i pasa a llamarse como el bloque (37 i + 11) mod 64, vía nombres
+    // temporales para no pisar ninguno
+    let files = list_block_files(dir.path()).unwrap();
+    for (i, file) in files.iter().enumerate() {
+        fs::rename(file, dir.path().join(format!("tmp_{}", i))).unwrap();
+    }
+    let shuffled: Vec<_> = (0..files.len() as u32)
+        .map(|i| dir.path().join(block_file_name((i * 37 + 11) % 64)))
+        .collect();
+    for (i, file) in shuffled.iter().enumerate() {
+        fs::rename(dir.path().join(format!("tmp_{}", i)), file).unwrap();
+    }
+    write_manifest(dir.path(), &shuffled).unwrap();
+
+    // El superblock quedó en un archivo que no es el primero por nombre
+    assert!(shuffled[0].ends_with(block_file_name(11)));
+    assert_eq!(list_block_files(dir.path()).unwrap(), shuffled);
+    let image = QrfsImage::open(dir.path()).unwrap();
+    assert_eq!(image.read_file(Path::new("/datos.bin")).unwrap(), data);
+    drop(image);
+    assert_fsck_clean(&dir);
+}
+
+/// Sin manifiesto y numerados desde otra hoja: el bloque i pasa a llamarse
+/// como el (i + shift) mod 64, vía nombres temporales para no pisar ninguno.
+fn renumber_from(dir: &tempfile::TempDir, shift: u32) {
+    fs::remove_file(dir.path().join(QRFS_MANIFEST_NAME)).unwrap();
+    let files = list_block_files(dir.path()).unwrap();
+    for (i, file) in files.iter().enumerate() {
+        fs::rename(file, dir.path().join(format!("tmp_{}", i))).unwrap();
+    }
+    for i in 0..files.len() as u32 {
+        let name = block_file_name((i + shift) % files.len() as u32);
+        fs::rename(dir.path().join(format!("tmp_{}", i)), dir.path().join(name)).unwrap();
+    }
+}
+
+#[test]
+fn block0_is_found_when_it_does_not_sort_first() {
+    let dir = fresh_image(64);
+    let data = pattern(5000);
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.create_file(Path::new("/datos.bin")).unwrap();
+        image.write_file(Path::new("/datos.bin"), &data).unwrap();
+    }
+    renumber_from(&dir, 5);
+
+    let files = list_block_files(dir.path()).unwrap();
+    assert!(files[0].ends_with(block_file_name(5)));
+    assert!(files[63].ends_with(block_file_name(4)));
+    let image = QrfsImage::open(dir.path()).unwrap();
+    assert_eq!(image.read_file(Path::new("/datos.bin")).unwrap(), data);
+    drop(image);
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn two_superblocks_ask_for_start_qr() {
+    let dir = fresh_image(64);
+    renumber_from(&dir, 5);
+    // Una copia del superblock en otro archivo: no se sabe cuál es el bloque 0
+    let block0 = fs::read(dir.path().join(block_file_name(5))).unwrap();
+    fs::write(dir.path().join(block_file_name(50)), block0).unwrap();
+
+    let err = QrfsImage::open(dir.path()).err().expect("dos superblocks tienen que fallar");
+    assert!(format!("{err:#}").contains("start_qr"), "{err:#}");
+}
+
+#[test]
+fn a_superblock_copy_sorting_first_is_not_taken_as_block0() {
+    let dir = fresh_image(64);
+    renumber_from(&dir, 5);
+    // El primero en orden (un bloque de datos libre) pasa a tener una copia del
+    // superblock: también es un candidato, no el bloque 0 sin más
+    let block0 = fs::read(dir.path().join(block_file_name(5))).unwrap();
+    fs::write(dir.path().join(block_file_name(0)), block0).unwrap();
+    assert!(list_block_files(dir.path()).unwrap()[0].ends_with(block_file_name(0)));
+
+    let err = QrfsImage::open(dir.path()).err().expect("dos superblocks tienen que fallar");
+    let msg = format!("{err:#}");
+    assert!(msg.contains("start_qr"), "{msg}");
+    assert!(msg.contains(&block_file_name(0)) && msg.contains(&block_file_name(5)), "{msg}");
+}
@@ -0,0 +1,63 @@
+//! Ayudas compartidas por las pruebas de integración.
+
+#![allow(dead_code)]
+
+use qrfs::{
+    build_layout, check, create_block_files, init_fresh_fs, list_block_files, write_fresh_fs, write_manifest,
+    BlockStore, SuperblockDisk, QRFS_DEFAULT_BLOCK_EXT,
+};
+use tempfile::TempDir;
+
+/// Carpeta temporal formateada como `mkfs.qrfs --blocks blocks`. Se borra al
+/// soltar el `TempDir`.
+pub fn fresh_image(blocks: u32) -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    create_block_files(dir.path(), blocks, QRFS_DEFAULT_BLOCK_EXT).unwrap();
+
+    let entries = list_block_files(dir.path()).unwrap();
+    let layout = build_layout(entries.len() as u32).unwrap();
+    let (superblock, inodes, bitmap) = init_fresh_fs(&layout).unwrap();
+    write_fresh_fs(&entries, &layout, &superblock, &inodes, &bitmap).unwrap();
+    write_manifest(dir.path(), &entries).unwrap();
+    dir
+}
+
+/// Contenido de prueba de `len` bytes que no se repite cada bloque.
+pub fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+/// Falla con los errores de fsck si la imagen (ya cerrada) no está limpia.
+pub fn assert_fsck_clean(dir: &TempDir) {
+    let report = check(dir.path());
+    assert!(report.errors.is_empty(), "fsck: {:?}", report.errors);
+}
+
+/// Nombre y contenido de cada archivo de la carpeta, en orden: para comprobar
+/// que una operación no escribió nada.
+pub fn folder_snapshot(dir: &TempDir) -> Vec<(String, Vec<u8>)> {
+    let mut files: Vec<(String, Vec<u8>)> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap())
+        .filter(|e| e.file_type().unwrap().is_file())
+        .map(|e| (e.file_name().to_string_lossy().into_owned(), std::fs::read(e.path()).unwrap()))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Superblock del bloque 0 de la imagen.
+pub fn read_superblock(dir: &TempDir) -> SuperblockDisk {
+    let block = BlockStore::open(dir.path()).unwrap().read_block(0).unwrap();
+    SuperblockDisk::from_block_bytes(&block).unwrap()
+}
+
+/// Escribe `sb` tal cual en el bloque 0 (sin sellarlo: el checksum es el que traiga).
+pub fn write_superblock(dir: &TempDir, sb: &SuperblockDisk) {
+    let store = BlockStore::open(dir.path()).unwrap();
+    let mut block = store.read_block(0).unwrap();
+    let size = std::mem::size_of::<SuperblockDisk>();
+    let bytes = unsafe { std::slice::from_raw_parts((sb as *const SuperblockDisk) as *const u8, size) };
+    block[..size].copy_from_slice(bytes);
+    store.write_block(0, &block).unwrap();
+}
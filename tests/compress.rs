@@ -0,0 +1,40 @@
+mod common;
+
+use std::path::Path;
+
+use common::{assert_fsck_clean, fresh_image, pattern, read_superblock};
+use qrfs::{QrfsImage, QRFS_FEATURE_COMPRESSION};
+use tempfile::TempDir;
+
+/// Escribe `data` en `path` y devuelve cuántos bloques de la imagen usó.
+fn blocks_used(dir: &TempDir, compress: bool, path: &str, data: &[u8]) -> u32 {
+    let before = read_superblock(dir).free_blocks;
+    let image = QrfsImage::open(dir.path()).unwrap().compress(compress);
+    image.create_file(Path::new(path)).unwrap();
+    image.write_file(Path::new(path), data).unwrap();
+    drop(image);
+    before - read_superblock(dir).free_blocks
+}
+
+#[test]
+fn compressible_data_takes_fewer_blocks() {
+    let text: Vec<u8> = b"QRFS guarda bloques en codigos QR. ".iter().copied().cycle().take(20 * 1024).collect();
+    let dir = fresh_image(64);
+
+    let plain = blocks_used(&dir, false, "/plano.txt", &text);
+    assert_eq!(read_superblock(&dir).features & QRFS_FEATURE_COMPRESSION, 0);
+
+    let compressed = blocks_used(&dir, true, "/comprimido.txt", &text);
+    assert!(compressed < plain, "{compressed} bloques comprimido contra {plain}");
+    assert_ne!(read_superblock(&dir).features & QRFS_FEATURE_COMPRESSION, 0);
+
+    // Lo que no se comprime también tiene que volver igual
+    blocks_used(&dir, true, "/ruido.bin", &pattern(3000));
+
+    let image = QrfsImage::open(dir.path()).unwrap();
+    assert_eq!(image.read_file(Path::new("/comprimido.txt")).unwrap(), text);
+    assert_eq!(image.read_at(Path::new("/comprimido.txt"), 5000, 100).unwrap(), &text[5000..5100]);
+    assert_eq!(image.read_file(Path::new("/ruido.bin")).unwrap(), pattern(3000));
+    drop(image);
+    assert_fsck_clean(&dir);
+}
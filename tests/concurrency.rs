@@ -0,0 +1,59 @@
+mod common;
+
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use common::{assert_fsck_clean, fresh_image, pattern};
+use qrfs::QrfsImage;
+
+#[test]
+fn concurrent_readers_and_a_writer_do_not_deadlock() {
+    let dir = fresh_image(128);
+    let fixed = pattern(5000);
+    let image = Arc::new(QrfsImage::open(dir.path()).unwrap());
+    image.create_file(Path::new("/fijo.bin")).unwrap();
+    image.write_file(Path::new("/fijo.bin"), &fixed).unwrap();
+
+    // Si algo se traba, la prueba falla por tiempo en vez de colgarse
+    let (done, finished) = mpsc::channel();
+    let worker = {
+        let image = Arc::clone(&image);
+        let fixed = fixed.clone();
+        thread::spawn(move || {
+            thread::scope(|scope| {
+                for _ in 0..4 {
+                    scope.spawn(|| {
+                        for _ in 0..100 {
+                            assert_eq!(image.read_file(Path::new("/fijo.bin")).unwrap(), fixed);
+                            assert_eq!(image.stat(Path::new("/fijo.bin")).unwrap().size, fixed.len() as u64);
+                            assert_eq!(image.read_at(Path::new("/fijo.bin"), 1000, 100).unwrap(), &fixed[1000..1100]);
+                        }
+                    });
+                }
+                scope.spawn(|| {
+                    for i in 0..12 {
+                        let path = format!("/nuevo{}.bin", i);
+                        image.create_file(Path::new(&path)).unwrap();
+                        image.write_file(Path::new(&path), &pattern(100 * (i + 1))).unwrap();
+                    }
+                });
+            });
+            done.send(()).unwrap();
+        })
+    };
+    // Un pánico de algún hilo desconecta el canal y sale en el join
+    if let Err(RecvTimeoutError::Timeout) = finished.recv_timeout(Duration::from_secs(120)) {
+        panic!("los lectores y el escritor no terminaron: posible deadlock");
+    }
+    worker.join().unwrap();
+
+    for i in 0..12 {
+        let path = format!("/nuevo{}.bin", i);
+        assert_eq!(image.read_file(Path::new(&path)).unwrap(), pattern(100 * (i + 1)));
+    }
+    drop(image);
+    assert_fsck_clean(&dir);
+}
@@ -0,0 +1,186 @@
+mod common;
+
+use std::path::Path;
+
+use common::{assert_fsck_clean, fresh_image, read_superblock};
+use qrfs::{
+    compact_directories, DirEntryDisk, InodeTable, QrfsFilesystem, QrfsImage, TypeConflict, QRFS_MAX_NAME,
+    QRFS_NAME_LEN,
+};
+
+const SLOT: usize = std::mem::size_of::<DirEntryDisk>();
+
+/// Slots usados del bloque del directorio `ino` en disco: `(inodo, nombre)`,
+/// con las lápidas como inodo 0.
+fn dir_slots(image: &QrfsImage, ino: u64) -> Vec<(u32, String)> {
+    let dir = image.read_inode_raw(ino).unwrap();
+    let block = image.read_block_raw(dir.direct_blocks[0]).unwrap();
+    let used = dir.size as usize / SLOT;
+    block
+        .chunks_exact(SLOT)
+        .take(used)
+        .map(|slot| {
+            let inode = u32::from_le_bytes(slot[..4].try_into().unwrap());
+            let name = &slot[4..];
+            let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+            (inode, String::from_utf8_lossy(&name[..len]).into_owned())
+        })
+        .collect()
+}
+
+#[test]
+fn removing_an_entry_leaves_a_tombstone() {
+    let dir = fresh_image(64);
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        for name in ["/a", "/b", "/c"] {
+            image.create_file(Path::new(name)).unwrap();
+        }
+        let before = dir_slots(&image, 1);
+
+        // "c" queda en su slot y el de "b" pasa a ser una lápida
+        image.remove(Path::new("/b")).unwrap();
+        let after = dir_slots(&image, 1);
+        assert_eq!(after.len(), before.len());
+        let b = before.iter().position(|(_, n)| n == "b").unwrap();
+        assert_eq!(after[b].0, 0);
+        for (i, slot) in before.iter().enumerate().filter(|&(i, _)| i != b) {
+            assert_eq!(&after[i], slot);
+        }
+
+        // Una entrada nueva reusa la lápida
+        image.create_file(Path::new("/d")).unwrap();
+        assert_eq!(dir_slots(&image, 1)[b].1, "d");
+        image.remove(Path::new("/d")).unwrap();
+    }
+
+    assert_eq!(compact_directories(dir.path()).unwrap(), 1);
+    assert_eq!(compact_directories(dir.path()).unwrap(), 0);
+
+    let image = QrfsImage::open(dir.path()).unwrap();
+    assert!(dir_slots(&image, 1).iter().all(|(ino, _)| *ino != 0));
+    assert!(image.stat(Path::new("/a")).is_ok());
+    assert!(image.stat(Path::new("/c")).is_ok());
+    drop(image);
+    assert_fsck_clean(&dir);
+}
+
+#[cfg(feature = "sorted-dirs")]
+#[test]
+fn large_directory_is_kept_sorted() {
+    let dir = fresh_image(64);
+    let names = ["m", "c", "x", "a", "q", "e", "z", "b", "k", "t", "d", "h", "w", "f"];
+    let ino;
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        ino = image.mkdir(Path::new("/grande")).unwrap();
+        for name in names {
+            image.create_file(Path::new(&format!("/grande/{}", name))).unwrap();
+        }
+        image.remove(Path::new("/grande/k")).unwrap();
+        image.create_file(Path::new("/grande/g")).unwrap();
+    }
+
+    let image = QrfsImage::open(dir.path()).unwrap();
+    assert!(image.read_inode_raw(ino).unwrap().is_sorted_dir());
+
+    // ".", ".." y el resto por nombre, sin lápidas
+    let mut expected: Vec<&str> = names.iter().copied().filter(|&n| n != "k").chain(["g"]).collect();
+    expected.sort();
+    let slots = dir_slots(&image, ino);
+    assert!(slots.iter().all(|(ino, _)| *ino != 0));
+    let on_disk: Vec<&str> = slots.iter().map(|(_, n)| n.as_str()).collect();
+    assert_eq!(on_disk[..2], [".", ".."]);
+    assert_eq!(on_disk[2..], expected[..]);
+
+    for name in &expected {
+        assert!(image.stat(Path::new(&format!("/grande/{}", name))).is_ok(), "{}", name);
+    }
+    assert!(image.stat(Path::new("/grande/k")).is_err());
+    drop(image);
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn names_up_to_the_statfs_limit_fit_and_longer_ones_are_refused() {
+    // Lo que statfs informa como namelen deja lugar al terminador
+    assert_eq!(QRFS_MAX_NAME, QRFS_NAME_LEN - 1);
+    assert_eq!(SLOT, 4 + QRFS_NAME_LEN);
+
+    let longest = format!("/{}", "n".repeat(QRFS_MAX_NAME));
+    let too_long = format!("/{}", "n".repeat(QRFS_MAX_NAME + 1));
+    let dir = fresh_image(64);
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.create_file(Path::new(&longest)).unwrap();
+        assert!(image.create_file(Path::new(&too_long)).is_err());
+        assert!(image.mkdir(Path::new(&too_long)).is_err());
+        assert!(image.rename(Path::new(&longest), Path::new(&too_long)).is_err());
+    }
+
+    let image = QrfsImage::open(dir.path()).unwrap();
+    assert!(image.stat(Path::new(&longest)).is_ok());
+    assert!(image.stat(Path::new(&too_long)).is_err());
+    drop(image);
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn mkfs_root_and_mkdir_start_with_dot_entries() {
+    let dir = fresh_image(64);
+    let image = QrfsImage::open(dir.path()).unwrap();
+    let root = image.resolve(Path::new("/")).unwrap();
+    assert_eq!(&dir_slots(&image, root)[..2], [(root as u32, ".".into()), (root as u32, "..".into())]);
+
+    let sub = image.mkdir(Path::new("/d")).unwrap();
+    assert_eq!(dir_slots(&image, sub), [(sub as u32, ".".into()), (root as u32, "..".into())]);
+}
+
+#[test]
+fn inode_types_that_contradict_the_tree_are_reported() {
+    let dir = fresh_image(64);
+    let (sub, inner, file) = {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        let sub = image.mkdir(Path::new("/sub")).unwrap();
+        let inner = image.mkdir(Path::new("/sub/dentro")).unwrap();
+        (sub, inner, image.create_file(Path::new("/f")).unwrap())
+    };
+    let fs = QrfsFilesystem::mount_from_folder(dir.path(), None, None).unwrap();
+    assert!(fs.type_conflicts().unwrap().is_empty());
+    drop(fs);
+
+    // "/sub" pasa a decir que es un archivo y "/f" que es un directorio (sin bloque)
+    let sb = read_superblock(&dir);
+    let table = InodeTable::open(dir.path(), &sb).unwrap();
+    let mut sub_inode = table.get(sub).unwrap();
+    let mut file_inode = table.get(file).unwrap();
+    std::mem::swap(&mut sub_inode.file_type, &mut file_inode.file_type);
+    table.put(sub, &sub_inode).unwrap();
+    table.put(file, &file_inode).unwrap();
+
+    let fs = QrfsFilesystem::mount_from_folder(dir.path(), None, None).unwrap();
+    let conflicts = fs.type_conflicts().unwrap();
+    assert_eq!(
+        conflicts,
+        [TypeConflict::FileUsedAsDir { ino: sub, child: Some(inner) }, TypeConflict::DirWithoutBlock { ino: file }]
+    );
+    assert!(conflicts[1].to_string().contains(&format!("inodo {}", file)));
+}
+
+#[test]
+fn resolve_walks_the_path_and_names_the_failing_component() {
+    let dir = fresh_image(64);
+    let image = QrfsImage::open(dir.path()).unwrap();
+    image.mkdir(Path::new("/a")).unwrap();
+    image.mkdir(Path::new("/a/b")).unwrap();
+    let file = image.create_file(Path::new("/a/b/c.txt")).unwrap();
+
+    assert_eq!(image.resolve(Path::new("/a/b/c.txt")).unwrap(), file);
+    assert_eq!(image.resolve(Path::new("/a/./b/c.txt")).unwrap(), file);
+
+    let err = image.resolve(Path::new("/a/x/c.txt")).unwrap_err().to_string();
+    assert!(err.contains("no existe \"/a/x\""), "{err}");
+
+    let err = image.resolve(Path::new("/a/b/c.txt/d")).unwrap_err().to_string();
+    assert!(err.contains("\"/a/b/c.txt\" no es un directorio"), "{err}");
+}
@@ -0,0 +1,37 @@
+mod common;
+
+use std::process::Command;
+
+use common::fresh_image;
+use qrfs::{lock_folder, QrfsImage, QRFS_LOCK_NAME};
+
+/// Corre `fsck.qrfs` con `args` sobre la imagen; devuelve si terminó bien y su stderr.
+fn fsck(dir: &tempfile::TempDir, args: &[&str]) -> (bool, String) {
+    let out = Command::new(env!("CARGO_BIN_EXE_fsck.qrfs")).args(args).arg(dir.path()).output().unwrap();
+    (out.status.success(), String::from_utf8_lossy(&out.stderr).into_owned())
+}
+
+#[test]
+fn writing_fsck_is_refused_while_the_image_is_open() {
+    let dir = fresh_image(32);
+    let image = QrfsImage::open(dir.path()).unwrap();
+    assert!(dir.path().join(QRFS_LOCK_NAME).is_file());
+
+    let (ok, stderr) = fsck(&dir, &["--repair-counters"]);
+    assert!(!ok);
+    assert!(stderr.contains("en uso por otro proceso"), "{stderr}");
+
+    // Sólo leer no necesita el lock
+    assert!(fsck(&dir, &["--repair-counters", "--dry-run"]).0);
+
+    drop(image);
+    assert!(fsck(&dir, &["--repair-counters"]).0);
+}
+
+#[test]
+fn the_lock_is_shared_within_the_process() {
+    let dir = fresh_image(32);
+    let _first = QrfsImage::open(dir.path()).unwrap();
+    let _second = QrfsImage::open(dir.path()).unwrap();
+    assert!(lock_folder(dir.path()).unwrap().is_some());
+}
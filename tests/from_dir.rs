@@ -0,0 +1,77 @@
+mod common;
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, UNIX_EPOCH};
+
+use common::{assert_fsck_clean, pattern};
+use qrfs::QrfsImage;
+use tempfile::TempDir;
+
+/// Corre `from_dir.qrfs` de `source` a una carpeta nueva y la devuelve.
+fn from_dir(source: &Path, extra: &[&str]) -> TempDir {
+    let dir = TempDir::new().unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_from_dir.qrfs"))
+        .args(extra)
+        .arg(source)
+        .arg(dir.path())
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "from_dir.qrfs: {}", String::from_utf8_lossy(&out.stderr));
+    dir
+}
+
+#[test]
+fn small_tree_round_trips_with_metadata() {
+    let source = TempDir::new().unwrap();
+    let big = pattern(10 * 1024 + 100);
+    fs::create_dir_all(source.path().join("docs/sub")).unwrap();
+    fs::write(source.path().join("hola.txt"), b"hola").unwrap();
+    fs::write(source.path().join("docs/grande.bin"), &big).unwrap();
+    fs::write(source.path().join("docs/sub/vacio"), b"").unwrap();
+
+    let mtime = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    let file = fs::File::options().write(true).open(source.path().join("hola.txt")).unwrap();
+    file.set_modified(mtime).unwrap();
+    fs::set_permissions(source.path().join("hola.txt"), fs::Permissions::from_mode(0o640)).unwrap();
+
+    let dir = from_dir(source.path(), &[]);
+    let image = QrfsImage::open(dir.path()).unwrap();
+
+    assert_eq!(image.read_file(Path::new("/hola.txt")).unwrap(), b"hola");
+    assert_eq!(image.read_file(Path::new("/docs/grande.bin")).unwrap(), big);
+    assert!(image.read_file(Path::new("/docs/sub/vacio")).unwrap().is_empty());
+    assert_eq!(image.stat(Path::new("/docs/sub")).unwrap().kind, fuser::FileType::Directory);
+
+    let attr = image.stat(Path::new("/hola.txt")).unwrap();
+    assert_eq!(attr.perm, 0o640);
+    assert_eq!(attr.mtime, mtime);
+    drop(image);
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn excluded_paths_are_left_out_with_their_subtrees() {
+    let source = TempDir::new().unwrap();
+    fs::create_dir_all(source.path().join(".git/objects")).unwrap();
+    fs::create_dir_all(source.path().join("build/cache")).unwrap();
+    fs::create_dir_all(source.path().join("src")).unwrap();
+    fs::write(source.path().join(".git/objects/abc"), b"objeto").unwrap();
+    fs::write(source.path().join("build/cache/x"), b"cache").unwrap();
+    fs::write(source.path().join("build/salida"), b"salida").unwrap();
+    fs::write(source.path().join("src/main.rs"), b"fn main() {}").unwrap();
+    fs::write(source.path().join("src/main.rs.tmp"), b"basura").unwrap();
+
+    let dir = from_dir(source.path(), &["--exclude", ".git", "--exclude", "build/cache", "--exclude", "*.tmp"]);
+    let image = QrfsImage::open(dir.path()).unwrap();
+
+    for gone in ["/.git", "/build/cache", "/src/main.rs.tmp"] {
+        assert!(image.stat(Path::new(gone)).is_err(), "{gone} no se excluyó");
+    }
+    assert_eq!(image.read_file(Path::new("/build/salida")).unwrap(), b"salida");
+    assert_eq!(image.read_file(Path::new("/src/main.rs")).unwrap(), b"fn main() {}");
+    drop(image);
+    assert_fsck_clean(&dir);
+}
@@ -0,0 +1,275 @@
+mod common;
+
+use std::path::Path;
+use std::process::Command;
+
+use common::{assert_fsck_clean, folder_snapshot, fresh_image, pattern, read_superblock, write_superblock};
+use qrfs::fsck::fsck::run_fsck_with;
+use qrfs::fsck::fsck_types::FsckOptions;
+use qrfs::fsck::qrfs_backend::QrfsBackend;
+use qrfs::fsck::repair::{find_repairs, run_repairs, RepairMode};
+use qrfs::{
+    check, check_dot_entries, dry_run, files_backing_path, refresh_manifest, repair_counters, BlockStore, InodeTable, PlannedChange,
+    QrfsImage,
+};
+
+#[test]
+fn check_reports_a_fresh_image_as_clean() {
+    let dir = fresh_image(64);
+    let report = check(dir.path());
+    assert!(report.errors.is_empty(), "{:?}", report.errors);
+    assert!(report.blocks_ok && report.inodes_ok && !report.truncated);
+
+    // Una carpeta sin imagen no es una imagen sana
+    let empty = tempfile::tempdir().unwrap();
+    assert!(!check(empty.path()).errors.is_empty());
+}
+
+#[test]
+fn moved_directory_passes_fsck() {
+    let dir = fresh_image(64);
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.mkdir(Path::new("/a")).unwrap();
+        image.mkdir(Path::new("/b")).unwrap();
+        image.mkdir(Path::new("/a/c")).unwrap();
+        image.rename(Path::new("/a/c"), Path::new("/b/c")).unwrap();
+    }
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn fsck_reports_a_stale_dotdot() {
+    let dir = fresh_image(64);
+    let (b, c, block) = {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.mkdir(Path::new("/a")).unwrap();
+        let b = image.mkdir(Path::new("/b")).unwrap();
+        let c = image.mkdir(Path::new("/a/c")).unwrap();
+        (b, c, image.read_inode_raw(c).unwrap().direct_blocks[0])
+    };
+
+    // ".." (slot 1) de /a/c apuntando a /b, como dejaba un rename sin reescribirlo
+    let store = BlockStore::open(dir.path()).unwrap();
+    let mut buf = store.read_block(block).unwrap();
+    let slot = std::mem::size_of::<qrfs::DirEntryDisk>();
+    buf[slot..slot + 4].copy_from_slice(&(b as u32).to_le_bytes());
+    store.write_block(block, &buf).unwrap();
+
+    let report = check(dir.path());
+    assert!(
+        report.errors.iter().any(|e| e.contains(&format!("Directorio {}: \"..\"", c))),
+        "fsck: {:?}",
+        report.errors
+    );
+
+    let problems = check_dot_entries(dir.path(), true).unwrap();
+    assert_eq!(problems.len(), 1);
+    assert!(problems[0].bad_dotdot && problems[0].repaired);
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn repair_dots_restores_a_missing_dotdot() {
+    let dir = fresh_image(64);
+    let (a, c, block) = {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        let a = image.mkdir(Path::new("/a")).unwrap();
+        let c = image.mkdir(Path::new("/a/c")).unwrap();
+        image.create_file(Path::new("/a/c/f")).unwrap();
+        (a, c, image.read_inode_raw(c).unwrap().direct_blocks[0])
+    };
+
+    // Slot de ".." en cero, como tras un bloque de directorio dañado
+    let store = BlockStore::open(dir.path()).unwrap();
+    let mut buf = store.read_block(block).unwrap();
+    let slot = std::mem::size_of::<qrfs::DirEntryDisk>();
+    buf[slot..2 * slot].fill(0);
+    store.write_block(block, &buf).unwrap();
+
+    let problems = check_dot_entries(dir.path(), false).unwrap();
+    assert_eq!(problems.len(), 1);
+    assert_eq!((problems[0].ino, problems[0].parent), (c, a));
+    assert!(!problems[0].bad_dot && problems[0].bad_dotdot && !problems[0].repaired);
+
+    assert!(check_dot_entries(dir.path(), true).unwrap()[0].repaired);
+    assert!(check_dot_entries(dir.path(), false).unwrap().is_empty());
+
+    let buf = BlockStore::open(dir.path()).unwrap().read_block(block).unwrap();
+    assert_eq!(&buf[slot..slot + 4], &(a as u32).to_le_bytes());
+    assert!(QrfsImage::open(dir.path()).unwrap().stat(Path::new("/a/c/f")).is_ok());
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn fsck_walks_subdirectories_to_find_orphans() {
+    let dir = fresh_image(64);
+    let (b, f, g) = {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.mkdir(Path::new("/a")).unwrap();
+        let b = image.mkdir(Path::new("/a/b")).unwrap();
+        let f = image.create_file(Path::new("/a/b/f")).unwrap();
+        let g = image.create_file(Path::new("/a/b/g")).unwrap();
+        (b, f, g)
+    };
+    assert_fsck_clean(&dir);
+
+    // Se borra la entrada de "f" (slot 2, después de "." y "..") en /a/b
+    let block = QrfsImage::open(dir.path()).unwrap().read_inode_raw(b).unwrap().direct_blocks[0];
+    let store = BlockStore::open(dir.path()).unwrap();
+    let mut buf = store.read_block(block).unwrap();
+    let slot = std::mem::size_of::<qrfs::DirEntryDisk>();
+    assert_eq!(&buf[2 * slot..2 * slot + 4], &(f as u32).to_le_bytes());
+    buf[2 * slot..3 * slot].fill(0);
+    store.write_block(block, &buf).unwrap();
+
+    let errors = check(dir.path()).errors;
+    assert!(errors.contains(&format!("Inodo {} huérfano", f)), "{:?}", errors);
+    assert!(!errors.iter().any(|e| e.contains(&format!("Inodo {} ", g))), "{:?}", errors);
+}
+
+#[test]
+fn repair_counters_fixes_drifted_free_counts() {
+    let dir = fresh_image(64);
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.create_file(Path::new("/f")).unwrap();
+        image.write_file(Path::new("/f"), &pattern(3000)).unwrap();
+    }
+    let good = read_superblock(&dir);
+
+    let mut drifted = good;
+    drifted.free_blocks += 5;
+    drifted.free_inodes -= 2;
+    drifted.seal();
+    write_superblock(&dir, &drifted);
+
+    let repair = repair_counters(dir.path()).unwrap();
+    assert!(repair.changed());
+    assert_eq!((repair.old_free_blocks, repair.old_free_inodes), (drifted.free_blocks, drifted.free_inodes));
+    assert_eq!((repair.free_blocks, repair.free_inodes), (good.free_blocks, good.free_inodes));
+
+    let sb = read_superblock(&dir);
+    assert_eq!((sb.free_blocks, sb.free_inodes), (good.free_blocks, good.free_inodes));
+    assert!(!repair_counters(dir.path()).unwrap().changed());
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn fsck_path_and_limit_narrow_the_report() {
+    let dir = fresh_image(64);
+    let (a, b) = {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.mkdir(Path::new("/foo")).unwrap();
+        image.mkdir(Path::new("/bar")).unwrap();
+        let a = image.create_file(Path::new("/foo/a")).unwrap();
+        let b = image.create_file(Path::new("/bar/b")).unwrap();
+        image.write_file(Path::new("/foo/a"), &pattern(3000)).unwrap();
+        image.write_file(Path::new("/bar/b"), &pattern(3000)).unwrap();
+        (a, b)
+    };
+
+    // Cada archivo con más bloques de los que su tamaño puede ocupar
+    let table = InodeTable::open(dir.path(), &read_superblock(&dir)).unwrap();
+    for ino in [a, b] {
+        let mut inode = table.get(ino).unwrap();
+        inode.size = 100;
+        table.put(ino, &inode).unwrap();
+    }
+
+    let backend = QrfsBackend::new(dir.path().to_path_buf());
+    let mentions = |errors: &[String], ino: u64| errors.iter().any(|e| e.contains(&format!("Inodo {}:", ino)));
+
+    let full = run_fsck_with(&backend, &FsckOptions::default());
+    assert!(mentions(&full.errors, a) && mentions(&full.errors, b), "{:?}", full.errors);
+
+    let opts = FsckOptions { path: Some("/foo".into()), ..Default::default() };
+    let scoped = run_fsck_with(&backend, &opts);
+    assert!(mentions(&scoped.errors, a), "{:?}", scoped.errors);
+    assert!(!mentions(&scoped.errors, b), "{:?}", scoped.errors);
+
+    let opts = FsckOptions { limit: Some(1), ..Default::default() };
+    assert_eq!(run_fsck_with(&backend, &opts).errors.len(), 1);
+
+    let opts = FsckOptions { path: Some("/no-existe".into()), ..Default::default() };
+    assert!(run_fsck_with(&backend, &opts).errors[0].contains("Ruta no encontrada"));
+}
+
+#[test]
+fn dry_run_lists_the_repairs_without_writing() {
+    let dir = fresh_image(64);
+    let mut sb = read_superblock(&dir);
+    // Un bloque libre marcado usado en el bitmap y contadores corridos
+    let leaked = sb.data_blocks_start + 5;
+    let store = BlockStore::open(dir.path()).unwrap();
+    let mut bitmap = store.read_block(sb.free_bitmap_start).unwrap();
+    bitmap[leaked as usize / 8] |= 1 << (leaked % 8);
+    store.write_block(sb.free_bitmap_start, &bitmap).unwrap();
+    sb.free_blocks += 3;
+    sb.seal();
+    write_superblock(&dir, &sb);
+    let before = folder_snapshot(&dir);
+
+    let (repair, changes) = dry_run(dir.path(), || repair_counters(dir.path())).unwrap();
+    assert!(repair.changed());
+    assert_eq!(
+        changes,
+        [PlannedChange::Superblock {
+            field: "free_blocks",
+            old: sb.free_blocks.to_string(),
+            new: repair.free_blocks.to_string(),
+        }]
+    );
+    assert_eq!(folder_snapshot(&dir), before);
+
+    let (summary, changes) = dry_run(dir.path(), || {
+        let mut backend = QrfsBackend::new(dir.path().to_path_buf());
+        let repairs = find_repairs(&backend);
+        run_repairs(&mut backend, &repairs, RepairMode::Yes, "".as_bytes(), &mut Vec::new())
+    })
+    .unwrap();
+    assert!(summary.applied > 0);
+    assert!(changes.contains(&PlannedChange::Bitmap { block: leaked, used: false }), "{:?}", changes);
+    assert_eq!(folder_snapshot(&dir), before);
+}
+
+/// Corre `fsck.qrfs --block-checksum-scan` sobre `dir` y devuelve su salida.
+fn block_checksum_scan(dir: &tempfile::TempDir) -> String {
+    let out = Command::new(env!("CARGO_BIN_EXE_fsck.qrfs"))
+        .arg("--block-checksum-scan")
+        .arg(dir.path())
+        .env("NO_COLOR", "1")
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "fsck.qrfs: {}", String::from_utf8_lossy(&out.stderr));
+    String::from_utf8_lossy(&out.stdout).into_owned()
+}
+
+#[test]
+fn block_checksum_scan_reports_a_flipped_byte_in_a_block_file() {
+    let dir = fresh_image(64);
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.create_file(Path::new("/datos.bin")).unwrap();
+        image.write_file(Path::new("/datos.bin"), &pattern(3000)).unwrap();
+    }
+    // Lo que hace el montaje al sincronizar: el manifiesto queda al día
+    refresh_manifest(dir.path()).unwrap();
+    let out = block_checksum_scan(&dir);
+    assert!(out.contains("limpio") && !out.contains("omitido"), "{}", out);
+
+    // El último bloque que hace falta para leerlo es uno de sus datos (antes
+    // vienen el superblock, la tabla de inodos y la raíz)
+    let (block, path) = files_backing_path(dir.path(), Path::new("/datos.bin")).unwrap().pop().unwrap();
+    let mut data = std::fs::read(&path).unwrap();
+    data[10] ^= 0xFF;
+    std::fs::write(&path, data).unwrap();
+
+    let out = block_checksum_scan(&dir);
+    assert!(out.contains(&format!("Bloque {}: checksum", block)), "{}", out);
+
+    // Sin manifiesto no hay checksums: la pasada se omite con una nota
+    std::fs::remove_file(dir.path().join(qrfs::QRFS_MANIFEST_NAME)).unwrap();
+    let out = block_checksum_scan(&dir);
+    assert!(out.contains("omitido") && !out.contains("Bloque"), "{}", out);
+}
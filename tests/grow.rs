@@ -0,0 +1,104 @@
+mod common;
+
+use std::path::Path;
+use std::process::Command;
+
+use common::{assert_fsck_clean, folder_snapshot, fresh_image, pattern};
+use qrfs::{list_deleted, BlockStore, QrfsImage, SuperblockDisk};
+use tempfile::TempDir;
+
+/// Corre `grow.qrfs` sobre `dir` y falla con su salida si no termina bien.
+fn grow(dir: &TempDir, total_blocks: u32) {
+    let out = Command::new(env!("CARGO_BIN_EXE_grow.qrfs"))
+        .arg(dir.path())
+        .arg(total_blocks.to_string())
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "grow.qrfs: {}", String::from_utf8_lossy(&out.stderr));
+}
+
+#[test]
+fn grow_is_refused_while_the_image_is_open() {
+    let dir = fresh_image(64);
+    let image = QrfsImage::open(dir.path()).unwrap();
+    let before = folder_snapshot(&dir);
+
+    let out = Command::new(env!("CARGO_BIN_EXE_grow.qrfs"))
+        .arg(dir.path())
+        .arg("200")
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+
+    // Ni siquiera se crearon los archivos de bloque nuevos
+    assert_eq!(folder_snapshot(&dir), before);
+    drop(image);
+    assert_eq!(superblock(&dir).total_blocks, 64);
+}
+
+fn superblock(dir: &TempDir) -> SuperblockDisk {
+    let block = BlockStore::open(dir.path()).unwrap().read_block(0).unwrap();
+    SuperblockDisk::from_block_bytes(&block).unwrap()
+}
+
+#[test]
+fn grow_and_write_into_the_new_space() {
+    let dir = fresh_image(64);
+    grow(&dir, 200);
+    assert_eq!(superblock(&dir).total_blocks, 200);
+
+    // Más de lo que entraba en la imagen original
+    let data = pattern(100 * 1024);
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.create_file(Path::new("/grande.bin")).unwrap();
+        image.write_file(Path::new("/grande.bin"), &data).unwrap();
+    }
+    assert_eq!(QrfsImage::open(dir.path()).unwrap().read_file(Path::new("/grande.bin")).unwrap(), data);
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn grow_with_more_bitmap_blocks_keeps_deleted_files_recoverable() {
+    // 8000 bloques: un bloque de bitmap; 16400 necesitan tres, que salen de
+    // los dos primeros bloques de datos (la raíz y el archivo borrado)
+    let dir = fresh_image(8000);
+    let data = pattern(1000);
+    let ino = {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        let ino = image.create_file(Path::new("/borrado.bin")).unwrap();
+        image.write_file(Path::new("/borrado.bin"), &data).unwrap();
+        image.remove(Path::new("/borrado.bin")).unwrap();
+        ino
+    };
+    let before = superblock(&dir);
+
+    grow(&dir, 16400);
+    let after = superblock(&dir);
+    assert_eq!(after.data_blocks_start, before.data_blocks_start + 2);
+
+    let deleted = list_deleted(dir.path()).unwrap();
+    assert!(deleted.iter().any(|d| d.ino == ino && d.recoverable), "{:?}", deleted.len());
+    let image = QrfsImage::open(dir.path()).unwrap();
+    image.undelete(ino, Path::new("/recuperado.bin")).unwrap();
+    assert_eq!(image.read_file(Path::new("/recuperado.bin")).unwrap(), data);
+    drop(image);
+    assert_fsck_clean(&dir);
+}
+
+#[cfg(feature = "inline-data")]
+#[test]
+fn grow_leaves_inline_data_alone() {
+    let dir = fresh_image(8000);
+    // Los datos inline empiezan con el número del bloque que se va a reubicar
+    let data = superblock(&dir).data_blocks_start.to_le_bytes().to_vec();
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.create_file(Path::new("/chico")).unwrap();
+        image.write_file(Path::new("/chico"), &data).unwrap();
+    }
+
+    grow(&dir, 16400);
+    assert_eq!(QrfsImage::open(dir.path()).unwrap().read_file(Path::new("/chico")).unwrap(), data);
+    assert_fsck_clean(&dir);
+}
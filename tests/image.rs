@@ -0,0 +1,297 @@
+mod common;
+
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use common::{assert_fsck_clean, fresh_image, pattern, read_superblock, write_superblock};
+use qrfs::{check_dot_entries, list_deleted, BlockStore, InodeTable, QrfsImage, QRFS_BLOCK_SIZE};
+
+const BLOCK: usize = QRFS_BLOCK_SIZE as usize;
+
+#[test]
+fn write_file_past_the_direct_pointers() {
+    let dir = fresh_image(64);
+    let path = Path::new("/grande.bin");
+    let data = pattern(13 * BLOCK + 100);
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.create_file(path).unwrap();
+        image.write_file(path, &data).unwrap();
+        assert_eq!(image.read_file(path).unwrap(), data);
+    }
+    assert_eq!(QrfsImage::open(dir.path()).unwrap().read_file(path).unwrap(), data);
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn write_file_through_the_double_indirect() {
+    // 12 directos + 256 del indirecto simple: el resto va al doble indirecto
+    let dir = fresh_image(400);
+    let path = Path::new("/enorme.bin");
+    let data = pattern(300 * BLOCK);
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.create_file(path).unwrap();
+        image.write_file(path, &data).unwrap();
+    }
+    assert_eq!(QrfsImage::open(dir.path()).unwrap().read_file(path).unwrap(), data);
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn write_file_frees_the_old_indirect_blocks() {
+    let dir = fresh_image(64);
+    let path = Path::new("/grande.bin");
+    let small = pattern(100);
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        let ino = image.create_file(path).unwrap();
+        image.write_file(path, &pattern(20 * BLOCK)).unwrap();
+        image.write_file(path, &small).unwrap();
+
+        let inode = image.read_inode_raw(ino).unwrap();
+        assert_eq!(inode.indirect_block, 0);
+        assert_eq!(inode.double_indirect_block, 0);
+        assert_eq!(image.read_file(path).unwrap(), small);
+    }
+    // Un bloque viejo sin liberar sería un bloque ocupado que nadie usa
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn rename_directory_to_another_parent_survives_remount() {
+    let dir = fresh_image(64);
+    let data = pattern(100);
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.mkdir(Path::new("/a")).unwrap();
+        image.mkdir(Path::new("/b")).unwrap();
+        image.mkdir(Path::new("/a/c")).unwrap();
+        image.create_file(Path::new("/a/c/f")).unwrap();
+        image.write_file(Path::new("/a/c/f"), &data).unwrap();
+        image.rename(Path::new("/a/c"), Path::new("/b/c")).unwrap();
+    }
+
+    // El ".." en disco ya apunta al padre nuevo
+    assert!(check_dot_entries(dir.path(), false).unwrap().is_empty());
+
+    let image = QrfsImage::open(dir.path()).unwrap();
+    assert!(image.stat(Path::new("/a/c")).is_err());
+    assert_eq!(image.read_file(Path::new("/b/c/f")).unwrap(), data);
+    image.rmdir(Path::new("/a")).unwrap();
+    drop(image);
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn zero_on_free_leaves_nothing_to_undelete() {
+    let dir = fresh_image(64);
+    let path = Path::new("/secreto.bin");
+    {
+        let image = QrfsImage::open(dir.path()).unwrap().zero_on_free(true);
+        let ino = image.create_file(path).unwrap();
+        image.write_file(path, &pattern(3 * BLOCK)).unwrap();
+        let block = image.read_inode_raw(ino).unwrap().direct_blocks[0];
+        image.remove(path).unwrap();
+
+        assert!(image.read_block_raw(block).unwrap().iter().all(|&b| b == 0));
+        assert!(!list_deleted(dir.path()).unwrap().iter().any(|d| d.ino == ino));
+        assert!(image.undelete(ino, path).is_err());
+    }
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn undelete_refuses_a_file_whose_blocks_were_reused_and_freed() {
+    let dir = fresh_image(64);
+    let first = Path::new("/primero.bin");
+    let second = Path::new("/segundo.bin");
+    let image = QrfsImage::open(dir.path()).unwrap();
+
+    let old = image.create_file(first).unwrap();
+    image.write_file(first, &pattern(BLOCK)).unwrap();
+    image.remove(first).unwrap();
+
+    // El segundo archivo reusa el bloque del primero y después también se borra:
+    // el bitmap vuelve a mostrarlo libre. Con 100 bytes no entra inline
+    let new = image.create_file(second).unwrap();
+    image.write_file(second, &pattern(BLOCK)[..100]).unwrap();
+    image.remove(second).unwrap();
+
+    let deleted = list_deleted(dir.path()).unwrap();
+    assert!(!deleted.iter().any(|d| d.ino == old));
+    assert!(image.undelete(old, first).is_err());
+
+    assert!(deleted.iter().any(|d| d.ino == new && d.recoverable));
+    image.undelete(new, second).unwrap();
+    assert_eq!(image.read_file(second).unwrap(), &pattern(BLOCK)[..100]);
+}
+
+#[test]
+fn create_in_a_root_without_a_block_survives_remount() {
+    let dir = fresh_image(64);
+
+    // Raíz sin bloque de datos: el puntero en 0 y su bloque de vuelta en el bitmap
+    let mut sb = read_superblock(&dir);
+    let table = InodeTable::open(dir.path(), &sb).unwrap();
+    let mut root = table.get(sb.root_inode as u64).unwrap();
+    let old_block = root.direct_blocks[0];
+    root.direct_blocks[0] = 0;
+    root.size = 0;
+    table.put(sb.root_inode as u64, &root).unwrap();
+
+    let store = BlockStore::open(dir.path()).unwrap();
+    let mut bitmap = store.read_block(sb.free_bitmap_start).unwrap();
+    bitmap[old_block as usize / 8] &= !(1 << (old_block % 8));
+    store.write_block(sb.free_bitmap_start, &bitmap).unwrap();
+    sb.free_blocks += 1;
+    sb.seal();
+    write_superblock(&dir, &sb);
+
+    let data = pattern(300);
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.create_file(Path::new("/f")).unwrap();
+        image.write_file(Path::new("/f"), &data).unwrap();
+        image.mkdir(Path::new("/d")).unwrap();
+    }
+
+    let image = QrfsImage::open(dir.path()).unwrap();
+    assert_eq!(image.read_file(Path::new("/f")).unwrap(), data);
+    assert!(image.stat(Path::new("/d")).is_ok());
+    assert_ne!(image.read_inode_raw(sb.root_inode as u64).unwrap().direct_blocks[0], 0);
+    drop(image);
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn create_with_a_full_inode_table_leaves_no_ghost() {
+    let dir = fresh_image(64);
+
+    // Tabla de 4 inodos: la raíz y tres libres
+    let mut sb = read_superblock(&dir);
+    sb.max_inodes = 4;
+    sb.free_inodes = 3;
+    sb.seal();
+    write_superblock(&dir, &sb);
+
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.create_file(Path::new("/a")).unwrap();
+        image.create_file(Path::new("/b")).unwrap();
+        image.mkdir(Path::new("/d")).unwrap();
+
+        assert!(image.create_file(Path::new("/c")).is_err());
+        assert!(image.mkdir(Path::new("/e")).is_err());
+        assert!(image.stat(Path::new("/c")).is_err());
+        assert!(image.stat(Path::new("/e")).is_err());
+    }
+
+    let image = QrfsImage::open(dir.path()).unwrap();
+    assert!(image.stat(Path::new("/c")).is_err());
+    assert_eq!(read_superblock(&dir).free_inodes, 0);
+
+    // Al borrar uno, su slot vuelve a estar disponible
+    image.remove(Path::new("/a")).unwrap();
+    image.create_file(Path::new("/c")).unwrap();
+    drop(image);
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn non_utf8_names_round_trip_without_colliding() {
+    let path = |bytes: &'static [u8]| Path::new(OsStr::from_bytes(bytes));
+    let dir = fresh_image(64);
+    // "f\xFFo" y su versión con pérdida "f\u{FFFD}o" son nombres distintos
+    let raw = path(b"/f\xFFo");
+    let lossy = Path::new("/f\u{FFFD}o");
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.create_file(raw).unwrap();
+        image.write_file(raw, b"crudo").unwrap();
+        image.create_file(lossy).unwrap();
+        image.write_file(lossy, b"con perdida").unwrap();
+        image.mkdir(path(b"/d\xC3")).unwrap();
+    }
+
+    let image = QrfsImage::open(dir.path()).unwrap();
+    assert_eq!(image.read_file(raw).unwrap(), b"crudo");
+    assert_eq!(image.read_file(lossy).unwrap(), b"con perdida");
+    image.rename(raw, path(b"/d\xC3/g\xFE")).unwrap();
+    assert_eq!(image.read_file(path(b"/d\xC3/g\xFE")).unwrap(), b"crudo");
+    assert!(image.stat(raw).is_err());
+    drop(image);
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn raw_reads_of_the_root_inode_and_its_block() {
+    let dir = fresh_image(64);
+    let sb = read_superblock(&dir);
+    let image = QrfsImage::open(dir.path()).unwrap();
+
+    let root = image.read_inode_raw(sb.root_inode as u64).unwrap();
+    assert_eq!(root.id, sb.root_inode);
+    assert!(root.is_dir());
+    assert_eq!(root.nlink, 2);
+    assert_ne!(root.direct_blocks[0], 0);
+
+    let store = BlockStore::open(dir.path()).unwrap();
+    let block = root.direct_blocks[0];
+    assert_eq!(image.read_block_raw(block).unwrap(), store.read_block(block).unwrap());
+    assert_eq!(image.read_block_raw(0).unwrap(), store.read_block(0).unwrap());
+
+    // Fuera de la tabla o de la imagen no hay nada que leer
+    assert!(image.read_inode_raw(sb.max_inodes as u64 + 10).is_none());
+    assert!(image.read_block_raw(sb.total_blocks + 10).is_none());
+}
+
+#[test]
+fn truncate_returns_the_freed_blocks() {
+    let dir = fresh_image(128);
+    let path = Path::new("/datos.bin");
+    let data = pattern(9000);
+    let free = || read_superblock(&dir).free_blocks;
+    let before = free();
+
+    let image = QrfsImage::open(dir.path()).unwrap();
+    image.create_file(path).unwrap();
+    image.write_file(path, &data).unwrap();
+    assert_eq!(free(), before - 9);
+
+    image.truncate(path, 1500).unwrap();
+    assert_eq!(free(), before - 2);
+    assert_eq!(image.read_file(path).unwrap(), &data[..1500]);
+
+    // Agrandar deja un hueco que se lee como ceros y no gasta bloques
+    image.truncate(path, 3000).unwrap();
+    assert_eq!(free(), before - 2);
+    let read = image.read_file(path).unwrap();
+    assert_eq!(&read[..1500], &data[..1500]);
+    assert!(read[1500..].iter().all(|&b| b == 0));
+
+    image.truncate(path, 0).unwrap();
+    assert_eq!(free(), before);
+    drop(image);
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn reused_inode_slot_gets_a_new_generation() {
+    // Con la tabla llena, el único slot para un archivo nuevo es el del borrado
+    let dir = fresh_image(16);
+    let max_inodes = read_superblock(&dir).max_inodes as u64;
+    let image = QrfsImage::open(dir.path()).unwrap();
+    for i in 2..=max_inodes {
+        image.create_file(Path::new(&format!("/f{i}"))).unwrap();
+    }
+    let ino = image.resolve(Path::new("/f2")).unwrap();
+    let first = image.read_inode_raw(ino).unwrap().generation;
+    image.remove(Path::new("/f2")).unwrap();
+
+    assert_eq!(image.create_file(Path::new("/nuevo")).unwrap(), ino);
+    assert_ne!(image.read_inode_raw(ino).unwrap().generation, first);
+    drop(image);
+    assert_fsck_clean(&dir);
+}
@@ -0,0 +1,71 @@
+#![cfg(feature = "inline-data")]
+
+mod common;
+
+use std::path::Path;
+
+use common::{assert_fsck_clean, fresh_image, pattern, read_superblock};
+use qrfs::{QrfsImage, QRFS_INLINE_MAX};
+
+#[test]
+fn tiny_files_stay_in_the_inode() {
+    let dir = fresh_image(64);
+    let free = read_superblock(&dir).free_blocks;
+    let path = Path::new("/chico");
+    let data = pattern(QRFS_INLINE_MAX);
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        let ino = image.create_file(path).unwrap();
+        image.write_file(path, &data).unwrap();
+        assert!(image.read_inode_raw(ino).unwrap().is_inline());
+    }
+
+    assert_eq!(read_superblock(&dir).free_blocks, free);
+    assert_eq!(QrfsImage::open(dir.path()).unwrap().read_file(path).unwrap(), data);
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn one_byte_past_the_limit_uses_a_block_and_back() {
+    let dir = fresh_image(64);
+    let free = read_superblock(&dir).free_blocks;
+    let path = Path::new("/borde");
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        let ino = image.create_file(path).unwrap();
+
+        image.write_file(path, &pattern(QRFS_INLINE_MAX + 1)).unwrap();
+        assert!(!image.read_inode_raw(ino).unwrap().is_inline());
+        assert_eq!(image.read_file(path).unwrap(), pattern(QRFS_INLINE_MAX + 1));
+
+        // Al volver a entrar, el bloque se libera
+        image.write_file(path, &pattern(10)).unwrap();
+        assert!(image.read_inode_raw(ino).unwrap().is_inline());
+    }
+
+    assert_eq!(read_superblock(&dir).free_blocks, free);
+    assert_eq!(QrfsImage::open(dir.path()).unwrap().read_file(path).unwrap(), pattern(10));
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn truncate_past_the_limit_migrates_to_a_block() {
+    let dir = fresh_image(64);
+    let path = Path::new("/crece");
+    let data = pattern(20);
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        let ino = image.create_file(path).unwrap();
+        image.write_file(path, &data).unwrap();
+
+        image.truncate(path, QRFS_INLINE_MAX as u64).unwrap();
+        assert!(image.read_inode_raw(ino).unwrap().is_inline());
+        image.truncate(path, 3000).unwrap();
+        assert!(!image.read_inode_raw(ino).unwrap().is_inline());
+    }
+
+    let mut expected = data;
+    expected.resize(3000, 0);
+    assert_eq!(QrfsImage::open(dir.path()).unwrap().read_file(path).unwrap(), expected);
+    assert_fsck_clean(&dir);
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use std::path::Path;
+
+use common::{fresh_image, pattern, read_superblock};
+use qrfs::{block_file_name, files_backing_path, InodeDisk, QrfsImage, QRFS_BLOCK_SIZE};
+
+#[test]
+fn small_file_is_backed_by_superblock_table_and_data_blocks() {
+    let dir = fresh_image(64);
+    let path = Path::new("/docs/reporte.txt");
+    let (ino, inode) = {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.mkdir(Path::new("/docs")).unwrap();
+        let ino = image.create_file(path).unwrap();
+        image.write_file(path, &pattern(2000)).unwrap();
+        (ino, image.read_inode_raw(ino).unwrap())
+    };
+
+    // El superblock, el bloque (o los dos) de la tabla con el inodo y sus dos bloques de datos
+    let sb = read_superblock(&dir);
+    let inode_size = std::mem::size_of::<InodeDisk>() as u64;
+    let first_byte = (ino - 1) * inode_size;
+    let table = |byte: u64| sb.inode_table_start + (byte / QRFS_BLOCK_SIZE as u64) as u32;
+    let mut expected = vec![0, table(first_byte), table(first_byte + inode_size - 1)];
+    expected.extend(&inode.direct_blocks[..2]);
+    expected.sort_unstable();
+    expected.dedup();
+
+    let found = files_backing_path(dir.path(), path).unwrap();
+    assert_eq!(found.iter().map(|(b, _)| *b).collect::<Vec<_>>(), expected);
+    for (block, file) in &found {
+        assert_eq!(file, &dir.path().join(block_file_name(*block)));
+    }
+
+    // La ruta relativa es la misma, y una que no existe es un error
+    assert_eq!(files_backing_path(dir.path(), Path::new("docs/reporte.txt")).unwrap(), found);
+    assert!(files_backing_path(dir.path(), Path::new("/docs/otro.txt")).is_err());
+}
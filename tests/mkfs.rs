@@ -0,0 +1,157 @@
+mod common;
+
+use std::path::Path;
+use std::process::Command;
+
+use common::{assert_fsck_clean, fresh_image};
+use fuser::FileType;
+use qrfs::{
+    build_layout, capacity_summary, create_block_files, init_fresh_fs, list_block_files, write_fresh_fs, BlockStore,
+    InodeDisk, QrfsImage, SuperblockDisk, QRFS_BLOCK_SIZE, QRFS_DEFAULT_BLOCK_EXT,
+};
+use tempfile::TempDir;
+
+/// Corre `mkfs.qrfs` con `args` y devuelve si terminó bien.
+fn mkfs(args: &[&str], dir: &TempDir) -> bool {
+    Command::new(env!("CARGO_BIN_EXE_mkfs.qrfs")).args(args).arg(dir.path()).output().unwrap().status.success()
+}
+
+/// Nombres de los archivos de `dir`, en orden.
+fn file_names(dir: &TempDir) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn too_few_blocks_for_the_root_is_an_error() {
+    assert!(build_layout(3).is_err());
+
+    let mut layout = build_layout(4).unwrap();
+    layout.max_inodes = 0;
+    assert!(init_fresh_fs(&layout).is_err());
+}
+
+#[test]
+fn minimum_image_has_a_root_and_passes_fsck() {
+    let dir = fresh_image(4);
+
+    let store = BlockStore::open(dir.path()).unwrap();
+    let sb = SuperblockDisk::from_block_bytes(&store.read_block(0).unwrap()).unwrap();
+    assert_eq!(sb.total_blocks, 4);
+    assert_eq!(sb.root_inode, 1);
+    assert_eq!(sb.free_inodes, sb.max_inodes - 1);
+    assert_eq!(sb.free_blocks, 0);
+    drop(store);
+
+    let image = QrfsImage::open(dir.path()).unwrap();
+    assert_eq!(image.resolve(Path::new("/")).unwrap(), 1);
+    assert_eq!(image.stat(Path::new("/")).unwrap().kind, FileType::Directory);
+    drop(image);
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn single_inode_table_holds_only_the_root() {
+    let dir = tempfile::tempdir().unwrap();
+    create_block_files(dir.path(), 4, QRFS_DEFAULT_BLOCK_EXT).unwrap();
+    let entries = list_block_files(dir.path()).unwrap();
+
+    let mut layout = build_layout(4).unwrap();
+    layout.max_inodes = 1;
+    let (superblock, inodes, bitmap) = init_fresh_fs(&layout).unwrap();
+    assert_eq!(superblock.free_inodes, 0);
+    write_fresh_fs(&entries, &layout, &superblock, &inodes, &bitmap).unwrap();
+
+    let image = QrfsImage::open(dir.path()).unwrap();
+    assert_eq!(image.resolve(Path::new("/")).unwrap(), 1);
+    assert_eq!(image.stat(Path::new("/")).unwrap().kind, FileType::Directory);
+    assert!(image.create_file(Path::new("/otro")).is_err());
+    drop(image);
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn mkfs_creates_the_block_files() {
+    let dir = tempfile::tempdir().unwrap();
+    assert!(mkfs(&["--blocks", "16", "--ext", "png"], &dir));
+
+    let names = file_names(&dir);
+    let blocks: Vec<&String> = names.iter().filter(|n| n.starts_with("block_")).collect();
+    assert_eq!(blocks.len(), 16);
+    assert!(blocks.iter().all(|n| n.ends_with(".png")));
+    assert_eq!(list_block_files(dir.path()).unwrap().len(), 16);
+
+    let image = QrfsImage::open(dir.path()).unwrap();
+    image.create_file(Path::new("/f")).unwrap();
+    drop(image);
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn mkfs_existing_formats_the_files_already_there() {
+    let dir = tempfile::tempdir().unwrap();
+    create_block_files(dir.path(), 8, QRFS_DEFAULT_BLOCK_EXT).unwrap();
+    let before = file_names(&dir);
+
+    // Sin --blocks ni --existing no sabe qué hacer; con los dos tampoco
+    assert!(!mkfs(&[], &dir));
+    assert!(!mkfs(&["--blocks", "8", "--existing"], &dir));
+    assert!(mkfs(&["--existing"], &dir));
+
+    assert!(before.iter().all(|n| file_names(&dir).contains(n)));
+    let store = BlockStore::open(dir.path()).unwrap();
+    assert_eq!(SuperblockDisk::from_block_bytes(&store.read_block(0).unwrap()).unwrap().total_blocks, 8);
+    drop(store);
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn capacity_summary_for_128_blocks() {
+    let layout = build_layout(128).unwrap();
+    let summary = capacity_summary(&layout);
+
+    // 14 bloques de metadatos; de los 114 de datos, uno es de la raíz
+    assert_eq!(summary.metadata_blocks, 14);
+    assert_eq!(summary.metadata_percent, 14.0 * 100.0 / 128.0);
+    assert_eq!(summary.data_bytes, 113 * 1024);
+
+    let files: Vec<_> = summary.files.iter().map(|f| (f.file_size, f.max_files, f.limited_by_inodes)).collect();
+    // De 1 KB entran tantos como inodos libres; uno de 64 KB usa 65 bloques (con el indirecto)
+    assert_eq!(files, [(1024, 108, true), (4096, 28, false), (64 * 1024, 1, false)]);
+}
+
+#[test]
+fn inodes_fill_the_whole_table_and_straddle_blocks() {
+    let inode_size = std::mem::size_of::<InodeDisk>() as u32;
+    let layout = build_layout(128).unwrap();
+    assert_eq!(layout.max_inodes, layout.inode_table_blocks * QRFS_BLOCK_SIZE / inode_size);
+
+    // Alguno de los inodos 2..=12 cruza el borde entre el primer y el segundo
+    // bloque de la tabla (con 112 bytes por inodo, el 10)
+    let block_of = |byte: u32| byte / QRFS_BLOCK_SIZE;
+    assert!((2..=12).any(|ino| block_of((ino - 1) * inode_size) != block_of(ino * inode_size - 1)));
+    let dir = fresh_image(128);
+    let paths: Vec<String> = (2..=12).map(|i| format!("/f{}", i)).collect();
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        for path in &paths {
+            image.create_file(Path::new(path)).unwrap();
+            image.write_file(Path::new(path), path.as_bytes()).unwrap();
+        }
+    }
+
+    let image = QrfsImage::open(dir.path()).unwrap();
+    for path in &paths {
+        let ino = image.resolve(Path::new(path)).unwrap();
+        assert_eq!(image.read_inode_raw(ino).unwrap().id as u64, ino);
+        assert_eq!(image.read_file(Path::new(path)).unwrap(), path.as_bytes());
+    }
+    assert!(image.read_inode_raw(layout.max_inodes as u64).is_some());
+    assert!(image.read_inode_raw(layout.max_inodes as u64 + 1).is_none());
+    drop(image);
+    assert_fsck_clean(&dir);
+}
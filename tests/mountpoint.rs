@@ -0,0 +1,26 @@
+use std::fs;
+
+use qrfs::check_mountpoint;
+
+#[test]
+fn missing_file_and_nonempty_mountpoints_are_explained() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let missing = dir.path().join("no-existe");
+    let err = format!("{:#}", check_mountpoint(&missing, false).unwrap_err());
+    assert!(err.contains("no existe") && err.contains("mkdir -p"), "{err}");
+
+    let file = dir.path().join("archivo");
+    fs::write(&file, b"x").unwrap();
+    let err = format!("{:#}", check_mountpoint(&file, false).unwrap_err());
+    assert!(err.contains("no es un directorio"), "{err}");
+
+    // `dir` tiene adentro el archivo de arriba
+    let err = format!("{:#}", check_mountpoint(dir.path(), false).unwrap_err());
+    assert!(err.contains("--allow-nonempty"), "{err}");
+    check_mountpoint(dir.path(), true).unwrap();
+
+    let empty = dir.path().join("vacio");
+    fs::create_dir(&empty).unwrap();
+    check_mountpoint(&empty, false).unwrap();
+}
@@ -0,0 +1,36 @@
+#![cfg(feature = "nsec-time")]
+
+mod common;
+
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+use common::{assert_fsck_clean, fresh_image, read_superblock};
+use qrfs::{InodeTable, QrfsImage, QRFS_INODE_NSEC_TIME};
+
+#[test]
+fn mtime_with_nanoseconds_round_trips() {
+    let dir = fresh_image(64);
+    let path = Path::new("/datos.bin");
+    let ino = QrfsImage::open(dir.path()).unwrap().create_file(path).unwrap();
+
+    // Un inodo de una versión sin la feature: tiempos en segundos
+    let sb = read_superblock(&dir);
+    let table = InodeTable::open(dir.path(), &sb).unwrap();
+    let mut inode = table.get(ino).unwrap();
+    inode.file_type &= !QRFS_INODE_NSEC_TIME;
+    inode.atime = 1_600_000_000;
+    inode.ctime = 1_600_000_000;
+
+    let mtime = UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789);
+    inode.set_mtime(mtime);
+    assert!(inode.has_nsec_time());
+    table.put(ino, &inode).unwrap();
+
+    let attr = QrfsImage::open(dir.path()).unwrap().stat(path).unwrap();
+    assert_eq!(attr.mtime, mtime);
+    // Los otros tiempos pasaron al formato nuevo sin cambiar
+    assert_eq!(attr.atime, UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+    assert_eq!(attr.ctime, UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+    assert_fsck_clean(&dir);
+}
@@ -0,0 +1,38 @@
+mod common;
+
+use std::path::Path;
+
+use common::{assert_fsck_clean, folder_snapshot, fresh_image, pattern};
+use qrfs::{QrfsFilesystem, QrfsImage};
+
+#[test]
+fn writes_through_the_overlay_leave_the_lower_image_alone() {
+    let lower = fresh_image(64);
+    {
+        let image = QrfsImage::open(lower.path()).unwrap();
+        image.create_file(Path::new("/base.txt")).unwrap();
+        image.write_file(Path::new("/base.txt"), b"original").unwrap();
+    }
+    let before = folder_snapshot(&lower);
+
+    let upper = tempfile::tempdir().unwrap();
+    drop(QrfsFilesystem::overlay(lower.path(), upper.path()).unwrap());
+    {
+        let image = QrfsImage::open(upper.path()).unwrap();
+        assert_eq!(image.read_file(Path::new("/base.txt")).unwrap(), b"original");
+        image.write_file(Path::new("/base.txt"), b"cambiado").unwrap();
+        image.create_file(Path::new("/nuevo.bin")).unwrap();
+        image.write_file(Path::new("/nuevo.bin"), &pattern(3000)).unwrap();
+    }
+
+    assert_eq!(folder_snapshot(&lower), before);
+    let base = QrfsImage::open(lower.path()).unwrap();
+    assert_eq!(base.read_file(Path::new("/base.txt")).unwrap(), b"original");
+    assert!(base.stat(Path::new("/nuevo.bin")).is_err());
+
+    let image = QrfsImage::open(upper.path()).unwrap();
+    assert_eq!(image.read_file(Path::new("/base.txt")).unwrap(), b"cambiado");
+    assert_eq!(image.read_file(Path::new("/nuevo.bin")).unwrap(), pattern(3000));
+    drop(image);
+    assert_fsck_clean(&upper);
+}
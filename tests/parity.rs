@@ -0,0 +1,60 @@
+mod common;
+
+use std::path::Path;
+
+use common::{assert_fsck_clean, fresh_image, pattern, read_superblock};
+use qrfs::{add_parity, BlockStore, QrfsImage, QRFS_BLOCK_SIZE};
+
+#[test]
+fn lost_blocks_are_rebuilt_from_parity() {
+    let dir = fresh_image(32);
+    assert_eq!(add_parity(dir.path(), 4, 2).unwrap(), 16);
+
+    let path = Path::new("/datos.bin");
+    let data = pattern(5 * QRFS_BLOCK_SIZE as usize + 100);
+    let direct = {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        let ino = image.create_file(path).unwrap();
+        image.write_file(path, &data).unwrap();
+        image.read_inode_raw(ino).unwrap().direct_blocks
+    };
+
+    // Se pierden dos páginas del mismo grupo de 4: las que alcanza `m = 2`
+    let lost = direct[1];
+    let partner = if lost % 4 == 3 { lost - 1 } else { lost + 1 };
+    {
+        let store = BlockStore::open(dir.path()).unwrap();
+        for b in [lost, partner] {
+            std::fs::remove_file(store.block_path(b).unwrap()).unwrap();
+        }
+    }
+
+    let image = QrfsImage::open(dir.path()).unwrap();
+    assert_eq!(image.read_file(path).unwrap(), data);
+    drop(image);
+    assert_eq!(read_superblock(&dir).parity_group, 4);
+}
+
+#[test]
+fn parity_follows_writes() {
+    let dir = fresh_image(32);
+    add_parity(dir.path(), 4, 1).unwrap();
+
+    let path = Path::new("/datos.bin");
+    let data = pattern(2 * QRFS_BLOCK_SIZE as usize);
+    let block = {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        let ino = image.create_file(path).unwrap();
+        image.write_file(path, &pattern(10)).unwrap();
+        // La segunda escritura cambia el grupo después de calculada la paridad
+        image.write_file(path, &data).unwrap();
+        image.read_inode_raw(ino).unwrap().direct_blocks[0]
+    };
+    assert_fsck_clean(&dir);
+
+    let store = BlockStore::open(dir.path()).unwrap();
+    std::fs::remove_file(store.block_path(block).unwrap()).unwrap();
+    drop(store);
+    let image = QrfsImage::open(dir.path()).unwrap();
+    assert_eq!(image.read_file(path).unwrap(), data);
+}
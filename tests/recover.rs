@@ -0,0 +1,42 @@
+mod common;
+
+use std::path::Path;
+
+use common::{fresh_image, pattern, read_superblock};
+use qrfs::{rebuild_inode_table, BlockStore, QrfsImage, QRFS_BLOCK_SIZE};
+
+#[test]
+fn rebuild_recovers_the_tree_after_losing_the_inode_table() {
+    let dir = fresh_image(64);
+    let (a, b, data_blocks) = {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        let a = image.mkdir(Path::new("/a")).unwrap();
+        let b = image.mkdir(Path::new("/a/b")).unwrap();
+        let f = image.create_file(Path::new("/a/f.txt")).unwrap();
+        image.write_file(Path::new("/a/f.txt"), &pattern(1500)).unwrap();
+        image.create_file(Path::new("/g")).unwrap();
+        (a, b, image.read_inode_raw(f).unwrap().direct_blocks[..2].to_vec())
+    };
+
+    // Se pierde la tabla de inodos entera
+    let sb = read_superblock(&dir);
+    let zeros = vec![0u8; (sb.inode_table_blocks * QRFS_BLOCK_SIZE) as usize];
+    BlockStore::open(dir.path()).unwrap().write_blocks(sb.inode_table_start, &zeros).unwrap();
+    assert!(QrfsImage::open(dir.path()).and_then(|image| image.stat(Path::new("/a"))).is_err());
+
+    let report = rebuild_inode_table(dir.path()).unwrap();
+    let mut dirs: Vec<u64> = report.directories.iter().map(|&(ino, _)| ino).collect();
+    dirs.sort();
+    assert_eq!(dirs, [1, a, b]);
+    let mut files: Vec<&str> = report.files.iter().map(|(_, name)| name.as_str()).collect();
+    files.sort();
+    assert_eq!(files, ["f.txt", "g"]);
+    // Los datos no se pueden atribuir: quedan reservados y reportados
+    assert_eq!(report.unplaced_blocks, data_blocks);
+
+    let image = QrfsImage::open(dir.path()).unwrap();
+    assert_eq!(image.resolve(Path::new("/a/b")).unwrap(), b);
+    assert!(image.read_file(Path::new("/a/f.txt")).unwrap().is_empty());
+    assert!(image.stat(Path::new("/g")).is_ok());
+    image.create_file(Path::new("/a/b/nuevo")).unwrap();
+}
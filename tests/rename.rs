@@ -0,0 +1,69 @@
+mod common;
+
+use std::path::Path;
+
+use common::{assert_fsck_clean, fresh_image, pattern};
+use qrfs::{QrfsImage, QRFS_BLOCK_SIZE, QRFS_NAME_LEN};
+
+#[test]
+fn rename_to_the_same_name_is_a_noop() {
+    let dir = fresh_image(64);
+    let path = Path::new("/a.txt");
+    let data = pattern(10);
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        let ino = image.create_file(path).unwrap();
+        image.write_file(path, &data).unwrap();
+        image.rename(path, path).unwrap();
+        assert_eq!(image.resolve(path).unwrap(), ino);
+        assert_eq!(image.read_file(path).unwrap(), data);
+    }
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn rename_within_a_directory() {
+    let dir = fresh_image(64);
+    let data = pattern(10);
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.mkdir(Path::new("/d")).unwrap();
+        let ino = image.create_file(Path::new("/d/viejo")).unwrap();
+        image.write_file(Path::new("/d/viejo"), &data).unwrap();
+        image.rename(Path::new("/d/viejo"), Path::new("/d/nuevo")).unwrap();
+        assert!(image.stat(Path::new("/d/viejo")).is_err());
+        assert_eq!(image.resolve(Path::new("/d/nuevo")).unwrap(), ino);
+    }
+    let image = QrfsImage::open(dir.path()).unwrap();
+    assert_eq!(image.read_file(Path::new("/d/nuevo")).unwrap(), data);
+    drop(image);
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn failed_rename_keeps_the_entry() {
+    let dir = fresh_image(256);
+    let from = Path::new("/origen/archivo");
+    let to = Path::new("/lleno/archivo");
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.mkdir(Path::new("/origen")).unwrap();
+        image.mkdir(Path::new("/lleno")).unwrap();
+        let ino = image.create_file(from).unwrap();
+
+        // Se llena el único bloque de /lleno: agregarle la entrada falla a mitad del rename
+        let slots = QRFS_BLOCK_SIZE as usize / (4 + QRFS_NAME_LEN);
+        for i in 0..slots - 2 {
+            image.create_file(&Path::new("/lleno").join(format!("f{i}"))).unwrap();
+        }
+        assert!(image.rename(from, to).is_err());
+
+        assert_eq!(image.resolve(from).unwrap(), ino);
+        assert!(image.stat(to).is_err());
+    }
+    let image = QrfsImage::open(dir.path()).unwrap();
+    assert!(image.stat(from).is_ok());
+    assert!(image.stat(to).is_err());
+    drop(image);
+    assert_fsck_clean(&dir);
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::{assert_fsck_clean, fresh_image, pattern};
+use qrfs::{block_payload, import_scans, BlockStore, QrfsImage};
+
+#[test]
+fn shuffled_scans_rebuild_the_image() {
+    let dir = fresh_image(40);
+    let data = pattern(3000);
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.create_file(Path::new("/datos.bin")).unwrap();
+        image.write_file(Path::new("/datos.bin"), &data).unwrap();
+    }
+
+    // Un escaneo por bloque con nombres que no siguen el orden, uno repetido y
+    // una foto que no es de la imagen
+    let scans = tempfile::tempdir().unwrap();
+    let store = BlockStore::open(dir.path()).unwrap();
+    for block in 0..40u32 {
+        let payload = block_payload(block, &store.read_block(block).unwrap());
+        fs::write(scans.path().join(format!("foto_{:02}.bin", (block * 17 + 3) % 40)), &payload).unwrap();
+        if block == 7 {
+            fs::write(scans.path().join("foto_repetida.bin"), &payload).unwrap();
+        }
+    }
+    fs::write(scans.path().join("otra_cosa.txt"), b"no es un QR de QRFS").unwrap();
+
+    let dest = tempfile::tempdir().unwrap();
+    let report = import_scans(scans.path(), dest.path()).unwrap();
+    assert_eq!(report.total_blocks, 40);
+    assert!(report.missing.is_empty());
+    assert_eq!(report.rejected, [scans.path().join("otra_cosa.txt")]);
+
+    let rebuilt = BlockStore::open(dest.path()).unwrap();
+    for block in 0..40 {
+        assert_eq!(rebuilt.read_block(block).unwrap(), store.read_block(block).unwrap());
+    }
+    assert_eq!(QrfsImage::open(dest.path()).unwrap().read_file(Path::new("/datos.bin")).unwrap(), data);
+    assert_fsck_clean(&dest);
+
+    // Sin dos de los escaneos no se escribe nada y se dice cuáles faltan
+    for block in [0u32, 5] {
+        fs::remove_file(scans.path().join(format!("foto_{:02}.bin", (block * 17 + 3) % 40))).unwrap();
+    }
+    let partial = tempfile::tempdir().unwrap();
+    let report = import_scans(scans.path(), partial.path()).unwrap();
+    assert_eq!(report.missing, [0, 5]);
+    assert_eq!(fs::read_dir(partial.path()).unwrap().count(), 0);
+}
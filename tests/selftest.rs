@@ -0,0 +1,32 @@
+use std::process::Command;
+
+#[test]
+fn selftest_passes_every_step() {
+    let out = Command::new(env!("CARGO_BIN_EXE_selftest.qrfs")).output().unwrap();
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "selftest.qrfs:\n{}{}", stdout, String::from_utf8_lossy(&out.stderr));
+
+    let steps = [
+        "mkfs",
+        "abrir",
+        "mkdir",
+        "create",
+        "write de varios bloques",
+        "read y comparar",
+        "rename",
+        "truncate",
+        "unlink",
+        "rmdir",
+        "fsck",
+    ];
+    for step in steps {
+        assert!(stdout.contains(step), "falta {:?} en:\n{}", step, stdout);
+    }
+    assert!(!stdout.contains("✗"), "{}", stdout);
+}
+
+#[test]
+fn selftest_takes_no_arguments() {
+    let out = Command::new(env!("CARGO_BIN_EXE_selftest.qrfs")).arg("extra").output().unwrap();
+    assert!(!out.status.success());
+}
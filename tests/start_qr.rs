@@ -0,0 +1,21 @@
+mod common;
+
+use std::path::PathBuf;
+
+use common::fresh_image;
+use qrfs::{block_file_name, QrfsFilesystem};
+
+#[test]
+fn strict_mode_refuses_a_missing_start_qr() {
+    let dir = fresh_image(64);
+    let missing = PathBuf::from("no_existe.png");
+
+    let err = QrfsFilesystem::mount_from_folder_strict(dir.path(), None, Some(missing.clone()))
+        .err()
+        .expect("un start_qr que no está en la carpeta tiene que fallar");
+    assert!(err.to_string().contains("no_existe.png"), "{err}");
+
+    // Sin modo estricto sólo avisa y monta con el primer archivo
+    drop(QrfsFilesystem::mount_from_folder(dir.path(), None, Some(missing)).unwrap());
+    QrfsFilesystem::mount_from_folder_strict(dir.path(), None, Some(PathBuf::from(block_file_name(0)))).unwrap();
+}
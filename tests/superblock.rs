@@ -0,0 +1,146 @@
+mod common;
+
+use std::path::Path;
+
+use common::{assert_fsck_clean, fresh_image, pattern, read_superblock, write_superblock};
+use qrfs::{
+    check, upgrade_image, BlockStore, QrfsImage, SuperblockDisk, QRFS_FEATURE_CHECKSUM, QRFS_MAGIC,
+    QRFS_SUPERBLOCK_BACKUP, QRFS_VERSION,
+};
+
+/// Lee el superblock de `dir`, lo pasa por `change` y lo escribe tal cual (sin sellar).
+fn tamper(dir: &tempfile::TempDir, change: impl FnOnce(&mut SuperblockDisk)) {
+    let mut sb = read_superblock(dir);
+    change(&mut sb);
+    write_superblock(dir, &sb);
+}
+
+#[test]
+fn zeroed_checksum_is_corruption() {
+    let dir = fresh_image(64);
+    tamper(&dir, |sb| {
+        assert_ne!(sb.features & QRFS_FEATURE_CHECKSUM, 0);
+        sb.checksum = 0;
+    });
+
+    assert!(QrfsImage::open(dir.path()).is_err());
+    assert!(check(dir.path()).errors.iter().any(|e| e.contains("checksum")));
+}
+
+#[test]
+fn legacy_image_without_checksum_still_mounts() {
+    let dir = fresh_image(64);
+    tamper(&dir, |sb| {
+        sb.features &= !QRFS_FEATURE_CHECKSUM;
+        sb.checksum = 0;
+    });
+
+    QrfsImage::open(dir.path()).unwrap();
+    assert!(check(dir.path()).errors.is_empty());
+}
+
+#[test]
+fn max_inodes_outside_the_table_is_rejected() {
+    for max_inodes in [0, u32::MAX] {
+        let dir = fresh_image(64);
+        tamper(&dir, |sb| {
+            sb.max_inodes = max_inodes;
+            sb.seal();
+        });
+
+        let err = QrfsImage::open(dir.path()).err().expect("la imagen no debería montarse");
+        assert!(format!("{err:#}").contains("max_inodes"), "{err:#}");
+        assert!(check(dir.path()).errors.iter().any(|e| e.contains("max_inodes")));
+    }
+}
+
+#[test]
+fn max_inodes_filling_the_table_mounts() {
+    let dir = fresh_image(64);
+    tamper(&dir, |sb| {
+        sb.free_inodes += (sb.inode_table_capacity() - sb.max_inodes as u64) as u32;
+        sb.max_inodes = sb.inode_table_capacity() as u32;
+        sb.seal();
+    });
+
+    QrfsImage::open(dir.path()).unwrap();
+}
+
+#[test]
+fn unknown_features_are_refused() {
+    // El bit 0x1 no está asignado: ninguna versión lo escribe
+    for feature in [0x0000_0001, 0x8000_0000] {
+        let dir = fresh_image(64);
+        tamper(&dir, |sb| {
+            sb.features |= feature;
+            sb.seal();
+        });
+
+        let err = QrfsImage::open(dir.path()).err().expect("la imagen no debería montarse");
+        assert!(format!("{err:#}").contains("desconocidas"), "{err:#}");
+        assert!(!check(dir.path()).errors.is_empty());
+    }
+}
+
+#[test]
+fn byte_swapped_superblock_is_named_as_such() {
+    let dir = fresh_image(64);
+    tamper(&dir, |sb| {
+        sb.magic = QRFS_MAGIC.swap_bytes();
+        sb.total_blocks = sb.total_blocks.swap_bytes();
+        sb.block_size = sb.block_size.swap_bytes();
+    });
+    let err = QrfsImage::open(dir.path()).err().expect("la imagen no debería montarse");
+    assert!(format!("{err:#}").contains("byte-swapped"), "{err:#}");
+
+    // Un magic cualquiera sigue dando el mensaje genérico
+    tamper(&dir, |sb| sb.magic = 0xDEAD_BEEF);
+    let err = QrfsImage::open(dir.path()).err().expect("la imagen no debería montarse");
+    assert!(!format!("{err:#}").contains("byte-swapped"), "{err:#}");
+    assert!(format!("{err:#}").contains("magic"), "{err:#}");
+}
+
+#[test]
+fn upgrade_a_v1_image_without_checksum() {
+    let dir = fresh_image(64);
+    let data = pattern(3000);
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.create_file(Path::new("/f")).unwrap();
+        image.write_file(Path::new("/f"), &data).unwrap();
+    }
+    // Como la dejaba la v1 antes del checksum del superblock
+    tamper(&dir, |sb| {
+        sb.version = 1;
+        sb.features &= !QRFS_FEATURE_CHECKSUM;
+        sb.checksum = 0;
+    });
+    let original = BlockStore::open(dir.path()).unwrap().read_block(0).unwrap();
+
+    let report = upgrade_image(dir.path()).unwrap();
+    assert_eq!((report.old_version, report.version), (1, QRFS_VERSION));
+    assert!(!report.changes.is_empty());
+    assert_eq!(std::fs::read(dir.path().join(QRFS_SUPERBLOCK_BACKUP)).unwrap(), original);
+
+    let sb = read_superblock(&dir);
+    assert_ne!(sb.features & QRFS_FEATURE_CHECKSUM, 0);
+    assert!(sb.checksum != 0 && sb.verify_checksum());
+    assert!(upgrade_image(dir.path()).unwrap().changes.is_empty());
+
+    assert_eq!(QrfsImage::open(dir.path()).unwrap().read_file(Path::new("/f")).unwrap(), data);
+    assert_fsck_clean(&dir);
+}
+
+#[test]
+fn upgrade_refuses_to_downgrade() {
+    let dir = fresh_image(64);
+    tamper(&dir, |sb| {
+        sb.version = QRFS_VERSION + 1;
+        sb.seal();
+    });
+    let before = BlockStore::open(dir.path()).unwrap().read_block(0).unwrap();
+
+    assert!(upgrade_image(dir.path()).is_err());
+    assert_eq!(BlockStore::open(dir.path()).unwrap().read_block(0).unwrap(), before);
+    assert!(!dir.path().join(QRFS_SUPERBLOCK_BACKUP).exists());
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use std::path::Path;
+use std::process::Command;
+
+use common::{assert_fsck_clean, fresh_image, pattern};
+use qrfs::{list_block_files, BlockStore, QrfsImage};
+use tempfile::TempDir;
+
+/// Corre `transcode.qrfs --ext ext` de `source` a una carpeta nueva y la devuelve.
+fn transcode(source: &TempDir, ext: &str) -> TempDir {
+    let dest = TempDir::new().unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_transcode.qrfs"))
+        .args(["--ext", ext])
+        .arg(source.path())
+        .arg(dest.path())
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "transcode.qrfs: {}", String::from_utf8_lossy(&out.stderr));
+    dest
+}
+
+#[test]
+fn raw_to_png_and_back_keeps_every_block() {
+    let dir = fresh_image(64);
+    let data = pattern(20 * 1024);
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.create_file(Path::new("/f")).unwrap();
+        image.write_file(Path::new("/f"), &data).unwrap();
+    }
+
+    let png = transcode(&dir, "png");
+    let entries = list_block_files(png.path()).unwrap();
+    assert_eq!(entries.len(), 64);
+    assert!(entries.iter().all(|e| e.extension().is_some_and(|ext| ext == "png")));
+
+    let back = transcode(&png, "qr");
+    let (original, copy) = (BlockStore::open(dir.path()).unwrap(), BlockStore::open(back.path()).unwrap());
+    for b in 0..64 {
+        assert_eq!(original.read_block(b).unwrap(), copy.read_block(b).unwrap(), "bloque {}", b);
+    }
+    assert_eq!(QrfsImage::open(back.path()).unwrap().read_file(Path::new("/f")).unwrap(), data);
+    assert_fsck_clean(&back);
+}
@@ -0,0 +1,44 @@
+mod common;
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use common::{fresh_image, pattern};
+use qrfs::{check, is_zip_image, QrfsFilesystem, QrfsImage};
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+#[test]
+fn zipped_image_mounts_read_only() {
+    let dir = fresh_image(64);
+    let data = pattern(5000);
+    {
+        let image = QrfsImage::open(dir.path()).unwrap();
+        image.create_file(Path::new("/datos.bin")).unwrap();
+        image.write_file(Path::new("/datos.bin"), &data).unwrap();
+    }
+
+    // Como `zip -r imagen.zip qrfolder/`: los bloques y el manifiesto dentro de una carpeta
+    let out = tempfile::tempdir().unwrap();
+    let zip_path = out.path().join("imagen.zip");
+    let mut zip = ZipWriter::new(File::create(&zip_path).unwrap());
+    let mut names: Vec<_> = fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+    names.sort();
+    for name in names {
+        let name = name.into_string().unwrap();
+        zip.start_file(format!("qrfolder/{}", name), SimpleFileOptions::default()).unwrap();
+        zip.write_all(&fs::read(dir.path().join(&name)).unwrap()).unwrap();
+    }
+    zip.finish().unwrap();
+    assert!(is_zip_image(&zip_path));
+
+    let image = QrfsImage::open(&zip_path).unwrap();
+    assert_eq!(image.read_file(Path::new("/datos.bin")).unwrap(), data);
+    assert!(image.create_file(Path::new("/nuevo")).is_err());
+    drop(image);
+
+    QrfsFilesystem::mount_from_folder(&zip_path, None, None).unwrap();
+    let report = check(&zip_path);
+    assert!(report.errors.is_empty(), "{:?}", report.errors);
+}